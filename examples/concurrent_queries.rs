@@ -0,0 +1,121 @@
+//! Serve extract-by-ID and intersect-by-region queries from several threads sharing one
+//! set of loaded indexes, and check every answer against a single-threaded run.
+//!
+//!     cargo run --release --example concurrent_queries --features testutil [THREADS]
+//!
+//! The loaders need no lock: lazy caches (`GofMap::index_cached`, `PrtMap::index_cached`,
+//! ...) are built once by whichever thread asks first, and GFF ranges are read with
+//! positioned reads when the file is not memory-mapped.
+
+use anyhow::{Context, Result, bail};
+use gffx::{
+    FtsMap, GofMap, IndexBuilder, PrtMap, RangeSource, TreeIndexData, load_fts, load_gof, load_prt, testutil::GffSynth,
+};
+use std::{env, fs, path::Path, sync::Arc, thread};
+
+/// Everything a query needs, loaded once and shared behind an `Arc`.
+struct Indexes {
+    fts: FtsMap,
+    prt: PrtMap,
+    gof: GofMap,
+    tree: TreeIndexData,
+    gff: RangeSource,
+}
+
+enum Query {
+    /// The model block holding a feature ID
+    Extract(String),
+    /// The model blocks overlapping a 0-based, half-open region
+    Intersect(String, u32, u32),
+}
+
+impl Indexes {
+    fn open(gff: &Path) -> Result<Self> {
+        Ok(Self {
+            fts: load_fts(gff)?,
+            prt: load_prt(gff)?,
+            gof: load_gof(gff)?,
+            tree: TreeIndexData::load_tree_index(gff)?,
+            gff: RangeSource::open(gff)?,
+        })
+    }
+
+    /// Bytes of the block of `root`.
+    fn block(&self, root: u32) -> Result<Vec<u8>> {
+        let &(s, e) = self.gof.index_cached().get(&root).with_context(|| format!("root {root} not in .gof"))?;
+        Ok(self.gff.range(s, e)?.into_owned())
+    }
+
+    fn answer(&self, q: &Query) -> Result<Vec<u8>> {
+        match q {
+            Query::Extract(id) => {
+                let fid = self.fts.get_fid(id).with_context(|| format!("ID {id} not indexed"))?;
+                let root = self.prt.root_of(fid).with_context(|| format!("no root for {id}"))?;
+                self.block(root)
+            }
+            Query::Intersect(seqid, start, end) => {
+                let mut out = Vec::new();
+                for root in self.tree.query(seqid, *start, *end)? {
+                    out.extend(self.block(root)?);
+                }
+                Ok(out)
+            }
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let threads: usize = env::args().nth(1).map(|s| s.parse()).transpose()?.unwrap_or(8);
+
+    let dir = env::temp_dir().join(format!("gffx-concurrent-{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+    let gff = dir.join("synth.gff");
+    let genome = GffSynth::new().chromosomes(4).genes_per_chrom(2_000).seed(7).write(&gff)?;
+    IndexBuilder::new()
+        .attr_key("Name")
+        .skip_types("remark,note,comment,region,gap,assembly_gap,contig,scaffold,source")
+        .name_index(false)
+        .build(&gff)?;
+
+    // Mixed workload: every gene and first transcript by ID, and every gene span
+    // widened by 1 kb on each side (catching neighbours) by region
+    let mut queries = Vec::new();
+    for g in &genome.genes {
+        queries.push(Query::Extract(g.id.clone()));
+        queries.push(Query::Extract(g.transcripts[0].id.clone()));
+        let (seqid, _) = &genome.chromosomes[g.chrom];
+        let (start, end) = ((g.start - 1).saturating_sub(1_000), g.end + 1_000);
+        queries.push(Query::Intersect(seqid.clone(), start as u32, end as u32));
+    }
+
+    let serial = Indexes::open(&gff)?;
+    let expected: Vec<Vec<u8>> = queries.iter().map(|q| serial.answer(q)).collect::<Result<_>>()?;
+
+    // A fresh copy, so the threads race to fill its lazy caches; each thread takes
+    // every `threads`-th query
+    let idx = Arc::new(Indexes::open(&gff)?);
+    let queries = Arc::new(queries);
+    let results: Vec<Vec<(usize, Vec<u8>)>> = (0..threads)
+        .map(|t| {
+            let (idx, queries) = (Arc::clone(&idx), Arc::clone(&queries));
+            thread::spawn(move || -> Result<Vec<(usize, Vec<u8>)>> {
+                (t..queries.len()).step_by(threads).map(|i| Ok((i, idx.answer(&queries[i])?))).collect()
+            })
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .map(|h| h.join().expect("query thread panicked"))
+        .collect::<Result<_>>()?;
+
+    let mut answered = 0;
+    for (i, got) in results.into_iter().flatten() {
+        if got != expected[i] {
+            bail!("query {i}: threaded answer differs from the serial one");
+        }
+        answered += 1;
+    }
+    println!("{answered} queries on {threads} threads match the serial answers");
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,67 @@
+#![no_main]
+
+use gffx::AttrEditor;
+use libfuzzer_sys::fuzz_target;
+use std::borrow::Cow;
+
+/// Bytes before column 9 and after it (the terminator) must never change.
+fn assert_outside_col9_kept(line: &[u8], editor: &AttrEditor, out: &[u8]) {
+    let Some(col9) = editor.attributes() else {
+        assert_eq!(out, line);
+        return;
+    };
+    let start = col9.as_ptr() as usize - line.as_ptr() as usize;
+    let tail = &line[start + col9.len()..];
+    assert_eq!(&out[..start], &line[..start]);
+    assert!(out.ends_with(tail));
+}
+
+// Column-9 edits splice spans: no-op edits return the input itself, and undoing an
+// edit restores the original bytes.
+fuzz_target!(|data: &[u8]| {
+    for line in data.split_inclusive(|&b| b == b'\n') {
+        let editor = AttrEditor::new(line);
+        assert!(matches!(editor.finish(), Cow::Borrowed(l) if l == line));
+
+        let pairs: Vec<(&[u8], Option<&[u8]>)> = editor.iter().collect();
+        let unique = |key: &[u8]| pairs.iter().filter(|(k, _)| *k == key).count() == 1;
+        let mut editor = AttrEditor::new(line);
+        editor.remove(b"\xff-no-such-key");
+        for &(key, value) in &pairs {
+            if let Some(value) = value
+                && unique(key)
+            {
+                editor.set(key, value);
+            }
+        }
+        assert!(!editor.is_modified());
+        assert_eq!(&*editor.finish(), line);
+
+        let Some(&(key, value)) = pairs.first() else {
+            continue;
+        };
+
+        let mut editor = AttrEditor::new(line);
+        editor.set(key, b"x%3By");
+        assert_outside_col9_kept(line, &editor, &editor.finish());
+        if let Some(value) = value
+            && unique(key)
+        {
+            editor.set(key, value);
+            assert_eq!(&*editor.finish(), line);
+        }
+
+        let mut editor = AttrEditor::new(line);
+        if editor.get(b"gffx_tmp").is_none() {
+            editor.rename(key, b"gffx_tmp");
+            assert_outside_col9_kept(line, &editor, &editor.finish());
+            editor.rename(b"gffx_tmp", key);
+            assert_eq!(&*editor.finish(), line);
+        }
+
+        let mut editor = AttrEditor::new(line);
+        editor.remove(key);
+        editor.append_raw(b"gffx_tag=1");
+        assert_outside_col9_kept(line, &editor, &editor.finish());
+    }
+});
@@ -0,0 +1,31 @@
+#![no_main]
+
+use gffx::{attr_value, fast_id};
+use libfuzzer_sys::fuzz_target;
+
+/// Straightforward `attr_value`: split on ';', trim, compare the key before '='.
+fn reference<'a>(attrs: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    attrs.split(|&b| b == b';').find_map(|pair| {
+        let pair = pair.trim_ascii_start();
+        let eq = pair.iter().position(|&b| b == b'=')?;
+        (&pair[..eq] == key).then(|| pair[eq + 1..].trim_ascii_end())
+    })
+}
+
+// Keys match exactly: `ID=` inside another key or a value is never taken for the ID.
+fuzz_target!(|data: &[u8]| {
+    let (key, attrs) = match data.iter().position(|&b| b == b'\n') {
+        Some(i) => (&data[..i], &data[i + 1..]),
+        None => (&b"ID"[..], data),
+    };
+    if !key.contains(&b'=') && !key.contains(&b';') {
+        assert_eq!(attr_value(attrs, key), reference(attrs, key));
+    }
+    assert_eq!(attr_value(attrs, b"ID"), reference(attrs, b"ID"));
+    if let Ok(text) = std::str::from_utf8(attrs)
+        && let Some(id) = fast_id(text)
+    {
+        assert!(!id.contains(|c: char| c == ';' || c.is_ascii_whitespace()), "{id:?}");
+        assert!(reference(attrs, b"ID").is_some_and(|v| v.starts_with(id.as_bytes())));
+    }
+});
@@ -0,0 +1,21 @@
+#![no_main]
+
+use gffx::{BedpeMode, BedpeSkips, SeqLengths, parse_bedpe_chunk};
+use gffx::commands::intersect::parse_bed_bytes;
+use libfuzzer_sys::fuzz_target;
+use rustc_hash::FxHashMap;
+
+fuzz_target!(|data: &[u8]| {
+    let mut seqid_map: FxHashMap<String, u32> = FxHashMap::default();
+    seqid_map.insert("chr1".to_string(), 0);
+    seqid_map.insert("chr2".to_string(), 1);
+    let _ = parse_bed_bytes(data, &seqid_map, &SeqLengths::default(), false);
+    let mut skips = BedpeSkips::default();
+    for pair in parse_bedpe_chunk(data) {
+        for mode in [BedpeMode::Span, BedpeMode::Ends] {
+            for (_, s, e) in mode.regions(pair, &mut skips).into_iter().flatten() {
+                assert!(s < e);
+            }
+        }
+    }
+});
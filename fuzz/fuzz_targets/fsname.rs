@@ -0,0 +1,51 @@
+#![no_main]
+
+use gffx::{MAX_NAME_BYTES, sanitize, sanitize_all, unsanitize};
+use libfuzzer_sys::fuzz_target;
+use std::path::{Component, Path};
+
+const WINDOWS_FORBIDDEN: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+const RESERVED: &[&str] = &["CON", "PRN", "AUX", "NUL"];
+
+/// A name must be a single plain component under Unix and Windows rules alike.
+fn assert_portable(id: &str, name: &str) {
+    assert!(!name.is_empty(), "{id:?}");
+    assert!(name.len() <= MAX_NAME_BYTES, "{id:?} -> {} bytes", name.len());
+    assert!(!name.contains(WINDOWS_FORBIDDEN) && !name.chars().any(|c| c.is_ascii_control()), "{id:?} -> {name:?}");
+    assert!(!name.starts_with('.') && !name.ends_with(['.', ' ']), "{id:?} -> {name:?}");
+    let stem = name.split('.').next().unwrap_or_default();
+    let device = RESERVED.iter().any(|r| stem.eq_ignore_ascii_case(r))
+        || ((stem.len() == 4) && ["COM", "LPT"].iter().any(|p| stem[..3].eq_ignore_ascii_case(p))
+            && stem.as_bytes()[3].is_ascii_digit()
+            && stem.as_bytes()[3] != b'0');
+    assert!(!device, "{id:?} -> {name:?}");
+    let mut parts = Path::new(name).components();
+    assert!(matches!(parts.next(), Some(Component::Normal(_))) && parts.next().is_none(), "{id:?} -> {name:?}");
+}
+
+// File names from IDs are portable, stable, and reversible unless cut short; IDs
+// that map to the same (case-folded) name are reported by `sanitize_all`.
+fuzz_target!(|data: &[u8]| {
+    let text = String::from_utf8_lossy(data);
+    let ids: Vec<&str> = text.split('\n').collect();
+    for id in &ids {
+        let name = sanitize(id);
+        assert_portable(id, &name);
+        assert_eq!(sanitize(id), name);
+        match unsanitize(&name) {
+            Some(back) => assert_eq!(&back, id),
+            None => assert!(name.contains("%~"), "{id:?} -> {name:?} is not reversible"),
+        }
+    }
+
+    let clash = ids.iter().enumerate().any(|(i, a)| {
+        ids[..i].iter().any(|b| a != b && sanitize(a).to_lowercase() == sanitize(b).to_lowercase())
+    });
+    match sanitize_all(ids.iter().copied()) {
+        Ok(names) => {
+            assert!(!clash);
+            assert_eq!(names.len(), ids.len());
+        }
+        Err(e) => assert!(clash, "{e}"),
+    }
+});
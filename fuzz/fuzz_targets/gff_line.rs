@@ -0,0 +1,25 @@
+#![no_main]
+
+use gffx::commands::intersect::{OverlapMode, gff_line_overlaps_queries, parse_u32_ascii};
+use gffx::{TypeFilter, fast_id};
+use libfuzzer_sys::fuzz_target;
+use rustc_hash::FxHashMap;
+
+fuzz_target!(|data: &[u8]| {
+    let mut ivmap: FxHashMap<String, Vec<(u32, u32)>> = FxHashMap::default();
+    ivmap.insert("chr1".to_string(), vec![(0, 100), (u32::MAX - 1, u32::MAX)]);
+    let allow = TypeFilter::parse("gene").unwrap();
+    let deny = TypeFilter::parse("!gene").unwrap();
+
+    for line in data.split(|&b| b == b'\n') {
+        for mode in [OverlapMode::Contained, OverlapMode::ContainsRegion, OverlapMode::Overlap] {
+            let _ = gff_line_overlaps_queries(line, &ivmap, mode);
+        }
+        let _ = allow.allows_line(line);
+        let _ = deny.allows_line(line);
+        let _ = parse_u32_ascii(line);
+        if let Ok(s) = std::str::from_utf8(line) {
+            let _ = fast_id(s);
+        }
+    }
+});
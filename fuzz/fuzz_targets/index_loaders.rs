@@ -0,0 +1,55 @@
+#![no_main]
+
+use gffx::{
+    Bundle, ByteSource, parse_a2f, parse_atn, parse_fbl, parse_fts, parse_gcnt, parse_gof, parse_prt, scan_fts, scan_gof,
+};
+use libfuzzer_sys::fuzz_target;
+use rustc_hash::FxHashSet;
+
+// Loaders must return Err, never panic (or hang), on malformed index files.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(gof) = parse_gof(data) {
+        let roots: Vec<u32> = gof.entries.iter().map(|e| e.feature_id).collect();
+        let _ = gof.roots_to_offsets(&roots, 1);
+        // The scan used for small query sets must agree with the GofMap lookup
+        let few: Vec<u32> = roots.iter().copied().take(8).chain([u32::MAX]).collect();
+        assert_eq!(scan_gof(data, &few).ok(), Some(gof.roots_to_offsets(&few, 1)));
+        // Offsets are arbitrary here: out-of-range blocks must be errors, not panics
+        for model in gof.iter_models(data).flatten() {
+            let _ = model.lines().count();
+        }
+        // Counts attached to a GofMap answer like the first record of each root
+        if let Ok(counts) = parse_gcnt(data) {
+            let first = counts.first().copied();
+            let gof = gof.with_feature_counts(counts);
+            if let Some((fid, n)) = first {
+                assert_eq!(gof.feature_count(fid), Some(n));
+            }
+        }
+    }
+    if let Ok(prt) = parse_prt(data) {
+        let fids: Vec<u32> = (0..prt.entries.len() as u32).chain([u32::MAX]).collect();
+        let _ = prt.map_fids_to_roots(&fids, 1);
+    }
+    if let Ok(a2f) = parse_a2f(data) {
+        let _ = a2f.map_aids_to_fids_vec(&[0, 1, u32::MAX]);
+    }
+    if let Ok(atn) = parse_atn(ByteSource::from(data.to_vec())) {
+        let _ = atn.iter().count();
+        let _ = atn.get(u32::MAX);
+    }
+    if let Ok(fts) = parse_fts(data) {
+        let _ = fts.get_fid("gene1");
+        let names: FxHashSet<String> = fts.ids.iter().take(8).cloned().chain(["gene1".to_string()]).collect();
+        for (name, fid) in scan_fts(data, &names) {
+            assert_eq!(fts.get_fid(name), Some(fid));
+        }
+    }
+    if let Ok(bloom) = parse_fbl(data) {
+        let _ = bloom.may_contain("gene1");
+    }
+    if let Ok(bundle) = Bundle::parse(ByteSource::from(data.to_vec())) {
+        let _ = bundle.get(".gof");
+        let _ = bundle.verify();
+    }
+});
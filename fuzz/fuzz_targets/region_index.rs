@@ -0,0 +1,28 @@
+#![no_main]
+
+use gffx::TreeIndexData;
+use libfuzzer_sys::fuzz_target;
+
+// Input layout: [n: u8] [n little-endian u64 offsets] [.rit bytes]
+fuzz_target!(|data: &[u8]| {
+    let Some((&n, rest)) = data.split_first() else {
+        return;
+    };
+    let n = n as usize;
+    if rest.len() < n * 8 {
+        return;
+    }
+    let (head, buf) = rest.split_at(n * 8);
+    let offsets: Vec<u64> = head
+        .chunks_exact(8)
+        .map(|c| u64::from_le_bytes(c.try_into().unwrap()))
+        .collect();
+    if let Ok(trees) = TreeIndexData::parse_region_index(buf, &offsets) {
+        let mut hits = Vec::new();
+        for tree in trees.values() {
+            hits.clear();
+            tree.query_interval(0, u32::MAX, &mut hits);
+            let _ = tree.query_point(0);
+        }
+    }
+});
@@ -0,0 +1,55 @@
+#![no_main]
+
+use gffx::testutil::{BamSynth, GffSynth, RegionSynth};
+use libfuzzer_sys::fuzz_target;
+
+// Synthetic fixtures are a function of their settings and seed: building twice gives
+// the same layout and bytes, and every layout is well formed.
+fuzz_target!(|data: &[u8]| {
+    let Some((head, seed)) = data.split_first_chunk::<4>() else {
+        return;
+    };
+    let seed = seed.iter().fold(0u64, |h, &b| h.rotate_left(8) ^ u64::from(b));
+    let tx = 1 + u32::from(head[2] % 4);
+    let ex = 1 + u32::from(head[3] % 12);
+    let synth = GffSynth::new()
+        .chromosomes(1 + usize::from(head[0] % 3))
+        .genes_per_chrom(usize::from(head[1] % 20))
+        .transcripts(tx..tx + 1 + u32::from(head[2] >> 4) % 3)
+        .exons(ex..ex + 1 + u32::from(head[3] >> 4) % 4)
+        .gene_len(100..2_000)
+        .seed(seed);
+    let genome = synth.build().unwrap();
+    assert_eq!(genome, synth.build().unwrap());
+
+    let (mut a, mut b) = (Vec::new(), Vec::new());
+    genome.write_gff_to(&mut a).unwrap();
+    synth.build().unwrap().write_gff_to(&mut b).unwrap();
+    assert_eq!(a, b);
+
+    for g in &genome.genes {
+        assert!(g.start >= 1 && g.start <= g.end && g.end <= genome.chromosomes[g.chrom].1);
+        for t in &g.transcripts {
+            assert!(!t.exons.is_empty());
+            for w in t.exons.windows(2) {
+                assert!(w[0].1 < w[1].0);
+            }
+            for &(s, e) in &t.exons {
+                assert!(g.start <= s && s <= e && e <= g.end);
+            }
+        }
+        assert_eq!(g.transcripts[0].exons.first().unwrap().0, g.start);
+        assert_eq!(g.transcripts[0].exons.last().unwrap().1, g.end);
+    }
+
+    if !genome.genes.is_empty() {
+        let regions = RegionSynth::new(&genome).count(50).seed(seed).build().unwrap();
+        assert_eq!(regions, RegionSynth::new(&genome).count(50).seed(seed).build().unwrap());
+        for &(c, s, e) in &regions {
+            assert!(s < e && e <= genome.chromosomes[c].1);
+        }
+    }
+    let reads = BamSynth::new(&genome).depth(0.5).seed(seed).build().unwrap();
+    assert_eq!(reads, BamSynth::new(&genome).depth(0.5).seed(seed).build().unwrap());
+    assert!(reads.windows(2).all(|w| w[0] <= w[1]));
+});
@@ -5,11 +5,21 @@ pub mod search;
 pub mod coverage;
 pub mod depth;
 pub mod sample;
+pub mod debug;
+pub mod diff_ids;
+pub mod stat;
+pub mod grep;
+pub mod completions;
 
 pub use index::{IndexArgs, run as run_index};
 pub use extract::{ExtractArgs, run as run_extract};
 pub use intersect::{IntersectArgs, run as run_intersect};
-pub use search::{SearchArgs, run as run_search};
+pub use search::{SearchArgs, find_roots, run as run_search};
 pub use coverage::{CoverageArgs, run as run_coverage};
 pub use depth::{DepthArgs, run as run_depth};
 pub use sample::{SampleArgs, run as run_sample};
+pub use debug::{DebugArgs, run as run_debug};
+pub use diff_ids::{DiffIdsArgs, run as run_diff_ids};
+pub use stat::{StatArgs, run as run_stat};
+pub use grep::{GrepArgs, GrepFormat, run as run_grep};
+pub use completions::{CompletionsArgs, MangenArgs, run as run_completions, run_mangen};
@@ -0,0 +1,42 @@
+use anyhow::{Context, Result};
+use clap::{Command, Parser};
+use clap_complete::{Shell, generate};
+use std::{fs, io, path::PathBuf};
+
+/// Arguments
+#[derive(Parser, Debug)]
+#[command(
+    about = "Print a shell completion script",
+    long_about = "This tool prints a bash, zsh, fish, elvish or PowerShell completion script generated from the gffx argument definitions, so every subcommand and flag is covered.\n\nExample: gffx completions bash > /etc/bash_completion.d/gffx"
+)]
+pub struct CompletionsArgs {
+    /// Target shell
+    #[arg(value_enum, value_name = "SHELL")]
+    pub shell: Shell,
+}
+
+/// Write the completion script for `cmd` (the full `gffx` command) to stdout.
+pub fn run(args: &CompletionsArgs, cmd: &mut Command) -> Result<()> {
+    let bin_name = cmd.get_name().to_string();
+    generate(args.shell, cmd, bin_name, &mut io::stdout());
+    Ok(())
+}
+
+/// Arguments
+#[derive(Parser, Debug)]
+#[command(about = "Write troff man pages for gffx and each subcommand", hide = true)]
+pub struct MangenArgs {
+    /// Directory for gffx.1 and one gffx-<subcommand>.1 per subcommand (created if missing)
+    #[arg(short = 'o', long = "out-dir", value_name = "DIR", default_value = "man")]
+    pub out_dir: PathBuf,
+}
+
+/// Render the man pages of `cmd` (the full `gffx` command) into `args.out_dir`.
+pub fn run_mangen(args: &MangenArgs, cmd: Command) -> Result<()> {
+    fs::create_dir_all(&args.out_dir)
+        .with_context(|| format!("Cannot create man page directory: {:?}", args.out_dir))?;
+    clap_mangen::generate_to(cmd, &args.out_dir)
+        .with_context(|| format!("Cannot write man pages to {:?}", args.out_dir))?;
+    diag!("[INFO] Wrote man pages to {:?}", args.out_dir);
+    Ok(())
+}
@@ -1,6 +1,5 @@
-use anyhow::{Result, bail, Context};
+use anyhow::{Result, bail};
 use rayon::prelude::*;
-use memmap2::Mmap;
 use rust_htslib::bam::{self, Read};
 use rustc_hash::{FxHashMap, FxHashSet};
 use std::{
@@ -11,7 +10,10 @@ use std::{
 };
 use clap::Parser;
 use crate::{
-    Interval, TreeIndexData, load_gof, GofMap,
+    AnnotationSet, BED_CHUNK_SIZE, BedReader, BedpeMode, BedpeSkips, Cell, Column, CountingWriter,
+    Interval, ReportFormat, RunSummary, ScorePhase, TableArgs, TableWriter, TypeFilter, UnknownContigs,
+    apply_index_prefix, deterministic, ensure_index, fast_id, parse_bed_chunk, parse_bedpe_chunk, parse_fraction,
+    read_feature_names, set_summary, source_extension,
 };
 use std::time::{Instant, Duration};
 use rust_htslib::bam::ext::BamRecordExtensions;
@@ -24,22 +26,22 @@ const WRITE_BUF_SIZE: usize = 32 * 1024 * 1024;
 /// Compute bin index for coordinate `x` given shift k (unused in the new pipeline).
 #[inline]
 fn _bin_of(x: u32, shift: u32) -> u32 {
-    let _ = shift;
-    x >> 0
+    x >> shift
 }
 
 /// Arguments
 #[derive(Parser, Debug)]
 #[command(
     about = "Compute coverage breadth across genomic feature.",
-    long_about = "This tool computes sequencing coverage breadth and fraction from high-throughput sequencing (HTS) alignment files (SAM/BAM/CRAM) or user-specified genomic intervals (BED)."
+    long_about = "This tool computes sequencing coverage breadth and fraction from high-throughput sequencing (HTS) alignment files (SAM/BAM/CRAM) or user-specified genomic intervals (BED, or BEDPE pairs)."
 )]
 pub struct CoverageArgs {
-    /// GFF file path (indexed via GOF)
-    #[arg(short = 'i', long = "input", value_name = "FILE")]
-    pub input: PathBuf,
+    /// GFF file path (indexed via GOF); repeat `-i` to compute breadth over several
+    /// annotations in a single pass over the source (adds an `annotation` output column)
+    #[arg(short = 'i', long = "input", value_name = "FILE", required = true)]
+    pub input: Vec<PathBuf>,
 
-    /// Source: BAM/SAM/CRAM or BED
+    /// Source: BAM/SAM/CRAM, BED or BEDPE
     #[arg(short = 's', long)]
     pub source: PathBuf,
     
@@ -47,6 +49,65 @@ pub struct CoverageArgs {
     #[arg(short = 'o', long = "output", value_name = "FILE")]
     pub output: Option<PathBuf>,
 
+    /// Only report features in the models of the IDs listed in FILE (one per line)
+    #[arg(short = 'F', long = "features", value_name = "FILE", conflicts_with = "feature_id")]
+    pub features: Option<PathBuf>,
+
+    /// Only report features in the model of this feature ID
+    #[arg(short = 'f', long = "feature-id", value_name = "ID")]
+    pub feature_id: Option<String>,
+
+    /// Only report features of these types (same syntax as `extract -T`); with
+    /// `--format gff` the models keep only their lines of these types
+    #[arg(short = 'T', long = "types", value_name = "TYPES", value_parser = TypeFilter::parse)]
+    pub types: Option<TypeFilter>,
+
+    /// Output format: `tsv` table, `bed` rows (fraction as score), or `gff` models with
+    /// `coverage_breadth=`/`coverage_fraction=` added to each reported feature (single `-i` only)
+    #[arg(long = "format", value_enum, default_value_t = ReportFormat::Tsv)]
+    pub format: ReportFormat,
+
+    /// Minimum depths to report breadth at (e.g. 1,5,20): adds one `breadth_<T>x` column
+    /// (tsv) or `coverage_breadth_<T>x=` attribute (gff) per threshold. The default (1)
+    /// reports only the ≥1x breadth and skips the per-root depth profile.
+    #[arg(
+        long = "breadth-thresholds",
+        value_name = "LIST",
+        value_delimiter = ',',
+        default_value = "1",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub breadth_thresholds: Vec<u32>,
+
+    /// BEDPE sources: `span` covers each cis pair's whole fragment (trans pairs are
+    /// skipped and counted); `ends` covers the two ends, adding at most 1 depth per pair
+    #[arg(long = "bedpe-mode", value_enum, value_name = "MODE", default_value_t = BedpeMode::Span)]
+    pub bedpe_mode: BedpeMode,
+
+    /// Fail when more than this fraction of reads/regions (default 0: any) lie on
+    /// sequences absent from the annotation; they are otherwise reported and skipped
+    #[arg(
+        long = "strict-contigs",
+        value_name = "FRACTION",
+        num_args = 0..=1,
+        default_missing_value = "0",
+        value_parser = parse_fraction
+    )]
+    pub strict_contigs: Option<f64>,
+
+    #[command(flatten)]
+    pub table: TableArgs,
+
+    /// Read the index files of the inputs from PREFIX (see `index --index-prefix`);
+    /// with several -i it must be a directory
+    #[arg(long = "index-prefix", value_name = "PREFIX")]
+    pub index_prefix: Option<PathBuf>,
+
+    /// Index any input whose index is missing files or stale before counting, instead
+    /// of stopping with an error (see `extract --auto-index`)
+    #[arg(long = "auto-index", default_value_t = false)]
+    pub auto_index: bool,
+
     /// Number of threads
     #[arg(short = 't', long = "threads", default_value_t = 12, value_name = "NUM")]
     pub threads: usize,
@@ -69,24 +130,6 @@ fn parse_u32_fast(s: &str) -> Option<u32> {
     Some(n)
 }
 
-/// Extract `ID=` from GFF attributes quickly
-#[inline(always)]
-fn fast_id(attrs: &str) -> Option<&str> {
-    let bytes = attrs.as_bytes();
-    let mut i = 0;
-    while i + 2 < bytes.len() {
-        if bytes[i] == b'I' && bytes[i+1] == b'D' && bytes[i+2] == b'=' {
-            let mut j = i + 3;
-            while j < bytes.len() && bytes[j] != b';' && bytes[j] != b' ' && bytes[j] != b'\t' {
-                j += 1;
-            }
-            return std::str::from_utf8(&bytes[i+3..j]).ok();
-        }
-        i += 1;
-    }
-    None
-}
-
 /// Disjoint union of intervals assumed to be half-open [s, e)
 /// Input may be unsorted and overlapping; output is sorted, non-overlapping.
 fn merge_intervals(mut ivs: Vec<(u32,u32)>) -> Vec<(u32,u32)> {
@@ -120,14 +163,118 @@ fn union_len(mut ivs: Vec<(u32,u32)>) -> usize {
     total
 }
 
+/// Raw coverage intervals per root: root_fid -> list of raw intervals (to be merged later)
+type ByRoot = FxHashMap<u32, Vec<(u32,u32)>>;
+
+/// Per-feature results: id -> (chrom, start0, end0, breadths). `breadths[0]` is the
+/// ≥1x breadth, followed by one entry per `--breadth-thresholds` value.
+type BreadthMap = FxHashMap<String, (String, u32, u32, Vec<usize>, ScorePhase)>;
+
+/// Disjoint intervals where the depth of `ivs` is at least 1 and at least each
+/// value of `thresholds` (in that order), from a difference array over their span.
+fn depth_threshold_intervals(ivs: &[(u32,u32)], thresholds: &[u32]) -> Vec<Vec<(u32,u32)>> {
+    let mut out: Vec<Vec<(u32,u32)>> = vec![Vec::new(); thresholds.len() + 1];
+    let (Some(lo), Some(hi)) = (ivs.iter().map(|iv| iv.0).min(), ivs.iter().map(|iv| iv.1).max()) else {
+        return out;
+    };
+    let mut diff: Vec<i32> = vec![0; (hi - lo) as usize + 1];
+    for &(s, e) in ivs {
+        if e > s {
+            diff[(s - lo) as usize] += 1;
+            diff[(e - lo) as usize] -= 1;
+        }
+    }
+
+    let mins: Vec<i32> = std::iter::once(1).chain(thresholds.iter().map(|&t| t as i32)).collect();
+    let mut open: Vec<Option<u32>> = vec![None; mins.len()];
+    let mut depth = 0i32;
+    for (i, d) in diff.iter().enumerate() {
+        depth += d;
+        let pos = lo + i as u32;
+        for (t, &min) in mins.iter().enumerate() {
+            match (open[t], depth >= min) {
+                (None, true) => open[t] = Some(pos),
+                (Some(s), false) => {
+                    out[t].push((s, pos));
+                    open[t] = None;
+                }
+                _ => {}
+            }
+        }
+    }
+    // Every interval ends by `hi`, so the depth is back to 0 and all runs are closed
+    out
+}
+
+/// Query one region against an annotation's tree index and record it under every
+/// (allowed) root it hits.
+#[inline]
+fn push_region_hits<'a>(
+    set: &'a AnnotationSet,
+    chr_id: u32,
+    start: u32,
+    end: u32,
+    hits: &mut Vec<&'a Interval<u32>>,
+    by_root: &mut ByRoot,
+) -> Result<()> {
+    // Query candidate roots for this region (the tree is decoded on first use)
+    if let Some(tree) = set.index_data.tree_for(chr_id)? {
+        hits.clear();
+        tree.query_interval(start, end, hits);
+        // De-duplicate roots within a single region
+        let mut seen_in_region: FxHashSet<u32> = FxHashSet::default();
+        for h in hits.iter() {
+            if seen_in_region.insert(h.payload) && set.allows_root(h.payload) {
+                by_root.entry(h.payload).or_default().push((start, end));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Record the ends of one BEDPE fragment (chr_id, start, end) under every (allowed) root
+/// they hit. Ends overlapping under the same root are merged first, so a pair adds at
+/// most 1 to the depth of any base.
+#[inline]
+fn push_fragment_hits<'a>(
+    set: &'a AnnotationSet,
+    ends: &[(u32, u32, u32)],
+    hits: &mut Vec<&'a Interval<u32>>,
+    pending: &mut Vec<(u32, u32, u32)>,
+    by_root: &mut ByRoot,
+) -> Result<()> {
+    pending.clear();
+    for &(chr_id, start, end) in ends {
+        if let Some(tree) = set.index_data.tree_for(chr_id)? {
+            hits.clear();
+            tree.query_interval(start, end, hits);
+            pending.extend(hits.iter().filter(|h| set.allows_root(h.payload)).map(|h| (h.payload, start, end)));
+        }
+    }
+    pending.sort_unstable();
+    let mut i = 0;
+    while i < pending.len() {
+        let (root, start, mut end) = pending[i];
+        i += 1;
+        while i < pending.len() && pending[i].0 == root && pending[i].1 <= end {
+            end = end.max(pending[i].2);
+            i += 1;
+        }
+        by_root.entry(root).or_default().push((start, end));
+    }
+    Ok(())
+}
+
 /// Collect coverage intervals per root (from BAM/SAM/CRAM).
 /// We DO NOT read GFF slices here; only group regions by root_fid.
+/// The BAM is streamed once; each read is queried against every annotation set.
 fn collect_by_root_from_bam(
     bam_path: &Path,
-    index_data: &TreeIndexData,
+    sets: &[AnnotationSet],
+    unknown: &mut UnknownContigs,
     verbose: bool,
     threads: usize,
-) -> Result<FxHashMap<u32, Vec<(u32,u32)>>> {
+) -> Result<Vec<ByRoot>> {
     let t_open = Instant::now();
     let mut reader = bam::Reader::from_path(bam_path)?;
     reader.set_threads(std::cmp::max(2, threads))?;
@@ -135,19 +282,25 @@ fn collect_by_root_from_bam(
 
     let header = reader.header().to_owned();
 
-    // Build tid -> chr_id mapping
+    // Build tid -> chr_id mapping for each annotation set
     let t_map_build = Instant::now();
-    let mut tid2num: Vec<Option<u32>> = Vec::with_capacity(header.target_count() as usize);
+    let mut target_names: Vec<String> = Vec::with_capacity(header.target_count() as usize);
     for tid in 0..header.target_count() {
         let chrom_bytes = header.tid2name(tid).to_owned();
-        let chrom = std::str::from_utf8(&chrom_bytes)?;
-        let chr_id = index_data.seqid_to_num.get(chrom).copied();
-        tid2num.push(chr_id);
+        target_names.push(std::str::from_utf8(&chrom_bytes)?.to_owned());
     }
+    let tid2num: Vec<Vec<Option<u32>>> = sets
+        .iter()
+        .map(|set| set.map_target_names(target_names.iter().map(String::as_str)))
+        .collect();
     let t_map_build_elapsed = t_map_build.elapsed();
 
-    // Storage: root_fid -> list of raw intervals (to be merged later)
-    let mut by_root: FxHashMap<u32, Vec<(u32,u32)>> = FxHashMap::default();
+    let mut by_root: Vec<ByRoot> = sets.iter().map(|_| ByRoot::default()).collect();
+    // Mapped reads per target that no annotation knows
+    let tid_known: Vec<bool> = (0..target_names.len())
+        .map(|t| tid2num.iter().any(|m| m[t].is_some()))
+        .collect();
+    let mut skipped_by_tid = vec![0u64; target_names.len()];
 
     let mut t_parse = Duration::ZERO;
     let mut t_tidmap = Duration::ZERO;
@@ -169,115 +322,149 @@ fn collect_by_root_from_bam(
             t_tidmap += t1.elapsed();
             continue;
         }
-        if let Some(chr_id) = tid2num[tid as usize] {
-            let start_i64 = rec.pos();
-            let end_i64 = rec.reference_end();
-            if start_i64 >= 0 && end_i64 > start_i64 {
-                let start = (start_i64 as i128).clamp(0, u32::MAX as i128) as u32;
-                let end   = (end_i64 as i128).clamp(0, u32::MAX as i128) as u32;
-
-                // Query candidate roots for this region
-                if let Some(tree) = index_data.chr_entries.get(&chr_id) {
-                    hits.clear();
-                    tree.query_interval(start, end, &mut hits);
-                    // De-duplicate roots within a single region
-                    let mut seen_in_region: FxHashSet<u32> = FxHashSet::default();
-                    for h in &hits {
-                        if seen_in_region.insert(h.root_fid) {
-                            by_root.entry(h.root_fid).or_default().push((start, end));
-                        }
-                    }
+        unknown.total += 1;
+        if !tid_known.get(tid as usize).copied().unwrap_or(false) {
+            if let Some(n) = skipped_by_tid.get_mut(tid as usize) {
+                *n += 1;
+            }
+            t_tidmap += t1.elapsed();
+            continue;
+        }
+        let start_i64 = rec.pos();
+        let end_i64 = rec.reference_end();
+        if start_i64 >= 0 && end_i64 > start_i64 {
+            let start = (start_i64 as i128).clamp(0, u32::MAX as i128) as u32;
+            let end   = (end_i64 as i128).clamp(0, u32::MAX as i128) as u32;
+
+            for (i, set) in sets.iter().enumerate() {
+                if let Some(chr_id) = tid2num[i].get(tid as usize).copied().flatten() {
+                    push_region_hits(set, chr_id, start, end, &mut hits, &mut by_root[i])?;
                 }
             }
         }
         t_tidmap += t1.elapsed();
     }
+    for (name, &n) in target_names.iter().zip(&skipped_by_tid) {
+        unknown.skip(name, n);
+    }
 
     if verbose {
-        eprintln!("[TIMER] (1) Opening BAM:       {:.2?}", t_open_elapsed);
-        eprintln!("[TIMER] (1b) Build tid2num:    {:.2?}", t_map_build_elapsed);
-        eprintln!("[TIMER] (2) Parse+map records: {:.2?}", t_parse + t_tidmap);
-        eprintln!("[TIMER] (3) Tree queries:      {:.2?}", t_tree);
-        eprintln!("[INFO] Collected {} roots with coverage", by_root.len());
+        diag!("[TIMER] (1) Opening BAM:       {:.2?}", t_open_elapsed);
+        diag!("[TIMER] (1b) Build tid2num:    {:.2?}", t_map_build_elapsed);
+        diag!("[TIMER] (2) Parse+map records: {:.2?}", t_parse + t_tidmap);
+        diag!("[TIMER] (3) Tree queries:      {:.2?}", t_tree);
+        for (set, m) in sets.iter().zip(&by_root) {
+            diag!("[INFO] Collected {} roots with coverage ({})", m.len(), set.name);
+        }
     }
 
     Ok(by_root)
 }
 
-/// Collect coverage intervals per root (from BED).
+/// Collect coverage intervals per root (from BED), once per annotation set.
 fn collect_by_root_from_bed(
     bed_path: &Path,
-    index_data: &TreeIndexData,
+    sets: &[AnnotationSet],
+    unknown: &mut UnknownContigs,
     verbose: bool,
-) -> Result<FxHashMap<u32, Vec<(u32,u32)>>> {
-    // mmap the entire BED file
-    let file = File::open(bed_path)?;
-    let mmap = unsafe { Mmap::map(&file)? };
-    let data = &mmap[..];
-
+) -> Result<Vec<ByRoot>> {
+    // mmap the entire BED file (or stream it through the gzip decoder)
+    let mut reader = BedReader::open(bed_path)?;
     if verbose {
-        eprintln!("[INFO] mmap BED file: {} bytes", data.len());
+        diag!("[INFO] Reading BED file{}", if reader.is_gzip() { " (gzip)" } else { "" });
     }
 
-    // collect line offsets
-    let mut line_offsets = Vec::with_capacity(1_000_000);
-    line_offsets.push(0usize);
-    for (i, &b) in data.iter().enumerate() {
-        if b == b'\n' {
-            line_offsets.push(i + 1);
-        }
-    }
-    if *line_offsets.last().unwrap_or(&0) != data.len() {
-        line_offsets.push(data.len());
-    }
-
-    let mut by_root: FxHashMap<u32, Vec<(u32,u32)>> = FxHashMap::default();
+    let mut by_root: Vec<ByRoot> = sets.iter().map(|_| ByRoot::default()).collect();
     let mut hits: Vec<&Interval<u32>> = Vec::new();
 
-    for w in line_offsets.windows(2) {
-        let start = w[0];
-        let end = w[1];
-        if start >= end { continue; }
-        let line = &data[start..end];
-        if line.is_empty() || line[0] == b'#' { continue; }
+    // Parse newline-aligned chunks in parallel; memory stays bounded by the chunk size
+    reader.for_each_chunk(BED_CHUNK_SIZE, |chunk| {
+        for (chrom, s, e) in parse_bed_chunk(chunk) {
+            unknown.total += 1;
+            let mut known = false;
+            for (i, set) in sets.iter().enumerate() {
+                let Some(&chr_num) = set.index_data.seqid_to_num.get(chrom) else { continue };
+                known = true;
+                push_region_hits(set, chr_num, s, e, &mut hits, &mut by_root[i])?;
+            }
+            if !known {
+                unknown.skip(chrom, 1);
+            }
+        }
+        Ok(())
+    })?;
 
-        let fields: Vec<&[u8]> = line
-            .split(|&b| b == b'\t' || b == b' ')
-            .filter(|f| !f.is_empty())
-            .collect();
-        if fields.len() < 3 { continue; }
+    if verbose {
+        for (set, m) in sets.iter().zip(&by_root) {
+            diag!("[INFO] Collected {} roots with coverage (BED, {})", m.len(), set.name);
+        }
+    }
 
-        let chrom = match std::str::from_utf8(fields[0]) { Ok(s) => s, Err(_) => continue };
-        let s = match std::str::from_utf8(fields[1]).ok().and_then(|x| x.parse::<u32>().ok()) { Some(v) => v, None => continue };
-        let e = match std::str::from_utf8(fields[2]).ok().and_then(|x| x.trim_end().parse::<u32>().ok()) { Some(v) => v, None => continue };
-        if s >= e { continue; }
+    Ok(by_root)
+}
 
-        let Some(&chr_num) = index_data.seqid_to_num.get(chrom) else { continue };
+/// Collect coverage intervals per root (from BEDPE), once per annotation set; `mode`
+/// turns each pair into regions (`BedpeMode::regions`).
+fn collect_by_root_from_bedpe(
+    bedpe_path: &Path,
+    sets: &[AnnotationSet],
+    mode: BedpeMode,
+    unknown: &mut UnknownContigs,
+    verbose: bool,
+) -> Result<Vec<ByRoot>> {
+    let mut reader = BedReader::open(bedpe_path)?;
+    if verbose {
+        diag!("[INFO] Reading BEDPE file{}", if reader.is_gzip() { " (gzip)" } else { "" });
+    }
 
-        if let Some(tree) = index_data.chr_entries.get(&chr_num) {
-            hits.clear();
-            tree.query_interval(s, e, &mut hits);
-            let mut seen_in_region: FxHashSet<u32> = FxHashSet::default();
-            for h in &hits {
-                if seen_in_region.insert(h.root_fid) {
-                    by_root.entry(h.root_fid).or_default().push((s, e));
+    let mut by_root: Vec<ByRoot> = sets.iter().map(|_| ByRoot::default()).collect();
+    let mut hits: Vec<&Interval<u32>> = Vec::new();
+    let mut pending: Vec<(u32, u32, u32)> = Vec::new();
+    let mut ends: Vec<(u32, u32, u32)> = Vec::with_capacity(2);
+    let mut skips = BedpeSkips::default();
+
+    reader.for_each_chunk(BED_CHUNK_SIZE, |chunk| {
+        for pair in parse_bedpe_chunk(chunk) {
+            let regions = mode.regions(pair, &mut skips);
+            let Some(first) = regions.iter().flatten().next() else { continue };
+            unknown.total += 1;
+            let mut known = false;
+            for (i, set) in sets.iter().enumerate() {
+                ends.clear();
+                ends.extend(regions.iter().flatten().filter_map(|&(chrom, s, e)| {
+                    set.index_data.seqid_to_num.get(chrom).map(|&chr_num| (chr_num, s, e))
+                }));
+                if ends.is_empty() {
+                    continue;
                 }
+                known = true;
+                push_fragment_hits(set, &ends, &mut hits, &mut pending, &mut by_root[i])?;
+            }
+            if !known {
+                unknown.skip(first.0, 1);
             }
         }
-    }
+        Ok(())
+    })?;
 
+    skips.report();
     if verbose {
-        eprintln!("[INFO] Collected {} roots with coverage (BED)", by_root.len());
+        for (set, m) in sets.iter().zip(&by_root) {
+            diag!("[INFO] Collected {} roots with coverage (BEDPE, {})", m.len(), set.name);
+        }
     }
 
     Ok(by_root)
 }
 
-/// Compute breadth for all features within a root using pre-merged disjoint coverage.
+/// Compute breadth for all features within a root using pre-merged disjoint coverage;
+/// one breadth per entry of `covs` (each sorted, non-overlapping). Features of types
+/// outside `types` are skipped.
 fn compute_breadth_for_root(
     gff_slice: &[u8],
-    cov_merged: &[(u32,u32)], // sorted, non-overlapping coverage intervals
-) -> FxHashMap<String, (String, u32, u32, usize)> {
+    covs: &[Vec<(u32,u32)>],
+    types: Option<&TypeFilter>,
+) -> BreadthMap {
     #[derive(Clone)]
     struct FeatLine {
         id_idx: u32,
@@ -289,6 +476,8 @@ fn compute_breadth_for_root(
     let mut id_to_idx: FxHashMap<String, u32> = FxHashMap::default();
     let mut id_strings: Vec<String> = Vec::new();
     let mut id_chrom: Vec<String> = Vec::new();
+    // Score and phase of the first line of each ID
+    let mut id_cols: Vec<ScorePhase> = Vec::new();
 
     // Collect feature lines (within this root)
     let mut lines: Vec<FeatLine> = Vec::new();
@@ -297,11 +486,12 @@ fn compute_breadth_for_root(
         for line in text.split_terminator('\n') {
             if line.is_empty() || line.as_bytes()[0] == b'#' { continue; }
             let mut cols = line.splitn(9, '\t');
-            let (Some(seqid), _, _, Some(start_s), Some(end_s), _, _, _, Some(attrs)) = (
+            let (Some(seqid), _, Some(ftype), Some(start_s), Some(end_s), Some(score), _, Some(phase), Some(attrs)) = (
                 cols.next(), cols.next(), cols.next(),
                 cols.next(), cols.next(), cols.next(),
                 cols.next(), cols.next(), cols.next()
             ) else { continue; };
+            if types.is_some_and(|t| !t.allows(ftype.as_bytes())) { continue; }
 
             let (Some(s1), Some(e1)) = (parse_u32_fast(start_s), parse_u32_fast(end_s)) else { continue; };
             if e1 == 0 { continue; }
@@ -315,6 +505,7 @@ fn compute_breadth_for_root(
                     let k = id_strings.len() as u32;
                     id_strings.push(id.to_owned());
                     id_chrom.push(seqid.to_owned());
+                    id_cols.push(ScorePhase::parse(score, phase));
                     k
                 });
                 lines.push(FeatLine { id_idx: idx, start0: fstart0, end0: fend0 });
@@ -322,175 +513,271 @@ fn compute_breadth_for_root(
         }
     }
 
-    if lines.is_empty() || cov_merged.is_empty() {
+    if lines.is_empty() || covs.iter().all(|c| c.is_empty()) {
         return FxHashMap::default();
     }
 
-    // Sort features by start for two-pointer sweep against cov_merged
+    // Sort features by start for two-pointer sweep against each coverage list
     lines.sort_unstable_by_key(|x| x.start0);
 
     let mut min_s: Vec<u32> = vec![u32::MAX; id_strings.len()];
     let mut max_e: Vec<u32> = vec![0; id_strings.len()];
-    // For each ID, collect overlaps with coverage (we'll union at the end per ID)
-    let mut id_overlap: Vec<Vec<(u32,u32)>> = vec![Vec::new(); id_strings.len()];
-
-    // Two-pointer scan: iterate features in order, and advance cov pointer monotonically
-    let mut j = 0usize;
     for fl in &lines {
-        // Advance coverage pointer until cov[j].end <= feature.start
-        while j < cov_merged.len() && cov_merged[j].1 <= fl.start0 {
-            j += 1;
-        }
         // Record feature span extents for this ID
         if fl.start0 < min_s[fl.id_idx as usize] { min_s[fl.id_idx as usize] = fl.start0; }
         if fl.end0   > max_e[fl.id_idx as usize] { max_e[fl.id_idx as usize] = fl.end0; }
+    }
 
-        // Walk through all coverage intervals that might overlap this feature
-        let mut k = j;
-        while k < cov_merged.len() && cov_merged[k].0 < fl.end0 {
-            let s = fl.start0.max(cov_merged[k].0);
-            let e = fl.end0.min(cov_merged[k].1);
-            if e > s {
-                id_overlap[fl.id_idx as usize].push((s, e));
+    let mut breadths: Vec<Vec<usize>> = vec![Vec::with_capacity(covs.len()); id_strings.len()];
+    for cov_merged in covs {
+        // For each ID, collect overlaps with coverage (we'll union at the end per ID)
+        let mut id_overlap: Vec<Vec<(u32,u32)>> = vec![Vec::new(); id_strings.len()];
+
+        // Two-pointer scan: iterate features in order, and advance cov pointer monotonically
+        let mut j = 0usize;
+        for fl in &lines {
+            // Advance coverage pointer until cov[j].end <= feature.start
+            while j < cov_merged.len() && cov_merged[j].1 <= fl.start0 {
+                j += 1;
             }
-            if cov_merged[k].1 <= fl.end0 {
-                k += 1;
-            } else {
-                break;
+
+            // Walk through all coverage intervals that might overlap this feature
+            let mut k = j;
+            while k < cov_merged.len() && cov_merged[k].0 < fl.end0 {
+                let s = fl.start0.max(cov_merged[k].0);
+                let e = fl.end0.min(cov_merged[k].1);
+                if e > s {
+                    id_overlap[fl.id_idx as usize].push((s, e));
+                }
+                if cov_merged[k].1 <= fl.end0 {
+                    k += 1;
+                } else {
+                    break;
+                }
             }
         }
+
+        // Per-ID breadth: union of all overlap pieces
+        for (b, ov) in breadths.iter_mut().zip(id_overlap) {
+            b.push(union_len(ov));
+        }
     }
 
-    // Finalize per-ID breadth (union of all overlap pieces), and produce outputs
-    let mut out: FxHashMap<String, (String, u32, u32, usize)> = FxHashMap::default();
-    for i in 0..id_strings.len() {
+    // Produce outputs
+    let mut out: BreadthMap = FxHashMap::default();
+    for (i, b) in breadths.into_iter().enumerate() {
         let length = if max_e[i] > min_s[i] { (max_e[i] - min_s[i]) as usize } else { 0 };
-        let breadth = union_len(std::mem::take(&mut id_overlap[i]));
-        if length > 0 || breadth > 0 {
-            out.insert(
-                id_strings[i].clone(),
-                (id_chrom[i].clone(), min_s[i], max_e[i], breadth),
-            );
+        if length > 0 || b.iter().any(|&x| x > 0) {
+            out.insert(id_strings[i].clone(), (id_chrom[i].clone(), min_s[i], max_e[i], b, id_cols[i]));
         }
     }
     out
 }
 
+/// Coverage lists for one root: the merged (≥1x) intervals, plus one list per
+/// threshold when `thresholds` is non-empty (from a depth profile).
+#[inline]
+fn root_coverage(ivs: Vec<(u32,u32)>, thresholds: &[u32]) -> Vec<Vec<(u32,u32)>> {
+    if thresholds.is_empty() {
+        vec![merge_intervals(ivs)]
+    } else {
+        depth_threshold_intervals(&ivs, thresholds)
+    }
+}
+
 /// After collecting raw intervals per root:
-/// 1) Merge (union) them into disjoint intervals;
+/// 1) Merge (union) them into disjoint intervals, or build a depth profile when
+///    `thresholds` is non-empty;
 /// 2) Parse GFF slice for that root;
 /// 3) Compute breadth/fraction for each feature under this root.
 fn finalize_compute_breadth(
     by_root_raw: FxHashMap<u32, Vec<(u32,u32)>>,
-    gof: &GofMap,
-    gff_mmap: &Mmap,
+    set: &AnnotationSet,
+    thresholds: &[u32],
     threads: usize,
     verbose: bool,
-) -> Result<FxHashMap<String, (String, u32, u32, usize)>> {
-    let gff_bytes: &[u8] = &gff_mmap[..];
-    let idx = gof.index_cached();
+) -> Result<BreadthMap> {
+    let gff_bytes: &[u8] = &set.gff_mmap[..];
+    let idx = set.gof.index_cached();
 
     if by_root_raw.is_empty() {
         return Ok(FxHashMap::default());
     }
 
-    let roots_iter = by_root_raw.into_iter();
-
-    // Parallel per-root processing if threads > 1
-    let partials: Vec<FxHashMap<String, (String, u32, u32, usize)>> = if threads > 1 {
-        roots_iter.par_bridge().map(|(root, ivs)| {
-            // Merge coverage intervals for this root
-            let cov = merge_intervals(ivs);
-            // Locate GFF slice for this root
-            match idx.get(&root) {
-                Some(&(s_off, e_off)) if s_off != MISSING && e_off != MISSING && e_off > s_off => {
-                    let su = usize::try_from(s_off).unwrap();
-                    let eu = usize::try_from(e_off).unwrap();
-                    compute_breadth_for_root(&gff_bytes[su..eu], &cov)
-                }
-                _ => FxHashMap::default(),
-            }
-        }).collect()
-    } else {
-        let mut v = Vec::new();
-        for (root, ivs) in roots_iter {
-            let cov = merge_intervals(ivs);
-            match idx.get(&root) {
-                Some(&(s_off, e_off)) if s_off != MISSING && e_off != MISSING && e_off > s_off => {
-                    let su = usize::try_from(s_off).unwrap();
-                    let eu = usize::try_from(e_off).unwrap();
-                    v.push(compute_breadth_for_root(&gff_bytes[su..eu], &cov));
-                }
-                _ => v.push(FxHashMap::default()),
+    let root_breadth = |(root, ivs): (u32, Vec<(u32, u32)>)| {
+        // Merge coverage intervals for this root
+        let cov = root_coverage(ivs, thresholds);
+        // Locate GFF slice for this root
+        match idx.get(&root) {
+            Some(&(s_off, e_off)) if s_off != MISSING && e_off != MISSING && e_off > s_off => {
+                let su = usize::try_from(s_off).unwrap();
+                let eu = usize::try_from(e_off).unwrap();
+                compute_breadth_for_root(&gff_bytes[su..eu], &cov, set.type_filter.as_ref())
             }
+            _ => FxHashMap::default(),
         }
-        v
+    };
+
+    let partials: Vec<BreadthMap> = if deterministic() {
+        // Merge in root order whatever the thread count (--deterministic)
+        let mut roots: Vec<_> = by_root_raw.into_iter().collect();
+        roots.sort_unstable_by_key(|(root, _)| *root);
+        roots.into_par_iter().map(root_breadth).collect()
+    } else if threads > 1 {
+        // Parallel per-root processing
+        by_root_raw.into_iter().par_bridge().map(root_breadth).collect()
+    } else {
+        by_root_raw.into_iter().map(root_breadth).collect()
     };
 
     // Merge per-root maps into global results
-    let mut global: FxHashMap<String, (String, u32, u32, usize)> = FxHashMap::default();
+    let mut global: BreadthMap = FxHashMap::default();
     for m in partials {
-        for (id, (chrom, s, e, b)) in m {
-            global.entry(id).and_modify(|(c0, s0, e0, breadth)| {
-                if s < *s0 { *s0 = s; }
-                if e > *e0 { *e0 = e; }
-                // Note: If the same ID appears under multiple roots (rare), breadth is summed.
-                // In well-formed GFF partitioning, one ID should belong to a single root.
-                *breadth += b;
-                let _ = c0;
-            }).or_insert((chrom, s, e, b));
+        for (id, (chrom, s, e, b, cols)) in m {
+            match global.entry(id) {
+                std::collections::hash_map::Entry::Occupied(mut o) => {
+                    let (_, s0, e0, breadths, _) = o.get_mut();
+                    if s < *s0 { *s0 = s; }
+                    if e > *e0 { *e0 = e; }
+                    // Note: If the same ID appears under multiple roots (rare), breadth is summed.
+                    // In well-formed GFF partitioning, one ID should belong to a single root.
+                    for (acc, x) in breadths.iter_mut().zip(b) {
+                        *acc += x;
+                    }
+                }
+                std::collections::hash_map::Entry::Vacant(v) => {
+                    v.insert((chrom, s, e, b, cols));
+                }
+            }
         }
     }
 
     if verbose {
-        eprintln!("[INFO] Aggregated {} feature IDs", global.len());
+        diag!("[INFO] Aggregated {} feature IDs", global.len());
     }
 
     Ok(global)
 }
 
-/// Write "id\tchr\tstart\tend\tbreadth\tfraction" per line.
-pub fn write_breadth_results<W: Write>(
-    id_map: FxHashMap<String, (String, u32, u32, usize)>,
+/// Covered fraction of the feature span `[start, end)`.
+#[inline]
+fn breadth_fraction(start: u32, end: u32, breadth: usize) -> f64 {
+    let length = end.saturating_sub(start) as usize;
+    if length > 0 {
+        breadth as f64 / length as f64
+    } else {
+        0.0
+    }
+}
+
+/// One row of the breadth table.
+struct BreadthRow<'a> {
+    annotation: &'a str,
+    id: String,
+    chr: String,
+    start: u32,
+    end: u32,
+    breadths: Vec<usize>,
+    cols: ScorePhase,
+}
+
+/// Breadth table columns; `annotation` leads when `labelled` (several annotation sets),
+/// and one `breadth_<T>x` column follows per entry of `thresholds`. `score` and `phase`
+/// (of the ID's first line) are written only when `--columns` lists them.
+fn breadth_columns<'a>(labelled: bool, thresholds: &[u32]) -> Vec<Column<'a, BreadthRow<'a>>> {
+    let mut cols = Vec::with_capacity(9 + thresholds.len());
+    if labelled {
+        cols.push(Column::new("annotation", |r: &BreadthRow<'a>| Cell::Str(r.annotation)));
+    }
+    cols.push(Column::new("id", |r: &BreadthRow<'a>| Cell::Str(&r.id)));
+    cols.push(Column::new("chr", |r: &BreadthRow<'a>| Cell::Str(&r.chr)));
+    cols.push(Column::start("start", |r: &BreadthRow<'a>| r.start as u64));
+    cols.push(Column::new("end", |r: &BreadthRow<'a>| Cell::UInt(r.end as u64)));
+    cols.push(Column::new("breadth", |r: &BreadthRow<'a>| Cell::UInt(r.breadths[0] as u64)));
+    cols.push(Column::new("fraction", |r: &BreadthRow<'a>| {
+        Cell::Float(breadth_fraction(r.start, r.end, r.breadths[0]))
+    }));
+    for (i, t) in thresholds.iter().enumerate() {
+        cols.push(Column::new(format!("breadth_{t}x"), move |r: &BreadthRow<'a>| {
+            Cell::UInt(r.breadths.get(i + 1).copied().unwrap_or(0) as u64)
+        }));
+    }
+    cols.push(Column::optional("score", |r: &BreadthRow<'a>| Cell::opt_num(r.cols.score)));
+    cols.push(Column::optional("phase", |r: &BreadthRow<'a>| Cell::opt_num(r.cols.phase.map(f64::from))));
+    cols
+}
+
+/// Write the breadth table, by default "[annotation\t]id\tchr\tstart\tend\tbreadth\tfraction"
+/// per line followed by one `breadth_<T>x` column per entry of `thresholds`; `table`
+/// selects columns and layout. The annotation column exists when `labelled`. Rows
+/// follow map order, or position order (then ID) under `--deterministic`.
+pub fn write_breadth_results<'a, W: Write>(
+    results: impl IntoIterator<Item = (&'a str, BreadthMap)>,
+    labelled: bool,
+    thresholds: &[u32],
+    table: &TableArgs,
     mut out: W,
     verbose: bool,
 ) -> Result<()> {
-    use std::fmt::Write as FmtWrite;
-    let mut buf = String::with_capacity(WRITE_BUF_SIZE);
-    let mut written = 0usize;
+    let writer = TableWriter::new(breadth_columns(labelled, thresholds), table)?;
+    let sort = deterministic();
+    let rows = results.into_iter().flat_map(|(annotation, id_map)| {
+        let mut entries: Vec<_> = id_map.into_iter().collect();
+        if sort {
+            entries.sort_unstable_by(|a, b| (&a.1.0, a.1.1, a.1.2, &a.0).cmp(&(&b.1.0, b.1.1, b.1.2, &b.0)));
+        }
+        entries
+            .into_iter()
+            .map(move |(id, (chr, start, end, breadths, cols))| {
+                BreadthRow { annotation, id, chr, start, end, breadths, cols }
+            })
+    });
+    let written = writer.write_all(rows, &mut out)?;
 
-    // Header: 6 columns
-    writeln!(buf, "id\tchr\tstart\tend\tbreadth\tfraction")?;
-    
-    for (id, (chr, start, end, breadth)) in id_map {
-        let length = end.saturating_sub(start) as usize;
-        let fraction = if length > 0 {
-            breadth as f64 / length as f64
-        } else {
-            0.0
-        };
-        writeln!(buf, "{id}\t{chr}\t{start}\t{end}\t{breadth}\t{:.6}", fraction)?;
-        written += 1;
+    if verbose {
+        diag!("[INFO] Wrote {written} feature coverage rows.");
+    }
+    Ok(())
+}
 
-        if buf.len() >= WRITE_BUF_SIZE {
-            out.write_all(buf.as_bytes())?;
-            buf.clear();
+/// Write one BED row "chr\tstart\tend\tname\tfraction" per feature, sorted by position.
+/// Names are "annotation:id" for labelled results (several annotation sets).
+pub fn write_breadth_bed<'a, W: Write>(
+    results: impl IntoIterator<Item = (Option<&'a str>, BreadthMap)>,
+    mut out: W,
+    verbose: bool,
+) -> Result<()> {
+    let mut rows: Vec<(String, u32, u32, String, usize)> = Vec::new();
+    for (annotation, id_map) in results {
+        for (id, (chr, start, end, breadths, _)) in id_map {
+            let breadth = breadths[0];
+            let name = match annotation {
+                Some(a) => format!("{a}:{id}"),
+                None => id,
+            };
+            rows.push((chr, start, end, name, breadth));
         }
     }
+    rows.sort_unstable();
 
-    if !buf.is_empty() {
-        out.write_all(buf.as_bytes())?;
+    for (chr, start, end, name, breadth) in &rows {
+        let fraction = breadth_fraction(*start, *end, *breadth);
+        writeln!(out, "{chr}\t{start}\t{end}\t{name}\t{:.6}", fraction)?;
     }
     out.flush()?;
 
     if verbose {
-        eprintln!("[INFO] Wrote {written} feature coverage rows.");
+        diag!("[INFO] Wrote {} BED coverage rows.", rows.len());
     }
     Ok(())
 }
 
 /// Main
 pub fn run(args: &CoverageArgs) -> Result<()> {
+    apply_index_prefix(&args.input, args.index_prefix.as_deref())?;
+    for gff in &args.input {
+        ensure_index(gff, args.auto_index, args.verbose)?;
+    }
     let verbose = args.verbose;
     let threads = if args.threads == 0 {
         std::thread::available_parallelism()
@@ -500,83 +787,147 @@ pub fn run(args: &CoverageArgs) -> Result<()> {
         args.threads
     };
     let _ = rayon::ThreadPoolBuilder::new().num_threads(threads).build_global();
-    let gff_path = &args.input;
+    if args.format == ReportFormat::Gff && args.input.len() > 1 {
+        bail!("--format gff writes one annotated GFF and accepts a single -i input");
+    }
+    // Threshold 1 alone is the plain breadth: no extra columns and no depth profile
+    let thresholds: &[u32] = if args.breadth_thresholds.iter().all(|&t| t == 1) {
+        &[]
+    } else {
+        &args.breadth_thresholds
+    };
+    if !thresholds.is_empty() && args.format == ReportFormat::Bed {
+        bail!("--breadth-thresholds adds columns and needs --format tsv or gff");
+    }
+    if args.table.is_set() && args.format != ReportFormat::Tsv {
+        bail!("--columns, --no-header, --table-format and --coords apply to --format tsv");
+    }
     
-    // Step 1: load GOF index + mmap GFF
+    // Step 1: load GOF, tree index and GFF mmap for each annotation
     let t0 = Instant::now();
-    let gof = load_gof(&gff_path)?;
-    let file = File::open(gff_path).with_context(|| format!("Cannot open GFF file: {:?}", gff_path))?;
-    let gff_mmap = unsafe { Mmap::map(&file) }.with_context(|| format!("GFF mmap failed for {:?}", gff_path))?;
-    let t_load_fts = t0.elapsed();
-    if verbose {
-        eprintln!("[TIMER] [run] Step 1: Load GOF & mmap GFF: {:.2?}", t_load_fts);
+    let mut sets: Vec<AnnotationSet> = args
+        .input
+        .iter()
+        .map(|p| AnnotationSet::load(p))
+        .collect::<Result<_>>()?;
+    if args.features.is_some() || args.feature_id.is_some() {
+        let feature_names = read_feature_names(args.features.as_deref(), args.feature_id.as_deref())?;
+        for set in sets.iter_mut() {
+            let n = set.restrict_to_features(&feature_names, threads)?;
+            if n == 0 {
+                diag!("[WARN] No requested feature resolved to a model in {}", set.name);
+            } else if verbose {
+                diag!("[INFO] {}: restricting output to {} model(s)", set.name, n);
+            }
+        }
     }
-
-    // Step 2: load interval tree index
-    let t1 = Instant::now();
-    let index_data = TreeIndexData::load_tree_index(&gff_path)?;
-    let t_build_index = t1.elapsed();
+    for set in sets.iter_mut() {
+        set.type_filter = args.types.clone();
+    }
+    let t_load_index = t0.elapsed();
     if verbose {
-        eprintln!("[TIMER] [run] Step 2: Load tree index: {:.2?}", t_build_index);
+        diag!(
+            "[TIMER] [run] Step 1: Load {} annotation index(es): {:.2?}",
+            sets.len(),
+            t_load_index
+        );
     }
 
-    // Step 3: collect coverage intervals per root
+    // Step 2: collect coverage intervals per root
     let t2 = Instant::now();
     let source_path = &args.source;
 
-    let ext = source_path
-        .extension()
-        .and_then(|s| s.to_str())
-        .map(|s| s.to_lowercase());
-
-    let by_root = match ext.as_deref() {
+    let mut unknown = UnknownContigs::default();
+    let (by_root, what) = match source_extension(source_path).as_deref() {
         Some("bam") | Some("sam") | Some("cram") => {
-            collect_by_root_from_bam(source_path.as_path(), &index_data, verbose, threads)?
+            (collect_by_root_from_bam(source_path.as_path(), &sets, &mut unknown, verbose, threads)?, "read")
         }
         Some("bed") => {
-            collect_by_root_from_bed(source_path.as_path(), &index_data, verbose)?
+            (collect_by_root_from_bed(source_path.as_path(), &sets, &mut unknown, verbose)?, "BED region")
+        }
+        Some("bedpe") => {
+            let mode = args.bedpe_mode;
+            (collect_by_root_from_bedpe(source_path.as_path(), &sets, mode, &mut unknown, verbose)?, "BEDPE pair")
         }
         _ => {
             bail!(
-                "Unsupported file type: {:?}. Expected .bam/.sam/.cram or .bed",
+                "Unsupported file type: {:?}. Expected .bam/.sam/.cram, .bed/.bed.gz or .bedpe/.bedpe.gz",
                 source_path
             );
         }
     };
     let t_collect = t2.elapsed();
     if verbose {
-        eprintln!("[TIMER] [run] Step 3: Collect intervals: {:.2?}", t_collect);
+        diag!("[TIMER] [run] Step 2: Collect intervals: {:.2?}", t_collect);
     }
+    unknown.report(what, args.strict_contigs)?;
 
-    // Step 4: per-root merge & compute breadth over GFF slices
+    // Step 3: per-root merge & compute breadth over GFF slices, per annotation
     let t3 = Instant::now();
-    let id_map = finalize_compute_breadth(by_root, &gof, &gff_mmap, threads, verbose)?;
+    let mut id_maps = Vec::with_capacity(sets.len());
+    for (set, roots) in sets.iter().zip(by_root) {
+        id_maps.push(finalize_compute_breadth(roots, set, thresholds, threads, verbose)?);
+    }
     let t_compute = t3.elapsed();
     if verbose {
-        eprintln!("[TIMER] [run] Step 4: Compute breadth: {:.2?}", t_compute);
+        diag!("[TIMER] [run] Step 3: Compute breadth: {:.2?}", t_compute);
     }
 
-    // Step 5: write results
+    let mut summary = args.input.iter().fold(RunSummary::new(), RunSummary::input).input(source_path);
+    summary = summary.count(id_maps.iter().map(|m| m.len()).sum(), "feature(s)");
+
+    // Step 4: write results
     let t4 = Instant::now();
     
-    let out: Box<dyn Write> = match &args.output {
+    let mut out: Box<dyn Write> = match &args.output {
         Some(path) => {
             let file = File::create(path)?;
-            Box::new(BufWriter::with_capacity(WRITE_BUF_SIZE, file))
+            Box::new(BufWriter::with_capacity(WRITE_BUF_SIZE, CountingWriter(file)))
         }
         None => {
             let stdout = std::io::stdout();
             let handle = stdout.lock();
-            Box::new(BufWriter::with_capacity(WRITE_BUF_SIZE, handle))
+            Box::new(BufWriter::with_capacity(WRITE_BUF_SIZE, CountingWriter(handle)))
         }
     };
-    write_breadth_results(id_map, out, verbose)?;
+    match args.format {
+        ReportFormat::Tsv => {
+            let names = sets.iter().map(|s| s.name.as_str());
+            write_breadth_results(names.zip(id_maps), sets.len() > 1, thresholds, &args.table, out, verbose)?;
+        }
+        ReportFormat::Bed => {
+            let labelled = sets.len() > 1;
+            let names = sets.iter().map(|s| labelled.then_some(s.name.as_str()));
+            write_breadth_bed(names.zip(id_maps), out, verbose)?;
+        }
+        ReportFormat::Gff => {
+            let attrs: FxHashMap<String, String> = id_maps
+                .pop()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(id, (_, start, end, breadths, _))| {
+                    let breadth = breadths[0];
+                    let fraction = breadth_fraction(start, end, breadth);
+                    let mut attr = format!("coverage_breadth={breadth};coverage_fraction={:.6}", fraction);
+                    for (t, b) in thresholds.iter().zip(&breadths[1..]) {
+                        attr.push_str(&format!(";coverage_breadth_{t}x={b}"));
+                    }
+                    (id, attr)
+                })
+                .collect();
+            let n = sets[0].write_annotated_gff(&attrs, &mut out, threads)?;
+            if verbose {
+                diag!("[INFO] Added coverage to {n} GFF lines");
+            }
+        }
+    }
     let t_write_out = t4.elapsed();
     if verbose {
-        eprintln!("[TIMER] [run] Step 5: Write output: {:.2?}", t_write_out);
+        diag!("[TIMER] [run] Step 4: Write output: {:.2?}", t_write_out);
         let total = t0.elapsed();
-        eprintln!("[TIMER] [run] Total time: {:.2?}", total);
+        diag!("[TIMER] [run] Total time: {:.2?}", total);
     }
+    set_summary(summary.output(args.output.as_deref()));
 
     Ok(())
 }
@@ -0,0 +1,194 @@
+use anyhow::{Result, bail};
+use clap::{Args, Parser, Subcommand};
+use memchr::memchr;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    str,
+};
+use crate::{
+    CountingWriter, RangeSource, RunSummary, fast_id, gff_text_path, load_fts, load_gof, load_sqs, set_index_prefix,
+    set_summary,
+};
+
+/// Arguments
+#[derive(Parser, Debug)]
+#[command(
+    about = "Inspect index artifacts",
+    long_about = "Read-only diagnostics over existing index files."
+)]
+pub struct DebugArgs {
+    #[command(subcommand)]
+    pub command: DebugCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DebugCommand {
+    /// Emit GOF root blocks as BED intervals (or file-offset ranges with --offsets)
+    Blocks(BlocksArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct BlocksArgs {
+    /// GFF file path (indexed via GOF)
+    #[arg(short = 'i', long = "input", value_name = "FILE")]
+    pub input: PathBuf,
+
+    /// Output file (stdout if not provided)
+    #[arg(short = 'o', long = "output", value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    /// Emit "root_id, seqid, start_offset, end_offset, length" TSV instead of BED
+    #[arg(long = "offsets", default_value_t = false)]
+    pub offsets: bool,
+
+    /// Read the index files from PREFIX (see `index --index-prefix`)
+    #[arg(long = "index-prefix", value_name = "PREFIX")]
+    pub index_prefix: Option<PathBuf>,
+
+    /// Verbose logs
+    #[arg(short = 'v', long = "verbose", default_value_t = false, value_name = "BOOL")]
+    pub verbose: bool,
+}
+
+/// Parse 0-based half-open (start, end) from the line of `root_id` in a block, or from
+/// its first feature line when the root line lies in another block of the model
+/// (children listed before their parent, interleaved models).
+fn peek_root_coords(block: &[u8], root_id: &str) -> Option<(u32, u32)> {
+    let mut first = None;
+    let mut pos = 0usize;
+    while pos < block.len() {
+        let nl = memchr(b'\n', &block[pos..]).map(|i| pos + i).unwrap_or(block.len());
+        let line = &block[pos..nl];
+        pos = nl + 1;
+        if line.is_empty() || line[0] == b'#' {
+            continue;
+        }
+        let Ok(line) = str::from_utf8(line) else { continue };
+        let cols: Vec<&str> = line.trim_end_matches('\r').splitn(9, '\t').collect();
+        if cols.len() < 5 {
+            continue;
+        }
+        let (Ok(s1), Ok(e1)) = (cols[3].trim().parse::<u32>(), cols[4].trim().parse::<u32>()) else { continue };
+        let (s1, e1) = if s1 > e1 { (e1, s1) } else { (s1, e1) };
+        let coords = (s1.saturating_sub(1), e1);
+        if cols.get(8).and_then(|a| fast_id(a)) == Some(root_id) {
+            return Some(coords);
+        }
+        first.get_or_insert(coords);
+    }
+    first
+}
+
+fn run_blocks(args: &BlocksArgs) -> Result<()> {
+    let gff_path = &args.input;
+    set_index_prefix(gff_path, args.index_prefix.as_deref());
+    let gof = load_gof(gff_path)?;
+    let fts = load_fts(gff_path)?;
+    let (seqids, _) = load_sqs(gff_path)?;
+    // --offsets needs only the index; offsets are checked against the GFF size when
+    // the file is there
+    let gff = if args.offsets { None } else { Some(RangeSource::open(gff_path)?) };
+    let file_len = match &gff {
+        Some(gff) => Some(gff.len()),
+        None => gff_text_path(gff_path).ok().and_then(|p| std::fs::metadata(p).ok()).map(|m| m.len()),
+    };
+
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(CountingWriter(File::create(path)?))),
+        None => Box::new(BufWriter::new(CountingWriter(std::io::stdout().lock()))),
+    };
+
+    if args.offsets {
+        writeln!(out, "#root_id\tseqid\tstart_offset\tend_offset\tlength")?;
+    }
+
+    let mut unparsable = 0usize;
+    for e in &gof.entries {
+        if e.end_offset < e.start_offset || file_len.is_some_and(|len| e.end_offset > len) {
+            bail!(
+                "GOF entry for root {} has invalid offsets {}..{} (file length {})",
+                e.feature_id, e.start_offset, e.end_offset, file_len.map_or("unknown".to_string(), |l| l.to_string())
+            );
+        }
+        let root_id = fts.get_id(e.feature_id).unwrap_or(".");
+        let seqid = seqids.get(e.seqid_num as usize).map(String::as_str).unwrap_or(".");
+        let len = e.end_offset - e.start_offset;
+
+        // `gff` is open unless --offsets
+        let Some(gff) = &gff else {
+            writeln!(out, "{root_id}\t{seqid}\t{}\t{}\t{len}", e.start_offset, e.end_offset)?;
+            continue;
+        };
+
+        let block = gff.range(e.start_offset, e.end_offset)?;
+        match peek_root_coords(&block, root_id) {
+            Some((start, end)) => writeln!(out, "{seqid}\t{start}\t{end}\t{root_id}\t{len}")?,
+            None => unparsable += 1,
+        }
+    }
+    out.flush()?;
+
+    if gof.is_seqid_interleaved() {
+        diag!("[WARN] Sequences are interleaved in the GFF; blocks follow file order and are not grouped by sequence (sort the output before tools that need sorted BED)");
+    }
+    if unparsable > 0 {
+        diag!("[WARN] {} blocks have no parsable feature line and were skipped", unparsable);
+    }
+    if args.verbose {
+        diag!("[INFO] Wrote {} blocks", gof.entries.len() - unparsable);
+    }
+    set_summary(
+        RunSummary::new()
+            .input(gff_path)
+            .count(gof.entries.len() - unparsable, "block(s)")
+            .output(args.output.as_deref()),
+    );
+    Ok(())
+}
+
+pub fn run(args: &DebugArgs) -> Result<()> {
+    match &args.command {
+        DebugCommand::Blocks(a) => run_blocks(a),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{SAMPLE_GFF, build_fixture, data_lines};
+
+    fn blocks(gff: &std::path::Path, out: &std::path::Path, extra: &[&str]) -> Vec<String> {
+        let mut argv = vec!["debug", "blocks", "-i", gff.to_str().unwrap(), "-o", out.to_str().unwrap()];
+        argv.extend_from_slice(extra);
+        run(&DebugArgs::try_parse_from(argv).unwrap()).unwrap();
+        data_lines(out).unwrap()
+    }
+
+    #[test]
+    fn one_row_per_gof_entry_at_root_coordinates() {
+        let fx = build_fixture(SAMPLE_GFF).unwrap();
+        let out = fx.dir.join("blocks.bed");
+        let gof = load_gof(&fx.gff).unwrap();
+        let mut bed = blocks(&fx.gff, &out, &[]);
+        assert_eq!(bed.len(), gof.entries.len());
+        bed.sort();
+        let coords: Vec<String> =
+            bed.iter().map(|r| r.split('\t').take(4).collect::<Vec<_>>().join("\t")).collect();
+        assert_eq!(coords, ["chr1\t1999\t3000\tg2", "chr1\t99\t900\tg1", "chr2\t9\t400\tg3"]);
+
+        // Offset rows cover the GFF bytes of each block
+        let text = std::fs::read(&fx.gff).unwrap();
+        let offsets = blocks(&fx.gff, &out, &["--offsets"]);
+        assert_eq!(offsets.len(), gof.entries.len());
+        for row in offsets {
+            let cols: Vec<&str> = row.split('\t').collect();
+            let (s, e): (usize, usize) = (cols[2].parse().unwrap(), cols[3].parse().unwrap());
+            assert_eq!(cols[4].parse::<usize>().unwrap(), e - s);
+            let block = str::from_utf8(&text[s..e]).unwrap();
+            let first = block.lines().find(|l| !l.starts_with('#')).unwrap();
+            assert!(first.contains(&format!("ID={};", cols[0])), "{row}: {first}");
+        }
+    }
+}
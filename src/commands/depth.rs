@@ -1,6 +1,5 @@
-use anyhow::{Result, bail, Context};
+use anyhow::{Result, bail};
 use rayon::prelude::*;
-use memmap2::Mmap;
 use rust_htslib::bam::{self, Read};
 use rust_htslib::bam::ext::BamRecordExtensions;
 use rustc_hash::{FxHashMap, FxHashSet};
@@ -12,7 +11,11 @@ use std::{
 };
 use clap::Parser;
 use crate::{
-    Interval, TreeIndexData, load_gof, GofMap,
+    AnnotationSet, BED_CHUNK_SIZE, BedReader, BedpeEnd, BedpeMode, BedpeSkips, ByteSource, Cell, Column, CountingWriter,
+    Interval, RunSummary, TableArgs, TableWriter, TreeIndexData, TypeFilter, GofMap, ReportFormat, ScorePhase,
+    UnknownContigs, apply_index_prefix, deterministic, ensure_index, fast_id, is_anonymous_id, load_fts, load_prt,
+    parse_bed_chunk, parse_bed_region, parse_bedpe_chunk, parse_bedpe_pair, parse_fraction, read_feature_names,
+    set_summary, source_extension, write_gff_output, write_roots_bed,
 };
 use std::time::{Instant, Duration};
 
@@ -23,6 +26,31 @@ const WRITE_BUF_SIZE: usize = 32 * 1024 * 1024;
 // BufWriter buffer size
 const BATCH_SIZE: usize = 100_000;
 
+/// Reads (or regions) whose lengths `--bin-shift auto` samples from the start of the source.
+const AUTO_SHIFT_SAMPLE: usize = 10_000;
+/// Range `--bin-shift auto` picks from (256 bp to 1 Mbp bins).
+const AUTO_SHIFT_RANGE: (u32, u32) = (8, 20);
+/// Shift used when `auto` has nothing to measure (an empty source or annotation).
+const DEFAULT_BIN_SHIFT: u32 = 12;
+
+/// `--bin-shift`: a fixed k, or `auto` to derive it from the data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinShift {
+    Auto,
+    Fixed(u32),
+}
+
+fn parse_bin_shift(s: &str) -> Result<BinShift, String> {
+    if s.eq_ignore_ascii_case("auto") {
+        return Ok(BinShift::Auto);
+    }
+    match s.parse::<u32>() {
+        Ok(k) if k < 32 => Ok(BinShift::Fixed(k)),
+        Ok(_) => Err("must be between 0 and 31, or auto".to_string()),
+        Err(e) => Err(format!("{e} (expected a number or auto)")),
+    }
+}
+
 /// Compute bin index for coordinate `x` given shift k.
 /// Each bin has width 2^k bp. Smaller k = finer bins, larger k = coarser bins.
 #[inline]
@@ -35,14 +63,15 @@ fn bin_of(x: u32, shift: u32) -> u32 {
 #[command(
     about = "Compute coverage depth across genomic features",
     long_about = "This tool computes sequencing depth (number of overlapping regions/reads per feature) \
-                  from SAM/BAM/CRAM or BED input. It does not compute breadth/fraction coverage."
+                  from SAM/BAM/CRAM, BED or BEDPE input. It does not compute breadth/fraction coverage."
 )]
 pub struct DepthArgs {
-    /// Input GFF file path
-    #[arg(short = 'i', long = "input", value_name = "FILE")]
-    pub input: PathBuf,
+    /// Input GFF file path; repeat `-i` to compute depth over several annotations
+    /// in a single pass over the source (adds an `annotation` output column)
+    #[arg(short = 'i', long = "input", value_name = "FILE", required = true)]
+    pub input: Vec<PathBuf>,
 
-    /// Input source (BAM/SAM/CRAM or BED)
+    /// Input source (BAM/SAM/CRAM, BED or BEDPE)
     #[arg(short = 's', long)]
     pub source: PathBuf,
     
@@ -50,8 +79,30 @@ pub struct DepthArgs {
     #[arg(short = 'o', long = "output", value_name = "FILE")]
     pub output: Option<PathBuf>,
 
-    /// Bin width parameter (2^k bp) for spatial bucketing of features and queries.
-    /// Choose k so that a typical read and feature span ~1–2 bins.
+    /// Only report features in the models of the IDs listed in FILE (one per line)
+    #[arg(short = 'F', long = "features", value_name = "FILE", conflicts_with = "feature_id")]
+    pub features: Option<PathBuf>,
+
+    /// Only report features in the model of this feature ID
+    #[arg(short = 'f', long = "feature-id", value_name = "ID")]
+    pub feature_id: Option<String>,
+
+    /// Only report features of these types (same syntax as `extract -T`); `--rollup`
+    /// aggregates only the leaves of these types, and with `--format gff` the models
+    /// keep only their lines of these types
+    #[arg(
+        short = 'T',
+        long = "types",
+        value_name = "TYPES",
+        value_parser = TypeFilter::parse,
+        conflicts_with = "uncovered_only"
+    )]
+    pub types: Option<TypeFilter>,
+
+    /// Bin width parameter (2^k bp) for spatial bucketing of features and queries, or
+    /// `auto` (default) to derive k from the median length of the first reads and the
+    /// median span of the annotation's models (the chosen k is logged with -v).
+    /// By hand, choose k so that a typical read and feature span ~1–2 bins.
     ///
     /// Typical values:
     ///   Short reads (Illumina 100–150 bp): k=10–11 (1–2 kb bins)
@@ -61,9 +112,58 @@ pub struct DepthArgs {
     /// Adjust:
     ///   Longer features → increase k (larger bins, less fragmentation).
     ///   Denser features → decrease k (smaller bins, stronger filtering).
-    #[arg(long = "bin-shift", default_value_t = 12)]
-    pub bin_shift: u32,
+    #[arg(long = "bin-shift", value_name = "K|auto", default_value = "auto", value_parser = parse_bin_shift)]
+    pub bin_shift: BinShift,
     
+    /// Exploit a coordinate-sorted BAM (header SO:coordinate) by sweeping reads against
+    /// sorted feature intervals instead of querying the interval tree per read
+    #[arg(long = "assume-sorted", default_value_t = false)]
+    pub assume_sorted: bool,
+
+    /// BEDPE sources: `span` counts each cis pair as its whole fragment (trans pairs are
+    /// skipped and counted); `ends` counts the two ends as regions, once per feature
+    #[arg(long = "bedpe-mode", value_enum, value_name = "MODE", default_value_t = BedpeMode::Span)]
+    pub bedpe_mode: BedpeMode,
+
+    /// Fail when more than this fraction of reads/regions (default 0: any) lie on
+    /// sequences absent from the annotation; they are otherwise reported and skipped
+    #[arg(
+        long = "strict-contigs",
+        value_name = "FRACTION",
+        num_args = 0..=1,
+        default_missing_value = "0",
+        value_parser = parse_fraction
+    )]
+    pub strict_contigs: Option<f64>,
+
+    /// Output format: `tsv` table, `bed` rows (depth as score), or `gff` models with
+    /// `depth=` added to each reported feature (single `-i` only)
+    #[arg(long = "format", value_enum, default_value_t = ReportFormat::Tsv)]
+    pub format: ReportFormat,
+
+    /// Aggregate leaf-feature depths (exons, CDS, UTRs, ...) per model level instead of
+    /// reporting every feature: `gene` writes n_children/min/max/mean/median per gene
+    #[arg(long = "rollup", value_enum, value_name = "LEVEL")]
+    pub rollup: Option<Rollup>,
+
+    /// Only list the models that no read or region overlaps, without counting depth:
+    /// root IDs (tsv), root extents as BED6 (bed) or the whole models (gff)
+    #[arg(long = "uncovered-only", default_value_t = false, conflicts_with = "rollup")]
+    pub uncovered_only: bool,
+
+    #[command(flatten)]
+    pub table: TableArgs,
+
+    /// Read the index files of the inputs from PREFIX (see `index --index-prefix`);
+    /// with several -i it must be a directory
+    #[arg(long = "index-prefix", value_name = "PREFIX")]
+    pub index_prefix: Option<PathBuf>,
+
+    /// Index any input whose index is missing files or stale before counting, instead
+    /// of stopping with an error (see `extract --auto-index`)
+    #[arg(long = "auto-index", default_value_t = false)]
+    pub auto_index: bool,
+
     /// Number of threads for parallel processing
     #[arg(short = 't', long = "threads", default_value_t = 12)]
     pub threads: usize,
@@ -73,6 +173,13 @@ pub struct DepthArgs {
     pub verbose: bool,
 }
 
+/// Model level that `--rollup` aggregates to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Rollup {
+    /// Nearest gene-level ancestor (`gene`, `pseudogene`, `*_gene`), else the model root
+    Gene,
+}
+
 /// Check half-open overlap: [a1, a2) vs [b1, b2)
 #[inline(always)]
 fn overlaps(a1: u32, a2: u32, b1: u32, b2: u32) -> bool {
@@ -95,48 +202,39 @@ fn parse_u32_fast(s: &str) -> Option<u32> {
 }
 
 #[derive(Clone, Copy)]
-struct RegionRef { start: u32, end: u32 }
+/// A region under one root; consecutive regions with the same `frag` (the ends of a
+/// BEDPE pair) count once per feature.
+struct RegionRef { start: u32, end: u32, frag: u32 }
 
 #[derive(Clone, Copy)]
 struct FeatureInst { start: u32, end: u32, id_idx: u32 }
 
-#[inline(always)]
-fn fast_id(attrs: &str) -> Option<&str> {
-    let bytes = attrs.as_bytes();
-    let mut i = 0;
-    while i + 2 < bytes.len() {
-        if bytes[i] == b'I' && bytes[i+1] == b'D' && bytes[i+2] == b'=' {
-            let mut j = i + 3;
-            while j < bytes.len() && bytes[j] != b';' && bytes[j] != b' ' && bytes[j] != b'\t' {
-                j += 1;
-            }
-            return std::str::from_utf8(&bytes[i+3..j]).ok();
-        }
-        i += 1;
-    }
-    None
-}
-
-/// Parse one GFF slice and count feature *depth* (how many regions overlap it; deduped per region)
+/// Parse one GFF slice and count feature *depth* (how many regions overlap it; deduped per
+/// region, or per fragment for runs of regions sharing `frag`); features of types outside
+/// `types` are skipped
 fn compute_root_depth(
     gff_slice: &[u8],
     regions: &[RegionRef],
-    bin_shift: u32,  
-) -> FxHashMap<String, (String, u32, u32, usize)> {
+    bin_shift: u32,
+    types: Option<&TypeFilter>,
+) -> DepthMap {
     let mut id_to_idx: FxHashMap<String, u32> = FxHashMap::default();
     let mut id_strings: Vec<String> = Vec::new();
     let mut id_chrom: Vec<String> = Vec::new();
+    // Score and phase of the first line of each ID
+    let mut id_cols: Vec<ScorePhase> = Vec::new();
     let mut feats: Vec<FeatureInst> = Vec::new();
 
     if let Ok(text) = str::from_utf8(gff_slice) {
         for line in text.split_terminator('\n') {
             if line.is_empty() || line.as_bytes()[0] == b'#' { continue; }
             let mut cols = line.splitn(9, '\t');
-            let (Some(seqid), _, _, Some(start_s), Some(end_s), _, _, _, Some(attrs)) = (
+            let (Some(seqid), _, Some(ftype), Some(start_s), Some(end_s), Some(score), _, Some(phase), Some(attrs)) = (
                 cols.next(), cols.next(), cols.next(),
                 cols.next(), cols.next(), cols.next(),
                 cols.next(), cols.next(), cols.next()
             ) else { continue; };
+            if types.is_some_and(|t| !t.allows(ftype.as_bytes())) { continue; }
 
             let (Some(s1), Some(e1)) = (parse_u32_fast(start_s), parse_u32_fast(end_s)) else { continue; };
             if e1 == 0 { continue; }
@@ -149,6 +247,7 @@ fn compute_root_depth(
                     let k = id_strings.len() as u32;
                     id_strings.push(id.to_owned());
                     id_chrom.push(seqid.to_owned());
+                    id_cols.push(ScorePhase::parse(score, phase));
                     k
                 });
                 feats.push(FeatureInst { start: fstart0, end: fend0, id_idx: idx });
@@ -177,8 +276,19 @@ fn compute_root_depth(
 
     let mut cand: Vec<u32> = Vec::new();
     let mut hit_ids: Vec<u32> = Vec::new();
+    let mut count_hits = |hit_ids: &mut Vec<u32>| {
+        hit_ids.sort_unstable();
+        hit_ids.dedup();
+        for &ii in hit_ids.iter() {
+            depths[ii as usize] += 1;
+        }
+        hit_ids.clear();
+    };
 
-    for r in regions {
+    for (i, r) in regions.iter().enumerate() {
+        if i > 0 && regions[i - 1].frag != r.frag {
+            count_hits(&mut hit_ids);
+        }
         cand.clear();
         let rb0 = bin_of(r.start, bin_shift);
         let rb1 = bin_of(r.end.saturating_sub(1), bin_shift);
@@ -190,7 +300,6 @@ fn compute_root_depth(
         cand.sort_unstable();
         cand.dedup();
 
-        hit_ids.clear();
         for &fi in &cand {
             let f = feats[fi as usize];
             if overlaps(f.start, f.end, r.start, r.end) {
@@ -199,18 +308,161 @@ fn compute_root_depth(
                 if f.end   > max_e[f.id_idx as usize] { max_e[f.id_idx as usize] = f.end; }
             }
         }
-        hit_ids.sort_unstable();
-        hit_ids.dedup();
-        for &ii in &hit_ids {
-            depths[ii as usize] += 1;
-        }
     }
+    count_hits(&mut hit_ids);
 
-    let mut out: FxHashMap<String, (String, u32, u32, usize)> = FxHashMap::default();
+    let mut out: DepthMap = FxHashMap::default();
     for (i, &d) in depths.iter().enumerate() {
         if d > 0 {
             let s = if min_s[i] == u32::MAX { 0 } else { min_s[i] };
-            out.insert(id_strings[i].clone(), (id_chrom[i].clone(), s, max_e[i], d));
+            out.insert(id_strings[i].clone(), (id_chrom[i].clone(), s, max_e[i], d, id_cols[i]));
+        }
+    }
+    out
+}
+
+/// Median of `values` (the upper one for an even count); `None` when empty.
+fn median(values: &mut [u32]) -> Option<u32> {
+    let mid = values.len() / 2;
+    (!values.is_empty()).then(|| *values.select_nth_unstable(mid).1)
+}
+
+/// k for `--bin-shift auto`: 2^k is the geometric mean of the median read (or region)
+/// length and the median root span, rounded to a power of two and kept within
+/// `AUTO_SHIFT_RANGE`. Short reads over gene models get ~1 kb bins (k=10), HiFi and ONT
+/// reads 8–32 kb bins (k=13–15), in line with the hand-tuning advice of `--bin-shift`:
+/// reads then span one or two bins and `compute_root_depth` compares each with the
+/// features of a few bins only. Depends only on the two samples, not on their order.
+fn auto_bin_shift(read_lens: &mut [u32], root_spans: &mut [u32]) -> u32 {
+    let (Some(read), Some(span)) = (median(read_lens), median(root_spans)) else {
+        return DEFAULT_BIN_SHIFT;
+    };
+    let mean = (f64::from(read.max(1)) * f64::from(span.max(1))).sqrt();
+    (mean.log2().round() as u32).clamp(AUTO_SHIFT_RANGE.0, AUTO_SHIFT_RANGE.1)
+}
+
+/// Lengths of the first `AUTO_SHIFT_SAMPLE` mapped reads, BED regions or BEDPE
+/// fragments (as `--bedpe-mode` forms them) of the source, in file order.
+fn sample_source_lengths(source: &Path, bedpe_mode: BedpeMode) -> Result<Vec<u32>> {
+    let mut lens = Vec::with_capacity(AUTO_SHIFT_SAMPLE);
+    match source_extension(source).as_deref() {
+        Some("bam") | Some("sam") | Some("cram") => {
+            let mut reader = bam::Reader::from_path(source)?;
+            for r in reader.records() {
+                let rec = r?;
+                if rec.is_unmapped() || rec.tid() < 0 || rec.pos() < 0 {
+                    continue;
+                }
+                let len = rec.reference_end() - rec.pos();
+                if len > 0 {
+                    lens.push(len.min(i64::from(u32::MAX)) as u32);
+                }
+                if lens.len() >= AUTO_SHIFT_SAMPLE {
+                    break;
+                }
+            }
+        }
+        Some("bedpe") => {
+            let head = BedReader::open(source)?.head(AUTO_SHIFT_SAMPLE)?;
+            let mut skips = BedpeSkips::default();
+            for pair in head.split(|&b| b == b'\n').filter_map(parse_bedpe_pair) {
+                lens.extend(bedpe_mode.regions(pair, &mut skips).iter().flatten().map(|&(_, s, e)| e - s));
+            }
+        }
+        _ => {
+            let head = BedReader::open(source)?.head(AUTO_SHIFT_SAMPLE)?;
+            lens.extend(head.split(|&b| b == b'\n').filter_map(parse_bed_region).map(|(_, s, e)| e - s));
+        }
+    }
+    Ok(lens)
+}
+
+/// Spans of the roots whose features are reported, per annotation set from its trees in
+/// sequence order until `AUTO_SHIFT_SAMPLE` are found (so only those trees are decoded).
+fn reported_root_spans(sets: &[AnnotationSet]) -> Result<Vec<u32>> {
+    let mut spans = Vec::new();
+    for set in sets {
+        let goal = spans.len() + AUTO_SHIFT_SAMPLE;
+        for num in 0..set.index_data.tree_count() as u32 {
+            if spans.len() >= goal {
+                break;
+            }
+            let Some(tree) = set.index_data.tree_for(num)? else { continue };
+            spans.extend(
+                tree.iter()
+                    .filter(|iv| set.root_filter.as_ref().is_none_or(|f| f.contains(&iv.payload)))
+                    .map(|iv| iv.end - iv.start),
+            );
+        }
+    }
+    Ok(spans)
+}
+
+/// Record region `[rstart, rend)` of fragment `frag` under each root in `roots` (deduped
+/// per region), skipping roots without a valid GOF block or outside `root_filter`.
+#[inline]
+fn push_region_roots(
+    by_root: &mut FxHashMap<u32, Vec<RegionRef>>,
+    idx: &FxHashMap<u32, (u64, u64)>,
+    root_filter: Option<&FxHashSet<u32>>,
+    roots: &[u32],
+    rstart: u32,
+    rend: u32,
+    frag: u32,
+) {
+    let mut seen_in_region: FxHashSet<u32> = FxHashSet::default();
+    for &root in roots {
+        if !seen_in_region.insert(root) { continue; }
+        if root_filter.is_some_and(|f| !f.contains(&root)) { continue; }
+        if let Some(&(s_off, e_off)) = idx.get(&root) {
+            if s_off == MISSING || e_off == MISSING || e_off <= s_off { continue; }
+            by_root.entry(root).or_default().push(RegionRef { start: rstart, end: rend, frag });
+        }
+    }
+}
+
+/// Count feature depth inside each root block hit by at least one region.
+fn depth_from_root_regions(
+    by_root: FxHashMap<u32, Vec<RegionRef>>,
+    idx: &FxHashMap<u32, (u64, u64)>,
+    gff_bytes: &[u8],
+    bin_shift: u32,
+    types: Option<&TypeFilter>,
+    threads: usize,
+) -> DepthMap {
+    let mut out: DepthMap = FxHashMap::default();
+    if by_root.is_empty() { return out; }
+
+    let root_depth = |(root, regs): (u32, Vec<RegionRef>)| {
+        let (s_off, e_off) = *idx.get(&root).unwrap();
+        let su = usize::try_from(s_off).unwrap();
+        let eu = usize::try_from(e_off).unwrap();
+        compute_root_depth(&gff_bytes[su..eu], &regs, bin_shift, types)
+    };
+    if deterministic() {
+        // Merge in root order whatever the thread count (--deterministic)
+        let mut roots: Vec<_> = by_root.into_iter().collect();
+        roots.sort_unstable_by_key(|(root, _)| *root);
+        let partials: Vec<DepthMap> = roots.into_par_iter().map(root_depth).collect();
+        for m in partials {
+            merge_depth_counts(&mut out, m);
+        }
+        return out;
+    }
+
+    let roots_iter = by_root.into_iter();
+    if threads > 1 {
+        // Parallel execution: each root slice is processed independently
+        let partials: Vec<_> = roots_iter.par_bridge().map(root_depth).collect();
+        
+        // Merge results from all roots
+        for m in partials {
+            merge_depth_counts(&mut out, m);
+        }
+    } else {
+        // Serial execution
+        for entry in roots_iter {
+            merge_depth_counts(&mut out, root_depth(entry));
         }
     }
     out
@@ -219,92 +471,397 @@ fn compute_root_depth(
 /// Batch API: for a batch of regions, return "feature ID -> (chrom, start, end, depth)".
 ///
 /// - depth  = how many regions overlap with the feature (count of regions, deduped per region)
+/// - roots outside `root_filter` (when given) are skipped
 pub fn compute_hit_depth(
     index_data: &TreeIndexData,
     regions: &[(u32, u32, u32)],
     gof: &GofMap,
-    gff_mmap: &Mmap,
+    gff_mmap: &ByteSource,
+    root_filter: Option<&FxHashSet<u32>>,
     bin_shift: u32,
     threads: usize,
-) -> Result<FxHashMap<String, (String, u32, u32, usize)>> {
-    let mut by_root: FxHashMap<u32, Vec<RegionRef>> = FxHashMap::default();
+) -> Result<DepthMap> {
+    let regions: Vec<(u32, u32, u32, u32)> =
+        regions.iter().enumerate().map(|(i, &(chr, s, e))| (chr, s, e, i as u32)).collect();
+    compute_fragment_depth(index_data, &regions, gof, gff_mmap, root_filter, bin_shift, threads)
+}
+
+/// Same as `compute_hit_depth` for (chr, start, end, fragment) regions: adjacent regions
+/// of one fragment (e.g. both ends of a BEDPE pair) count once per feature.
+pub fn compute_fragment_depth(
+    index_data: &TreeIndexData,
+    regions: &[(u32, u32, u32, u32)],
+    gof: &GofMap,
+    gff_mmap: &ByteSource,
+    root_filter: Option<&FxHashSet<u32>>,
+    bin_shift: u32,
+    threads: usize,
+) -> Result<DepthMap> {
     let idx = gof.index_cached();
-    let gff_bytes: &[u8] = &gff_mmap[..];
+    let by_root = root_regions(index_data, regions, idx, root_filter)?;
+    Ok(depth_from_root_regions(by_root, idx, &gff_mmap[..], bin_shift, None, threads))
+}
 
+/// Group (chr, start, end, fragment) regions under the roots whose interval they
+/// overlap, as `push_region_roots` does.
+fn root_regions(
+    index_data: &TreeIndexData,
+    regions: &[(u32, u32, u32, u32)],
+    idx: &FxHashMap<u32, (u64, u64)>,
+    root_filter: Option<&FxHashSet<u32>>,
+) -> Result<FxHashMap<u32, Vec<RegionRef>>> {
+    let mut by_root: FxHashMap<u32, Vec<RegionRef>> = FxHashMap::default();
     let mut hits: Vec<&Interval<u32>> = Vec::new();
-    for &(chr, rstart, rend) in regions {
-        if let Some(tree) = index_data.chr_entries.get(&chr) {
+    let mut roots: Vec<u32> = Vec::new();
+    for &(chr, rstart, rend, frag) in regions {
+        if let Some(tree) = index_data.tree_for(chr)? {
             hits.clear();
             tree.query_interval(rstart, rend, &mut hits);
-            let mut seen_in_region: FxHashSet<u32> = FxHashSet::default();
-            for h in &hits {
-                if !seen_in_region.insert(h.root_fid) { continue; }
-                if let Some(&(s_off, e_off)) = idx.get(&h.root_fid) {
-                    if s_off == MISSING || e_off == MISSING || e_off <= s_off { continue; }
-                    by_root.entry(h.root_fid).or_default().push(RegionRef { start: rstart, end: rend });
-                }
+            roots.clear();
+            roots.extend(hits.iter().map(|h| h.payload));
+            push_region_roots(&mut by_root, idx, root_filter, &roots, rstart, rend, frag);
+        }
+    }
+    Ok(by_root)
+}
+
+/// Cursor over root intervals sorted by start, for coordinate-sorted input (`--assume-sorted`).
+///
+/// Regions must arrive with non-decreasing starts per sequence; a region whose start
+/// regresses is answered from the interval tree instead, so results never depend on
+/// the input actually being sorted.
+struct SortedSweep<'a> {
+    index_data: &'a TreeIndexData,
+    /// seqid number -> (start, end, root) sorted by start, filled as sequences come up
+    by_chr: FxHashMap<u32, Vec<(u32, u32, u32)>>,
+    chr: u32,
+    next: usize,
+    last_start: u32,
+    active: Vec<(u32, u32, u32)>,
+    fallbacks: usize,
+}
+
+impl<'a> SortedSweep<'a> {
+    fn new(index_data: &'a TreeIndexData) -> Self {
+        Self {
+            index_data,
+            by_chr: FxHashMap::default(),
+            chr: u32::MAX,
+            next: 0,
+            last_start: 0,
+            active: Vec::new(),
+            fallbacks: 0,
+        }
+    }
+
+    /// Push roots overlapping `[start, end)` on `chr` into `out`.
+    /// Returns false (and leaves the cursor untouched) when `start` regresses.
+    fn roots_for(&mut self, chr: u32, start: u32, end: u32, out: &mut Vec<u32>) -> Result<bool> {
+        if chr != self.chr {
+            self.chr = chr;
+            self.next = 0;
+            self.last_start = 0;
+            self.active.clear();
+            if !self.by_chr.contains_key(&chr)
+                && let Some(tree) = self.index_data.tree_for(chr)?
+            {
+                let ivs = tree.sorted_intervals().into_iter().map(|iv| (iv.start, iv.end, iv.payload)).collect();
+                self.by_chr.insert(chr, ivs);
             }
+        } else if start < self.last_start {
+            self.fallbacks += 1;
+            return Ok(false);
         }
+        self.last_start = start;
+
+        let Some(ivs) = self.by_chr.get(&chr) else { return Ok(true); };
+        while self.next < ivs.len() && ivs[self.next].0 < end {
+            self.active.push(ivs[self.next]);
+            self.next += 1;
+        }
+        // Later regions start at or after `start`, so intervals ending before it are done
+        self.active.retain(|&(_, e, _)| e > start);
+        for &(s, e, root) in &self.active {
+            if s < end && e > start {
+                out.push(root);
+            }
+        }
+        Ok(true)
     }
+}
 
-    let mut out: FxHashMap<String, (String, u32, u32, usize)> = FxHashMap::default();
-    if by_root.is_empty() { return Ok(out); }
+/// Same as `root_regions` for (chr, start, end) regions, but maps them to roots with a
+/// `SortedSweep`.
+fn sorted_root_regions(
+    set: &AnnotationSet,
+    sweep: &mut SortedSweep,
+    regions: &[(u32, u32, u32)],
+) -> Result<FxHashMap<u32, Vec<RegionRef>>> {
+    let mut by_root: FxHashMap<u32, Vec<RegionRef>> = FxHashMap::default();
+    let idx = set.gof.index_cached();
 
-    let roots_iter = by_root.into_iter();
-    if threads > 1 {
-        // Parallel execution: each root slice is processed independently
-        let partials: Vec<_> = roots_iter.par_bridge().map(|(root, regs)| {
-            let (s_off, e_off) = *idx.get(&root).unwrap();
-            let su = usize::try_from(s_off).unwrap();
-            let eu = usize::try_from(e_off).unwrap();
-            compute_root_depth(&gff_bytes[su..eu], &regs, bin_shift)
-        }).collect();
-        
-        // Merge results from all roots
-        for m in partials {
-            for (id, (chrom, s, e, d)) in m {
-                out.entry(id).and_modify(|(c0, s0, e0, depth)| {
-                    if s < *s0 { *s0 = s; }
-                    if e > *e0 { *e0 = e; }
-                    *depth += d;
-                    let _ = c0;
-                }).or_insert((chrom, s, e, d));
+    let mut hits: Vec<&Interval<u32>> = Vec::new();
+    let mut roots: Vec<u32> = Vec::new();
+    for (frag, &(chr, rstart, rend)) in regions.iter().enumerate() {
+        roots.clear();
+        if !sweep.roots_for(chr, rstart, rend, &mut roots)?
+            && let Some(tree) = set.index_data.tree_for(chr)?
+        {
+            hits.clear();
+            tree.query_interval(rstart, rend, &mut hits);
+            roots.extend(hits.iter().map(|h| h.payload));
+        }
+        push_region_roots(&mut by_root, idx, set.root_filter.as_ref(), &roots, rstart, rend, frag as u32);
+    }
+    Ok(by_root)
+}
+
+/// Per-feature depth table: "feature ID -> (chrom, start, end, depth)".
+pub type DepthMap = FxHashMap<String, (String, u32, u32, usize, ScorePhase)>;
+
+/// What a pass over the source accumulates, one entry per annotation set: feature
+/// depths, or with `--uncovered-only` just the roots hit by at least one region (their
+/// blocks are never parsed).
+pub enum DepthTally {
+    Depth(Vec<DepthMap>),
+    Touched(Vec<FxHashSet<u32>>),
+}
+
+impl DepthTally {
+    pub fn new(n_sets: usize, touched_only: bool) -> Self {
+        if touched_only {
+            Self::Touched(vec![FxHashSet::default(); n_sets])
+        } else {
+            Self::Depth(vec![DepthMap::default(); n_sets])
+        }
+    }
+
+    /// Add the regions that one batch put under each root of set `i`.
+    fn add(
+        &mut self,
+        i: usize,
+        set: &AnnotationSet,
+        by_root: FxHashMap<u32, Vec<RegionRef>>,
+        bin_shift: u32,
+        threads: usize,
+    ) {
+        match self {
+            Self::Depth(maps) => {
+                let (idx, types) = (set.gof.index_cached(), set.type_filter.as_ref());
+                let id_counts = depth_from_root_regions(by_root, idx, &set.gff_mmap[..], bin_shift, types, threads);
+                merge_depth_counts(&mut maps[i], id_counts);
             }
+            Self::Touched(roots) => roots[i].extend(by_root.into_keys()),
         }
-    } else {
-        // Serial execution
-        for (root, regs) in roots_iter {
-            let (s_off, e_off) = *idx.get(&root).unwrap();
-            let su = usize::try_from(s_off).unwrap();
-            let eu = usize::try_from(e_off).unwrap();
-            let m = compute_root_depth(&gff_bytes[su..eu], &regs, bin_shift);
-            for (id, (chrom, s, e, d)) in m {
-                out.entry(id).and_modify(|(c0, s0, e0, depth)| {
-                    if s < *s0 { *s0 = s; }
-                    if e > *e0 { *e0 = e; }
-                    *depth += d;
-                    let _ = c0;
-                }).or_insert((chrom, s, e, d));
+    }
+}
+
+/// Merge a batch result into the running per-annotation totals.
+fn merge_depth_counts(global: &mut DepthMap, id_counts: DepthMap) {
+    for (id, (chrom, s, e, d, cols)) in id_counts {
+        global.entry(id).and_modify(|(c0, s0, e0, depth, _)| {
+            if s < *s0 { *s0 = s; }
+            if e > *e0 { *e0 = e; }
+            *depth += d;
+            let _ = c0; // chrom assumed consistent
+        }).or_insert((chrom, s, e, d, cols));
+    }
+}
+
+/// Per-model rollup table: "model ID -> (chrom, start, end, depths of its leaf features)".
+pub type RollupMap = FxHashMap<String, (String, u32, u32, Vec<usize>)>;
+
+#[inline]
+fn is_gene_level(ftype: &str) -> bool {
+    ftype == "gene" || ftype == "pseudogene" || ftype.ends_with("_gene")
+}
+
+/// Group the leaf features of one root block under their nearest gene-level ancestor
+/// (following the first `Parent=` value), or under the block root when there is none.
+/// Leaves without a row in `depths` count as depth 0, leaves of types outside `types` are
+/// left out; groups with no covered leaf are dropped.
+fn rollup_root_block(gff_slice: &[u8], depths: &DepthMap, types: Option<&TypeFilter>) -> RollupMap {
+    struct Feat<'a> { seqid: &'a str, ftype: &'a str, start: u32, end: u32, parent: Option<&'a str> }
+
+    let mut out: RollupMap = FxHashMap::default();
+    let Ok(text) = str::from_utf8(gff_slice) else { return out; };
+
+    // Features by ID in file order; multi-line features (e.g. CDS) widen their extent
+    let mut order: Vec<&str> = Vec::new();
+    let mut feats: FxHashMap<&str, Feat> = FxHashMap::default();
+    for line in text.split_terminator('\n') {
+        if line.is_empty() || line.as_bytes()[0] == b'#' { continue; }
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let mut cols = line.splitn(9, '\t');
+        let (Some(seqid), _, Some(ftype), Some(start_s), Some(end_s), _, _, _, Some(attrs)) = (
+            cols.next(), cols.next(), cols.next(),
+            cols.next(), cols.next(), cols.next(),
+            cols.next(), cols.next(), cols.next()
+        ) else { continue; };
+        let (Some(s1), Some(e1)) = (parse_u32_fast(start_s), parse_u32_fast(end_s)) else { continue; };
+        let (s1, e1) = if s1 > e1 { (e1, s1) } else { (s1, e1) };
+        let Some(id) = fast_id(attrs) else { continue; };
+        let parent = attrs
+            .split(';')
+            .find_map(|kv| kv.trim().strip_prefix("Parent="))
+            .and_then(|v| v.split(',').map(str::trim).find(|p| !p.is_empty()));
+        feats
+            .entry(id)
+            .and_modify(|f| {
+                f.start = f.start.min(s1.saturating_sub(1));
+                f.end = f.end.max(e1);
+            })
+            .or_insert_with(|| {
+                order.push(id);
+                Feat { seqid, ftype, start: s1.saturating_sub(1), end: e1, parent }
+            });
+    }
+
+    let has_children: FxHashSet<&str> = feats.values().filter_map(|f| f.parent).collect();
+    let Some(&root) = order
+        .iter()
+        .find(|id| feats[*id].parent.is_none_or(|p| !feats.contains_key(p)))
+    else { return out; };
+
+    let is_leaf = |id: &&str| !has_children.contains(id) && types.is_none_or(|t| t.allows(feats[id].ftype.as_bytes()));
+    for &id in order.iter().filter(|id| is_leaf(id)) {
+        let mut group = root;
+        let mut cur = id;
+        // A chain longer than the block can only be a cycle
+        for _ in 0..=feats.len() {
+            let f = &feats[cur];
+            if is_gene_level(f.ftype) {
+                group = cur;
+                break;
+            }
+            match f.parent.filter(|p| feats.contains_key(p)) {
+                Some(p) => cur = p,
+                None => break,
             }
         }
+        let g = &feats[group];
+        let depth = depths.get(id).map_or(0, |v| v.3);
+        out.entry(group.to_owned())
+            .or_insert_with(|| (g.seqid.to_owned(), g.start, g.end, Vec::new()))
+            .3
+            .push(depth);
     }
+    out.retain(|_, (_, _, _, ds)| ds.iter().any(|&d| d > 0));
+    out
+}
 
+/// Roll the feature depths of one annotation set up to gene level (`--rollup gene`),
+/// re-reading only the root blocks that contain a reported feature.
+fn rollup_depths(set: &AnnotationSet, depths: &DepthMap, threads: usize) -> Result<RollupMap> {
+    let fts = load_fts(&set.path)?;
+    let prt = load_prt(&set.path)?;
+    let mut roots: Vec<u32> = depths
+        .keys()
+        .filter_map(|id| fts.get_fid(id))
+        .filter_map(|fid| prt.root_of(fid))
+        .collect();
+    roots.sort_unstable();
+    roots.dedup();
+
+    let (blocks, missing) = set.gof.roots_to_offsets(&roots, threads);
+    if !missing.is_empty() {
+        diag!(
+            "[WARN] {} of {} roots not found in the GOF index of {}; their features are not rolled up.",
+            missing.len(),
+            roots.len(),
+            set.name
+        );
+    }
+
+    let data = &set.gff_mmap[..];
+    let partials: Vec<RollupMap> = blocks
+        .par_iter()
+        .map(|&(_, s, e)| {
+            let eu = (e as usize).min(data.len());
+            rollup_root_block(&data[(s as usize).min(eu)..eu], depths, set.type_filter.as_ref())
+        })
+        .collect();
+    let mut out = RollupMap::default();
+    for m in partials {
+        out.extend(m);
+    }
     Ok(out)
 }
 
+/// (n_children, min, max, mean, median) of the leaf depths of one rollup group.
+fn rollup_stats(depths: &mut [usize]) -> (usize, usize, usize, f64, f64) {
+    depths.sort_unstable();
+    let n = depths.len();
+    if n == 0 {
+        return (0, 0, 0, 0.0, 0.0);
+    }
+    let mean = depths.iter().sum::<usize>() as f64 / n as f64;
+    let median = if n % 2 == 1 {
+        depths[n / 2] as f64
+    } else {
+        (depths[n / 2 - 1] + depths[n / 2]) as f64 / 2.0
+    };
+    (n, depths[0], depths[n - 1], mean, median)
+}
+
+/// Query one batch of source regions `(tid, start, end)` against every annotation set.
+/// `tid2num[i]` maps a source target id to the seqid number of `sets[i]`.
+/// With `sweeps` (one per set, `--assume-sorted`) roots are found by a sorted sweep.
+#[allow(clippy::too_many_arguments)]
+fn flush_depth_batch(
+    sets: &[AnnotationSet],
+    tid2num: &[Vec<Option<u32>>],
+    batch: &[(u32, u32, u32)],
+    tally: &mut DepthTally,
+    mut sweeps: Option<&mut [SortedSweep]>,
+    bin_shift: u32,
+    threads: usize,
+    timers: &mut (Duration, Duration, Duration),
+) -> Result<()> {
+    for (i, set) in sets.iter().enumerate() {
+        let t2 = Instant::now();
+        let regions: Vec<(u32, u32, u32)> = batch
+            .iter()
+            .filter_map(|&(tid, s, e)| tid2num[i].get(tid as usize).copied().flatten().map(|c| (c, s, e)))
+            .collect();
+        timers.0 += t2.elapsed();
+        if regions.is_empty() {
+            continue;
+        }
+
+        let t3 = Instant::now();
+        let by_root = match sweeps.as_deref_mut() {
+            Some(sw) => sorted_root_regions(set, &mut sw[i], &regions)?,
+            None => {
+                let regions: Vec<(u32, u32, u32, u32)> =
+                    regions.iter().enumerate().map(|(f, &(chr, s, e))| (chr, s, e, f as u32)).collect();
+                root_regions(&set.index_data, &regions, set.gof.index_cached(), set.root_filter.as_ref())?
+            }
+        };
+        timers.1 += t3.elapsed();
+
+        let t4 = Instant::now();
+        tally.add(i, set, by_root, bin_shift, threads);
+        timers.2 += t4.elapsed();
+    }
+    Ok(())
+}
+
 /// Process BAM input with mmap/htslib and batch queries.
-/// Returns: "feature ID -> (chrom, start, end, depth)".
+/// The BAM is streamed once; each batch of reads is queried against every annotation set
+/// and added to `tally` (one entry per set).
+/// Mapped reads on targets that no set knows are counted in `unknown`.
+#[allow(clippy::too_many_arguments)]
 pub fn process_bam(
     bam_path: &Path,
-    index_data: &TreeIndexData,
-    gof: GofMap,
-    gff_mmap: Mmap,
+    sets: &[AnnotationSet],
+    tally: &mut DepthTally,
     bin_shift: u32,
     threads: usize,
+    assume_sorted: bool,
+    unknown: &mut UnknownContigs,
     verbose: bool,
-) -> Result<FxHashMap<String, (String, u32, u32, usize)>> {
-    let mut global_id_counts: FxHashMap<String, (String, u32, u32, usize)> = FxHashMap::default();
-
+) -> Result<()> {
     let t_open = Instant::now();
     let mut reader = bam::Reader::from_path(bam_path)?;
     reader.set_threads(std::cmp::max(2, threads))?;
@@ -312,25 +869,47 @@ pub fn process_bam(
 
     let header = reader.header().to_owned();
 
-    // Build tid -> chr_id mapping
+    // Sorted fast path: only when the header declares coordinate order
+    let mut sweeps: Option<Vec<SortedSweep>> = None;
+    if assume_sorted {
+        let text = String::from_utf8_lossy(header.as_bytes());
+        let coord_sorted = text
+            .lines()
+            .find(|l| l.starts_with("@HD"))
+            .is_some_and(|l| l.split('\t').any(|f| f == "SO:coordinate"));
+        if coord_sorted {
+            sweeps = Some(sets.iter().map(|s| SortedSweep::new(&s.index_data)).collect());
+        } else {
+            diag!("[WARN] --assume-sorted: BAM header lacks @HD SO:coordinate; using the interval tree path");
+        }
+    }
+
+    // Build tid -> chr_id mapping for each annotation set
     let t_map_build = Instant::now();
-    let mut tid2num: Vec<Option<u32>> = Vec::with_capacity(header.target_count() as usize);
+    let mut target_names: Vec<String> = Vec::with_capacity(header.target_count() as usize);
     for tid in 0..header.target_count() {
         let chrom_bytes = header.tid2name(tid).to_owned();
-        let chrom = std::str::from_utf8(&chrom_bytes)?;
-        let chr_id = index_data.seqid_to_num.get(chrom).copied();
-        tid2num.push(chr_id);
+        target_names.push(std::str::from_utf8(&chrom_bytes)?.to_owned());
     }
+    let tid2num: Vec<Vec<Option<u32>>> = sets
+        .iter()
+        .map(|set| set.map_target_names(target_names.iter().map(String::as_str)))
+        .collect();
+    // A target is worth batching if at least one annotation knows it
+    let tid_used: Vec<bool> = (0..target_names.len())
+        .map(|t| tid2num.iter().any(|m| m[t].is_some()))
+        .collect();
     let t_map_build_elapsed = t_map_build.elapsed();
 
     let mut batch: Vec<(u32, u32, u32)> = Vec::with_capacity(BATCH_SIZE);
+    // Mapped reads per target that no annotation knows
+    let mut skipped_by_tid = vec![0u64; target_names.len()];
 
     // Timers
     let mut t_parse = Duration::ZERO;
     let mut t_tidmap = Duration::ZERO;
-    let mut t_filtermap = Duration::ZERO;
-    let mut t_tree = Duration::ZERO;
-    let mut t_depthmap = Duration::ZERO;
+    // (batch filter_map, interval tree query, depth map updates)
+    let mut t_batch = (Duration::ZERO, Duration::ZERO, Duration::ZERO);
 
     for r in reader.records() {
         let t0 = Instant::now();
@@ -347,7 +926,12 @@ pub fn process_bam(
             t_tidmap += t1.elapsed();
             continue;
         }
-        if let Some(chr_id) = tid2num[tid as usize] {
+        unknown.total += 1;
+        if !tid_used.get(tid as usize).copied().unwrap_or(false) {
+            if let Some(n) = skipped_by_tid.get_mut(tid as usize) {
+                *n += 1;
+            }
+        } else {
             let start_i64 = rec.pos();
             if start_i64 < 0 {
                 t_tidmap += t1.elapsed();
@@ -361,193 +945,379 @@ pub fn process_bam(
             let start = (start_i64 as i128).clamp(0, u32::MAX as i128) as u32;
             let end   = (end_i64 as i128).clamp(0, u32::MAX as i128) as u32;
 
-            batch.push((chr_id, start, end));
+            batch.push((tid as u32, start, end));
         }
         t_tidmap += t1.elapsed();
 
         if batch.len() >= BATCH_SIZE {
-            let t2 = Instant::now();
-            let regions = std::mem::take(&mut batch);
-            t_filtermap += t2.elapsed();
-
-            let t3 = Instant::now();
-            let id_counts = compute_hit_depth(index_data, &regions, &gof, &gff_mmap, bin_shift, threads)?;
-            t_tree += t3.elapsed();
-
-            let t4 = Instant::now();
-            for (id, (chrom, s, e, d)) in id_counts {
-                global_id_counts.entry(id).and_modify(|(c0, s0, e0, depth)| {
-                    if s < *s0 { *s0 = s; }
-                    if e > *e0 { *e0 = e; }
-                    *depth += d;
-                    let _ = c0;
-                }).or_insert((chrom, s, e, d));
-            }
-            t_depthmap += t4.elapsed();
+            flush_depth_batch(sets, &tid2num, &batch, tally, sweeps.as_deref_mut(), bin_shift, threads, &mut t_batch)?;
             batch.clear();
         }
     }
 
     if !batch.is_empty() {
-        let t2 = Instant::now();
-        let regions = std::mem::take(&mut batch);
-        t_filtermap += t2.elapsed();
-
-        let t3 = Instant::now();
-        let id_counts = compute_hit_depth(index_data, &regions, &gof, &gff_mmap, bin_shift, threads)?;
-        t_tree += t3.elapsed();
-
-        let t4 = Instant::now();
-        for (id, (chrom, s, e, d)) in id_counts {
-            global_id_counts.entry(id).and_modify(|(c0, s0, e0, depth)| {
-                if s < *s0 { *s0 = s; }
-                if e > *e0 { *e0 = e; }
-                *depth += d;
-                let _ = c0;
-            }).or_insert((chrom, s, e, d));
-        }
-        t_depthmap += t4.elapsed();
+        flush_depth_batch(sets, &tid2num, &batch, tally, sweeps.as_deref_mut(), bin_shift, threads, &mut t_batch)?;
+    }
+    for (name, &n) in target_names.iter().zip(&skipped_by_tid) {
+        unknown.skip(name, n);
     }
 
     if verbose {
-        eprintln!("[TIMER] (1) Opening BAM file:     {:.2?}", t_open_elapsed);
-        eprintln!("[TIMER] (1b) Build tid2num map:  {:.2?}", t_map_build_elapsed);
-        eprintln!("[TIMER] (2) Parsing records:     {:.2?}", t_parse);
-        eprintln!("[TIMER] (3) Chrom ID mapping:    {:.2?}", t_tidmap);
-        eprintln!("[TIMER] (4) Batch filter_map:    {:.2?}", t_filtermap);
-        eprintln!("[TIMER] (5) Interval tree query: {:.2?}", t_tree);
-        eprintln!("[TIMER] (6) DepthMap updates:    {:.2?}", t_depthmap);
+        diag!("[TIMER] (1) Opening BAM file:     {:.2?}", t_open_elapsed);
+        diag!("[TIMER] (1b) Build tid2num map:  {:.2?}", t_map_build_elapsed);
+        diag!("[TIMER] (2) Parsing records:     {:.2?}", t_parse);
+        diag!("[TIMER] (3) Chrom ID mapping:    {:.2?}", t_tidmap);
+        diag!("[TIMER] (4) Batch filter_map:    {:.2?}", t_batch.0);
+        diag!("[TIMER] (5) Interval tree query: {:.2?}", t_batch.1);
+        diag!("[TIMER] (6) Depth counting:      {:.2?}", t_batch.2);
+        if let Some(sw) = &sweeps {
+            let fallbacks: usize = sw.iter().map(|s| s.fallbacks).sum();
+            diag!("[INFO] Sorted sweep: {} region(s) fell back to the interval tree", fallbacks);
+        }
     }
 
-    Ok(global_id_counts)
+    Ok(())
 }
 
 /// Process BED input with mmap, parallel line parsing, and batch queries.
-/// 
-/// Returns: "feature ID -> (chrom, start, end, depth)".
-/// - depth   = number of regions overlapping the feature
+///
+/// Adds the regions to `tally` (one entry per annotation set); a feature's depth is the
+/// number of regions overlapping it.
+///
+/// Regions on sequences that no set knows are counted in `unknown`.
 pub fn process_bed(
     bed_path: &Path,
-    index_data: &TreeIndexData,
-    gof: GofMap,
-    gff_mmap: Mmap,
+    sets: &[AnnotationSet],
+    tally: &mut DepthTally,
     bin_shift: u32,
     threads: usize,
+    unknown: &mut UnknownContigs,
     verbose: bool,
-) -> Result<FxHashMap<String, (String, u32, u32, usize)>> {
-    let mut global_id_counts: FxHashMap<String, (String, u32, u32, usize)> = FxHashMap::default();
-
-    // mmap the entire BED file
-    let file = File::open(bed_path)?;
-    let mmap = unsafe { Mmap::map(&file)? };
-    let data = &mmap[..];
-
+) -> Result<()> {
+    // mmap the entire BED file (or stream it through the gzip decoder)
+    let mut reader = BedReader::open(bed_path)?;
     if verbose {
-        eprintln!("[INFO] mmap BED file: {} bytes", data.len());
+        diag!("[INFO] Reading BED file{}", if reader.is_gzip() { " (gzip)" } else { "" });
     }
 
-    // collect line offsets
-    let mut line_offsets = Vec::with_capacity(1_000_000);
-    line_offsets.push(0usize);
-    for (i, &b) in data.iter().enumerate() {
-        if b == b'\n' {
-            line_offsets.push(i + 1);
+    // Parse newline-aligned chunks in parallel and feed them in batches,
+    // so memory stays bounded regardless of the BED size
+    let mut n_regions = 0usize;
+    reader.for_each_chunk(BED_CHUNK_SIZE, |chunk| {
+        let parsed_chunk = parse_bed_chunk(chunk);
+        n_regions += parsed_chunk.len();
+        unknown.total += parsed_chunk.len() as u64;
+        for &(chrom, _, _) in &parsed_chunk {
+            if !sets.iter().any(|set| set.index_data.seqid_to_num.contains_key(chrom)) {
+                unknown.skip(chrom, 1);
+            }
         }
-    }
-    if *line_offsets.last().unwrap_or(&0) != data.len() {
-        line_offsets.push(data.len());
+        for parsed in parsed_chunk.chunks(BATCH_SIZE) {
+            // map to each annotation's seqid numbers and compute depth only
+            for (i, set) in sets.iter().enumerate() {
+                let regions: Vec<(u32, u32, u32, u32)> = parsed
+                    .iter()
+                    .filter_map(|&(chrom, s, e)| {
+                        set.index_data.seqid_to_num.get(chrom).map(|&chr_num| (chr_num, s, e))
+                    })
+                    .enumerate()
+                    .map(|(frag, (chr, s, e))| (chr, s, e, frag as u32))
+                    .collect();
+                if regions.is_empty() {
+                    continue;
+                }
+                let by_root =
+                    root_regions(&set.index_data, &regions, set.gof.index_cached(), set.root_filter.as_ref())?;
+                tally.add(i, set, by_root, bin_shift, threads);
+            }
+        }
+        Ok(())
+    })?;
+
+    if verbose {
+        diag!("[INFO] Parsed {} BED regions", n_regions);
     }
 
-    // process chunks in batches
-    for chunk in line_offsets.windows(2).collect::<Vec<_>>().chunks(BATCH_SIZE) {
-        // parse BED lines into (chr_id, start, end)
-        let regions: Vec<(u32, u32, u32)> = chunk
-            .par_iter()
-            .filter_map(|w| {
-                let start = w[0];
-                let end = w[1];
-                if start >= end {
-                    return None;
-                }
-                let line = &data[start..end];
-                if line.is_empty() || line[0] == b'#' {
-                    return None;
-                }
+    Ok(())
+}
+
+/// Process BEDPE input like `process_bed`; `mode` turns each pair into regions
+/// (`BedpeMode::regions`), and the ends of one pair count once per feature.
+///
+/// Pairs with no end on a known sequence are counted in `unknown`.
+#[allow(clippy::too_many_arguments)]
+pub fn process_bedpe(
+    bedpe_path: &Path,
+    sets: &[AnnotationSet],
+    tally: &mut DepthTally,
+    mode: BedpeMode,
+    bin_shift: u32,
+    threads: usize,
+    unknown: &mut UnknownContigs,
+    verbose: bool,
+) -> Result<()> {
+    let mut reader = BedReader::open(bedpe_path)?;
+    if verbose {
+        diag!("[INFO] Reading BEDPE file{}", if reader.is_gzip() { " (gzip)" } else { "" });
+    }
 
-                let fields: Vec<&[u8]> = line
-                    .split(|&b| b == b'\t' || b == b' ')
-                    .filter(|f| !f.is_empty())
+    let mut n_pairs = 0usize;
+    let mut skips = BedpeSkips::default();
+    reader.for_each_chunk(BED_CHUNK_SIZE, |chunk| {
+        let pairs = parse_bedpe_chunk(chunk);
+        n_pairs += pairs.len();
+        let fragments: Vec<[BedpeEnd; 2]> = pairs.into_iter().map(|p| mode.regions(p, &mut skips)).collect();
+        for ends in &fragments {
+            let Some(first) = ends.iter().flatten().next() else { continue };
+            unknown.total += 1;
+            let known = ends
+                .iter()
+                .flatten()
+                .any(|(chrom, _, _)| sets.iter().any(|set| set.index_data.seqid_to_num.contains_key(*chrom)));
+            if !known {
+                unknown.skip(first.0, 1);
+            }
+        }
+        for batch in fragments.chunks(BATCH_SIZE) {
+            for (i, set) in sets.iter().enumerate() {
+                let regions: Vec<(u32, u32, u32, u32)> = batch
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(frag, ends)| {
+                        ends.iter().flatten().filter_map(move |&(chrom, s, e)| {
+                            set.index_data.seqid_to_num.get(chrom).map(|&chr_num| (chr_num, s, e, frag as u32))
+                        })
+                    })
                     .collect();
-                if fields.len() < 3 {
-                    return None;
+                if regions.is_empty() {
+                    continue;
                 }
+                let by_root =
+                    root_regions(&set.index_data, &regions, set.gof.index_cached(), set.root_filter.as_ref())?;
+                tally.add(i, set, by_root, bin_shift, threads);
+            }
+        }
+        Ok(())
+    })?;
 
-                let chrom = std::str::from_utf8(fields[0]).ok()?;
-                let s = std::str::from_utf8(fields[1]).ok()?.parse::<u32>().ok()?;
-                let e = std::str::from_utf8(fields[2]).ok()?.trim_end().parse::<u32>().ok()?;
-                if s >= e {
-                    return None;
-                }
+    skips.report();
+    if verbose {
+        diag!("[INFO] Parsed {} BEDPE pairs", n_pairs);
+    }
 
-                let &chr_num = index_data.seqid_to_num.get(chrom)?;
-                Some((chr_num, s, e))
-            })
-            .collect();
+    Ok(())
+}
 
-        // compute depth only
-        let id_counts = compute_hit_depth(index_data, &regions, &gof, &gff_mmap, bin_shift, threads)?;
+/// One row of the depth table.
+struct DepthRow<'a> {
+    annotation: &'a str,
+    id: String,
+    chr: String,
+    start: u32,
+    end: u32,
+    depth: usize,
+    cols: ScorePhase,
+}
 
-        // merge into global results
-        for (id, (chrom, s, e, d)) in id_counts {
-            global_id_counts.entry(id).and_modify(|(c0, s0, e0, depth)| {
-                if s < *s0 { *s0 = s; }
-                if e > *e0 { *e0 = e; }
-                *depth += d;
-                let _ = c0; // chrom assumed consistent
-            }).or_insert((chrom, s, e, d));
-        }
+/// Depth table columns; `annotation` leads when `labelled` (several annotation sets).
+/// `score` and `phase` (of the ID's first line) are written only when `--columns` lists them.
+fn depth_columns<'a>(labelled: bool) -> Vec<Column<'a, DepthRow<'a>>> {
+    let mut cols = Vec::with_capacity(8);
+    if labelled {
+        cols.push(Column::new("annotation", |r: &DepthRow<'a>| Cell::Str(r.annotation)));
     }
-
-    Ok(global_id_counts)
+    cols.push(Column::new("id", |r: &DepthRow<'a>| Cell::Str(&r.id)));
+    cols.push(Column::new("chr", |r: &DepthRow<'a>| Cell::Str(&r.chr)));
+    cols.push(Column::start("start", |r: &DepthRow<'a>| r.start as u64));
+    cols.push(Column::new("end", |r: &DepthRow<'a>| Cell::UInt(r.end as u64)));
+    cols.push(Column::new("depth", |r: &DepthRow<'a>| Cell::UInt(r.depth as u64)));
+    cols.push(Column::optional("score", |r: &DepthRow<'a>| Cell::opt_num(r.cols.score)));
+    cols.push(Column::optional("phase", |r: &DepthRow<'a>| Cell::opt_num(r.cols.phase.map(f64::from))));
+    cols
 }
 
-/// Write "id\tchr\tstart\tend\tdepth" per line to output file.
-pub fn write_depth_results<W: Write>(
-    id_counts: FxHashMap<String, (String, u32, u32, usize)>,
+/// Write the depth table, by default "[annotation\t]id\tchr\tstart\tend\tdepth" per line;
+/// `table` selects columns and layout. The annotation column exists when `labelled`.
+/// Rows follow map order, or position order (then ID) under `--deterministic`.
+pub fn write_depth_results<'a, W: Write>(
+    results: impl IntoIterator<Item = (&'a str, DepthMap)>,
+    labelled: bool,
+    table: &TableArgs,
     mut out: W,
     verbose: bool,
 ) -> Result<()> {
-    use std::fmt::Write as FmtWrite;
-    let mut buf = String::with_capacity(WRITE_BUF_SIZE);
-    let mut written = 0usize;
+    let writer = TableWriter::new(depth_columns(labelled), table)?;
+    let sort = deterministic();
+    let rows = results.into_iter().flat_map(|(annotation, id_counts)| {
+        let mut entries: Vec<_> = id_counts.into_iter().collect();
+        if sort {
+            entries.sort_unstable_by(|a, b| (&a.1.0, a.1.1, a.1.2, &a.0).cmp(&(&b.1.0, b.1.1, b.1.2, &b.0)));
+        }
+        entries
+            .into_iter()
+            .map(move |(id, (chr, start, end, depth, cols))| DepthRow { annotation, id, chr, start, end, depth, cols })
+    });
+    let written = writer.write_all(rows, &mut out)?;
 
-    writeln!(buf, "id\tchr\tstart\tend\tdepth")?;
-    
-    for (id, (chr, start, end, depth)) in id_counts {
-        writeln!(buf, "{id}\t{chr}\t{start}\t{end}\t{depth}")?;
-        written += 1;
+    if verbose {
+        diag!("[INFO] Wrote {written} ID depth records");
+    }
+    Ok(())
+}
 
-        if buf.len() >= WRITE_BUF_SIZE {
-            out.write_all(buf.as_bytes())?;
-            buf.clear();
+/// Write one BED row "chr\tstart\tend\tname\tdepth" per feature, sorted by position.
+/// Names are "annotation:id" for labelled results (several annotation sets).
+pub fn write_depth_bed<'a, W: Write>(
+    results: impl IntoIterator<Item = (Option<&'a str>, DepthMap)>,
+    mut out: W,
+    verbose: bool,
+) -> Result<()> {
+    let mut rows: Vec<(String, u32, u32, String, usize)> = Vec::new();
+    for (annotation, id_counts) in results {
+        for (id, (chr, start, end, depth, _)) in id_counts {
+            let name = match annotation {
+                Some(a) => format!("{a}:{id}"),
+                None => id,
+            };
+            rows.push((chr, start, end, name, depth));
         }
     }
+    rows.sort_unstable();
 
-    if !buf.is_empty() {
-        out.write_all(buf.as_bytes())?;
+    for (chr, start, end, name, depth) in &rows {
+        writeln!(out, "{chr}\t{start}\t{end}\t{name}\t{depth}")?;
     }
     out.flush()?;
 
     if verbose {
-        eprintln!("[INFO] Wrote {written} ID depth records");
+        diag!("[INFO] Wrote {} BED depth records", rows.len());
     }
     Ok(())
 }
 
+/// One row of the rollup table.
+struct RollupRow<'a> {
+    annotation: &'a str,
+    id: String,
+    chr: String,
+    start: u32,
+    end: u32,
+    stats: (usize, usize, usize, f64, f64),
+}
+
+/// Rollup table columns; `annotation` leads when `labelled` (several annotation sets).
+fn rollup_columns<'a>(labelled: bool) -> Vec<Column<'a, RollupRow<'a>>> {
+    let mut cols = Vec::with_capacity(10);
+    if labelled {
+        cols.push(Column::new("annotation", |r: &RollupRow<'a>| Cell::Str(r.annotation)));
+    }
+    cols.push(Column::new("id", |r: &RollupRow<'a>| Cell::Str(&r.id)));
+    cols.push(Column::new("chr", |r: &RollupRow<'a>| Cell::Str(&r.chr)));
+    cols.push(Column::start("start", |r: &RollupRow<'a>| r.start as u64));
+    cols.push(Column::new("end", |r: &RollupRow<'a>| Cell::UInt(r.end as u64)));
+    cols.push(Column::new("n_children", |r: &RollupRow<'a>| Cell::UInt(r.stats.0 as u64)));
+    cols.push(Column::new("min", |r: &RollupRow<'a>| Cell::UInt(r.stats.1 as u64)));
+    cols.push(Column::new("max", |r: &RollupRow<'a>| Cell::UInt(r.stats.2 as u64)));
+    cols.push(Column::new("mean", |r: &RollupRow<'a>| Cell::Float(r.stats.3)));
+    cols.push(Column::new("median", |r: &RollupRow<'a>| Cell::Num(r.stats.4)));
+    cols
+}
+
+/// Write the rollup table, by default
+/// "[annotation\t]id\tchr\tstart\tend\tn_children\tmin\tmax\tmean\tmedian" per rollup
+/// group, sorted by position. The annotation column exists when `labelled`.
+pub fn write_rollup_results<'a, W: Write>(
+    results: impl IntoIterator<Item = (&'a str, RollupMap)>,
+    labelled: bool,
+    table: &TableArgs,
+    mut out: W,
+    verbose: bool,
+) -> Result<()> {
+    let writer = TableWriter::new(rollup_columns(labelled), table)?;
+    let rows = results.into_iter().flat_map(|(annotation, rollups)| {
+        let mut groups: Vec<_> = rollups.into_iter().collect();
+        groups.sort_unstable_by(|a, b| (&a.1.0, a.1.1, a.1.2, &a.0).cmp(&(&b.1.0, b.1.1, b.1.2, &b.0)));
+        groups.into_iter().map(move |(id, (chr, start, end, mut depths))| {
+            let stats = rollup_stats(&mut depths);
+            RollupRow { annotation, id, chr, start, end, stats }
+        })
+    });
+    let written = writer.write_all(rows, &mut out)?;
+
+    if verbose {
+        diag!("[INFO] Wrote {written} rollup records");
+    }
+    Ok(())
+}
+
+/// `--uncovered-only`: write the reported roots of each set that `touched` lacks, in
+/// file order, as IDs (`[annotation\t]id` per line), BED6 root extents or whole models.
+/// Returns the number of uncovered roots and of reported roots.
+fn write_uncovered(
+    sets: &[AnnotationSet],
+    touched: &[FxHashSet<u32>],
+    format: ReportFormat,
+    output: &Option<PathBuf>,
+    verbose: bool,
+) -> Result<(usize, usize)> {
+    let labelled = sets.len() > 1;
+    let mut out: Option<Box<dyn Write>> = None;
+    let (mut n_uncovered, mut n_reported) = (0, 0);
+    for (set, touched) in sets.iter().zip(touched) {
+        let reported: Vec<(u32, u64, u64)> = set
+            .gof
+            .entries
+            .iter()
+            .filter(|e| e.start_offset != MISSING && e.end_offset > e.start_offset)
+            .filter(|e| set.allows_root(e.feature_id))
+            .map(|e| (e.feature_id, e.start_offset, e.end_offset))
+            .collect();
+        let blocks: Vec<(u32, u64, u64)> =
+            reported.iter().copied().filter(|(root, _, _)| !touched.contains(root)).collect();
+        n_reported += reported.len();
+        n_uncovered += blocks.len();
+        if verbose {
+            diag!("[INFO] {}: {} of {} model(s) uncovered", set.name, blocks.len(), reported.len());
+        }
+
+        match format {
+            ReportFormat::Tsv => {
+                let fts = load_fts(&set.path)?;
+                let out = match &mut out {
+                    Some(w) => w,
+                    None => out.insert(match output {
+                        Some(p) => Box::new(BufWriter::new(CountingWriter(File::create(p)?))),
+                        None => Box::new(BufWriter::new(CountingWriter(std::io::stdout().lock()))),
+                    }),
+                };
+                for &(root, _, _) in &blocks {
+                    let id = fts.get_id(root).filter(|id| !is_anonymous_id(id)).unwrap_or(".");
+                    if labelled {
+                        write!(out, "{}\t", set.name)?;
+                    }
+                    writeln!(out, "{id}")?;
+                }
+            }
+            ReportFormat::Bed => {
+                let bed_path = output.as_deref().unwrap_or(Path::new("-"));
+                write_roots_bed(&set.path, &blocks, &FxHashMap::default(), bed_path, None, verbose)?;
+            }
+            ReportFormat::Gff => write_gff_output(&set.path, &blocks, output, verbose)?,
+        }
+    }
+    if let Some(mut out) = out {
+        out.flush()?;
+    }
+    Ok((n_uncovered, n_reported))
+}
+
 /// Main entry for depth pipeline
 pub fn run(args: &DepthArgs) -> Result<()> {
+    apply_index_prefix(&args.input, args.index_prefix.as_deref())?;
+    for gff in &args.input {
+        ensure_index(gff, args.auto_index, args.verbose)?;
+    }
     let verbose = args.verbose;
-    let bin_shift = args.bin_shift;
     let threads = if args.threads == 0 {
             std::thread::available_parallelism()
                 .map(|n| n.get())
@@ -556,80 +1326,443 @@ pub fn run(args: &DepthArgs) -> Result<()> {
             args.threads
         };
     let _ = rayon::ThreadPoolBuilder::new().num_threads(threads).build_global();
-    let gff_path = &args.input;
+    if args.format == ReportFormat::Gff && args.input.len() > 1 {
+        bail!("--format gff writes one annotated GFF and accepts a single -i input");
+    }
+    if args.rollup.is_some() && args.format == ReportFormat::Bed {
+        bail!("--rollup reports several statistics per model; use --format tsv or gff");
+    }
+    if args.table.is_set() && args.format != ReportFormat::Tsv {
+        bail!("--columns, --no-header, --table-format and --coords apply to --format tsv");
+    }
+    if args.uncovered_only && args.table.is_set() {
+        bail!("--uncovered-only writes a plain ID list; --columns, --no-header, --table-format and --coords do not apply");
+    }
+    if args.uncovered_only && args.format == ReportFormat::Bed && args.input.len() > 1 {
+        bail!("--uncovered-only --format bed writes the root extents of a single -i input");
+    }
     
-    // Step 1: load GFF index
+    // Step 1: load GOF, tree index and GFF mmap for each annotation
     let t0 = Instant::now();
-    let gof = load_gof(&gff_path)?;
-    let file = File::open(gff_path).with_context(|| format!("Cannot open GFF file: {:?}", gff_path))?;
-    let gff_mmap = unsafe { Mmap::map(&file) }.with_context(|| format!("GFF mmap failed for {:?}", gff_path))?;
-    let t_load_fts = t0.elapsed();
-    if verbose {
-        eprintln!("[TIMER] [run] Step 1: Loading index took {:.2?}", t_load_fts);
+    let mut sets: Vec<AnnotationSet> = args
+        .input
+        .iter()
+        .map(|p| AnnotationSet::load(p))
+        .collect::<Result<_>>()?;
+    if args.features.is_some() || args.feature_id.is_some() {
+        let feature_names = read_feature_names(args.features.as_deref(), args.feature_id.as_deref())?;
+        for set in sets.iter_mut() {
+            let n = set.restrict_to_features(&feature_names, threads)?;
+            if n == 0 {
+                diag!("[WARN] No requested feature resolved to a model in {}", set.name);
+            } else if verbose {
+                diag!("[INFO] {}: restricting output to {} model(s)", set.name, n);
+            }
+        }
     }
-
-    // Step 2: build interval index
-    let t1 = Instant::now();
-    let index_data = TreeIndexData::load_tree_index(&gff_path)?;
-    let t_build_index = t1.elapsed();
+    for set in sets.iter_mut() {
+        set.type_filter = args.types.clone();
+    }
+    let t_load_index = t0.elapsed();
     if verbose {
-        eprintln!("[TIMER] [run] Step 2: Building tree index took {:.2?}", t_build_index);
+        diag!(
+            "[TIMER] [run] Step 1: Loading {} annotation index(es) took {:.2?}",
+            sets.len(),
+            t_load_index
+        );
     }
 
-    // Step 3: process input file
+    // Step 2: process input file
     let t2 = Instant::now();
     let source_path = &args.source;
+    let bin_shift = match args.bin_shift {
+        BinShift::Fixed(k) => k,
+        // Nothing is binned when only touched roots are collected
+        BinShift::Auto if args.uncovered_only => DEFAULT_BIN_SHIFT,
+        BinShift::Auto => {
+            let mut read_lens = sample_source_lengths(source_path, args.bedpe_mode)?;
+            let mut root_spans = reported_root_spans(&sets)?;
+            let k = auto_bin_shift(&mut read_lens, &mut root_spans);
+            if verbose {
+                diag!(
+                    "[INFO] --bin-shift auto: k={} ({} bp bins; median length {} of {} sampled {}, median span {} of {} model(s))",
+                    k,
+                    1u64 << k,
+                    median(&mut read_lens).unwrap_or(0),
+                    read_lens.len(),
+                    match source_extension(source_path).as_deref() {
+                        Some("bam") | Some("sam") | Some("cram") => "read(s)",
+                        _ => "region(s)",
+                    },
+                    median(&mut root_spans).unwrap_or(0),
+                    root_spans.len()
+                );
+            }
+            k
+        }
+    };
 
-    let ext = source_path
-        .extension()
-        .and_then(|s| s.to_str())
-        .map(|s| s.to_lowercase());
-
-    let id_counts = match ext.as_deref() {
+    let mut unknown = UnknownContigs::default();
+    let mut tally = DepthTally::new(sets.len(), args.uncovered_only);
+    let what = match source_extension(source_path).as_deref() {
         Some("bam") | Some("sam") | Some("cram") => {
-            process_bam(source_path.as_path(), &index_data, gof, gff_mmap, bin_shift, threads, verbose)?
+            let sorted = args.assume_sorted;
+            process_bam(source_path.as_path(), &sets, &mut tally, bin_shift, threads, sorted, &mut unknown, verbose)?;
+            "read"
         }
         Some("bed") => {
-            process_bed(source_path.as_path(), &index_data, gof, gff_mmap, bin_shift, threads, verbose)?
+            process_bed(source_path.as_path(), &sets, &mut tally, bin_shift, threads, &mut unknown, verbose)?;
+            "BED region"
+        }
+        Some("bedpe") => {
+            let mode = args.bedpe_mode;
+            process_bedpe(source_path.as_path(), &sets, &mut tally, mode, bin_shift, threads, &mut unknown, verbose)?;
+            "BEDPE pair"
         }
         _ => {
             bail!(
-                "Unsupported file type: {:?}. Expected .bam/.sam/.cram or .bed",
+                "Unsupported file type: {:?}. Expected .bam/.sam/.cram, .bed/.bed.gz or .bedpe/.bedpe.gz",
                 source_path
             );
         }
     };
     let t_process_input = t2.elapsed();
     if verbose {
-        eprintln!("[TIMER] [run] Step 3: Processing input took {:.2?}", t_process_input);
+        diag!("[TIMER] [run] Step 2: Processing input took {:.2?}", t_process_input);
     }
+    unknown.report(what, args.strict_contigs)?;
+
+    let mut summary = args.input.iter().fold(RunSummary::new(), RunSummary::input).input(source_path);
+    let mut id_counts = match tally {
+        DepthTally::Depth(maps) => maps,
+        DepthTally::Touched(touched) => {
+            let (n, of) = write_uncovered(&sets, &touched, args.format, &args.output, verbose)?;
+            if verbose {
+                diag!("[TIMER] [run] Total pipeline time: {:.2?}", t0.elapsed());
+            }
+            set_summary(summary.count(n, &format!("of {} model(s) uncovered", of)).output(args.output.as_deref()));
+            return Ok(());
+        }
+    };
+    summary = summary.count(id_counts.iter().map(|m| m.len()).sum(), "feature(s)");
 
-    // Step 4: write results
+    // Step 3: write results
     let t3 = Instant::now();
     
-    let out: Box<dyn Write> = match &args.output {
+    let mut out: Box<dyn Write> = match &args.output {
         Some(path) => {
             let file = File::create(path)?;
-            Box::new(BufWriter::with_capacity(WRITE_BUF_SIZE, file))
+            Box::new(BufWriter::with_capacity(WRITE_BUF_SIZE, CountingWriter(file)))
         }
         None => {
             let stdout = std::io::stdout();
             let handle = stdout.lock();
-            Box::new(BufWriter::with_capacity(WRITE_BUF_SIZE, handle))
+            Box::new(BufWriter::with_capacity(WRITE_BUF_SIZE, CountingWriter(handle)))
         }
     };
     
-    write_depth_results(id_counts, out, verbose)?;
+    if args.rollup == Some(Rollup::Gene) {
+        let rollups: Vec<RollupMap> = sets
+            .iter()
+            .zip(&id_counts)
+            .map(|(set, depths)| rollup_depths(set, depths, threads))
+            .collect::<Result<_>>()?;
+        if args.format == ReportFormat::Gff {
+            let attrs: FxHashMap<String, String> = rollups
+                .into_iter()
+                .next()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(id, (_, _, _, mut depths))| {
+                    let (n, min, max, mean, median) = rollup_stats(&mut depths);
+                    let tag = format!(
+                        "depth_n_children={n};depth_min={min};depth_max={max};depth_mean={mean:.6};depth_median={median}"
+                    );
+                    (id, tag)
+                })
+                .collect();
+            let n = sets[0].write_annotated_gff(&attrs, &mut out, threads)?;
+            if verbose {
+                diag!("[INFO] Added depth rollups to {n} GFF lines");
+            }
+        } else {
+            let names = sets.iter().map(|s| s.name.as_str());
+            write_rollup_results(names.zip(rollups), sets.len() > 1, &args.table, out, verbose)?;
+        }
+    } else {
+        match args.format {
+            ReportFormat::Tsv => {
+                let names = sets.iter().map(|s| s.name.as_str());
+                write_depth_results(names.zip(id_counts), sets.len() > 1, &args.table, out, verbose)?;
+            }
+            ReportFormat::Bed => {
+                let labelled = sets.len() > 1;
+                let names = sets.iter().map(|s| labelled.then_some(s.name.as_str()));
+                write_depth_bed(names.zip(id_counts), out, verbose)?;
+            }
+            ReportFormat::Gff => {
+                let attrs: FxHashMap<String, String> = id_counts
+                    .pop()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(id, (_, _, _, depth, _))| (id, format!("depth={depth}")))
+                    .collect();
+                let n = sets[0].write_annotated_gff(&attrs, &mut out, threads)?;
+                if verbose {
+                    diag!("[INFO] Added depth to {n} GFF lines");
+                }
+            }
+        }
+    }
     
     let t_write_out = t3.elapsed();
     if verbose {
-        eprintln!("[TIMER] [run] Step 4: Writing results took {:.2?}", t_write_out);
+        diag!("[TIMER] [run] Step 3: Writing results took {:.2?}", t_write_out);
     }
 
     if verbose {
         let total = t0.elapsed();
-        eprintln!("[TIMER] [run] Total pipeline time: {:.2?}", total);
+        diag!("[TIMER] [run] Total pipeline time: {:.2?}", total);
     }
+    set_summary(summary.output(args.output.as_deref()));
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::extract::{self, ExtractArgs};
+    use crate::set_deterministic;
+    use crate::testutil::{BamSynth, GffSynth, RegionSynth, SAMPLE_GFF, SynthGenome, build_fixture};
+    use rust_htslib::bam::{
+        header::{Header, HeaderRecord},
+        record::{Cigar, CigarString},
+    };
+
+    /// A coordinate-sorted BAM over `targets` with one read `(tid, pos, len)` per entry.
+    fn write_bam(path: &Path, targets: &[(&str, u32)], reads: &[(i32, i64, u32)]) {
+        let mut header = Header::new();
+        header.push_record(HeaderRecord::new(b"HD").push_tag(b"VN", "1.6").push_tag(b"SO", "coordinate"));
+        for (name, len) in targets {
+            header.push_record(HeaderRecord::new(b"SQ").push_tag(b"SN", name).push_tag(b"LN", len));
+        }
+        let mut writer = bam::Writer::from_path(path, &header, bam::Format::Bam).unwrap();
+        let mut record = bam::Record::new();
+        for (i, &(tid, pos, len)) in reads.iter().enumerate() {
+            let cigar = CigarString(vec![Cigar::Match(len)]);
+            record.set(format!("r{i}").as_bytes(), Some(&cigar), &vec![b'A'; len as usize], &vec![30; len as usize]);
+            record.set_tid(tid);
+            record.set_pos(pos);
+            record.set_mtid(-1);
+            record.set_mpos(-1);
+            record.set_flags(0);
+            writer.write(&record).unwrap();
+        }
+    }
+
+    #[test]
+    fn several_annotations_share_one_pass_over_a_bam() {
+        let a = build_fixture(SAMPLE_GFF).unwrap();
+        let b = build_fixture(&SAMPLE_GFF.replace("chr2", "scaffold_2")).unwrap();
+        let bam_path = a.dir.join("reads.bam");
+        let reads = [(0, 150, 100), (0, 2100, 100), (0, 2500, 100), (1, 20, 10)];
+        write_bam(&bam_path, &[("chr1", 10000), ("chr2", 5000)], &reads);
+        let out = a.dir.join("depth.tsv");
+        let args = DepthArgs::try_parse_from([
+            "depth", "-i", a.gff.to_str().unwrap(), "-i", b.gff.to_str().unwrap(), "-s", bam_path.to_str().unwrap(),
+            "-o", out.to_str().unwrap(), "-t", "2",
+        ])
+        .unwrap();
+        run(&args).unwrap();
+
+        // Rows of each annotation come together, in `-i` order; both files are named
+        // fixture.gff, so tell them apart by position
+        let text = std::fs::read_to_string(&out).unwrap();
+        let mut lines = text.lines().filter(|l| !l.starts_with('#'));
+        let header: Vec<&str> = lines.next().unwrap().split('\t').collect();
+        assert_eq!(header[0], "annotation");
+        let col = |name: &str| header.iter().position(|h| *h == name).unwrap();
+        let (id, depth) = (col("id"), col("depth"));
+        let rows: Vec<Vec<&str>> = lines.map(|l| l.split('\t').collect()).collect();
+        let ids = |rows: &[Vec<&str>]| -> FxHashSet<String> { rows.iter().map(|r| r[id].to_string()).collect() };
+
+        // chr2 is scaffold_2 in the second annotation, so the chr2 read misses it
+        let on_chr1 = ["g1", "t1", "e1", "c1", "t2", "e3", "g2", "t3", "e4"];
+        let mut in_a: FxHashSet<String> = on_chr1.iter().map(|s| s.to_string()).collect();
+        let in_b = in_a.clone();
+        in_a.extend(["g3", "t4", "e5"].map(String::from));
+        assert_eq!(rows.len(), in_a.len() + in_b.len());
+        assert_eq!(ids(&rows[..in_a.len()]), in_a);
+        assert_eq!(ids(&rows[in_a.len()..]), in_b);
+        // Two reads fall on g2 in either annotation
+        let g2: Vec<&str> = rows.iter().filter(|r| r[id] == "g2").map(|r| r[depth]).collect();
+        assert_eq!(g2, ["2", "2"]);
+    }
+
+    /// Sorted data rows of a `depth` run over `gff` with the extra arguments `extra`.
+    fn depth_rows(gff: &Path, source: &Path, out: &Path, extra: &[&str]) -> Vec<String> {
+        let mut argv = vec!["depth", "-i", gff.to_str().unwrap(), "-s", source.to_str().unwrap()];
+        argv.extend_from_slice(&["-o", out.to_str().unwrap(), "-t", "2"]);
+        argv.extend_from_slice(extra);
+        run(&DepthArgs::try_parse_from(argv).unwrap()).unwrap();
+        let text = std::fs::read_to_string(out).unwrap();
+        let mut rows: Vec<String> = text.lines().filter(|l| !l.starts_with('#')).skip(1).map(String::from).collect();
+        rows.sort();
+        rows
+    }
+
+    #[test]
+    fn feature_list_matches_extract_then_depth() {
+        let fx = build_fixture(SAMPLE_GFF).unwrap();
+        let bed = fx.dir.join("reads.bed");
+        let reads = "chr1\t150\t250\nchr1\t600\t2100\nchr1\t2500\t2600\nchr2\t20\t30\nchr2\t300\t310\n";
+        std::fs::write(&bed, reads).unwrap();
+        let ids = fx.dir.join("ids.txt");
+        std::fs::write(&ids, "e3\ng3\nno_such_id\n").unwrap();
+        let out = fx.dir.join("depth.tsv");
+        let direct = depth_rows(&fx.gff, &bed, &out, &["-F", ids.to_str().unwrap()]);
+        assert!(!direct.is_empty());
+        assert!(direct.iter().all(|r| !r.starts_with("g2\t")));
+
+        // The two-step pipeline: extract the models, index them, compute depth
+        let models = fx.dir.join("models.gff");
+        let extract_args = ExtractArgs::try_parse_from([
+            "extract", "-i", fx.gff.to_str().unwrap(), "-F", ids.to_str().unwrap(), "-e", "-o",
+            models.to_str().unwrap(),
+        ])
+        .unwrap();
+        extract::run(&extract_args).unwrap();
+        let subset = build_fixture(&std::fs::read_to_string(&models).unwrap()).unwrap();
+        let two_step = depth_rows(&subset.gff, &bed, &out, &[]);
+        assert_eq!(direct, two_step);
+
+        let single = depth_rows(&fx.gff, &bed, &out, &["-f", "g3"]);
+        assert_eq!(single, direct.iter().filter(|r| r.contains("\tchr2\t")).cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn deterministic_tables_do_not_depend_on_threads() {
+        // Only fixes row order, which the other tests do not rely on
+        set_deterministic(true);
+        let (genome, fx) = GffSynth::new().chromosomes(3).genes_per_chrom(30).seed(11).fixture().unwrap();
+        let reads = fx.dir.join("reads.bed");
+        RegionSynth::new(&genome).count(2000).seed(3).write(&reads).unwrap();
+        let depth = |threads: &str| {
+            let out = fx.dir.join(format!("depth-{threads}.tsv"));
+            let argv = [
+                "depth", "-i", fx.gff.to_str().unwrap(), "-s", reads.to_str().unwrap(), "-o", out.to_str().unwrap(),
+                "-t", threads,
+            ];
+            run(&DepthArgs::try_parse_from(argv).unwrap()).unwrap();
+            std::fs::read_to_string(out).unwrap()
+        };
+        let serial = depth("1");
+        assert_eq!(serial, depth("4"));
+
+        let mut lines = serial.lines().filter(|l| !l.starts_with('#'));
+        let header: Vec<&str> = lines.next().unwrap().split('\t').collect();
+        let col = |name: &str| header.iter().position(|h| *h == name).unwrap();
+        let (id, chr, start, end) = (col("id"), col("chr"), col("start"), col("end"));
+        let keys: Vec<(String, u64, u64, String)> = lines
+            .map(|l| {
+                let r: Vec<&str> = l.split('\t').collect();
+                (r[chr].to_string(), r[start].parse().unwrap(), r[end].parse().unwrap(), r[id].to_string())
+            })
+            .collect();
+        assert!(keys.len() > 100);
+        assert!(keys.is_sorted());
+    }
+
+    /// Stream `bam` against the indexed `gff` as `run` does.
+    fn tally_bam(gff: &Path, bam: &Path, assume_sorted: bool) -> (DepthMap, UnknownContigs) {
+        let sets = vec![AnnotationSet::load(gff).unwrap()];
+        let mut tally = DepthTally::new(1, false);
+        let mut unknown = UnknownContigs::default();
+        process_bam(bam, &sets, &mut tally, DEFAULT_BIN_SHIFT, 2, assume_sorted, &mut unknown, false).unwrap();
+        match tally {
+            DepthTally::Depth(mut maps) => (maps.pop().unwrap(), unknown),
+            DepthTally::Touched(_) => unreachable!(),
+        }
+    }
+
+    /// `genome` plus a sequence `chrU` carrying a copy of its first gene.
+    fn with_unknown_contig(genome: &SynthGenome) -> SynthGenome {
+        let mut out = genome.clone();
+        let mut gene = genome.genes[0].clone();
+        let len = gene.end - gene.start + 1;
+        (gene.chrom, gene.id, gene.start, gene.end) = (out.chromosomes.len(), "geneU".to_string(), 1_001, 1_000 + len);
+        out.chromosomes.push(("chrU".to_string(), len + 2_000));
+        out.genes.push(gene);
+        out
+    }
+
+    #[test]
+    fn reads_on_unknown_contigs_are_counted_and_skipped() {
+        let (genome, fx) = GffSynth::new().chromosomes(2).genes_per_chrom(20).seed(5).fixture().unwrap();
+        let (dir, gff) = (&fx.dir, &fx.gff);
+        let known_bam = dir.join("known.bam");
+        let n_known = BamSynth::new(&genome).depth(4.0).seed(6).write(&known_bam).unwrap();
+
+        // Same reads on chr1/chr2 (the extra gene is drawn last), plus reads on chrU
+        let extra = with_unknown_contig(&genome);
+        let bam = dir.join("r.bam");
+        let synth = BamSynth::new(&extra).depth(4.0).seed(6);
+        let reads = synth.build().unwrap();
+        let n_unknown = reads.iter().filter(|r| r.0 == 2).count() as u64;
+        assert!(n_unknown > 0);
+        assert_eq!(reads.len() as u64, n_known as u64 + n_unknown);
+        synth.write(&bam).unwrap();
+
+        let (depths, unknown) = tally_bam(gff, &bam, false);
+        assert_eq!(unknown.total, reads.len() as u64);
+        assert_eq!(unknown.n_skipped(), n_unknown);
+        assert_eq!(unknown.fraction(), n_unknown as f64 / reads.len() as f64);
+        // Warned about, then --strict-contigs fails only past its fraction
+        assert!(unknown.report("read", None).is_ok());
+        assert!(unknown.report("read", Some(0.0)).is_err());
+        assert!(unknown.report("read", Some(unknown.fraction())).is_ok());
+
+        // The reads on known sequences are counted as without chrU
+        let (want, none) = tally_bam(gff, &known_bam, false);
+        assert_eq!((none.total, none.n_skipped()), (n_known as u64, 0));
+        assert!(!want.is_empty());
+        assert_eq!(depths, want);
+    }
+
+    #[test]
+    fn uncovered_only_lists_the_genes_without_reads() {
+        let (genome, fx) = GffSynth::new().chromosomes(2).genes_per_chrom(15).seed(8).fixture().unwrap();
+        let (dir, gff) = (&fx.dir, &fx.gff);
+        // Reads stay within their gene and genes never overlap, so the genes left out
+        // of the read layout get none
+        let mut covered = genome.clone();
+        covered.genes = genome.genes.iter().enumerate().filter(|(i, _)| i % 3 != 1).map(|(_, g)| g.clone()).collect();
+        let bam = dir.join("r.bam");
+        BamSynth::new(&covered).depth(2.0).seed(9).write(&bam).unwrap();
+        let want: Vec<&str> = genome.genes.iter().skip(1).step_by(3).map(|g| g.id.as_str()).collect();
+
+        let out = dir.join("uncovered.txt");
+        let argv = ["depth", "-i", gff.to_str().unwrap(), "-s", bam.to_str().unwrap(), "--uncovered-only"];
+        let args = DepthArgs::try_parse_from(argv.into_iter().chain(["-t", "2", "-o", out.to_str().unwrap()])).unwrap();
+        run(&args).unwrap();
+        let got = std::fs::read_to_string(&out).unwrap();
+        assert_eq!(got.lines().collect::<Vec<_>>(), want);
+    }
+
+    #[test]
+    fn sorted_sweep_counts_like_the_interval_tree() {
+        let synth = GffSynth::new().chromosomes(3).genes_per_chrom(25).gap(0..800).seed(12);
+        let (genome, fx) = synth.fixture().unwrap();
+        let (dir, gff) = (&fx.dir, &fx.gff);
+        // Coordinate-sorted, with an @HD SO:coordinate header
+        let bam = dir.join("sorted.bam");
+        let n = BamSynth::new(&genome).depth(6.0).read_len(150).seed(13).write(&bam).unwrap();
+
+        let (swept, unknown) = tally_bam(gff, &bam, true);
+        assert_eq!((unknown.total, unknown.n_skipped()), (n as u64, 0));
+        let (queried, _) = tally_bam(gff, &bam, false);
+        assert!(swept.len() > genome.genes.len());
+        assert_eq!(swept, queried);
+    }
+}
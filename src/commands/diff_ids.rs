@@ -0,0 +1,272 @@
+use anyhow::Result;
+use clap::Parser;
+use memchr::memchr;
+use rayon::prelude::*;
+use std::{
+    cmp::Ordering,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    str,
+    time::Instant,
+};
+use crate::{
+    AtnTable, ByteSource, CountingWriter, FtsMap, GofMap, PrtMap, RunSummary, apply_index_prefix, check_gff_present,
+    check_index_matches, is_anonymous_id, load_a2f, load_atn, load_fts, load_gof, load_prt, set_summary,
+};
+
+/// Arguments
+#[derive(Parser, Debug)]
+#[command(
+    about = "Report feature ID collisions between two indexed GFF files",
+    long_about = "Compares the feature IDs of two indexed GFF files: IDs present in both, IDs unique \
+                  to each, and whether the root extents of shared IDs differ."
+)]
+pub struct DiffIdsArgs {
+    /// First GFF file (indexed)
+    #[arg(short = 'a', long = "input-a", value_name = "FILE")]
+    pub input_a: PathBuf,
+
+    /// Second GFF file (indexed)
+    #[arg(short = 'b', long = "input-b", value_name = "FILE")]
+    pub input_b: PathBuf,
+
+    /// Output file (stdout if not provided)
+    #[arg(short = 'o', long = "output", value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    /// Also compare the indexed attribute value (.atn/.a2f) of shared IDs
+    #[arg(long = "attributes", default_value_t = false)]
+    pub attributes: bool,
+
+    /// Read the index files of both GFFs from directory PREFIX (see `index
+    /// --index-prefix`)
+    #[arg(long = "index-prefix", value_name = "PREFIX")]
+    pub index_prefix: Option<PathBuf>,
+
+    /// Verbose logs
+    #[arg(short = 'v', long = "verbose", default_value_t = false, value_name = "BOOL")]
+    pub verbose: bool,
+}
+
+/// One side of the comparison: its ID table sorted for a merge walk.
+struct IdSide {
+    fts: FtsMap,
+    prt: PrtMap,
+    gof: GofMap,
+    gff: ByteSource,
+    /// Per-fid AID of the indexed attribute and its value table, with `--attributes`
+    attrs: Option<(Vec<Option<u32>>, AtnTable)>,
+    /// Fids ordered by ID string
+    order: Vec<u32>,
+}
+
+impl IdSide {
+    fn load(gff_path: &Path, with_attrs: bool) -> Result<Self> {
+        let fts = load_fts(gff_path)?;
+        let prt = load_prt(gff_path)?;
+        let gof = load_gof(gff_path)?;
+        check_gff_present(gff_path)?;
+        check_index_matches(gff_path)?;
+        let gff = ByteSource::open_gff(gff_path)?;
+        let attrs = if with_attrs {
+            let a2f = load_a2f(gff_path)?;
+            let values = load_atn(gff_path)?;
+            let per_fid = (0..fts.ids.len() as u32).map(|f| a2f.aid_for_fid(f)).collect();
+            Some((per_fid, values))
+        } else {
+            None
+        };
+
+        // Sort fids instead of hashing strings: 4 bytes per ID on top of the table;
+        // features without an ID cannot be matched across files and are left out
+        let mut order: Vec<u32> =
+            (0..fts.ids.len() as u32).filter(|&f| !is_anonymous_id(&fts.ids[f as usize])).collect();
+        order.par_sort_unstable_by(|&x, &y| fts.ids[x as usize].cmp(&fts.ids[y as usize]));
+
+        Ok(Self { fts, prt, gof, gff, attrs, order })
+    }
+
+    #[inline]
+    fn id(&self, fid: u32) -> &str {
+        &self.fts.ids[fid as usize]
+    }
+
+    /// (seqid, start, end) of the first feature line of the block holding `fid`'s root.
+    fn root_extent(&self, fid: u32) -> Option<(&str, &str, &str)> {
+        let root = self.prt.root_of(fid)?;
+        let &(s, e) = self.gof.get(root)?;
+        let block = self.gff.get(s as usize..e as usize)?;
+        let mut pos = 0usize;
+        while pos < block.len() {
+            let nl = memchr(b'\n', &block[pos..]).map(|i| pos + i).unwrap_or(block.len());
+            let line = &block[pos..nl];
+            pos = nl + 1;
+            if line.is_empty() || line[0] == b'#' {
+                continue;
+            }
+            let mut cols = str::from_utf8(line).ok()?.split('\t');
+            let seqid = cols.next()?;
+            let start = cols.nth(2)?;
+            let end = cols.next()?;
+            return Some((seqid, start, end));
+        }
+        None
+    }
+
+    fn attr(&self, fid: u32) -> Option<&str> {
+        let (per_fid, values) = self.attrs.as_ref()?;
+        values.get(per_fid.get(fid as usize).copied().flatten()?)
+    }
+}
+
+/// One step of the merge walk over both sorted ID tables.
+enum IdPair {
+    OnlyA(u32),
+    OnlyB(u32),
+    Both(u32, u32),
+}
+
+/// Walk both sorted ID tables in lockstep. Repeated IDs within one file are
+/// reported once (by their first fid in sort order).
+fn merge_ids(a: &IdSide, b: &IdSide, mut f: impl FnMut(IdPair) -> Result<()>) -> Result<()> {
+    let (oa, ob) = (&a.order, &b.order);
+    let (mut i, mut j) = (0usize, 0usize);
+    // Skip the remaining fids of the current ID run
+    let skip_run = |side: &IdSide, order: &[u32], mut k: usize| {
+        let id = side.id(order[k]);
+        while k < order.len() && side.id(order[k]) == id {
+            k += 1;
+        }
+        k
+    };
+    while i < oa.len() || j < ob.len() {
+        let ord = match (oa.get(i), ob.get(j)) {
+            (Some(&x), Some(&y)) => a.id(x).cmp(b.id(y)),
+            (Some(_), None) => Ordering::Less,
+            _ => Ordering::Greater,
+        };
+        match ord {
+            Ordering::Less => {
+                f(IdPair::OnlyA(oa[i]))?;
+                i = skip_run(a, oa, i);
+            }
+            Ordering::Greater => {
+                f(IdPair::OnlyB(ob[j]))?;
+                j = skip_run(b, ob, j);
+            }
+            Ordering::Equal => {
+                f(IdPair::Both(oa[i], ob[j]))?;
+                i = skip_run(a, oa, i);
+                j = skip_run(b, ob, j);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct DiffCounts {
+    shared: usize,
+    only_a: usize,
+    only_b: usize,
+    extent_differs: usize,
+    attr_differs: usize,
+}
+
+fn fmt_extent(ext: Option<(&str, &str, &str)>) -> String {
+    match ext {
+        Some((seqid, s, e)) => format!("{seqid}:{s}-{e}"),
+        None => ".".to_string(),
+    }
+}
+
+pub fn run(args: &DiffIdsArgs) -> Result<()> {
+    let t0 = Instant::now();
+    apply_index_prefix(&[&args.input_a, &args.input_b], args.index_prefix.as_deref())?;
+    let a = IdSide::load(&args.input_a, args.attributes)?;
+    let b = IdSide::load(&args.input_b, args.attributes)?;
+    if args.verbose {
+        diag!(
+            "[TIMER] Loaded and sorted {} + {} IDs: {:.2?}",
+            a.order.len(),
+            b.order.len(),
+            t0.elapsed()
+        );
+    }
+
+    // Pass 1: counts for the summary header
+    let mut counts = DiffCounts::default();
+    merge_ids(&a, &b, |pair| {
+        match pair {
+            IdPair::OnlyA(_) => counts.only_a += 1,
+            IdPair::OnlyB(_) => counts.only_b += 1,
+            IdPair::Both(fa, fb) => {
+                counts.shared += 1;
+                if a.root_extent(fa) != b.root_extent(fb) {
+                    counts.extent_differs += 1;
+                }
+                if args.attributes && a.attr(fa) != b.attr(fb) {
+                    counts.attr_differs += 1;
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(CountingWriter(File::create(path)?))),
+        None => Box::new(BufWriter::new(CountingWriter(std::io::stdout().lock()))),
+    };
+
+    writeln!(out, "#a={}", args.input_a.display())?;
+    writeln!(out, "#b={}", args.input_b.display())?;
+    write!(
+        out,
+        "#shared={}\tonly_a={}\tonly_b={}\textent_differs={}",
+        counts.shared, counts.only_a, counts.only_b, counts.extent_differs
+    )?;
+    if args.attributes {
+        write!(out, "\tattr_differs={}", counts.attr_differs)?;
+    }
+    writeln!(out)?;
+    write!(out, "id\tstatus\ta_root_extent\tb_root_extent")?;
+    if args.attributes {
+        write!(out, "\ta_attr\tb_attr")?;
+    }
+    writeln!(out)?;
+
+    // Pass 2: stream one row per distinct ID
+    merge_ids(&a, &b, |pair| {
+        let (id, status, ea, eb, aa, ab) = match pair {
+            IdPair::OnlyA(fa) => (a.id(fa), "only_a", a.root_extent(fa), None, a.attr(fa), None),
+            IdPair::OnlyB(fb) => (b.id(fb), "only_b", None, b.root_extent(fb), None, b.attr(fb)),
+            IdPair::Both(fa, fb) => {
+                let (ea, eb) = (a.root_extent(fa), b.root_extent(fb));
+                let status = if ea == eb { "same_extent" } else { "extent_differs" };
+                (a.id(fa), status, ea, eb, a.attr(fa), b.attr(fb))
+            }
+        };
+        write!(out, "{id}\t{status}\t{}\t{}", fmt_extent(ea), fmt_extent(eb))?;
+        if args.attributes {
+            write!(out, "\t{}\t{}", aa.unwrap_or("."), ab.unwrap_or("."))?;
+        }
+        writeln!(out)?;
+        Ok(())
+    })?;
+    out.flush()?;
+
+    if args.verbose {
+        diag!("[TIMER] Total elapsed: {:.2?}", t0.elapsed());
+    }
+    set_summary(
+        RunSummary::new()
+            .input(&args.input_a)
+            .input(&args.input_b)
+            .count(counts.shared, "shared ID(s)")
+            .count(counts.only_a, "only in A")
+            .count(counts.only_b, "only in B")
+            .output(args.output.as_deref()),
+    );
+    Ok(())
+}
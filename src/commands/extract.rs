@@ -1,13 +1,17 @@
 use crate::{
-    CommonArgs,  load_fts, load_gof, load_prt, write_gff_output, write_gff_output_filtered
+    ByteSource, CommonArgs, DryRunPlan, FTS_SCAN_MAX, LengthFilter, LineSelection, RunSummary, SeqLengths,
+    TranscriptMetric, TreeIndexData, command_line_key, context_roots, drop_context_roots, ensure_index,
+    expand_to_descendants, index_path, load_fts, load_gof, load_id_key, load_parent_key, load_prt, load_sqs, pad_roots,
+    read_feature_names, resolve_feature_roots, resolve_feature_roots_scan, scan_gof, set_summary, write_context_blocks,
+    write_gff_output, write_gff_output_filtered, write_gff_output_resumable, write_gff_output_typed,
+    write_gff_longest_transcripts, write_gff_roots_only, write_gff_with_derived_utrs, write_padded_blocks,
+    write_roots_bed,
 };
-use anyhow::{Context, Result, bail};
+use anyhow::{Result, bail};
 use clap::Parser;
 use rustc_hash::{FxHashMap, FxHashSet};
 use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-    path::PathBuf,
+    path::{Path, PathBuf},
     time::Instant,
 };
 
@@ -21,7 +25,7 @@ use std::{
 #[clap(group(
     clap::ArgGroup::new("feature")
         .required(true)
-        .args(&["feature_file", "feature_id"])
+        .args(&["feature_file", "feature_id", "seqid"])
 ))]
 pub struct ExtractArgs {
     #[clap(flatten)]
@@ -32,118 +36,274 @@ pub struct ExtractArgs {
 
     #[arg(short = 'F', long, group = "feature")]
     pub feature_file: Option<PathBuf>,
+
+    /// Emit each matched feature with all its descendants (e.g. an mRNA with its
+    /// exons, CDS and UTRs), but not its siblings or ancestors; for a root feature this
+    /// is the whole model. Lines are still filtered by -T and the length bounds
+    #[arg(
+        short = 'd',
+        long = "descendants",
+        default_value_t = false,
+        conflicts_with_all = ["entire_group", "roots_only", "roots_bed", "seqid"]
+    )]
+    pub descendants: bool,
+
+    /// Export every model on this sequence (repeatable), with the GFF header lines;
+    /// whole blocks are copied without per-line work unless -T/--types is given
+    #[arg(
+        long = "seqid",
+        value_name = "SEQID",
+        group = "feature",
+        conflicts_with_all = ["context", "pad_models", "provenance"]
+    )]
+    pub seqid: Vec<String>,
+
+    /// Add five_prime_UTR/three_prime_UTR lines computed as exonic minus CDS for
+    /// transcripts with exon and CDS children (and no UTR lines of their own)
+    #[arg(
+        long = "derive-utrs",
+        default_value_t = false,
+        conflicts_with_all = ["roots_only", "provenance", "roots_bed"]
+    )]
+    pub derive_utrs: bool,
+
+    /// Reduce each model to its longest transcript (mRNA/transcript children of the root,
+    /// or children with exons) by total CDS length, exonic length (default) or span;
+    /// ties go to the smaller ID. Emits the root line, the chosen transcript and its
+    /// descendants. Requires -e/--entire-group unless --seqid is given
+    #[arg(
+        long = "longest-transcript",
+        value_name = "METRIC",
+        value_enum,
+        num_args = 0..=1,
+        default_missing_value = "exonic",
+        conflicts_with_all = ["derive_utrs", "roots_only", "checkpoint", "roots_bed"]
+    )]
+    pub longest_transcript: Option<TranscriptMetric>,
+
+    /// Also emit the N models before and after each matched model on the same
+    /// sequence (whole models, appended after the matches under `# context-for: <ID>`)
+    #[arg(long = "context", value_name = "N", conflicts_with = "roots_bed")]
+    pub context: Option<usize>,
+
+    /// Also emit the other models lying within BP bases of a matched model on the same
+    /// sequence (whole models, appended after the matches under `# padded-for: <ID>`;
+    /// models already written by --context are not repeated); requires -e/--entire-group
+    #[arg(long = "pad-models", value_name = "BP", requires = "entire_group")]
+    pub pad_models: Option<u32>,
+
+    /// Write the output in synced chunks and record progress in FILE; rerunning the
+    /// same command after a crash resumes after the last recorded chunk (requires -o)
+    #[arg(
+        long = "checkpoint",
+        value_name = "FILE",
+        requires = "output",
+        conflicts_with_all = ["context", "pad_models", "roots_only", "derive_utrs", "dry_run"]
+    )]
+    pub checkpoint: Option<PathBuf>,
 }
 
 pub fn run(args: &ExtractArgs) -> Result<()> {
     let gff_path = &args.common.input;
 
+    // Validate argument combinations and size the thread pool (-t)
+    args.common.post_parse()?;
+    ensure_index(gff_path, args.common.auto_index, args.common.verbose)?;
+
+    if !args.seqid.is_empty() {
+        return run_seqids(args);
+    }
+    if args.longest_transcript.is_some() && !args.common.entire_group {
+        bail!("--longest-transcript selects within whole models; add -e/--entire-group");
+    }
+    if args.checkpoint.is_some() && args.common.entire_group && args.common.types.is_some() {
+        bail!("--checkpoint writes -e models whole; drop -T/--types or --checkpoint");
+    }
+
     // Start overall timer
     let overall_start = Instant::now();
     let verbose = args.common.verbose;
     if verbose {
-        eprintln!("[DEBUG] Starting processing of {:?}", gff_path);
-        eprintln!(
+        diag!("[DEBUG] Starting processing of {:?}", gff_path);
+        diag!(
             "[DEBUG] Thread pool initialized with {} threads",
             args.common.effective_threads()
         );
     }
 
+    // Read feature string IDs （feature name）
+    let feature_names = read_feature_names(
+        args.feature_file.as_deref(),
+        args.feature_id.as_deref(),
+    )?;
+
+    // A few IDs are looked up by scanning .fts/.gof; building the full ID map and GOF
+    // index costs seconds on large annotations. --context and --pad-models need both anyway
+    let scan = feature_names.len() <= FTS_SCAN_MAX && args.context.is_none() && args.pad_models.is_none();
+
     // Load features
-    let fts = load_fts(gff_path)?;
+    let fts = if scan { None } else { Some(load_fts(gff_path)?) };
 
     // Load parent relations
     let prt = load_prt(gff_path)?;
 
     // Load GFF offsets
-    let gof = load_gof(gff_path)?;
+    let gof = if scan { None } else { Some(load_gof(gff_path)?) };
 
-    // Read feature string IDs （feature name）
-    let feature_names: FxHashSet<String> = if let Some(ref file_path) = args.feature_file {
-        let file = File::open(file_path)
-            .with_context(|| format!("Cannot open feature list: {:?}", file_path))?;
-        let reader = BufReader::new(file);
-        reader.lines().try_fold(
-            FxHashSet::default(),
-            |mut set, line| -> Result<FxHashSet<String>, std::io::Error> {
-                let s = line?;
-                let s = s.trim();
-                if !s.is_empty() {
-                    set.insert(s.to_owned());
-                }
-                Ok(set)
-            },
-        )?
-    } else if let Some(ref single_id) = args.feature_id {
-        [single_id.clone()].into_iter().collect()
-    } else {
-        bail!("Either --feature-id (-f) or --feature-file (-F) must be specified");
+    // Phase A: map feature names to numeric fids, then fids to roots
+    let threads = args.common.effective_threads();
+    let (fid_vec, roots_vec, fid_ids) = match &fts {
+        Some(fts) => {
+            let (fid_vec, roots_vec) = resolve_feature_roots(fts, &prt, &feature_names, threads);
+            let ids = fid_vec.iter().filter_map(|&f| fts.get_id(f).map(|id| (f, id.to_string()))).collect();
+            (fid_vec, roots_vec, ids)
+        }
+        None => resolve_feature_roots_scan(gff_path, &prt, &feature_names, threads)?,
     };
-
-    // Phase A: group matches by root
-    // Phase A: map feature names to numeric fids
-    let (fids_set, missing) = fts.map_fnames_to_fids(
-        &feature_names,
-        args.common.effective_threads()
-    );
-    if !missing.is_empty() {
-        eprintln!("[WARN] {} feature IDs not found: {:?}", missing.len(), missing);
+    if verbose && scan {
+        diag!("[DEBUG] Resolved {} feature ID(s) by scanning .fts/.gof", feature_names.len());
     }
 
-    // Convert set to vec for alignment with roots
-    let fid_vec: Vec<u32> = fids_set.iter().copied().collect();
+    // (fid, root) of every match; a feature whose parents lie in several models is listed
+    // under each of them
+    let fid_roots = prt.fid_root_pairs(&fid_vec, &roots_vec);
 
-    // Use PrtMap fast resolver to map fid -> root (u32::MAX = invalid)
-    let threads = args.common.effective_threads();
-    let roots_vec: Vec<u32> = prt.map_fids_to_roots(&fid_vec, threads);
-
-    // Collect invalid fids (print once) and exclude invalid roots
-    let mut invalid_fids: Vec<u32> = fid_vec.iter()
-        .zip(roots_vec.iter())
-        .filter_map(|(&fid, &r)| if r == u32::MAX { Some(fid) } else { None })
-        .collect();
-    invalid_fids.sort_unstable();
-    invalid_fids.dedup();
-    if !invalid_fids.is_empty() {
-        eprintln!(
-            "[WARN] {} numeric feature IDs are invalid (out-of-range child or parent), skipped: {:?}",
-            invalid_fids.len(), invalid_fids
-        );
-    }
-
-    // Deduplicate valid roots (exclude u32::MAX)
-    let mut roots: Vec<u32> = roots_vec.iter().copied().filter(|&r| r != u32::MAX).collect();
+    // Deduplicate valid roots
+    let mut roots: Vec<u32> = fid_roots.iter().map(|&(_, r)| r).collect();
     roots.sort_unstable();
     roots.dedup();
 
     // Phase B: roots -> block offsets
-    let blocks: Vec<(u32, u64, u64)> = gof.roots_to_offsets(&roots, args.common.effective_threads());
+    let (mut blocks, missing_roots) = match &gof {
+        Some(gof) => gof.roots_to_offsets(&roots, threads),
+        None => {
+            let bytes = ByteSource::open_artifact(gff_path, ".gof")?;
+            scan_gof(&bytes, &roots).map_err(|e| e.for_index_file(&index_path(gff_path, ".gof")))?
+        }
+    };
+    if !missing_roots.is_empty() {
+        diag!(
+            "[WARN] {} of {} roots not found in the GOF index; their blocks are skipped.",
+            missing_roots.len(),
+            roots.len()
+        );
+        if verbose {
+            diag!("[DEBUG] Missing roots: {:?}", missing_roots);
+        }
+    }
+
+    // Whole-block modes apply the length bounds to the root extent
+    if !args.common.feature_only() {
+        blocks = args.common.length_filter().filter_blocks(gff_path, blocks)?;
+    }
+
+    let matched_terms = roots_vec.iter().filter(|&&r| r != u32::MAX).count();
+    let summary = RunSummary::new()
+        .input(gff_path)
+        .matched(matched_terms, feature_names.len(), "feature ID(s)")
+        .count(roots.len(), "model(s)");
+
+    if args.common.dry_run {
+        set_summary(summary);
+        let plan = DryRunPlan {
+            term_kind: "feature ID(s)",
+            terms: feature_names.len(),
+            matched_terms,
+            roots: roots.len(),
+        };
+        return plan.report(gff_path, &blocks, args.common.types.as_ref());
+    }
+
+    if let Some(bed) = &args.common.roots_bed {
+        // Score: the requested IDs resolved into each model
+        let mut counts: FxHashMap<u32, usize> = FxHashMap::default();
+        for &(_, root) in &fid_roots {
+            *counts.entry(root).or_default() += 1;
+        }
+        let rows = write_roots_bed(gff_path, &blocks, &counts, bed, args.common.types.as_ref(), verbose)?;
+        set_summary(summary.count(rows, "BED row(s)").output(Some(bed.as_path()).filter(|p| *p != Path::new("-"))));
+        return Ok(());
+    }
+
+    if args.common.roots_only {
+        write_gff_roots_only(
+            gff_path,
+            &blocks,
+            &args.common.output,
+            args.common.types.as_ref(),
+            verbose,
+        )?;
+    } else if !args.common.entire_group {
+        // Matched lines are found by their ID under the key the index was built with
+        let id_key = load_id_key(gff_path)?;
+        let parent_key = load_parent_key(gff_path)?;
 
-    if !args.common.entire_group || args.common.types.is_some() {
         // Build per_root_matches: root_id -> set of STRING feature IDs
         let mut per_root_matches: FxHashMap<u32, FxHashSet<String>> = FxHashMap::default();
         per_root_matches.reserve(roots.len());
         
-        // roots_vec[i] is the root, fid_vec[i] is the numeric fid
-        for (i, &root) in roots_vec.iter().enumerate() {
-            if root == u32::MAX {
-                continue;
-            }
-            // Convert numeric fid -> string ID only once here
-            if let Some(id_str) = fts.ids.get(fid_vec[i] as usize) {
+        for &(fid, root) in &fid_roots {
+            if let Some(id_str) = fid_ids.get(&fid) {
                 per_root_matches.entry(root).or_default().insert(id_str.clone());
             }
         }
-        
-        // Emit only exactly matched lines within blocks
-        write_gff_output_filtered(
+        // -d: the subtree lines carry a selected ID, or a selected parent when ID-less
+        let attr_keys: &[&str] = if args.descendants {
+            expand_to_descendants(gff_path, &blocks, &mut per_root_matches, &id_key, &parent_key)?;
+            &[id_key.as_str(), parent_key.as_str()]
+        } else {
+            &[id_key.as_str()]
+        };
+        let selection = LineSelection {
+            per_root_matches: &per_root_matches,
+            attr_keys,
+            types_filter: args.common.types.as_ref(),
+            lengths: args.common.length_filter(),
+            provenance: args.common.provenance,
+        };
+
+        if args.derive_utrs {
+            write_gff_with_derived_utrs(
+                gff_path,
+                &blocks,
+                Some(&per_root_matches),
+                &args.common.output,
+                args.common.types.as_ref(),
+                args.common.length_filter(),
+                verbose,
+            )?;
+        } else if let (Some(checkpoint), Some(output)) = (&args.checkpoint, &args.common.output) {
+            write_gff_output_resumable(
+                gff_path,
+                &blocks,
+                Some(selection),
+                output,
+                checkpoint,
+                &command_line_key(),
+                verbose,
+            )?;
+        } else {
+            // Emit only exactly matched lines within blocks
+            write_gff_output_filtered(gff_path, &blocks, selection, &args.common.output, verbose)?;
+        }
+    } else if args.derive_utrs {
+        write_gff_with_derived_utrs(
             gff_path,
             &blocks,
-            &per_root_matches,
-            "ID",
+            None,
             &args.common.output,
-            args.common.types.as_deref(),
+            args.common.types.as_ref(),
+            args.common.length_filter(),
             verbose,
         )?;
+    } else if let Some(metric) = args.longest_transcript {
+        let types = args.common.types.as_ref();
+        write_gff_longest_transcripts(gff_path, &blocks, metric, &args.common.output, types, verbose)?;
+    } else if let Some(types) = &args.common.types {
+        // Whole models, minus the lines of other types (lengths were applied to the root extent)
+        write_gff_output_typed(gff_path, &blocks, &args.common.output, types, LengthFilter::default(), verbose)?;
+    } else if let (Some(checkpoint), Some(output)) = (&args.checkpoint, &args.common.output) {
+        write_gff_output_resumable(gff_path, &blocks, None, output, checkpoint, &command_line_key(), verbose)?;
     } else {
         // Entire-group mode: emit entire blocks without filtering
         write_gff_output(
@@ -154,10 +314,350 @@ pub fn run(args: &ExtractArgs) -> Result<()> {
         )?;
     }
 
+    let mut context = Vec::new();
+    if let (Some(n), Some(fts), Some(gof)) = (args.context, &fts, &gof) {
+        let index = TreeIndexData::load_tree_index(gff_path)?;
+        context = context_roots(&index, &roots, n)?;
+        let written = write_context_blocks(gff_path, gof, fts, &context, &args.common.output)?;
+        if verbose {
+            diag!("[INFO] Wrote {} context model(s) (--context {})", written, n);
+        }
+    }
+    if let (Some(bp), Some(fts), Some(gof)) = (args.pad_models, &fts, &gof) {
+        let index = TreeIndexData::load_tree_index(gff_path)?;
+        let seq_lens = SeqLengths::load(gff_path, None)?;
+        let (mut padding, n_clamped) = pad_roots(&index, &roots, bp, &seq_lens)?;
+        drop_context_roots(&mut padding, &context);
+        let written = write_padded_blocks(gff_path, gof, fts, &padding, &args.common.output)?;
+        if verbose {
+            diag!("[INFO] Wrote {} padding model(s) (--pad-models {})", written, bp);
+            if n_clamped > 0 {
+                diag!("[INFO] {} padding window(s) cut at a sequence end", n_clamped);
+            }
+        }
+    }
+
+    set_summary(summary.output(args.common.output.as_deref()));
+
     if verbose {
-        eprintln!("[timing] Total elapsed: {:?}", overall_start.elapsed());
+        diag!("[timing] Total elapsed: {:?}", overall_start.elapsed());
+    }
+
+    Ok(())
+}
+
+/// `--seqid`: every root block on the requested sequences, found through the GOF
+/// seqid index, plus the GFF header. Every line of such a block matches, so the
+/// feature-only and entire-group modes both copy whole blocks.
+fn run_seqids(args: &ExtractArgs) -> Result<()> {
+    let gff_path = &args.common.input;
+    let verbose = args.common.verbose;
+    let overall_start = Instant::now();
+    if args.checkpoint.is_some() && args.common.types.is_some() {
+        bail!("--checkpoint is not supported for --seqid with -T/--types");
     }
 
+    let gof = load_gof(gff_path)?;
+    let (_, seqid_to_num) = load_sqs(gff_path)?;
+
+    let mut seqids: Vec<&str> = args.seqid.iter().map(String::as_str).collect();
+    seqids.sort_unstable();
+    seqids.dedup();
+    let mut matched = 0usize;
+    let mut blocks: Vec<(u32, u64, u64)> = Vec::new();
+    for seqid in &seqids {
+        let Some(&num) = seqid_to_num.get(*seqid) else {
+            diag!("[WARN] Sequence '{}' not found in the index", seqid);
+            continue;
+        };
+        matched += 1;
+        let entries = gof.roots_for_seqid(num);
+        if verbose {
+            diag!("[INFO] {}: {} root block(s)", seqid, entries.len());
+        }
+        blocks.extend(entries.iter().map(|e| (e.feature_id, e.start_offset, e.end_offset)));
+    }
+    blocks = args.common.length_filter().filter_blocks(gff_path, blocks)?;
+    let n_roots = blocks.len();
+
+    let summary = RunSummary::new()
+        .input(gff_path)
+        .matched(matched, seqids.len(), "seqid(s)")
+        .count(n_roots, "model(s)");
+
+    if args.common.dry_run {
+        set_summary(summary);
+        let plan = DryRunPlan {
+            term_kind: "seqid(s)",
+            terms: seqids.len(),
+            matched_terms: matched,
+            roots: n_roots,
+        };
+        return plan.report(gff_path, &blocks, args.common.types.as_ref());
+    }
+
+    if let Some(bed) = &args.common.roots_bed {
+        let rows = write_roots_bed(gff_path, &blocks, &FxHashMap::default(), bed, args.common.types.as_ref(), verbose)?;
+        set_summary(summary.count(rows, "BED row(s)").output(Some(bed.as_path()).filter(|p| *p != Path::new("-"))));
+        return Ok(());
+    }
+
+    if args.common.roots_only {
+        write_gff_roots_only(gff_path, &blocks, &args.common.output, args.common.types.as_ref(), verbose)?;
+    } else {
+        if args.derive_utrs {
+            write_gff_with_derived_utrs(
+                gff_path,
+                &blocks,
+                None,
+                &args.common.output,
+                args.common.types.as_ref(),
+                args.common.length_filter(),
+                verbose,
+            )?;
+        } else if let Some(metric) = args.longest_transcript {
+            write_gff_longest_transcripts(
+                gff_path,
+                &blocks,
+                metric,
+                &args.common.output,
+                args.common.types.as_ref(),
+                verbose,
+            )?;
+        } else if let Some(types) = &args.common.types {
+            // Lengths were applied to the root extent above
+            write_gff_output_typed(gff_path, &blocks, &args.common.output, types, LengthFilter::default(), verbose)?;
+        } else if let (Some(checkpoint), Some(output)) = (&args.checkpoint, &args.common.output) {
+            write_gff_output_resumable(gff_path, &blocks, None, output, checkpoint, &command_line_key(), verbose)?;
+        } else {
+            write_gff_output(gff_path, &blocks, &args.common.output, verbose)?;
+        }
+    }
+
+    set_summary(summary.output(args.common.output.as_deref()));
+
+    if verbose {
+        diag!("[INFO] Exported {} root block(s) on {} sequence(s)", n_roots, matched);
+        diag!("[timing] Total elapsed: {:?}", overall_start.elapsed());
+    }
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{Fixture, GffSynth, SAMPLE_GFF, build_fixture, data_lines};
+    use crate::load_a2f;
+
+    #[test]
+    fn roots_only_writes_one_line_per_root() {
+        let fx = build_fixture(SAMPLE_GFF).unwrap();
+        let ids = fx.dir.join("ids.txt");
+        std::fs::write(&ids, "e1\nt2\ng1\ne5\n").unwrap();
+        let out = fx.dir.join("out.gff");
+        let args = ExtractArgs::try_parse_from([
+            "extract", "-i", fx.gff.to_str().unwrap(), "-F", ids.to_str().unwrap(), "-o", out.to_str().unwrap(),
+            "--roots-only",
+        ])
+        .unwrap();
+        run(&args).unwrap();
+
+        let lines = data_lines(&out).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("ID=g1;Name=alpha"));
+        assert!(lines[1].ends_with("ID=g3;Name=gamma"));
+    }
+
+    fn extract_ids(fx: &Fixture, ids: &str, extra: &[&str]) -> Vec<String> {
+        let list = fx.dir.join("ids.txt");
+        std::fs::write(&list, ids).unwrap();
+        let out = fx.dir.join("out.gff");
+        let mut argv = vec!["extract", "-i", fx.gff.to_str().unwrap(), "-F", list.to_str().unwrap()];
+        argv.extend_from_slice(&["-o", out.to_str().unwrap()]);
+        argv.extend_from_slice(extra);
+        run(&ExtractArgs::try_parse_from(argv).unwrap()).unwrap();
+        let mut ids: Vec<String> = data_lines(&out)
+            .unwrap()
+            .iter()
+            .filter_map(|l| l.split("ID=").nth(1)?.split(';').next().map(String::from))
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn length_bounds_hold_at_the_threshold() {
+        let fx = build_fixture(SAMPLE_GFF).unwrap();
+        // e1 is 201 bp, c1 151 bp; g1 spans 801 bp, g3 391 bp
+        let features = "e1\nc1\n";
+        assert_eq!(extract_ids(&fx, features, &["--min-length", "151"]), ["c1", "e1"]);
+        assert_eq!(extract_ids(&fx, features, &["--min-length", "152"]), ["e1"]);
+        assert_eq!(extract_ids(&fx, features, &["--max-length", "151"]), ["c1"]);
+        assert_eq!(extract_ids(&fx, features, &["--max-length", "150"]), Vec::<String>::new());
+        // AND with --types
+        assert_eq!(extract_ids(&fx, features, &["--min-length", "151", "-T", "CDS"]), ["c1"]);
+
+        // Whole models are kept or dropped by their root extent
+        let models = "e1\ne5\n";
+        let g3 = ["e5", "g3", "t4"];
+        assert_eq!(extract_ids(&fx, models, &["-e", "--max-length", "801"]).len(), 7 + g3.len());
+        assert_eq!(extract_ids(&fx, models, &["-e", "--max-length", "800"]), g3);
+        assert_eq!(extract_ids(&fx, models, &["-e", "--min-length", "392"]).len(), 7);
+    }
+
+    #[test]
+    fn negated_types_drop_only_the_listed_types() {
+        let fx = build_fixture(SAMPLE_GFF).unwrap();
+        let features = "t1\ne1\nc1\ne2\n";
+        assert_eq!(extract_ids(&fx, features, &["-T", "!exon"]), ["c1", "t1"]);
+        assert_eq!(extract_ids(&fx, features, &["-T", "!exon,CDS"]), ["t1"]);
+
+        let list = fx.dir.join("types.txt");
+        std::fs::write(&list, "exon\n# not a type\nmRNA\n").unwrap();
+        let spec = format!("!@{}", list.display());
+        assert_eq!(extract_ids(&fx, features, &["-T", &spec]), ["c1"]);
+        assert!(ExtractArgs::try_parse_from(["extract", "-i", "x.gff", "-f", "e1", "-T", "exon,!CDS"]).is_err());
+    }
+
+    #[test]
+    fn pad_models_reach_exactly_bp_bases() {
+        let fx = build_fixture(SAMPLE_GFF).unwrap();
+        let g1 = ["c1", "e1", "e2", "e3", "g1", "t1", "t2"];
+        let g1_g2 = ["c1", "e1", "e2", "e3", "e4", "g1", "g2", "t1", "t2", "t3"];
+        // g1 ends at 900 and g2 starts at 2000: 1099 bases between them
+        assert_eq!(extract_ids(&fx, "e1\n", &["-e", "--pad-models", "1098"]), g1);
+        assert_eq!(extract_ids(&fx, "e1\n", &["-e", "--pad-models", "1099"]), g1_g2);
+        // A pad past both ends of chr1 adds nothing from chr2, and a match is never its own padding
+        assert_eq!(extract_ids(&fx, "e1\ne4\n", &["-e", "--pad-models", "100000"]), g1_g2);
+
+        let out = fx.dir.join("out.gff");
+        let text = std::fs::read_to_string(&out).unwrap();
+        assert!(!text.contains("# padded-for"));
+        extract_ids(&fx, "g2\n", &["-e", "--pad-models", "5000"]);
+        let text = std::fs::read_to_string(&out).unwrap();
+        let (matched, padded) = text.split_once("# padded-for: g2\n").unwrap();
+        assert!(matched.contains("ID=g2;") && padded.starts_with("chr1\tsrc\tgene\t100\t900"));
+    }
+
+    #[test]
+    fn seqid_types_applies_lengths_to_roots_only() {
+        let (genome, fx) = GffSynth::new().chromosomes(2).genes_per_chrom(30).seed(33).fixture().unwrap();
+        let (dir, gff) = (&fx.dir, &fx.gff);
+        let min = 8_000;
+        let out = dir.join("out.gff");
+        let argv = ["extract", "-i", gff.to_str().unwrap(), "--seqid", "chr2", "-T", "gene,exon"];
+        let args = ExtractArgs::try_parse_from(
+            argv.into_iter().chain(["--min-length", "8000", "-o", out.to_str().unwrap()]),
+        )
+        .unwrap();
+        run(&args).unwrap();
+
+        let text = std::fs::read_to_string(gff).unwrap();
+        let kept: Vec<&str> = genome
+            .genes
+            .iter()
+            .filter(|g| g.chrom == 1 && g.end - g.start + 1 >= min)
+            .map(|g| g.id.as_str())
+            .collect();
+        assert!(!kept.is_empty() && kept.len() < 30);
+        // Every gene and exon line of the long genes, short exons included
+        let want: Vec<&str> = text
+            .lines()
+            .filter(|l| {
+                let f: Vec<&str> = l.split('\t').collect();
+                f.len() == 9
+                    && (f[2] == "gene" || f[2] == "exon")
+                    && kept.iter().any(|id| f[8].contains(&format!("ID={};", id)) || f[8].contains(&format!("ID={}.", id)))
+            })
+            .collect();
+        let got = std::fs::read_to_string(&out).unwrap();
+        let got: Vec<&str> = got.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(got, want);
+        assert!(got.iter().any(|l| {
+            let f: Vec<u64> = l.split('\t').skip(3).take(2).map(|c| c.parse().unwrap()).collect();
+            f[1] - f[0] + 1 < min
+        }));
+    }
+
+    #[test]
+    fn context_and_padding_write_each_model_once() {
+        let (genome, fx) = GffSynth::new().chromosomes(1).genes_per_chrom(30).seed(41).fixture().unwrap();
+        let (dir, gff) = (&fx.dir, &fx.gff);
+        let m = &genome.genes[15];
+        // Reaches the 4th gene on the right, past the 2 context models on each side
+        let bp = genome.genes[19].start - m.end;
+        let near: Vec<&str> = genome
+            .genes
+            .iter()
+            .filter(|g| g.id != m.id && g.start.saturating_sub(m.end).max(m.start.saturating_sub(g.end)) <= bp)
+            .map(|g| g.id.as_str())
+            .collect();
+        assert!(near.len() > 4);
+
+        let out = dir.join("out.gff");
+        let (bp, gff, out_s) = (bp.to_string(), gff.to_str().unwrap(), out.to_str().unwrap());
+        let argv = ["extract", "-i", gff, "-e", "-f", &m.id, "--context", "2", "--pad-models", &bp, "-o", out_s];
+        run(&ExtractArgs::try_parse_from(argv).unwrap()).unwrap();
+
+        let got = std::fs::read_to_string(&out).unwrap();
+        let mut genes: Vec<&str> = got
+            .lines()
+            .filter(|l| l.split('\t').nth(2) == Some("gene"))
+            .map(|l| l.split("ID=").nth(1).unwrap().split(';').next().unwrap())
+            .collect();
+        let n_context = got.lines().filter(|l| l.starts_with("# context-for: ")).count();
+        let n_padded = got.lines().filter(|l| l.starts_with("# padded-for: ")).count();
+        assert_eq!((n_context, n_padded), (4, near.len() - 4));
+        genes.sort_unstable();
+        let mut want = near.clone();
+        want.push(&m.id);
+        want.sort_unstable();
+        assert_eq!(genes, want);
+    }
+
+    #[test]
+    fn split_cds_is_one_feature_with_every_segment_extracted() {
+        let rows = [
+            "gene\t1000\t5000\t.\tID=gene-A;Name=A",
+            "mRNA\t1000\t5000\t.\tID=rna-A;Parent=gene-A",
+            "exon\t1000\t1500\t.\tID=exon-A1;Parent=rna-A",
+            "CDS\t1200\t1500\t0\tID=cds-XYZ;Parent=rna-A;Name=XYZ",
+            "exon\t2000\t2600\t.\tID=exon-A2;Parent=rna-A",
+            "CDS\t2000\t2600\t1\tID=cds-XYZ;Parent=rna-A",
+            "exon\t3000\t5000\t.\tID=exon-A3;Parent=rna-A",
+            "CDS\t3000\t3400\t0\tID=cds-XYZ;Parent=rna-A",
+            "gene\t7000\t9000\t.\tID=gene-B;Name=B",
+            "mRNA\t7000\t9000\t.\tID=rna-B;Parent=gene-B",
+            "exon\t7000\t9000\t.\tID=exon-B1;Parent=rna-B",
+        ];
+        let lines: Vec<String> = rows
+            .iter()
+            .map(|r| {
+                let f: Vec<&str> = r.split('\t').collect();
+                format!("chr1\tsynth\t{}\t{}\t{}\t.\t+\t{}\t{}", f[0], f[1], f[2], f[3], f[4])
+            })
+            .collect();
+        let fx = build_fixture(&format!("##gff-version 3\n{}\n", lines.join("\n"))).unwrap();
+        let (dir, gff) = (&fx.dir, &fx.gff);
+
+        // Nine distinct IDs: one .fts, .prt and .a2f entry each
+        let (fts, prt, a2f) = (load_fts(gff).unwrap(), load_prt(gff).unwrap(), load_a2f(gff).unwrap());
+        assert_eq!((fts.ids.len(), prt.entries.len(), a2f.len_fids()), (9, 9, 9));
+        let cds = fts.get_fid("cds-XYZ").unwrap();
+        assert_eq!(prt.get_parent(cds), fts.get_fid("rna-A"));
+        assert!(a2f.aid_for_fid(cds).is_some());
+
+        let cds_lines: Vec<&str> = lines.iter().filter(|l| l.contains("ID=cds-XYZ;")).map(String::as_str).collect();
+        let extract = |extra: &[&str]| {
+            let out = dir.join("out.gff");
+            let argv = ["extract", "-i", gff.to_str().unwrap(), "-f", "cds-XYZ", "-o", out.to_str().unwrap()];
+            run(&ExtractArgs::try_parse_from(argv.iter().chain(extra)).unwrap()).unwrap();
+            let text = std::fs::read_to_string(&out).unwrap();
+            text.lines().filter(|l| !l.starts_with('#')).map(str::to_string).collect::<Vec<_>>()
+        };
+        // The whole model of gene-A, all three segments included
+        assert_eq!(extract(&["-e"]), lines[..8]);
+        // Per feature: just the segments
+        assert_eq!(extract(&[]), cds_lines);
+    }
+}
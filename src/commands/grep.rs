@@ -0,0 +1,307 @@
+use anyhow::{Context, Result, bail};
+use clap::{Parser, ValueEnum};
+use memchr::memmem;
+use rayon::prelude::*;
+use regex::bytes::Regex;
+use std::{
+    fs::File,
+    io::{BufWriter, Write, stdout},
+    path::PathBuf,
+    str,
+    time::Instant,
+};
+
+use crate::{
+    AttrEditor, ByteSource, Cell, Column, CountingWriter, ModelBlock, RunSummary, TableArgs, TableWriter, TypeFilter,
+    check_gff_present, check_index_matches, did_you_mean, fast_id, load_gof, load_meta, set_index_prefix, set_summary,
+    write_gff_output,
+};
+
+/// Output of `grep`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GrepFormat {
+    /// The matching feature lines (or, with -e, their whole models) as GFF
+    Gff,
+    /// One row per matching attribute: root, id, key, value
+    Tsv,
+}
+
+/// Arguments
+#[derive(Parser, Debug)]
+#[command(
+    about = "Search raw attribute text without an attribute index (slower than `search`)",
+    long_about = "Scans column 9 of every model in the GFF for attribute values matching a pattern, \
+                  including attributes that were not indexed with `index -a`. Every block is read, so \
+                  this is much slower than `search`; use it for one-off lookups and rebuild the index \
+                  for repeated ones."
+)]
+pub struct GrepArgs {
+    /// Input GFF file (indexed)
+    #[arg(short = 'i', long = "input", value_name = "FILE")]
+    pub input: PathBuf,
+
+    /// Output file (stdout if not provided)
+    #[arg(short = 'o', long = "output", value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    /// Text to find in attribute values: a fixed string, or a regex with -r
+    #[arg(short = 'p', long = "pattern", value_name = "PATTERN")]
+    pub pattern: String,
+
+    /// Treat the pattern as a regular expression
+    #[arg(short = 'r', long = "regex", default_value_t = false)]
+    pub regex: bool,
+
+    /// The pattern must match the whole value instead of a part of it
+    #[arg(short = 'x', long = "whole-value", default_value_t = false)]
+    pub whole_value: bool,
+
+    /// Only look at the values of this attribute key (repeatable); default: every key
+    #[arg(short = 'k', long = "key", value_name = "KEY")]
+    pub key: Vec<String>,
+
+    /// Emit the whole model of each matching feature instead of the matching lines
+    #[arg(
+        short = 'e',
+        long = "entire-group",
+        visible_alias = "full-model",
+        default_value_t = false
+    )]
+    pub entire_group: bool,
+
+    /// Only search feature lines of these types (a comma-separated list or `@FILE`; a
+    /// leading `!` excludes the listed types). With -e, whole models are still emitted.
+    #[arg(short = 'T', long = "types", value_name = "TYPES", value_parser = TypeFilter::parse)]
+    pub types: Option<TypeFilter>,
+
+    /// Output format
+    #[arg(long = "format", value_enum, default_value_t = GrepFormat::Gff, value_name = "FORMAT")]
+    pub format: GrepFormat,
+
+    #[command(flatten)]
+    pub table: TableArgs,
+
+    /// Read the index files from PREFIX (see `index --index-prefix`) instead of from
+    /// next to the input
+    #[arg(long = "index-prefix", value_name = "PREFIX")]
+    pub index_prefix: Option<PathBuf>,
+
+    /// Number of threads for parallel processing
+    #[arg(short = 't', long = "threads", default_value_t = 12)]
+    pub threads: usize,
+
+    /// Enable verbose output
+    #[arg(short = 'v', long = "verbose", default_value_t = false)]
+    pub verbose: bool,
+}
+
+/// How attribute values are tested against the pattern (raw bytes, as in the file).
+enum ValueMatcher {
+    Substring(Box<memmem::Finder<'static>>),
+    Exact(Vec<u8>),
+    Regex(Regex),
+}
+
+impl ValueMatcher {
+    fn new(args: &GrepArgs) -> Result<Self> {
+        Ok(match (args.regex, args.whole_value) {
+            (false, false) => Self::Substring(Box::new(memmem::Finder::new(args.pattern.as_bytes()).into_owned())),
+            (false, true) => Self::Exact(args.pattern.as_bytes().to_vec()),
+            (true, whole) => {
+                let pattern = if whole { format!("^(?:{})$", args.pattern) } else { args.pattern.clone() };
+                Self::Regex(Regex::new(&pattern).with_context(|| format!("Invalid regex '{}'", args.pattern))?)
+            }
+        })
+    }
+
+    #[inline]
+    fn is_match(&self, value: &[u8]) -> bool {
+        match self {
+            Self::Substring(f) => f.find(value).is_some(),
+            Self::Exact(v) => value == v.as_slice(),
+            Self::Regex(re) => re.is_match(value),
+        }
+    }
+}
+
+/// One matching attribute of a feature line.
+struct Hit<'a> {
+    /// ID of the model's root feature
+    root: &'a str,
+    id: &'a str,
+    seqid: &'a str,
+    /// 1-based, inclusive (as in the GFF)
+    start: u64,
+    end: u64,
+    ftype: &'a str,
+    key: &'a str,
+    value: &'a str,
+    /// Byte range of the whole line in the GFF, terminator included
+    line: (u64, u64),
+}
+
+fn grep_columns<'a>() -> Vec<Column<'a, Hit<'a>>> {
+    vec![
+        Column::new("root", |h: &Hit<'a>| Cell::Str(h.root)),
+        Column::new("id", |h: &Hit<'a>| Cell::Str(h.id)),
+        Column::new("key", |h: &Hit<'a>| Cell::Str(h.key)),
+        Column::new("value", |h: &Hit<'a>| Cell::Str(h.value)),
+        Column::optional("seqid", |h: &Hit<'a>| Cell::Str(h.seqid)),
+        Column::optional_start("start", |h: &Hit<'a>| h.start - 1),
+        Column::optional("end", |h: &Hit<'a>| Cell::UInt(h.end)),
+        Column::optional("type", |h: &Hit<'a>| Cell::Str(h.ftype)),
+    ]
+}
+
+/// Matching attributes of one model, in line order; `base` is the start of the GFF.
+fn model_hits<'a>(
+    model: &ModelBlock<'a>,
+    base: usize,
+    matcher: &ValueMatcher,
+    keys: &[String],
+    types: Option<&TypeFilter>,
+) -> Vec<Hit<'a>> {
+    let mut hits = Vec::new();
+    let mut root: Option<&'a str> = None;
+    for raw in model.block.split_inclusive(|&b| b == b'\n') {
+        let body = raw.strip_suffix(b"\n").unwrap_or(raw);
+        let body = body.strip_suffix(b"\r").unwrap_or(body);
+        if body.is_empty() || body[0] == b'#' {
+            continue;
+        }
+        let Ok(text) = str::from_utf8(body) else { continue };
+        let f: Vec<&'a str> = text.splitn(9, '\t').collect();
+        if f.len() < 9 {
+            continue;
+        }
+        let id = fast_id(f[8]).unwrap_or(".");
+        let root = *root.get_or_insert(id);
+        if types.is_some_and(|t| !t.allows(f[2].as_bytes())) {
+            continue;
+        }
+        let (Ok(start), Ok(end)) = (f[3].parse::<u64>(), f[4].parse::<u64>()) else { continue };
+        let at = (raw.as_ptr() as usize - base) as u64;
+        let editor = AttrEditor::new(body);
+        for (key, value) in editor.iter() {
+            let Some(value) = value else { continue };
+            let (Ok(key), Ok(value)) = (str::from_utf8(key), str::from_utf8(value)) else { continue };
+            if (keys.is_empty() || keys.iter().any(|k| k == key)) && matcher.is_match(value.as_bytes()) {
+                hits.push(Hit {
+                    root,
+                    id,
+                    seqid: f[0],
+                    start,
+                    end,
+                    ftype: f[2],
+                    key,
+                    value,
+                    line: (at, at + raw.len() as u64),
+                });
+            }
+        }
+    }
+    hits
+}
+
+pub fn run(args: &GrepArgs) -> Result<()> {
+    let t0 = Instant::now();
+    let _ = rayon::ThreadPoolBuilder::new().num_threads(args.threads).build_global();
+    let gff_path = &args.input;
+    set_index_prefix(gff_path, args.index_prefix.as_deref());
+    if args.table.is_set() && args.format != GrepFormat::Tsv {
+        bail!("--columns, --no-header, --table-format and --coords apply to --format tsv");
+    }
+    if args.entire_group && args.format == GrepFormat::Tsv {
+        bail!("-e/--entire-group selects GFF output; --format tsv lists the matching attributes only");
+    }
+
+    check_gff_present(gff_path)?;
+    check_index_matches(gff_path)?;
+    let matcher = ValueMatcher::new(args)?;
+    if let Some(meta) = load_meta(gff_path)?
+        && !meta.attr_keys.is_empty()
+    {
+        for k in args.key.iter().filter(|k| !meta.attr_keys.contains(k)) {
+            let hint = did_you_mean(k, meta.attr_keys.iter().map(String::as_str))
+                .map(|s| format!("; did you mean '{s}'?"))
+                .unwrap_or_default();
+            diag!("[WARN] No feature in the GFF has a '{}' attribute{}", k, hint);
+        }
+    }
+    let gof = load_gof(gff_path)?;
+    let gff = ByteSource::open_gff(gff_path).with_context(|| format!("Cannot open GFF file: {:?}", gff_path))?;
+    let base = gff.as_ptr() as usize;
+
+    // Every block is scanned: one work unit per model, results kept in file order
+    let models: Vec<ModelBlock<'_>> = gof.iter_models(&gff).collect::<Result<_, _>>()?;
+    let per_model: Vec<(usize, Vec<Hit<'_>>)> = models
+        .par_iter()
+        .enumerate()
+        .map(|(i, m)| (i, model_hits(m, base, &matcher, &args.key, args.types.as_ref())))
+        .filter(|(_, hits)| !hits.is_empty())
+        .collect();
+    let n_models = per_model.len();
+    let n_lines: usize = per_model
+        .iter()
+        .map(|(_, hits)| {
+            let mut lines: Vec<u64> = hits.iter().map(|h| h.line.0).collect();
+            lines.dedup();
+            lines.len()
+        })
+        .sum();
+    if args.verbose {
+        diag!(
+            "[TIMER] Scanned {} model(s), {} with a match: {:.2?}",
+            models.len(),
+            n_models,
+            t0.elapsed()
+        );
+    }
+
+    match args.format {
+        GrepFormat::Tsv => {
+            let writer = TableWriter::new(grep_columns(), &args.table)?;
+            let raw: Box<dyn Write> = match &args.output {
+                Some(p) => {
+                    Box::new(CountingWriter(File::create(p).with_context(|| format!("Cannot create output: {:?}", p))?))
+                }
+                None => Box::new(CountingWriter(stdout())),
+            };
+            let mut out = BufWriter::new(raw);
+            writer.write_all(per_model.into_iter().flat_map(|(_, hits)| hits), &mut out)?;
+        }
+        GrepFormat::Gff => {
+            // Matching lines (or whole models) go through the block writer as ranges
+            let blocks: Vec<(u32, u64, u64)> = if args.entire_group {
+                per_model
+                    .iter()
+                    .map(|&(i, _)| {
+                        let m = &models[i];
+                        let s = (m.block.as_ptr() as usize - base) as u64;
+                        (m.root, s, s + m.block.len() as u64)
+                    })
+                    .collect()
+            } else {
+                let mut lines: Vec<(u32, u64, u64)> = per_model
+                    .iter()
+                    .flat_map(|(i, hits)| {
+                        let root = models[*i].root;
+                        hits.iter().map(move |h| (root, h.line.0, h.line.1))
+                    })
+                    .collect();
+                lines.dedup();
+                lines
+            };
+            write_gff_output(gff_path, &blocks, &args.output, args.verbose)?;
+        }
+    }
+
+    set_summary(
+        RunSummary::new()
+            .input(gff_path)
+            .count(n_lines, "matching line(s)")
+            .count(n_models, "model(s)")
+            .output(args.output.as_deref()),
+    );
+    Ok(())
+}
@@ -1,5 +1,8 @@
-use crate::build_index;
-use anyhow::Result;
+use crate::{
+    DEFAULT_ID_KEY, DEFAULT_PARENT_KEY, DEFAULT_SKIP_TYPES, IndexBuilder, MAX_ATTR_KEYS, RunSummary, bundle_index,
+    set_index_prefix, set_summary, unbundle_index, write_tabix_companion,
+};
+use anyhow::{Result, bail};
 use clap::Parser;
 use std::path::PathBuf;
 
@@ -9,29 +12,230 @@ use std::path::PathBuf;
     long_about = "This command builds index files for fast retrieval from a GFF file."
 )]
 pub struct IndexArgs {
-    #[arg(short, long)]
+    /// Input GFF file (may be gzip- or bgzip-compressed)
+    #[arg(short, long, value_name = "FILE")]
     input: PathBuf,
 
-    #[arg(short, long, default_value = "gene_name")]
+    /// Attribute keys whose values `search` looks up, comma-separated (e.g.
+    /// Name,locus_tag,gene_id; at most 8); case-sensitive. The first is searched by
+    /// default, the others with `search -k`. The build reports how many features carry each
+    #[arg(short, long, value_name = "KEYS", default_value = "gene_name", value_parser = parse_keys)]
     pub attribute: String,
 
-    #[arg(short, long, default_value = "remark,note,comment,region,gap,assembly_gap,contig,scaffold,source")]
+    /// Comma-separated feature types left out of the index like comments (e.g. add
+    /// chromosome,biological_region for Ensembl); they need no ID. `""` skips none
+    #[arg(
+        short,
+        long = "skip-types",
+        value_name = "TYPES",
+        default_value = DEFAULT_SKIP_TYPES
+    )]
     pub skip_types: String,
 
+    /// Attribute key holding the feature IDs, for GFF dialects that do not use `ID`
+    /// (e.g. `id` or `locus`); case-sensitive, recorded in the index
+    #[arg(long = "id-key", value_name = "KEY", default_value = DEFAULT_ID_KEY, value_parser = parse_key)]
+    pub id_key: String,
+
+    /// Attribute key holding the parent references (e.g. `parent`); case-sensitive
+    #[arg(long = "parent-key", value_name = "KEY", default_value = DEFAULT_PARENT_KEY, value_parser = parse_key)]
+    pub parent_key: String,
+
+    /// Comma-separated feature types always treated as roots (e.g. gene,pseudogene,ncRNA_gene),
+    /// even when they carry a Parent (e.g. Ensembl genes with Parent=chromosome)
+    #[arg(long = "root-types", value_name = "TYPES")]
+    pub root_types: Option<String>,
+
+    /// Do not build the secondary Name attribute index (.natn/.na2f)
+    #[arg(long = "no-name-index", default_value_t = false)]
+    pub no_name_index: bool,
+
+    /// Also write a BGZF copy (<input>.gz) with a tabix index for tabix/pysam;
+    /// the GFF must be sorted by seqid and start
+    #[arg(long = "emit-tabix", default_value_t = false)]
+    pub emit_tabix: bool,
+
+    /// With --emit-tabix, write a CSI index instead of TBI (sequences over 512 Mbp)
+    #[arg(long = "csi", default_value_t = false, requires = "emit_tabix")]
+    pub csi: bool,
+
+    /// Fail on the first line without exactly 9 tab-separated columns
+    /// instead of skipping it with a warning
+    #[arg(long = "strict", default_value_t = false)]
+    pub strict: bool,
+
+    /// Fail on the first feature without an ID attribute instead of indexing it
+    /// under an internal name (it is still extracted with its model)
+    #[arg(long = "strict-ids", default_value_t = false)]
+    pub strict_ids: bool,
+
+    /// Keep only the first of several roots with identical seqid, start, end and type
+    /// (e.g. a gene duplicated under another ID); the others get no block
+    #[arg(long = "dedup-identical", default_value_t = false)]
+    pub dedup_identical: bool,
+
+    /// Also write a Bloom filter over feature IDs (.fbl) with this false-positive rate
+    /// (e.g. 0.01), so lookups of absent IDs skip the .fts hash map
+    #[arg(long = "bloom-fpr", value_name = "RATE", value_parser = parse_fpr)]
+    pub bloom_fpr: Option<f64>,
+
+    /// Pack the index files into a single <input>.gxi (with per-file checksums)
+    /// instead of leaving them as loose files
+    #[arg(long = "bundle", default_value_t = false)]
+    pub bundle: bool,
+
+    /// Do not build: check <input>.gxi against its checksums, extract it back to
+    /// loose index files and delete it
+    #[arg(long = "unbundle", default_value_t = false, conflicts_with = "bundle")]
+    pub unbundle: bool,
+
+    /// Write the index files as PREFIX.gof, PREFIX.fts, ... instead of next to the
+    /// input (e.g. a GFF on a read-only volume), or into PREFIX when it is a directory
+    /// or ends in `/`; other commands then need the same --index-prefix
+    #[arg(long = "index-prefix", value_name = "PREFIX")]
+    pub index_prefix: Option<PathBuf>,
+
     #[arg(short, long, default_value_t = false)]
     verbose: bool,
 }
 
+fn parse_key(s: &str) -> Result<String, String> {
+    if s.is_empty() || s.contains(['=', ';', ',', '\t', '\n', '\r']) || s.trim() != s {
+        return Err("must be a non-empty attribute key without '=', ';', ',', tabs or surrounding spaces".to_string());
+    }
+    Ok(s.to_string())
+}
+
+fn parse_keys(s: &str) -> Result<String, String> {
+    let mut keys: Vec<String> = Vec::new();
+    for k in s.split(',') {
+        let k = parse_key(k)?;
+        if !keys.contains(&k) {
+            keys.push(k);
+        }
+    }
+    if keys.len() > MAX_ATTR_KEYS {
+        return Err(format!("at most {MAX_ATTR_KEYS} attribute keys can be indexed, got {}", keys.len()));
+    }
+    Ok(keys.join(","))
+}
+
+fn parse_fpr(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(r) if r > 0.0 && r < 1.0 => Ok(r),
+        Ok(_) => Err("must be between 0 and 1 (exclusive)".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
 pub fn run(args: &IndexArgs) -> Result<()> {
+    set_index_prefix(&args.input, args.index_prefix.as_deref());
+    if args.unbundle {
+        let n = unbundle_index(&args.input, args.verbose)?;
+        diag!("[INFO] Extracted {} index file(s) from the bundle", n);
+        set_summary(RunSummary::new().input(&args.input).count(n, "index file(s) extracted"));
+        return Ok(());
+    }
+
+    if args.id_key == args.parent_key {
+        bail!("--id-key and --parent-key must differ (both are '{}')", args.id_key);
+    }
     if args.verbose {
         println!("Indexing: {}", args.input.display());
     }
 
-    build_index(&args.input, &args.attribute, &args.skip_types, args.verbose)?;
+    let stats = IndexBuilder::new()
+        .attr_key(&args.attribute)
+        .id_keys(&args.id_key, &args.parent_key)
+        .skip_types(&args.skip_types)
+        .root_types(args.root_types.as_deref())
+        .name_index(!args.no_name_index)
+        .strict(args.strict)
+        .strict_ids(args.strict_ids)
+        .dedup_identical(args.dedup_identical)
+        .bloom_fpr(args.bloom_fpr)
+        .verbose(args.verbose)
+        .build(&args.input)?;
+
+    if args.bundle {
+        bundle_index(&args.input, args.verbose)?;
+    }
+
+    if args.emit_tabix {
+        write_tabix_companion(&args.input, args.csi, args.verbose)?;
+    }
 
     if args.verbose {
         println!("Index created successfully.");
     }
+    let summary = args
+        .attribute
+        .split(',')
+        .zip(&stats.with_attribute)
+        .fold(RunSummary::new().input(&args.input).count(stats.features, "feature(s)"), |s, (key, &n)| {
+            s.count(n, &format!("with '{}'", key))
+        });
+    set_summary(summary.count(stats.skipped, "skipped by type"));
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::extract::{self, ExtractArgs};
+    use crate::Error;
+    use crate::testutil::{SAMPLE_GFF, ScratchDir, build_fixture, data_lines};
+
+    /// Ensembl-style: genes point at a `chromosome` feature, which is not a skipped type.
+    const ENSEMBL_GFF: &str = "\
+chr1\tsrc\tchromosome\t1\t10000\t.\t.\t.\tID=chromosome:chr1
+chr1\tsrc\tgene\t100\t900\t.\t+\t.\tID=g1;Parent=chromosome:chr1;Name=alpha
+chr1\tsrc\tmRNA\t100\t900\t.\t+\t.\tID=t1;Parent=g1
+chr1\tsrc\texon\t100\t900\t.\t+\t.\tID=e1;Parent=t1
+chr1\tsrc\tgene\t2000\t3000\t.\t-\t.\tID=g2;Parent=chromosome:chr1;Name=beta
+chr1\tsrc\tmRNA\t2000\t3000\t.\t-\t.\tID=t2;Parent=g2
+chr1\tsrc\texon\t2000\t3000\t.\t-\t.\tID=e2;Parent=t2
+";
+
+    fn model_of(gff: &std::path::Path, id: &str, out: &std::path::Path) -> Vec<String> {
+        let argv = ["extract", "-i", gff.to_str().unwrap(), "-f", id, "-e", "-o", out.to_str().unwrap()];
+        extract::run(&ExtractArgs::try_parse_from(argv).unwrap()).unwrap();
+        data_lines(out).unwrap()
+    }
+
+    #[test]
+    fn root_types_split_genes_parented_to_a_chromosome() {
+        let fx = build_fixture(ENSEMBL_GFF).unwrap();
+        let out = fx.dir.join("out.gff");
+        // By default the chromosome is the only root: every line is one model
+        assert_eq!(model_of(&fx.gff, "e1", &out).len(), ENSEMBL_GFF.lines().count());
+
+        let gff = fx.gff.to_str().unwrap();
+        run(&IndexArgs::try_parse_from(["index", "-i", gff, "-a", "Name", "--root-types", "gene,pseudogene"]).unwrap())
+            .unwrap();
+        let model = model_of(&fx.gff, "e1", &out);
+        let ids: Vec<&str> = model.iter().map(|l| l.rsplit('\t').next().unwrap().split(';').next().unwrap()).collect();
+        assert_eq!(ids, ["ID=g1", "ID=t1", "ID=e1"]);
+        assert_eq!(model_of(&fx.gff, "g2", &out).len(), 3);
+    }
+
+    #[test]
+    fn strict_builds_fail_with_the_line_number() {
+        let dir = ScratchDir::new("index-strict").unwrap();
+        let gff = dir.join("g.gff");
+        let build = |text: &str, builder: IndexBuilder| {
+            std::fs::write(&gff, text).unwrap();
+            builder.attr_key("Name").build(&gff)
+        };
+        let short = SAMPLE_GFF.replace("\tID=e2;Parent=t1", "");
+        assert!(build(&short, IndexBuilder::new()).is_ok());
+        let err = build(&short, IndexBuilder::new().strict(true)).unwrap_err();
+        assert!(matches!(&err, Error::Parse { line: 8, detail } if detail.contains("found 8")), "{err:?}");
+
+        let anonymous = SAMPLE_GFF.replace("ID=c1;", "");
+        assert!(build(&anonymous, IndexBuilder::new()).is_ok());
+        let err = build(&anonymous, IndexBuilder::new().strict_ids(true)).unwrap_err();
+        assert!(matches!(err, Error::Parse { line: 7, .. }), "{err:?}");
+    }
+}
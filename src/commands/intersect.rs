@@ -2,7 +2,6 @@ use anyhow::{Context, Result};
 use clap::{ArgGroup, Parser};
 use lexical_core::parse;
 use memchr::memchr;
-use memmap2::Mmap;
 use rayon::prelude::*;
 use rustc_hash::{FxHashMap, FxHashSet};
 use std::{
@@ -12,7 +11,10 @@ use std::{
 };
 
 use crate::{
-    CommonArgs, Interval, TreeIndexData, load_gof, write_gff_output,
+    BedReader, ByteSource, Clamp, CommonArgs, Counter, CountingWriter, DryRunPlan, Interval, LengthFilter,
+    LineIndex, Provenance, RangeSource, RunSummary, SeqLengths, TreeIndexData, TypeFilter, align_blocks_to_lines,
+    ensure_index, load_gof, push_line_with_provenance, record, set_summary, source_extension, write_gff_output,
+    write_gff_output_typed, write_gff_roots_only, write_roots_bed, write_source_header,
 };
 
 const MISSING: u64 = u64::MAX; // Set sentinel value for missing entries
@@ -35,7 +37,7 @@ pub struct RootMatched {
     long_about = "This tool extracts features and their parent models that intersect with specified regions"
 )]
 #[clap(group(
-    ArgGroup::new("regions").required(true).args(&["region", "bed"])
+    ArgGroup::new("regions").args(&["region", "bed"])
 ))]
 #[clap(group(
     ArgGroup::new("mode").args(&["contained", "contains_region", "overlap"])
@@ -45,13 +47,21 @@ pub struct IntersectArgs {
     pub common: CommonArgs,
 
     /// Single region in format "chr:start-end"
-    #[arg(short = 'r', long, group = "regions")]
+    #[arg(short = 'r', long, group = "regions", required_unless_present_any = ["bed", "density"])]
     pub region: Option<String>,
 
-    /// BED file containing regions
+    /// Region file: BED, or GFF/GTF (detected by extension, see --regions-format)
     #[arg(short = 'b', long, group = "regions")]
     pub bed: Option<PathBuf>,
 
+    /// Format of the region file given with -b (default: detect by extension)
+    #[arg(long = "regions-format", value_enum, requires = "bed")]
+    pub regions_format: Option<RegionFormat>,
+
+    /// Feature types to use as regions from a GFF/GTF region file (e.g. gene); same syntax as --types
+    #[arg(long = "region-types", value_name = "TYPES", requires = "bed", value_parser = TypeFilter::parse)]
+    pub region_types: Option<TypeFilter>,
+
     /// Only return features fully contained within regions
     #[arg(short = 'c', long, group = "mode")]
     pub contained: bool,
@@ -64,9 +74,86 @@ pub struct IntersectArgs {
     #[arg(short = 'O', long, group = "mode")]
     pub overlap: bool,
 
-    /// Invert the selection (exclude matching features)
+    /// Invert the selection: emit every model of the GFF (in file order) except those
+    /// the regions select under the chosen mode, so with -c the models fully contained
+    /// in a region are excluded and with -C those containing a region
     #[arg(short = 'I', long, default_value_t = false)]
     pub invert: bool,
+
+    /// Chromosome sizes file (`<seqid>\t<length>`) used to clamp regions;
+    /// defaults to the `##sequence-region` directives of the GFF header
+    #[arg(short = 'g', long, value_name = "FILE")]
+    pub genome: Option<PathBuf>,
+
+    /// Write region-file lines with no surviving root hit (original order and columns) to FILE
+    #[arg(long = "unmatched-bed", value_name = "FILE", requires = "bed", conflicts_with = "dry_run")]
+    pub unmatched_bed: Option<PathBuf>,
+
+    /// Report root model counts per window (by span midpoint) instead of GFF lines,
+    /// over the queried regions or, without -r/-b, over every indexed sequence
+    #[arg(
+        long = "density",
+        default_value_t = false,
+        conflicts_with_all = [
+            "mode", "invert", "unmatched_bed", "types", "roots_only", "entire_group", "provenance", "dry_run",
+            "roots_bed"
+        ]
+    )]
+    pub density: bool,
+
+    /// Window size in bp for --density
+    #[arg(
+        long = "window",
+        value_name = "BP",
+        default_value_t = 1_000_000,
+        requires = "density",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub window: u32,
+
+    /// Output layout for --density
+    #[arg(long = "density-format", value_enum, value_name = "FMT", default_value = "tsv", requires = "density")]
+    pub density_format: DensityFormat,
+}
+
+/// Raw line of a region file: byte range (without the '\n') and the index of the
+/// region it produced, or `None` when its seqid is unknown or it lies beyond the sequence end.
+#[derive(Debug, Clone, Copy)]
+pub struct RegionSource {
+    pub start: usize,
+    pub end: usize,
+    pub region: Option<usize>,
+}
+
+/// Parsed regions plus the source line of each region-file line.
+pub type ParsedRegions = (Vec<(u32, u32, u32)>, Vec<RegionSource>);
+
+/// Format of a region file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum RegionFormat {
+    /// 0-based half-open `seqid start end`
+    Bed,
+    /// GFF3/GTF feature lines (1-based inclusive columns 4-5)
+    Gff,
+}
+
+/// Layout of `--density` output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DensityFormat {
+    /// `seqid start end models` with a header line
+    Tsv,
+    /// bedGraph with a track line
+    Bedgraph,
+}
+
+impl RegionFormat {
+    /// Guess the format from the file extension (ignoring `.gz`); anything unrecognised is BED.
+    pub fn from_path(path: &Path) -> Self {
+        match source_extension(path).as_deref() {
+            Some("gff") | Some("gff3") | Some("gtf") | Some("gff2") => RegionFormat::Gff,
+            _ => RegionFormat::Bed,
+        }
+    }
 }
 
 /// Overlap detection modes
@@ -77,27 +164,16 @@ pub enum OverlapMode {
     Overlap,
 }
 
-pub fn gff_type_allowed(line: &[u8], allow: &FxHashSet<String>) -> bool {
-    // Fast parse the 3rd field (type) without allocations
-    let mut off = 0usize;
-    let mut tabs = 0u8;
-    while tabs < 2 {
-        match memchr(b'\t', &line[off..]) {
-            Some(i) => {
-                off += i + 1;
-                tabs += 1;
-            }
-            None => return false,
-        }
-    }
-    let i2 = match memchr(b'\t', &line[off..]) {
-        Some(i) => off + i,
-        None => return false,
-    };
-    let ty = &line[off..i2];
-    match std::str::from_utf8(ty) {
-        Ok(s) => allow.contains(s),
-        Err(_) => false,
+/// Whether a feature interval satisfies `mode` against a region.
+#[inline]
+fn mode_keeps(mode: OverlapMode, fstart: u32, fend: u32, rstart: u32, rend: u32) -> bool {
+    match mode {
+        // Feature must be fully contained in region
+        OverlapMode::Contained => fstart >= rstart && fend <= rend,
+        // Feature must fully contain region
+        OverlapMode::ContainsRegion => fstart <= rstart && fend >= rend,
+        // Any overlap is acceptable
+        OverlapMode::Overlap => true,
     }
 }
 
@@ -106,7 +182,6 @@ pub fn query_features(
     index_data: &TreeIndexData,
     regions: &[(u32, u32, u32)],
     mode: OverlapMode,
-    invert: bool,
     verbose: bool,
 ) -> Result<Vec<(u32, u32, u32)>> {
 
@@ -121,13 +196,16 @@ pub fn query_features(
 
     let mut results = Vec::new();
     {
-        for (&seq_num, tree) in &index_data.chr_entries {
-            let chr_regs = &buckets[seq_num as usize];
+        // Only the trees of sequences with regions are decoded
+        for (seq_num, chr_regs) in buckets.iter().enumerate() {
             if chr_regs.is_empty() {
                 continue;
             }
+            let Some(tree) = index_data.tree_for(seq_num as u32)? else {
+                continue;
+            };
             if verbose {
-                eprintln!(
+                diag!(
                     "[DEBUG] Querying chromosome {} with {} regions",
                     seq_num,
                     chr_regs.len()
@@ -142,24 +220,8 @@ pub fn query_features(
     
                 for &iv in &hits {
                     // Decide whether to keep this feature based on mode
-                    let keep = match mode {
-                        OverlapMode::Contained => {
-                            // Feature must be fully contained in region
-                            iv.start >= rstart && iv.end <= rend
-                        }
-                        OverlapMode::ContainsRegion => {
-                            // Feature must fully contain region
-                            iv.start <= rstart && iv.end >= rend
-                        }
-                        OverlapMode::Overlap => {
-                            // Any overlap is acceptable
-                            true
-                        }
-                    };
-    
-                    // Apply invert flag (XOR logic)
-                    if invert ^ keep {
-                        results.push((iv.root_fid, iv.start, iv.end));
+                    if mode_keeps(mode, iv.start, iv.end, rstart, rend) {
+                        results.push((iv.payload, iv.start, iv.end));
                     }
                 }
             }
@@ -168,10 +230,35 @@ pub fn query_features(
     Ok(results)
 }
 
+/// For each region, whether at least one root survives the same mode test as
+/// `query_features` and the root-extent length filter.
+pub fn region_hit_flags(
+    index_data: &TreeIndexData,
+    regions: &[(u32, u32, u32)],
+    mode: OverlapMode,
+    lengths: LengthFilter,
+) -> Result<Vec<bool>> {
+    regions
+        .par_iter()
+        .map_init(Vec::new, |hits, &(chr, rstart, rend)| {
+            let Some(tree) = index_data.tree_for(chr)? else {
+                return Ok(false);
+            };
+            hits.clear();
+            tree.query_interval(rstart, rend, hits);
+            Ok(hits
+                .iter()
+                .any(|iv| mode_keeps(mode, iv.start, iv.end, rstart, rend) && lengths.accepts(iv.end - iv.start)))
+        })
+        .collect()
+}
+
 /// Parse a single genomic region string (chr:start-end)
+/// When `seq_lens` knows the sequence, the region is clamped to its length.
 pub fn parse_region(
     region: &str,
     seqid_map: &FxHashMap<String, u32>,
+    seq_lens: &SeqLengths,
     common: &CommonArgs,
 ) -> Result<(u32, u32, u32)> {
     let (seq, range) = region
@@ -188,8 +275,26 @@ pub fn parse_region(
     if start >= end {
         anyhow::bail!("Region start must be less than end ({} >= {})", start, end);
     }
+    let (start, end) = match seq_lens.clamp(seq, start, end) {
+        Clamp::Inside(s, e) => (s, e),
+        Clamp::Clamped(s, e) => {
+            diag!(
+                "[WARN] Region {} extends past the end of {} (length {}); clamped to {}-{}",
+                region, seq, e, s, e
+            );
+            (s, e)
+        }
+        Clamp::Outside => {
+            anyhow::bail!(
+                "Region {} starts beyond the end of {} (length {})",
+                region,
+                seq,
+                seq_lens.get(seq).unwrap_or(0)
+            );
+        }
+    };
     if common.verbose {
-        eprintln!(
+        diag!(
             "[DEBUG] Parsed region: chr={}, start={}, end={}",
             chr, start, end
         );
@@ -198,84 +303,303 @@ pub fn parse_region(
 }
 
 /// Parse BED file using mmap zero-copy field splitting
+/// Regions running past a known sequence end are clamped; regions starting beyond it are dropped.
 pub fn parse_bed_file(
     bed_path: &Path,
     seqid_map: &FxHashMap<String, u32>,
+    seq_lens: &SeqLengths,
+    verbose: bool,
 ) -> Result<Vec<(u32, u32, u32)>> {
-    let mmap = {
-        let file = File::open(bed_path)?;
-        unsafe { Mmap::map(&file)? }
-    };
+    parse_region_file(bed_path, RegionFormat::Bed, None, seqid_map, seq_lens, verbose)
+}
+
+/// Parse a BED or GFF/GTF region file (see `parse_region_bytes`).
+pub fn parse_region_file(
+    path: &Path,
+    format: RegionFormat,
+    region_types: Option<&TypeFilter>,
+    seqid_map: &FxHashMap<String, u32>,
+    seq_lens: &SeqLengths,
+    verbose: bool,
+) -> Result<Vec<(u32, u32, u32)>> {
+    let mmap = map_region_file(path)?;
+    parse_region_bytes(&mmap[..], format, region_types, seqid_map, seq_lens, verbose)
+}
+
+/// Region file bytes; gzip-compressed files are decompressed into memory.
+fn map_region_file(path: &Path) -> Result<ByteSource> {
+    BedReader::open(path)
+        .and_then(BedReader::into_bytes)
+        .with_context(|| format!("Cannot read region file: {:?}", path))
+}
+
+/// Parse in-memory BED bytes (see `parse_bed_file`).
+pub fn parse_bed_bytes(
+    data: &[u8],
+    seqid_map: &FxHashMap<String, u32>,
+    seq_lens: &SeqLengths,
+    verbose: bool,
+) -> Result<Vec<(u32, u32, u32)>> {
+    parse_region_bytes(data, RegionFormat::Bed, None, seqid_map, seq_lens, verbose)
+}
+
+/// Split one region line into (seqid, start, end) in 0-based half-open coordinates.
+/// GFF/GTF lines are filtered by `region_types` (column 3) when given.
+#[inline]
+fn split_region_line<'a>(
+    line: &'a str,
+    format: RegionFormat,
+    region_types: Option<&TypeFilter>,
+) -> Result<Option<(&'a str, u32, u32)>> {
+    match format {
+        RegionFormat::Bed => {
+            let mut parts = line.split_ascii_whitespace();
+            let (Some(seq), Some(s), Some(e)) = (parts.next(), parts.next(), parts.next()) else {
+                return Ok(None);
+            };
+            let start = parse::<u32>(s.as_bytes())?;
+            let end = parse::<u32>(e.as_bytes())?;
+            Ok(Some((seq, start, end)))
+        }
+        RegionFormat::Gff => {
+            let mut cols = line.split('\t');
+            let (Some(seq), _, Some(ty), Some(s), Some(e)) =
+                (cols.next(), cols.next(), cols.next(), cols.next(), cols.next())
+            else {
+                return Ok(None);
+            };
+            if region_types.is_some_and(|t| !t.allows(ty.as_bytes())) {
+                return Ok(None);
+            }
+            let s1 = parse::<u32>(s.trim().as_bytes())?;
+            let e1 = parse::<u32>(e.trim().as_bytes())?;
+            let (s1, e1) = if s1 > e1 { (e1, s1) } else { (s1, e1) };
+            Ok(Some((seq, s1.saturating_sub(1), e1)))
+        }
+    }
+}
+
+/// Parse in-memory region bytes (BED or GFF/GTF) using zero-copy line splitting.
+/// Regions running past a known sequence end are clamped; regions starting beyond it are dropped.
+pub fn parse_region_bytes(
+    data: &[u8],
+    format: RegionFormat,
+    region_types: Option<&TypeFilter>,
+    seqid_map: &FxHashMap<String, u32>,
+    seq_lens: &SeqLengths,
+    verbose: bool,
+) -> Result<Vec<(u32, u32, u32)>> {
+    parse_region_bytes_with_lines(data, format, region_types, seqid_map, seq_lens, verbose)
+        .map(|(regions, _)| regions)
+}
+
+/// Like `parse_region_bytes`, but also returns one `RegionSource` per region line,
+/// in file order, so callers can echo the original lines back.
+pub fn parse_region_bytes_with_lines(
+    data: &[u8],
+    format: RegionFormat,
+    region_types: Option<&TypeFilter>,
+    seqid_map: &FxHashMap<String, u32>,
+    seq_lens: &SeqLengths,
+    verbose: bool,
+) -> Result<ParsedRegions> {
+    let mut n_clamped = 0usize;
+    let mut n_outside = 0usize;
+    let mut sources = Vec::new();
     let regions = {
         let mut regions = Vec::new();
-        for line in mmap.split(|&b| b == b'\n') {
+        let mut line_start = 0usize;
+        for line in data.split(|&b| b == b'\n') {
+            let span = (line_start, line_start + line.len());
+            line_start = span.1 + 1;
+            // Embedded sequences end the feature section of a GFF3 file
+            if format == RegionFormat::Gff && line.starts_with(b"##FASTA") {
+                break;
+            }
             if line.is_empty() || line[0] == b'#' {
                 continue;
             }
-            let line_str = std::str::from_utf8(line)?;
-            let mut parts = line_str.split_ascii_whitespace();
-            let (Some(seq), Some(s), Some(e)) = (parts.next(), parts.next(), parts.next()) else {
+            let line_str = std::str::from_utf8(line)?.trim_end_matches('\r');
+            let Some((seq, start, end)) = split_region_line(line_str, format, region_types)? else {
                 continue;
             };
-            let Some(&chr) = seqid_map.get(seq) else {
-                continue;
-            };
-            let start = parse::<u32>(s.as_bytes())?;
-            let end = parse::<u32>(e.as_bytes())?;
-            regions.push((chr, start, end));
+            let mut source = RegionSource { start: span.0, end: span.1, region: None };
+            if let Some(&chr) = seqid_map.get(seq) {
+                match seq_lens.clamp(seq, start, end) {
+                    Clamp::Inside(s, e) => {
+                        source.region = Some(regions.len());
+                        regions.push((chr, s, e));
+                    }
+                    Clamp::Clamped(s, e) => {
+                        n_clamped += 1;
+                        source.region = Some(regions.len());
+                        regions.push((chr, s, e));
+                    }
+                    Clamp::Outside => n_outside += 1,
+                }
+            }
+            sources.push(source);
         }
         regions
     };
-    Ok(regions)
+    if n_clamped > 0 || n_outside > 0 {
+        diag!(
+            "[WARN] {} region(s) clamped to sequence ends, {} dropped beyond sequence ends",
+            n_clamped, n_outside
+        );
+    }
+    if verbose {
+        diag!(
+            "[DEBUG] Parsed {} {:?} regions (clamped={}, out_of_bounds={})",
+            regions.len(),
+            format,
+            n_clamped,
+            n_outside
+        );
+    }
+    Ok((regions, sources))
+}
+
+/// Write the lines of `sources` whose region has no hit, in file order.
+/// Lines that produced no region (unknown seqid, beyond the sequence end) count as unmatched.
+pub fn write_unmatched_regions(
+    data: &[u8],
+    sources: &[RegionSource],
+    hit: &[bool],
+    output_path: &Path,
+) -> Result<usize> {
+    let file = File::create(output_path)
+        .with_context(|| format!("Cannot create unmatched region file: {:?}", output_path))?;
+    let mut writer = BufWriter::new(CountingWriter(file));
+    let mut n = 0usize;
+    for src in sources {
+        if src.region.is_some_and(|i| hit[i]) {
+            continue;
+        }
+        writer.write_all(&data[src.start..src.end])?;
+        writer.write_all(b"\n")?;
+        n += 1;
+    }
+    writer.flush()?;
+    Ok(n)
+}
+
+/// Count root models per `window`-bp window by the midpoint of their span and write one
+/// row per window (0-based half-open, empty windows included) to `out`.
+///
+/// Windows tile each of `regions`, or every indexed sequence when `regions` is empty,
+/// up to its length from `seq_lens` or else the last root end. Roots are the intervals
+/// of the `.rit` trees; `lengths` applies to their span. Returns the number of rows.
+pub fn write_density<W: Write>(
+    index_data: &TreeIndexData,
+    regions: &[(u32, u32, u32)],
+    window: u32,
+    seq_lens: &SeqLengths,
+    lengths: LengthFilter,
+    format: DensityFormat,
+    out: &mut W,
+) -> Result<usize> {
+    let spans: Vec<(u32, u32, u32)> = if regions.is_empty() {
+        let mut spans = Vec::new();
+        for chr in 0..index_data.num_to_seqid.len() as u32 {
+            let end = match seq_lens.get(&index_data.num_to_seqid[chr as usize]) {
+                Some(len) => Some(len),
+                None => index_data.tree_for(chr)?.and_then(|tree| tree.iter().map(|iv| iv.end).max()),
+            };
+            spans.extend(end.map(|end| (chr, 0, end)));
+        }
+        spans
+    } else {
+        regions.to_vec()
+    };
+
+    // Sorted root midpoints of each sequence the windows cover
+    let mut chrs: Vec<u32> = spans.iter().map(|&(chr, _, _)| chr).collect();
+    chrs.sort_unstable();
+    chrs.dedup();
+    let midpoints: FxHashMap<u32, Vec<u32>> = chrs
+        .par_iter()
+        .filter_map(|&chr| {
+            let tree = match index_data.tree_for(chr) {
+                Ok(tree) => tree?,
+                Err(e) => return Some(Err(e)),
+            };
+            let mut mids: Vec<u32> = tree
+                .iter()
+                .filter(|iv| lengths.accepts(iv.end.saturating_sub(iv.start)))
+                .map(|iv| iv.start + iv.end.saturating_sub(iv.start) / 2)
+                .collect();
+            mids.sort_unstable();
+            Some(Ok((chr, mids)))
+        })
+        .collect::<crate::error::Result<_>>()?;
+
+    match format {
+        DensityFormat::Tsv => writeln!(out, "seqid\tstart\tend\tmodels")?,
+        DensityFormat::Bedgraph => writeln!(out, "track type=bedGraph name=\"gffx density\"")?,
+    }
+    let no_roots: Vec<u32> = Vec::new();
+    let mut rows = 0usize;
+    for (chr, start, end) in spans {
+        let Some(seqid) = index_data.num_to_seqid.get(chr as usize) else {
+            continue;
+        };
+        let mids = midpoints.get(&chr).unwrap_or(&no_roots);
+        let mut ws = start;
+        while ws < end {
+            let we = ws.saturating_add(window).min(end);
+            let count = mids.partition_point(|&m| m < we) - mids.partition_point(|&m| m < ws);
+            writeln!(out, "{}\t{}\t{}\t{}", seqid, ws, we, count)?;
+            rows += 1;
+            ws = we;
+        }
+    }
+    out.flush()?;
+    Ok(rows)
 }
 
+/// (block_start, block_end, matched line ranges) of one scanned block
+type MatchedBlock = (u64, u64, Vec<(u64, u64)>);
+
+/// Write the lines of `blocks` that pass `types_filter` and `lengths` and, unless
+/// `query_ivmap` is `None` (every line of an `--invert` complement), satisfy `mode`
+/// against a query region.
+#[allow(clippy::too_many_arguments)]
 pub fn write_gff_match_only_by_coords(
     gff_path: &Path,
     blocks: &[(u32, u64, u64)], //Per-block parallel scan to collect (line_start, line_end) offsets
-    query_ivmap: &FxHashMap<String, Vec<(u32, u32)>>,
-    types_filter: Option<&str>,
+    query_ivmap: Option<&FxHashMap<String, Vec<(u32, u32)>>>,
+    types_filter: Option<&TypeFilter>,
     output_path: &Option<PathBuf>,
     mode: OverlapMode,
+    lengths: LengthFilter,
+    provenance: Option<Provenance>,
     verbose: bool,
 ) -> Result<()> {
-    // mmap the whole GFF once
-    let (mmap, file_len) = {
-        let file = std::fs::File::open(gff_path)
-            .with_context(|| format!("Cannot open GFF: {:?}", gff_path))?;
-        let mmap = unsafe { Mmap::map(&file) }
-            .with_context(|| format!("mmap failed for {:?}", gff_path))?;
-        let len = mmap.len();
-        (mmap, len)
-    };
+    // mmap the whole GFF once (or read each block with positioned reads)
+    let gff = RangeSource::open(gff_path)?;
+    let file_len = gff.len();
+    let blocks = &align_blocks_to_lines(&gff, blocks)?;
 
-    // parse type filters to a set once
-    let type_allow: Option<FxHashSet<String>> = {
-        types_filter.map(|s| {
-            s.split(',')
-                .map(|t| t.trim().to_string())
-                .filter(|t| !t.is_empty())
-                .collect()
-        })
-    };
-
-    // Parallel scan blocks: produce (block_start, Vec<(line_start,line_end)>)
+    // Parallel scan blocks: produce (block_start, block_end, Vec<(line_start,line_end)>)
     // Note: we never copy line bytes, only collect offsets.
-    let mut parts: Vec<(u64, Vec<(u64, u64)>)> = {
+    let mut parts: Vec<MatchedBlock> = {
         let bytes_out = std::sync::atomic::AtomicU64::new(0);
 
-        let parts: Vec<(u64, Vec<(u64, u64)>)> = blocks
+        let parts: Vec<Option<MatchedBlock>> = blocks
             .par_iter()
-            .filter_map(|&(root, start, end)| {
+            .map(|&(root, start, end)| -> Result<Option<MatchedBlock>> {
                 if start == MISSING {
-                    eprintln!("[WARN] skipped fid={} due to sentinel start offset", root);
-                    return None;
+                    diag!("[WARN] skipped fid={} due to sentinel start offset", root);
+                    return Ok(None);
                 }
-                let s = start as usize;
-                let e = (end as usize).min(file_len);
-                if s >= e || e > file_len {
-                    return None;
+                let end = end.min(file_len);
+                if start >= end {
+                    return Ok(None);
                 }
-                let src = &mmap[s..e];
+                let block = gff.range(start, end)?;
+                record(Counter::BlockScans, 1);
+                let src = &block[..];
 
                 // Collect matched line ranges as global file offsets.
                 let mut matched_offsets: Vec<(u64, u64)> = Vec::with_capacity(256);
@@ -299,12 +623,13 @@ pub fn write_gff_match_only_by_coords(
                     if !line_nocr.is_empty() && line_nocr[0] != b'#' {
                         // Optional: type filter first to early discard
                         let mut pass = true;
-                        if let Some(allow) = &type_allow
-                            && !gff_type_allowed(line_nocr, allow)
-                        {
+                        if types_filter.is_some_and(|t| !t.allows_line(line_nocr)) {
                             pass = false;
                         }
-                        if pass && gff_line_overlaps_queries(line_nocr, query_ivmap, mode) {
+                        if pass && !lengths.accepts_line(line_nocr) {
+                            pass = false;
+                        }
+                        if pass && query_ivmap.is_none_or(|m| gff_line_overlaps_queries(line_nocr, m, mode)) {
                             // Record absolute offsets in the file (including '\n')
                             let abs_start = start + pos as u64;
                             let abs_end = start + nl as u64;
@@ -320,20 +645,30 @@ pub fn write_gff_match_only_by_coords(
                     pos = nl;
                 }
 
-                if matched_offsets.is_empty() {
-                    None
-                } else {
-                    Some((start, matched_offsets))
-                }
+                Ok((!matched_offsets.is_empty()).then_some((start, end, matched_offsets)))
             })
-            .collect();
-        parts
+            .collect::<Result<_>>()?;
+        parts.into_iter().flatten().collect()
     };
 
     // Keep global order stable by block start (we don't merge offsets as per user's requirement)
     {
-        parts.sort_unstable_by_key(|(s, _)| *s);
+        parts.sort_unstable_by_key(|(s, _, _)| *s);
+    }
+
+    // Overlapping blocks (e.g. several GOF ranges of one root) scan the same lines;
+    // write each matched line once
+    let mut dup_lines = 0usize;
+    let mut written_end = 0u64;
+    for (_, _, ranges) in parts.iter_mut() {
+        let before = ranges.len();
+        ranges.retain(|&(ls, _)| ls >= written_end);
+        dup_lines += before - ranges.len();
+        if let Some(&(_, le)) = ranges.last() {
+            written_end = written_end.max(le);
+        }
     }
+    parts.retain(|(_, _, ranges)| !ranges.is_empty());
 
     // Helper: write all slices using write_vectored with partial-write handling.
     // We construct a temporary Vec<IoSlice> per batch; batch size is small (<= IOV_BATCH).
@@ -382,62 +717,55 @@ pub fn write_gff_match_only_by_coords(
         Ok(())
     }
 
-    // Write out: use large BufWriter and batch IoSlice slices across consecutive parts.
-    {
-        // Assemble and write batches, reusing a small Vec<&[u8]> to avoid reallocs
-        let mut batch: Vec<&[u8]> = Vec::with_capacity(IOV_BATCH);
-
-        if let Some(p) = output_path {
-            // File output path: create file and large BufWriter
-            let file = std::fs::File::create(p)?;
-            let mut writer = BufWriter::with_capacity(WRITE_BUF_SIZE, file);
+    let line_index = provenance
+        .filter(|p| p.needs_lines())
+        .map(|_| LineIndex::new(&gff))
+        .transpose()?;
+    let raw: Box<dyn Write> = match output_path {
+        Some(p) => Box::new(CountingWriter(std::fs::File::create(p)?)),
+        None => Box::new(CountingWriter(std::io::stdout().lock())),
+    };
+    let mut writer = BufWriter::with_capacity(WRITE_BUF_SIZE, raw);
+    write_source_header(&gff, &mut writer)?;
+    let mut buf: Vec<u8> = Vec::with_capacity(1024);
 
-            for (_, ranges) in parts.iter() {
+    for (bs, be, ranges) in parts.iter() {
+        // Re-read the block: borrowed from the mapping, or one positioned read
+        let block = gff.range(*bs, *be)?;
+        let line = |ls: u64, le: u64| &block[(ls - bs) as usize..(le - bs) as usize];
+        match provenance {
+            // Provenance mode: each matched line is rewritten with a `gffx_src=` attribute
+            Some(prov) => {
                 for &(ls, le) in ranges {
-                    // Safety: ls/le were validated against file_len earlier
-                    let slice = &mmap[ls as usize..le as usize];
-                    batch.push(slice);
-                    if batch.len() >= IOV_BATCH {
-                        write_all_vectored(&mut writer, std::mem::take(&mut batch))?;
-                    }
+                    buf.clear();
+                    push_line_with_provenance(&mut buf, line(ls, le), ls, line_index.as_ref(), prov)?;
+                    writer.write_all(&buf)?;
                 }
             }
-            if !batch.is_empty() {
-                write_all_vectored(&mut writer, std::mem::take(&mut batch))?;
-            }
-            writer.flush()?;
-        } else {
-            // Stdout path: lock stdout and use large BufWriter
-            let stdout = std::io::stdout();
-            let handle = stdout.lock();
-            let mut writer = BufWriter::with_capacity(WRITE_BUF_SIZE, handle);
-
-            for (_, ranges) in parts.iter() {
-                for &(ls, le) in ranges {
-                    let slice = &mmap[ls as usize..le as usize];
-                    batch.push(slice);
-                    if batch.len() >= IOV_BATCH {
-                        write_all_vectored(&mut writer, std::mem::take(&mut batch))?;
-                    }
+            // Batch the block's lines into vectored writes
+            None => {
+                for batch in ranges.chunks(IOV_BATCH) {
+                    write_all_vectored(&mut writer, batch.iter().map(|&(ls, le)| line(ls, le)).collect())?;
                 }
             }
-            if !batch.is_empty() {
-                write_all_vectored(&mut writer, std::mem::take(&mut batch))?;
-            }
-            writer.flush()?;
         }
     }
+    writer.flush()?;
 
     if verbose {
-        eprintln!(
+        diag!(
             "[INFO] match-only by coords completed; minput blocks {}",
             blocks.len()
         );
+        if dup_lines > 0 {
+            diag!("[INFO] Suppressed {} line(s) matched again by an overlapping block", dup_lines);
+        }
     }
     Ok(())
 }
 
-/// Parse GFF line and check if it overlaps with query intervals
+/// Parse GFF line and check if it overlaps with query intervals (0-based, half-open,
+/// like the regions given to `query_features`) under `mode`
 pub fn gff_line_overlaps_queries(
     line: &[u8],
     ivmap: &FxHashMap<String, Vec<(u32, u32)>>,
@@ -497,33 +825,15 @@ pub fn gff_line_overlaps_queries(
         None => return false,
     };
 
-    for &(qs, qe) in ivs {
-        let keep = match mode {
-            OverlapMode::Contained => {
-                // feature must be fully inside query
-                start >= qs && end <= qe
-            }
-            OverlapMode::ContainsRegion => {
-                // feature must fully contain query
-                start <= qs && end >= qe
-            }
-            OverlapMode::Overlap => {
-                // any overlap
-                (qs <= start && start <= qe)
-                    || (qs <= end && end <= qe)
-                    || (start <= qs && qs <= end)
-                    || (start <= qe && qe <= end)
-            }
-        };
-        if keep {
-            return true;
-        }
-    }
-    false
+    // Same coordinates and test as the tree hits in `query_features`: the feature as a
+    // 0-based half-open interval, overlapping the region and satisfying `mode`
+    let start = start.saturating_sub(1);
+    ivs.iter()
+        .any(|&(qs, qe)| start < qe && end > qs && mode_keeps(mode, start, end, qs, qe))
 }
 
 #[inline]
-fn parse_u32_ascii(s: &[u8]) -> Option<u32> {
+pub fn parse_u32_ascii(s: &[u8]) -> Option<u32> {
     let mut v: u32 = 0;
     if s.is_empty() {
         return None;
@@ -540,10 +850,13 @@ fn parse_u32_ascii(s: &[u8]) -> Option<u32> {
 /// Main execution function
 pub fn run(args: &IntersectArgs) -> Result<()> {
     let verbose = args.common.verbose;
+    // Validate argument combinations and size the thread pool (-t)
+    args.common.post_parse()?;
+    ensure_index(&args.common.input, args.common.auto_index, verbose)?;
     
     if verbose {
-        eprintln!("[DEBUG] Starting processing of {:?}", args.common.input);
-        eprintln!(
+        diag!("[DEBUG] Starting processing of {:?}", args.common.input);
+        diag!(
             "[DEBUG] Thread pool initialized with {} threads",
             args.common.effective_threads()
         );
@@ -560,24 +873,80 @@ pub fn run(args: &IntersectArgs) -> Result<()> {
 
     let index_data = TreeIndexData::load_tree_index(&args.common.input)?;
     let seqid_map = &index_data.seqid_to_num;
+    let seq_lens = SeqLengths::load(&args.common.input, args.genome.as_deref())?;
+    if verbose {
+        diag!(
+            "[DEBUG] Sequence lengths available: {}",
+            if seq_lens.is_empty() { "none (no bounds checking)" } else { "yes" }
+        );
+    }
 
+    // Region file bytes and line spans, kept for --unmatched-bed
+    let mut region_lines: Option<(ByteSource, Vec<RegionSource>)> = None;
     let regions = {
         if let Some(bed) = &args.bed {
-            parse_bed_file(bed, seqid_map)?
+            let format = args.regions_format.unwrap_or_else(|| RegionFormat::from_path(bed));
+            let region_types = args.region_types.as_ref();
+            if region_types.is_some() && format != RegionFormat::Gff {
+                diag!("[WARN] --region-types only applies to GFF/GTF region files; ignored");
+            }
+            let mmap = map_region_file(bed)?;
+            let (regions, sources) = parse_region_bytes_with_lines(
+                &mmap[..],
+                format,
+                region_types,
+                seqid_map,
+                &seq_lens,
+                verbose,
+            )?;
+            if args.unmatched_bed.is_some() {
+                region_lines = Some((mmap, sources));
+            }
+            regions
         } else if let Some(r) = &args.region {
-            vec![parse_region(r, seqid_map, &args.common)?]
+            vec![parse_region(r, seqid_map, &seq_lens, &args.common)?]
+        } else if args.density {
+            // Whole genome
+            Vec::new()
         } else {
             anyhow::bail!("No region specified");
         }
     };
 
+    if args.density {
+        let mut out: Box<dyn Write> = match &args.common.output {
+            Some(p) => Box::new(BufWriter::new(CountingWriter(
+                File::create(p).with_context(|| format!("Cannot create output: {:?}", p))?,
+            ))),
+            None => Box::new(BufWriter::new(CountingWriter(io::stdout().lock()))),
+        };
+        let rows = write_density(
+            &index_data,
+            &regions,
+            args.window,
+            &seq_lens,
+            args.common.length_filter(),
+            args.density_format,
+            &mut out,
+        )?;
+        if verbose {
+            diag!("[INFO] Wrote {} density window(s) of {} bp", rows, args.window);
+        }
+        let mut summary = RunSummary::new().input(&args.common.input);
+        if let Some(bed) = &args.bed {
+            summary = summary.input(bed);
+        }
+        set_summary(summary.count(rows, "density window(s)").output(args.common.output.as_deref()));
+        return Ok(());
+    }
+
 
     if verbose {
-        eprintln!(
+        diag!(
             "[DEBUG] Starting query_features with {} regions",
             regions.len()
         );
-        eprintln!(
+        diag!(
             "[DEBUG] Mode: {:?}",
             mode
         );
@@ -588,16 +957,41 @@ pub fn run(args: &IntersectArgs) -> Result<()> {
             &index_data,
             &regions,
             mode,
-            args.invert,
             args.common.verbose,
         )?
     };
+    if verbose {
+        diag!(
+            "[DEBUG] Decoded {} of {} interval tree(s)",
+            index_data.loaded_tree_count(),
+            index_data.tree_count()
+        );
+    }
+
+    if let (Some(path), Some((data, sources))) = (&args.unmatched_bed, &region_lines) {
+        // Whole-block modes drop roots by length; feature-only modes filter lines instead
+        let lengths = if args.common.feature_only() {
+            LengthFilter::default()
+        } else {
+            args.common.length_filter()
+        };
+        let hit = region_hit_flags(&index_data, &regions, mode, lengths)?;
+        let n = write_unmatched_regions(&data[..], sources, &hit, path)?;
+        if verbose {
+            diag!(
+                "[INFO] {} of {} region lines had no hits; written to {:?}",
+                n,
+                sources.len(),
+                path
+            );
+        }
+    }
 
     // Collect IDs for root features only
     let gof = load_gof(&args.common.input)?;
     let root_matches: Vec<RootMatched> = {
         let mut grouped: FxHashMap<u32, Vec<u32>> = FxHashMap::default();
-        for (root, _s, _e) in feats {
+        for &(root, _s, _e) in &feats {
             grouped.entry(root).or_default().push(root);
         }
         grouped
@@ -606,17 +1000,98 @@ pub fn run(args: &IntersectArgs) -> Result<()> {
             .collect()
     };
 
-    let roots: Vec<u32> = {
+    let mut roots: Vec<u32> = {
         let mut s: FxHashSet<u32> = FxHashSet::default();
         for rm in &root_matches {
             s.insert(rm.root);
         }
-        s.into_iter().collect()
+        let mut v: Vec<u32> = s.into_iter().collect();
+        v.sort_unstable();
+        v
+    };
+
+    let (mut blocks, missing_roots) = if args.invert {
+        // The complement: every GOF block whose root the regions did not select
+        let hit: FxHashSet<u32> = roots.iter().copied().collect();
+        let blocks: Vec<(u32, u64, u64)> = gof
+            .entries
+            .iter()
+            .filter(|e| !hit.contains(&e.feature_id))
+            .map(|e| (e.feature_id, e.start_offset, e.end_offset))
+            .collect();
+        if verbose {
+            diag!("[INFO] --invert: excluding {} model(s) selected by the regions", hit.len());
+        }
+        roots = blocks.iter().map(|&(root, _, _)| root).collect();
+        roots.sort_unstable();
+        roots.dedup();
+        (blocks, Vec::new())
+    } else {
+        gof.roots_to_offsets(&roots, args.common.effective_threads())
     };
+    if !missing_roots.is_empty() {
+        diag!(
+            "[WARN] {} of {} roots not found in the GOF index; their blocks are skipped.",
+            missing_roots.len(),
+            roots.len()
+        );
+        if verbose {
+            diag!("[DEBUG] Missing roots: {:?}", missing_roots);
+        }
+    }
+
+    // Whole-block modes apply the length bounds to the root extent
+    if !args.common.feature_only() {
+        blocks = args.common.length_filter().filter_blocks(&args.common.input, blocks)?;
+    }
+
+    let mut summary = RunSummary::new().input(&args.common.input);
+    if let Some(bed) = &args.bed {
+        summary = summary.input(bed);
+    }
+    let summary = summary.count(regions.len(), "region(s)").count(roots.len(), "model(s)");
 
-    let blocks: Vec<(u32, u64, u64)> = gof.roots_to_offsets(&roots, args.common.effective_threads());
+    if args.common.dry_run {
+        set_summary(summary);
+        let lengths = if args.common.feature_only() {
+            LengthFilter::default()
+        } else {
+            args.common.length_filter()
+        };
+        let hit = region_hit_flags(&index_data, &regions, mode, lengths)?;
+        let plan = DryRunPlan {
+            term_kind: "region(s)",
+            terms: regions.len(),
+            matched_terms: hit.iter().filter(|&&h| h).count(),
+            roots: roots.len(),
+        };
+        return plan.report(&args.common.input, &blocks, args.common.types.as_ref());
+    }
+
+    if let Some(bed) = &args.common.roots_bed {
+        // Score: the query regions hitting each model
+        let counts: FxHashMap<u32, usize> = root_matches.iter().map(|rm| (rm.root, rm.matched.len())).collect();
+        let rows = write_roots_bed(
+            &args.common.input,
+            &blocks,
+            &counts,
+            bed,
+            args.common.types.as_ref(),
+            args.common.verbose,
+        )?;
+        set_summary(summary.count(rows, "BED row(s)").output(Some(bed.as_path()).filter(|p| *p != Path::new("-"))));
+        return Ok(());
+    }
 
-    if !args.common.entire_group || args.common.types.is_some() {
+    if args.common.roots_only {
+        write_gff_roots_only(
+            args.common.input.as_path(),
+            &blocks,
+            &args.common.output,
+            args.common.types.as_ref(),
+            args.common.verbose,
+        )?;
+    } else if !args.common.entire_group {
         // Build query interval map by seq name
         let query_ivmap: FxHashMap<String, Vec<(u32, u32)>> = {
             let mut num_to_seq: FxHashMap<u32, String> = FxHashMap::default();
@@ -629,6 +1104,17 @@ pub fn run(args: &IntersectArgs) -> Result<()> {
                     m.entry(seq_name.clone()).or_default().push((s, e));
                 }
             }
+            // Duplicated regions (common in peak-caller output) add nothing but comparisons
+            let mut dup_regions = 0usize;
+            for ivs in m.values_mut() {
+                let before = ivs.len();
+                ivs.sort_unstable();
+                ivs.dedup();
+                dup_regions += before - ivs.len();
+            }
+            if verbose && dup_regions > 0 {
+                diag!("[INFO] Suppressed {} duplicate query region(s)", dup_regions);
+            }
             m
         };
 
@@ -636,13 +1122,25 @@ pub fn run(args: &IntersectArgs) -> Result<()> {
             write_gff_match_only_by_coords(
                 args.common.input.as_path(),
                 &blocks,
-                &query_ivmap,
-                args.common.types.as_deref(),
+                (!args.invert).then_some(&query_ivmap),
+                args.common.types.as_ref(),
                 &args.common.output,
                 mode,
+                args.common.length_filter(),
+                args.common.provenance,
                 args.common.verbose,
             )?;
         }
+    } else if let Some(types) = &args.common.types {
+        // Whole models, minus the lines of other types (lengths were applied to the root extent)
+        write_gff_output_typed(
+            args.common.input.as_path(),
+            &blocks,
+            &args.common.output,
+            types,
+            LengthFilter::default(),
+            args.common.verbose,
+        )?;
     } else {
         write_gff_output(
             args.common.input.as_path(),
@@ -651,5 +1149,114 @@ pub fn run(args: &IntersectArgs) -> Result<()> {
             args.common.verbose,
         )?;
     }
+    set_summary(summary.output(args.common.output.as_deref()));
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{SAMPLE_GFF, ScratchDir, build_fixture, data_lines};
+
+    #[test]
+    fn bed_regions_are_clamped_to_sequence_ends() {
+        let dir = ScratchDir::new("intersect-clamp").unwrap();
+        let sizes = dir.join("g.sizes");
+        std::fs::write(&sizes, "chr1\t1000\n").unwrap();
+        let seq_lens = SeqLengths::from_chrom_sizes(&sizes).unwrap();
+        let seqid_map: FxHashMap<String, u32> =
+            [("chr1".to_string(), 0), ("chr2".to_string(), 1)].into_iter().collect();
+
+        let bed = dir.join("q.bed");
+        std::fs::write(&bed, "chr1\t0\t10\nchr1\t990\t1200\nchr1\t1000\t1100\nchr2\t5000\t6000\nchr3\t0\t5\n").unwrap();
+        let regions = parse_bed_file(&bed, &seqid_map, &seq_lens, false).unwrap();
+        // The region at coordinate 0 is kept as is, the one past the end of chr1 is cut,
+        // the one starting at its end is dropped; chr2 has no known length
+        assert_eq!(regions, vec![(0, 0, 10), (0, 990, 1000), (1, 5000, 6000)]);
+    }
+
+    fn intersect_ids(gff: &Path, regions: &Path, out: &Path, extra: &[&str]) -> Vec<String> {
+        let mut argv = vec!["intersect", "-i", gff.to_str().unwrap(), "-b", regions.to_str().unwrap()];
+        argv.extend_from_slice(&["-o", out.to_str().unwrap()]);
+        argv.extend_from_slice(extra);
+        run(&IntersectArgs::try_parse_from(argv).unwrap()).unwrap();
+        let mut ids: Vec<String> = data_lines(out)
+            .unwrap()
+            .iter()
+            .filter_map(|l| l.split("ID=").nth(1)?.split(';').next().map(String::from))
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn gff_region_lines_are_one_based_inclusive() {
+        let seqid_map: FxHashMap<String, u32> = [("chr1".to_string(), 0)].into_iter().collect();
+        let data = b"##gff-version 3\nchr1\t.\tgene\t100\t900\t.\t+\t.\tID=g\r\n\
+                     chr1\t.\texon\t1\t1\t.\t+\t.\tID=e\nchr9\t.\tgene\t5\t6\t.\t+\t.\tID=x\n\
+                     ##FASTA\n>chr1\nACGT\n";
+        let none = SeqLengths::default();
+        let all = parse_region_bytes(data, RegionFormat::Gff, None, &seqid_map, &none, false).unwrap();
+        assert_eq!(all, vec![(0, 99, 900), (0, 0, 1)]);
+        let genes = TypeFilter::parse("gene").unwrap();
+        let only = parse_region_bytes(data, RegionFormat::Gff, Some(&genes), &seqid_map, &none, false).unwrap();
+        assert_eq!(only, vec![(0, 99, 900)]);
+
+        assert_eq!(RegionFormat::from_path(Path::new("a.GFF3")), RegionFormat::Gff);
+        assert_eq!(RegionFormat::from_path(Path::new("a.gtf")), RegionFormat::Gff);
+        assert_eq!(RegionFormat::from_path(Path::new("a.bed")), RegionFormat::Bed);
+    }
+
+    #[test]
+    fn annotation_against_itself_and_a_shifted_copy() {
+        let fx = build_fixture(SAMPLE_GFF).unwrap();
+        let out = fx.dir.join("out.gff");
+        let all: Vec<String> = {
+            let mut ids: Vec<String> = (1..=3).map(|i| format!("g{i}")).collect();
+            ids.extend((1..=4).map(|i| format!("t{i}")));
+            ids.extend((1..=5).map(|i| format!("e{i}")));
+            ids.push("c1".to_string());
+            ids.sort();
+            ids
+        };
+        // Every feature lies within its own gene
+        assert_eq!(intersect_ids(&fx.gff, &fx.gff, &out, &["--region-types", "gene", "-c"]), all);
+
+        // g1 moved 500 bp right overlaps only the parts of g1 past base 600
+        let shifted = fx.dir.join("shifted.txt");
+        std::fs::write(&shifted, "chr1\tsrc\tgene\t600\t1400\t.\t+\t.\tID=g1\n").unwrap();
+        let hits = intersect_ids(&fx.gff, &shifted, &out, &["--regions-format", "gff"]);
+        assert_eq!(hits, ["e2", "e3", "g1", "t1", "t2"]);
+    }
+
+    #[test]
+    fn unmatched_bed_partitions_the_regions_exactly() {
+        let fx = build_fixture(SAMPLE_GFF).unwrap();
+        let bed = fx.dir.join("q.bed");
+        let lines = [
+            "chr1\t150\t250\tr1\t0\t+",
+            "chr1\t1000\t1500\tr2\t0\t+",
+            "# comment",
+            "chr1\t2500\t2600\tr3",
+            "chr2\t0\t500\tr4\t7",
+            "chrUn\t1\t10\tr5",
+        ];
+        std::fs::write(&bed, lines.join("\n") + "\n").unwrap();
+        let unmatched = fx.dir.join("unmatched.bed");
+        let out = fx.dir.join("out.gff");
+        let unmatched_lines = |extra: &[&str]| -> Vec<String> {
+            let mut argv = vec!["intersect", "-i", fx.gff.to_str().unwrap(), "-b", bed.to_str().unwrap()];
+            argv.extend_from_slice(&["-o", out.to_str().unwrap(), "--unmatched-bed", unmatched.to_str().unwrap()]);
+            argv.extend_from_slice(extra);
+            run(&IntersectArgs::try_parse_from(argv).unwrap()).unwrap();
+            std::fs::read_to_string(&unmatched).unwrap().lines().map(String::from).collect()
+        };
+
+        // Original bytes, in file order; unknown seqids count as unmatched
+        assert_eq!(unmatched_lines(&[]), [lines[1], lines[5]]);
+        // Hits are roots: only g3 lies within its region
+        assert_eq!(unmatched_lines(&["-c"]), [lines[0], lines[1], lines[3], lines[5]]);
+        // Whole-model output drops roots longer than --max-length, so their regions are unmatched
+        assert_eq!(unmatched_lines(&["-e", "--max-length", "500"]), [lines[0], lines[1], lines[3], lines[5]]);
+    }
+}
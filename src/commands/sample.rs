@@ -1,32 +1,48 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{ArgGroup, Parser};
 use rayon::prelude::*;
 use rand::seq::{IndexedRandom};
-use rand::rng;
+use rand::{RngCore, SeedableRng, rng};
+use rand::rngs::StdRng;
+use rustc_hash::FxHashSet;
 use std::{
     path::PathBuf,
 };
-use crate::{load_gof, write_gff_output};
+use crate::{
+    BED_CHUNK_SIZE, BedReader, RunSummary, TreeIndexData, deterministic, load_fts, load_gof, load_prt, load_sqs,
+    parse_bed_chunk, parse_fraction, read_feature_names, resolve_feature_roots, set_index_prefix, set_summary,
+    set_write_header, write_gff_output,
+};
 
 /// Arguments
 #[derive(Parser, Debug)]
 #[command(
     about = "Sample feature groups per chromosome",
-    long_about = "Sample feature groups per chromosome."
+    long_about = "Sample feature groups per chromosome.",
+    group = ArgGroup::new("amount").args(["ratio", "number"]).required(true)
 )]
 pub struct SampleArgs {
     /// GFF file path (indexed via GOF)
     #[arg(short = 'i', long = "input", value_name = "FILE")]
     pub input: PathBuf,
 
-    /// Ratio of downsampling
-    #[arg(short = 'r', long = "ratio")]
-    pub ratio: f32,
-    
+    /// Fraction of the models of each chromosome to keep (0 to 1, rounded up)
+    #[arg(short = 'r', long = "ratio", value_parser = parse_fraction)]
+    pub ratio: Option<f64>,
+
+    /// Keep exactly N models per chromosome (all of them on chromosomes with fewer)
+    #[arg(short = 'n', long = "number", value_name = "N")]
+    pub number: Option<usize>,
+
     /// Output file (required)
     #[arg(short = 'o', long = "output", value_name = "FILE")]
     pub output: Option<PathBuf>,
 
+    /// Read the index files from PREFIX (see `index --index-prefix`) instead of from
+    /// next to the input
+    #[arg(long = "index-prefix", value_name = "PREFIX")]
+    pub index_prefix: Option<PathBuf>,
+
     /// Number of threads
     #[arg(short = 't', long = "threads", default_value_t = 12, value_name = "NUM")]
     pub threads: usize,
@@ -34,9 +50,94 @@ pub struct SampleArgs {
     /// Verbose logs
     #[arg(short = 'v', long = "verbose", default_value_t = false, value_name = "BOOL")]
     pub verbose: bool,
+
+    /// Sample only among the models overlapping a region of this BED file
+    #[arg(long = "bed", value_name = "FILE")]
+    pub bed: Option<PathBuf>,
+
+    /// Sample only among the models of the feature IDs in this file (one per line);
+    /// with --bed, among the models selected by both
+    #[arg(long = "feature-file", value_name = "FILE")]
+    pub feature_file: Option<PathBuf>,
+
+    /// Seed the sampling so that the same input, amount and seed always select the same
+    /// models (independent of --threads); default: a fresh random sample each run, or
+    /// seed 0 under --deterministic
+    #[arg(long = "seed", value_name = "N")]
+    pub seed: Option<u64>,
+
+    /// Leave out the leading '#' lines of the input (`##gff-version 3`, ...) that the
+    /// output otherwise starts with
+    #[arg(long = "no-header", default_value_t = false)]
+    pub no_header: bool,
+}
+
+/// (seqid number, sampled blocks, models available) of one chromosome
+type SeqidSample = (u32, Vec<(u32, u64, u64)>, usize);
+
+/// Seed used under `--deterministic` when `--seed` is not given.
+const DETERMINISTIC_SEED: u64 = 0;
+
+/// RNG of one sequence: seeded from `seed` and the sequence number, so each sequence
+/// draws the same models whichever thread handles it; unseeded without `seed`.
+fn sequence_rng(seed: Option<u64>, seqid_num: u32) -> Box<dyn RngCore> {
+    match seed {
+        Some(seed) => Box::new(StdRng::seed_from_u64(seed ^ u64::from(seqid_num).wrapping_mul(0x9E37_79B9_7F4A_7C15))),
+        None => Box::new(rng()),
+    }
+}
+
+/// Roots selected by `--bed` and `--feature-file` (both: the intersection);
+/// `None` when neither is given.
+fn candidate_roots(args: &SampleArgs, threads: usize) -> Result<Option<FxHashSet<u32>>> {
+    let gff_path = &args.input;
+    let mut candidates: Option<FxHashSet<u32>> = None;
+
+    if let Some(bed) = &args.bed {
+        let index = TreeIndexData::load_tree_index(gff_path)?;
+        let mut roots: FxHashSet<u32> = FxHashSet::default();
+        let mut unknown = 0usize;
+        BedReader::open(bed)?.for_each_chunk(BED_CHUNK_SIZE, |chunk| {
+            for (seqid, start, end) in parse_bed_chunk(chunk) {
+                match index.query(seqid, start, end) {
+                    Ok(hits) => roots.extend(hits),
+                    Err(_) => unknown += 1,
+                }
+            }
+            Ok(())
+        })?;
+        if unknown > 0 {
+            diag!("[WARN] {} BED region(s) on sequences not in the index were skipped", unknown);
+        }
+        if args.verbose {
+            diag!("[INFO] {} model(s) overlap the regions of {:?}", roots.len(), bed);
+        }
+        candidates = Some(roots);
+    }
+
+    if let Some(feature_file) = &args.feature_file {
+        let names = read_feature_names(Some(feature_file), None)?;
+        let fts = load_fts(gff_path)?;
+        let prt = load_prt(gff_path)?;
+        let (_, roots_vec) = resolve_feature_roots(&fts, &prt, &names, threads);
+        let roots: FxHashSet<u32> = roots_vec.into_iter().filter(|&r| r != u32::MAX).collect();
+        if args.verbose {
+            diag!("[INFO] {} model(s) hold the features of {:?}", roots.len(), feature_file);
+        }
+        candidates = Some(match candidates {
+            Some(bed_roots) => roots.intersection(&bed_roots).copied().collect(),
+            None => roots,
+        });
+    }
+
+    if args.verbose && let Some(c) = &candidates {
+        diag!("[INFO] Sampling among {} candidate model(s)", c.len());
+    }
+    Ok(candidates)
 }
 
 pub fn run(args: &SampleArgs) -> Result<()> {
+    set_index_prefix(&args.input, args.index_prefix.as_deref());
     let verbose = args.verbose;
     let threads = if args.threads == 0 {
         std::thread::available_parallelism()
@@ -46,43 +147,96 @@ pub fn run(args: &SampleArgs) -> Result<()> {
         args.threads
     };
     let _ = rayon::ThreadPoolBuilder::new().num_threads(threads).build_global();
+    set_write_header(!args.no_header);
     let gff_path = &args.input;
     
-    let gof = load_gof(&gff_path)?;
+    let gof = load_gof(gff_path)?;
+    let candidates = candidate_roots(args, threads)?;
+    let seed = args.seed.or(deterministic().then_some(DETERMINISTIC_SEED));
 
-    let blocks: Vec<(u32, u64, u64)> = gof.seqid_index
+    let mut per_seqid: Vec<SeqidSample> = gof.seqid_index
         .par_iter()
-        .flat_map(|(_seqid_num, indices)| {
-            let mut rng = rng();
+        .map(|(&seqid_num, indices)| {
+            let mut rng = sequence_rng(seed, seqid_num);
     
-            // 1. collect all fids for this chromosome
+            // 1. collect all fids for this chromosome (among the candidates, if any);
+            // a root split into several blocks is one model
+            let mut seen: FxHashSet<u32> = FxHashSet::default();
             let fids: Vec<u32> = indices
                 .iter()
                 .map(|&i| gof.entries[i].feature_id)
+                .filter(|fid| candidates.as_ref().is_none_or(|c| c.contains(fid)))
+                .filter(|&fid| seen.insert(fid))
                 .collect();
     
             if fids.is_empty() {
-                return Vec::new();
+                return (seqid_num, Vec::new(), 0);
             }
     
-            // 2. sample 10% fids
-            let sample_size = (fids.len() as f32 * args.ratio).ceil() as usize;
+            // 2. sample a fraction (rounded up) or a fixed number of fids
+            let sample_size = match (args.number, args.ratio) {
+                (Some(n), _) => n.min(fids.len()),
+                (None, ratio) => (fids.len() as f64 * ratio.unwrap_or(1.0)).ceil() as usize,
+            };
             let sampled: Vec<u32> = fids.choose_multiple(&mut rng, sample_size).cloned().collect();
     
             // 3. use index_cached() to get offsets
             let idx = gof.index_cached();
-            sampled
+            let blocks = sampled
                 .into_iter()
                 .filter_map(|fid| idx.get(&fid).map(|&(s, e)| (fid, s, e)))
-                .collect::<Vec<_>>()
+                .collect::<Vec<_>>();
+            (seqid_num, blocks, fids.len())
         })
         .collect();
 
+    if verbose {
+        per_seqid.sort_unstable_by_key(|&(seqid_num, _, _)| seqid_num);
+        let (names, _) = load_sqs(gff_path)?;
+        for (seqid_num, blocks, available) in per_seqid.iter().filter(|(_, _, n)| *n > 0) {
+            let name = names.get(*seqid_num as usize).map_or("?", String::as_str);
+            diag!("[INFO] {}: sampled {} of {} model(s)", name, blocks.len(), available);
+        }
+    }
+    let blocks: Vec<(u32, u64, u64)> = per_seqid.into_iter().flat_map(|(_, blocks, _)| blocks).collect();
+
     // Step 3: write sampled GFF blocks
     write_gff_output(gff_path, &blocks, &args.output, verbose)?;
+    let pool = candidates.as_ref().map_or(gof.entries.len(), |c| c.len());
+    set_summary(
+        RunSummary::new()
+            .input(gff_path)
+            .matched(blocks.len(), pool, "candidate model(s) sampled")
+            .output(args.output.as_deref()),
+    );
     Ok(())
 }
 
 
 
-        
\ No newline at end of file
+        
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{GffSynth, data_lines};
+
+    #[test]
+    fn seeded_samples_do_not_depend_on_threads() {
+        let (_, fx) = GffSynth::new().chromosomes(3).genes_per_chrom(40).seed(5).fixture().unwrap();
+        let out = fx.dir.join("sample.gff");
+        let sample = |seed: &str, threads: &str| {
+            let argv = [
+                "sample", "-i", fx.gff.to_str().unwrap(), "-r", "0.25", "-o", out.to_str().unwrap(), "-t", threads,
+                "--seed", seed,
+            ];
+            run(&SampleArgs::try_parse_from(argv).unwrap()).unwrap();
+            data_lines(&out).unwrap()
+        };
+        let first = sample("7", "1");
+        assert_eq!(first, sample("7", "4"));
+        assert_ne!(first, sample("8", "1"));
+        // A quarter of the 40 genes of each sequence
+        assert_eq!(first.iter().filter(|l| l.split('\t').nth(2) == Some("gene")).count(), 30);
+    }
+}
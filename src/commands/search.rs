@@ -1,18 +1,22 @@
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
 use clap::{ArgGroup, Parser};
 use rustc_hash::{FxHashMap, FxHashSet};
-use regex::Regex;
+use regex::{Regex, escape};
+use rayon::prelude::*;
 use std::{
     fs::File,
     io::{BufReader, BufRead},
-    path::PathBuf,
+    path::{Path, PathBuf},
     time::Instant,
 };
 
 
 use crate::{
-    CommonArgs, load_gof, load_prt, load_a2f, load_atn,
-    write_gff_output, write_gff_output_filtered,
+    A2fMap, CommonArgs, DryRunPlan, Error, LengthFilter, LineSelection, NAME_KEY, RunSummary, SeqLengths, TreeIndexData,
+    context_roots, load_fts, load_gof, load_prt, AtnTable, command_line_key, indexed_attr_keys, load_a2f, load_atn,
+    load_attr_index, load_name_index, set_summary, write_context_blocks, write_gff_output, write_gff_output_filtered,
+    write_gff_output_resumable, write_gff_output_typed, write_gff_roots_only, pad_roots, write_padded_blocks,
+    did_you_mean, drop_context_roots, ensure_index, load_meta, write_roots_bed, write_gff_output_grouped, QueryGroup,
 };
 
 #[derive(Parser, Debug)]
@@ -48,19 +52,234 @@ pub struct SearchArgs {
         long,
         help = "Enable regex mode for attribute matching")]
     regex: bool,
+
+    /// Regex mode: wrap each pattern in ^...$ so it must match the whole value
+    #[arg(long, requires = "regex")]
+    anchored: bool,
+
+    /// Regex mode: treat each pattern as a literal string (metacharacters escaped)
+    #[arg(long = "fixed-strings", requires = "regex")]
+    fixed_strings: bool,
+
+    /// Attribute key to search: any key the index was built with (`index -a`) or
+    /// `Name`. Defaults to the first indexed key, falling back to the Name index
+    /// when that yields no hits. Repeat (`-k gene_name -k Name`) to match the
+    /// values in any of the keys.
+    #[arg(short = 'k', long, value_name = "KEY")]
+    key: Vec<String>,
+
+    /// Also emit the N models before and after each matched model on the same
+    /// sequence (whole models, appended after the matches under `# context-for: <ID>`)
+    #[arg(long = "context", value_name = "N")]
+    context: Option<usize>,
+
+    /// Also emit the other models lying within BP bases of a matched model on the same
+    /// sequence (whole models, appended after the matches under `# padded-for: <ID>`;
+    /// models already written by --context are not repeated); requires -e/--entire-group
+    #[arg(long = "pad-models", value_name = "BP", requires = "entire_group")]
+    pad_models: Option<u32>,
+
+    /// Write the output in synced chunks and record progress in FILE; rerunning the
+    /// same command after a crash resumes after the last recorded chunk (requires -o)
+    #[arg(
+        long = "checkpoint",
+        value_name = "FILE",
+        requires = "output",
+        conflicts_with_all = ["context", "pad_models", "roots_only", "dry_run"]
+    )]
+    checkpoint: Option<PathBuf>,
+
+    /// Write the models grouped by query, in the order of the values (or patterns) in
+    /// -A/--attr-list, each group under `# query: <value>`; a model matched by several
+    /// queries goes under the first. Default: one list in file order
+    #[arg(long = "preserve-query-order", conflicts_with_all = ["checkpoint", "roots_only", "roots_bed"])]
+    preserve_query_order: bool,
+}
+
+/// A value to search for, with where it came from (for error messages).
+struct AttrQuery {
+    value: String,
+    origin: String,
+}
+
+/// How query values are turned into matchers.
+#[derive(Clone, Copy)]
+struct MatchOptions {
+    regex: bool,
+    anchored: bool,
+    fixed_strings: bool,
+}
+
+/// Compile one query into a regex, applying --fixed-strings and --anchored.
+fn compile_pattern(query: &AttrQuery, opts: MatchOptions) -> Result<Regex> {
+    let body = if opts.fixed_strings {
+        escape(&query.value)
+    } else {
+        query.value.clone()
+    };
+    let pattern = if opts.anchored {
+        format!("^(?:{})$", body)
+    } else {
+        body
+    };
+    Regex::new(&pattern)
+        .with_context(|| format!("Invalid regex '{}' ({})", query.value, query.origin))
+}
+
+/// Values scanned per parallel work unit; only the matches of a chunk are kept.
+const SCAN_CHUNK: u32 = 1 << 16;
+
+/// Index of the query matching a value, if any.
+type Matcher<'q> = Box<dyn Fn(&str) -> Option<usize> + Sync + 'q>;
+
+/// (key, its value table unless it is the primary `.atn`, AIDs per query, a2f of that key)
+type KeyMatches<'k> = (&'k str, Option<AtnTable>, Vec<Vec<u32>>, A2fMap);
+
+/// (key, matched value -> AIDs, a2f of that key)
+type KeyHits<'t> = (&'t str, FxHashMap<&'t str, Vec<u32>>, &'t A2fMap);
+
+/// AIDs of `table` matched by each query, indexed like `queries` (by regex in regex
+/// mode, exact string match otherwise). A value matched by several patterns is
+/// credited to the first of them.
+fn match_attr_values(queries: &[AttrQuery], table: &AtnTable, opts: MatchOptions) -> Result<Vec<Vec<u32>>> {
+    let matcher: Matcher<'_> = if opts.regex {
+        let patterns: Vec<Regex> = queries
+            .iter()
+            .map(|q| compile_pattern(q, opts))
+            .collect::<Result<Vec<_>>>()?;
+        Box::new(move |val| patterns.iter().position(|re| re.is_match(val)))
+    } else {
+        exact_matcher(queries.iter().map(|q| q.value.as_str()))
+    };
+    Ok(scan_table(table, &matcher, queries.len()))
+}
+
+/// Matcher crediting a value to the first of `values` equal to it.
+fn exact_matcher<'q>(values: impl Iterator<Item = &'q str>) -> Matcher<'q> {
+    let mut wanted: FxHashMap<&str, usize> = FxHashMap::default();
+    for (i, v) in values.enumerate() {
+        wanted.entry(v).or_insert(i);
+    }
+    Box::new(move |val| wanted.get(val).copied())
+}
+
+/// AIDs of `table` that `matcher` credits to each of `queries` queries.
+fn scan_table(table: &AtnTable, matcher: &Matcher<'_>, queries: usize) -> Vec<Vec<u32>> {
+    let n = table.len() as u32;
+    let chunks: Vec<Vec<(u32, u32)>> = (0..n.div_ceil(SCAN_CHUNK))
+        .into_par_iter()
+        .map(|c| {
+            table
+                .range(c * SCAN_CHUNK..(c + 1).saturating_mul(SCAN_CHUNK))
+                .filter_map(|(aid, val)| matcher(val).map(|q| (q as u32, aid)))
+                .collect()
+        })
+        .collect();
+
+    let mut hits: Vec<Vec<u32>> = vec![Vec::new(); queries];
+    for (q, aid) in chunks.into_iter().flatten() {
+        hits[q as usize].push(aid);
+    }
+    hits
+}
+
+/// Group the AIDs matched under one key by their value, borrowed from `table`.
+fn hits_by_value<'t>(hits: &[Vec<u32>], table: &'t AtnTable) -> FxHashMap<&'t str, Vec<u32>> {
+    let mut attr_to_aids: FxHashMap<&str, Vec<u32>> = FxHashMap::default();
+    for &aid in hits.iter().flatten() {
+        attr_to_aids.entry(table.value(aid)).or_default().push(aid);
+    }
+    attr_to_aids
+}
+
+/// Map the AIDs matched under one key to sorted, deduplicated FIDs; values whose
+/// AIDs resolve to no feature are dropped.
+fn aids_to_fids<'t>(attr_to_aids: &FxHashMap<&'t str, Vec<u32>>, a2f: &A2fMap) -> FxHashMap<&'t str, Vec<u32>> {
+    let mut attr_to_fids: FxHashMap<&str, Vec<u32>> = FxHashMap::default();
+    for (&attr_val, aids) in attr_to_aids {
+        let mut fids = a2f.map_aids_to_fids_vec(aids);
+        fids.sort_unstable();
+        fids.dedup();
+        if !fids.is_empty() {
+            attr_to_fids.insert(attr_val, fids);
+        }
+    }
+    attr_to_fids
+}
+
+/// Models (root FIDs, sorted) of the features whose `key` value equals one of `values`,
+/// like `gffx search -k <key>` without `--regex`; `None` searches the primary key of
+/// the index. `Name` is served by the Name index when it has no value table of its own.
+///
+/// Fails with `IndexMissing` when `gff` is not indexed, and with `NoMatches` when `key`
+/// is not indexed or no value resolves to a model.
+pub fn find_roots(gff: &Path, key: Option<&str>, values: &[&str]) -> crate::error::Result<Vec<u32>> {
+    let atn = load_atn(gff)?;
+    let key = key.unwrap_or(atn.attr_name());
+    let (table, a2f) = if key == atn.attr_name() {
+        (None, load_a2f(gff)?)
+    } else {
+        let loaded = match load_attr_index(gff, key)? {
+            Some(t) => Some(t),
+            None if key == NAME_KEY => load_name_index(gff)?,
+            None => None,
+        };
+        let Some((values, key_a2f)) = loaded else {
+            return Err(Error::NoMatches { query: format!("key '{}', which is not indexed", key) });
+        };
+        (Some(values), key_a2f)
+    };
+    let table = table.as_ref().unwrap_or(&atn);
+
+    let hits = scan_table(table, &exact_matcher(values.iter().copied()), values.len());
+    let mut fids: Vec<u32> = aids_to_fids(&hits_by_value(&hits, table), &a2f).into_values().flatten().collect();
+    fids.sort_unstable();
+    fids.dedup();
+    let prt = load_prt(gff)?;
+    let roots = prt.map_fids_to_roots(&fids, rayon::current_num_threads());
+    let mut roots: Vec<u32> = prt.fid_root_pairs(&fids, &roots).into_iter().map(|(_, r)| r).collect();
+    roots.sort_unstable();
+    roots.dedup();
+    if roots.is_empty() {
+        return Err(Error::NoMatches { query: format!("{}={}", key, values.join(",")) });
+    }
+    Ok(roots)
+}
+
+/// Hint for a search key that found nothing, from the attribute keys recorded in
+/// `.meta`: whether `key` occurs in the GFF at all and, if not, the closest key that
+/// does. `None` when the index records no keys.
+fn key_hint(gff_path: &Path, key: &str, indexed: &[&str]) -> Option<String> {
+    let keys = load_meta(gff_path).ok().flatten()?.attr_keys;
+    if keys.is_empty() {
+        return None;
+    }
+    if keys.iter().any(|k| k == key) {
+        return (!indexed.contains(&key))
+            .then(|| format!("'{}' occurs in the GFF; rebuild the index with -a {}", key, key));
+    }
+    let hint = format!("no feature in the GFF has a '{}' attribute", key);
+    Some(match did_you_mean(key, keys.iter().map(String::as_str)) {
+        Some(k) if indexed.contains(&k) => format!("{hint}; did you mean '{k}'?"),
+        Some(k) => format!("{hint}; did you mean '{k}'? (rebuild the index with -a {k})"),
+        None => hint,
+    })
 }
 
-/// When in `per-feature` mode: within each root block, emit only lines whose `ID` exactly matches
-/// the user-specified features under that root. Optional `types_filter` is applied to column 3.
 pub fn run(args: &SearchArgs) -> Result<()> {
     let verbose = args.common.verbose;
     let gff_path = &args.common.input;
 
     // Init thread pool
+    args.common.post_parse()?;
+    ensure_index(gff_path, args.common.auto_index, verbose)?;
+    if args.checkpoint.is_some() && args.common.entire_group && args.common.types.is_some() {
+        bail!("--checkpoint writes -e models whole; drop -T/--types or --checkpoint");
+    }
     let overall_start = Instant::now();
     if verbose {
-        eprintln!("[DEBUG] Starting processing of {:?}", gff_path);
-        eprintln!(
+        diag!("[DEBUG] Starting processing of {:?}", gff_path);
+        diag!(
             "[DEBUG] Thread pool initialized with {} threads",
             args.common.effective_threads()
         );
@@ -70,81 +289,204 @@ pub fn run(args: &SearchArgs) -> Result<()> {
     let prt = load_prt(gff_path)?;          // parent pointers (fid -> parent fid)
     let gof = load_gof(gff_path)?;          // GOF offsets (fid -> (start,end))
     let a2f = load_a2f(gff_path)?;          // attribute index -> fid
-    let (atn_attr_name, atn_values) = load_atn(gff_path)?; // attribute values table (index-aligned)
+    let atn = load_atn(gff_path)?;          // attribute values table (index-aligned)
+    let atn_attr_name = atn.attr_name();
+    // Every key with its own value table (older indexes: just the .atn key); `Name`
+    // is served by the secondary Name index otherwise
+    let mut indexed_keys = indexed_attr_keys(gff_path)?;
+    if indexed_keys.is_empty() {
+        indexed_keys.push(atn_attr_name.to_string());
+    }
+    let mut available: Vec<&str> = indexed_keys.iter().map(String::as_str).collect();
+    if !available.contains(&NAME_KEY) {
+        available.push(NAME_KEY);
+    }
 
     // Collect attribute values from file or single arg
-    let attr_values: Vec<String> = if let Some(file) = &args.attr_list {
+    let attr_values: Vec<AttrQuery> = if let Some(file) = &args.attr_list {
         let reader = BufReader::new(File::open(file)?);
-        reader
-            .lines()
-            .map(|r| r.map(|s| s.trim().to_owned()))
-            .filter(|r| r.as_ref().map_or(true, |s| !s.is_empty()))
-            .collect::<Result<Vec<_>, _>>()?
+        let mut queries = Vec::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let value = line.trim();
+            if value.is_empty() {
+                continue;
+            }
+            queries.push(AttrQuery {
+                value: value.to_owned(),
+                origin: format!("line {} of {}", i + 1, file.display()),
+            });
+        }
+        queries
     } else if let Some(val) = &args.attr {
-        vec![val.clone()]
+        vec![AttrQuery { value: val.clone(), origin: "--attr".to_string() }]
     } else {
         bail!("Either --attr-list (-A) or --attr (-a) must be provided.");
     };
 
-    // Step 1: build attribute -> AID list
+    // Step 1: build attribute -> AID list per searched key
     // In regex mode, match by regex; otherwise exact string match.
-    let mut attr_to_aids: FxHashMap<String, Vec<u32>> = FxHashMap::default();
-    if args.regex {
-        let patterns: Vec<Regex> = attr_values
-            .iter()
-            .map(String::as_str)
-            .map(Regex::new)
-            .collect::<std::result::Result<Vec<_>, _>>()?;
+    let mut keys: Vec<&str> = Vec::new();
+    for k in &args.key {
+        if !available.contains(&k.as_str()) {
+            let hint = key_hint(gff_path, k, &available)
+                .or_else(|| did_you_mean(k, available.iter().copied()).map(|s| format!("did you mean '{s}'?")));
+            bail!(
+                "Attribute key '{}' is not indexed (available: {}){}",
+                k,
+                available.iter().map(|k| format!("'{k}'")).collect::<Vec<_>>().join(", "),
+                hint.map(|h| format!("; {h}")).unwrap_or_default()
+            );
+        }
+        if !keys.contains(&k.as_str()) {
+            keys.push(k);
+        }
+    }
 
-        for (i, val) in atn_values.iter().enumerate() {
-            if patterns.iter().any(|re| re.is_match(val)) {
-                attr_to_aids.entry(val.clone()).or_default().push(i as u32);
-            }
+    let match_opts = MatchOptions {
+        regex: args.regex,
+        anchored: args.anchored,
+        fixed_strings: args.fixed_strings,
+    };
+    // Value table and a2f of a key other than the first: its own table, or for `Name`
+    // the secondary Name index
+    let load_key = |k: &str| -> Result<Option<(AtnTable, A2fMap)>> {
+        Ok(match load_attr_index(gff_path, k)? {
+            Some(t) => Some(t),
+            None if k == NAME_KEY => load_name_index(gff_path)?,
+            None => None,
+        })
+    };
+
+    // Every searched key with hits; values are looked up only for the hits, once the
+    // tables are loaded
+    let mut matched: Vec<KeyMatches> = Vec::new();
+    let no_hits = |hits: &[Vec<u32>]| hits.iter().all(Vec::is_empty);
+    if keys.is_empty() {
+        let hits = match_attr_values(&attr_values, &atn, match_opts)?;
+        if !no_hits(&hits) {
+            matched.push((atn_attr_name, None, hits, a2f));
+        } else if atn_attr_name != NAME_KEY
+            && let Some((values, name_a2f)) = load_key(NAME_KEY)?
+        {
+            // Fall back to the Name index
+            diag!(
+                "[INFO] No matches for primary key '{}'; falling back to the {} index.",
+                atn_attr_name, NAME_KEY
+            );
+            let hits = match_attr_values(&attr_values, &values, match_opts)?;
+            matched.push((NAME_KEY, Some(values), hits, name_a2f));
         }
     } else {
-        let wanted: FxHashSet<&str> = attr_values.iter().map(String::as_str).collect();
-        for (i, val) in atn_values.iter().enumerate() {
-            if wanted.contains(val.as_str()) {
-                attr_to_aids.entry(val.clone()).or_default().push(i as u32);
+        let mut a2f = Some(a2f);
+        for &k in &keys {
+            let (table, hits, key_a2f) = if k == atn_attr_name {
+                let hits = match_attr_values(&attr_values, &atn, match_opts)?;
+                (None, hits, a2f.take().expect("keys are deduplicated"))
+            } else {
+                let (values, key_a2f) = load_key(k)?.with_context(|| {
+                    format!(
+                        "No {} index found for {:?}; rebuild the index without --no-name-index.",
+                        k, gff_path
+                    )
+                })?;
+                let hits = match_attr_values(&attr_values, &values, match_opts)?;
+                (Some(values), hits, key_a2f)
+            };
+            if no_hits(&hits) {
+                if keys.len() > 1 {
+                    diag!("[INFO] Key '{}': no values matched", k);
+                }
+            } else {
+                matched.push((k, table, hits, key_a2f));
             }
         }
     }
 
+    // (key, matched value -> AIDs, a2f of that key) for every key with hits
+    let mut key_hits: Vec<KeyHits> = matched
+        .iter()
+        .map(|(key, table, hits, key_a2f)| (*key, hits_by_value(hits, table.as_ref().unwrap_or(&atn)), key_a2f))
+        .collect();
+    key_hits.retain(|(_, attr_to_aids, _)| !attr_to_aids.is_empty());
+
     // Nothing matched → early exit with a helpful error
-    if attr_to_aids.is_empty() {
-        bail!("None of the attributes matched.");
+    if key_hits.is_empty() {
+        let searched: Vec<&str> = if keys.is_empty() { vec![atn_attr_name] } else { keys.clone() };
+        let mut hints: Vec<String> = searched
+            .iter()
+            .filter_map(|k| key_hint(gff_path, k, &available))
+            .collect();
+        let unsearched: Vec<String> = indexed_keys
+            .iter()
+            .filter(|k| !searched.contains(&k.as_str()))
+            .map(|k| format!("'{k}'"))
+            .collect();
+        if !unsearched.is_empty() {
+            hints.push(format!("also indexed: {} (select with -k)", unsearched.join(", ")));
+        }
+        if hints.is_empty() {
+            bail!("None of the attributes matched.");
+        }
+        bail!("None of the attributes matched: {}", hints.join("; "));
     }
 
     if verbose {
-        eprintln!("[DEBUG] Matched attribute -> AIDs:");
-        for (attr_val, aids) in &attr_to_aids {
-            eprintln!("  {} => {:?}", attr_val, aids);
+        diag!("[DEBUG] Matched attribute -> AIDs:");
+        for (key, attr_to_aids, _) in &key_hits {
+            let mut listed: Vec<_> = attr_to_aids.iter().collect();
+            listed.sort_unstable();
+            for (attr_val, aids) in listed {
+                diag!("  {}={} => {:?}", key, attr_val, aids);
+            }
         }
     }
 
-    // Step 2: map AIDs -> FIDs via a2f (attribute index to feature id)
-    // Note: a2f is expected to be indexable by AID (usize).
-    // We also deduplicate per attribute to keep vectors lean.
-    let mut attr_to_fids: FxHashMap<String, Vec<u32>> = FxHashMap::default();
-    
-    for (attr_val, aids) in &attr_to_aids {
-        let mut fids = a2f.map_aids_to_fids_vec(aids);
+    // Step 2: map AIDs -> FIDs via each key's a2f (attribute index to feature id)
+    // and union the FIDs of a value across keys.
+    let mut attr_to_fids: FxHashMap<&str, Vec<u32>> = FxHashMap::default();
+    let mut matched_keys: Vec<&str> = Vec::new();
+    for (key, attr_to_aids, key_a2f) in &key_hits {
+        let key_fids = aids_to_fids(attr_to_aids, key_a2f);
+        if keys.len() > 1 {
+            diag!(
+                "[INFO] Key '{}': {} value(s) matched, {} feature(s)",
+                key,
+                attr_to_aids.len(),
+                key_fids.values().map(Vec::len).sum::<usize>()
+            );
+        }
+        if key_fids.is_empty() {
+            continue;
+        }
+        matched_keys.push(key);
+        let mut key_fids: Vec<_> = key_fids.into_iter().collect();
+        key_fids.sort_unstable();
+        for (attr_val, fids) in key_fids {
+            if verbose && keys.len() > 1 {
+                diag!("[DEBUG] {}={} => {:?}", key, attr_val, fids);
+            }
+            attr_to_fids.entry(attr_val).or_default().extend(fids);
+        }
+    }
+    for fids in attr_to_fids.values_mut() {
         fids.sort_unstable();
         fids.dedup();
-    
-        if !fids.is_empty() {
-            attr_to_fids.insert(attr_val.clone(), fids);
-        }
     }
 
     if attr_to_fids.is_empty() {
-        bail!("No feature IDs (FIDs) resolved from matched attributes.");
+        return Err(Error::NoMatches {
+            query: "the attribute search (no feature IDs resolved from matched attributes)".to_string(),
+        }
+        .into());
     }
 
     if verbose {
-        eprintln!("[DEBUG] Attribute -> FIDs after a2f mapping:");
-        for (attr_val, fids) in &attr_to_fids {
-            eprintln!("  {} => {:?} ", attr_val, fids);
+        diag!("[DEBUG] Attribute -> FIDs after a2f mapping:");
+        let mut listed: Vec<_> = attr_to_fids.iter().collect();
+        listed.sort_unstable();
+        for (attr_val, fids) in listed {
+            diag!("  {} => {:?} ", attr_val, fids);
         }
     }
 
@@ -157,27 +499,21 @@ pub fn run(args: &SearchArgs) -> Result<()> {
     fid_vec.dedup();
 
     if verbose {
-        eprintln!("[DEBUG] Total unique FIDs: {}", fid_vec.len());
+        diag!("[DEBUG] Total unique FIDs: {}", fid_vec.len());
     }
 
     let threads = args.common.effective_threads();
     let root: Vec<u32> = prt.map_fids_to_roots(&fid_vec, threads);
 
-    // Collect invalid fids (mapped to u32::MAX), and build a unique root list
-    let mut invalid_fids: Vec<u32> = Vec::new();
-    let mut roots_effective: Vec<u32> = Vec::with_capacity(root.len());
-    for (&fid, r) in fid_vec.iter().zip(root.iter()) {
-        if *r == u32::MAX {
-            invalid_fids.push(fid);
-        } else {
-            roots_effective.push(*r);
-        }
-    }
+    // Collect invalid fids (mapped to u32::MAX), and build a unique root list; a feature
+    // whose parents lie in several models counts for each of them
+    let invalid_fids: Vec<u32> =
+        fid_vec.iter().zip(root.iter()).filter(|&(_, &r)| r == u32::MAX).map(|(&fid, _)| fid).collect();
+    let fid_roots = prt.fid_root_pairs(&fid_vec, &root);
+    let mut roots_effective: Vec<u32> = fid_roots.iter().map(|&(_, r)| r).collect();
 
     if !invalid_fids.is_empty() {
-        invalid_fids.sort_unstable();
-        invalid_fids.dedup();
-        eprintln!(
+        diag!(
             "[WARN] {} FIDs have invalid parent chains (or out-of-range): {:?}",
             invalid_fids.len(),
             invalid_fids
@@ -190,45 +526,167 @@ pub fn run(args: &SearchArgs) -> Result<()> {
         bail!("No valid root features resolved from matched attributes.");
     }
     if verbose {
-        eprintln!("[DEBUG] Total unique roots: {}", roots_effective.len());
+        diag!("[DEBUG] Total unique roots: {}", roots_effective.len());
     }
 
-    let blocks: Vec<(u32, u64, u64)> = gof.roots_to_offsets(&roots_effective, args.common.effective_threads());
-    
-    if !args.common.entire_group|| args.common.types.is_some() {
-        let allowed_roots: FxHashSet<u32> = roots_effective.iter().copied().collect();
-        
-        let mut fid_to_root: FxHashMap<u32, u32> = FxHashMap::default();
-        fid_to_root.reserve(fid_vec.len());
-        for (fid, r) in fid_vec.iter().copied().zip(root.iter().copied()) {
-            if r != u32::MAX && allowed_roots.contains(&r) {
-                fid_to_root.insert(fid, r);
-            }
+    let (mut blocks, missing_roots) = gof.roots_to_offsets(&roots_effective, args.common.effective_threads());
+    if !missing_roots.is_empty() {
+        diag!(
+            "[WARN] {} of {} roots not found in the GOF index; their blocks are skipped.",
+            missing_roots.len(),
+            roots_effective.len()
+        );
+        if verbose {
+            diag!("[DEBUG] Missing roots: {:?}", missing_roots);
+        }
+    }
+
+    // Whole-block modes apply the length bounds to the root extent
+    if !args.common.feature_only() {
+        blocks = args.common.length_filter().filter_blocks(gff_path, blocks)?;
+    }
+    // File order, however the roots were resolved
+    blocks.sort_unstable_by_key(|&(root, s, e)| (s, e, root));
+
+    // The query credited with a matched value: the first pattern matching it in regex
+    // mode (as in `match_attr_values`), else the first query equal to it
+    let patterns = if args.regex {
+        attr_values
+            .iter()
+            .map(|q| compile_pattern(q, match_opts))
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        Vec::new()
+    };
+    let mut first_query: FxHashMap<&str, usize> = FxHashMap::default();
+    for (i, q) in attr_values.iter().enumerate() {
+        first_query.entry(q.value.as_str()).or_insert(i);
+    }
+    let query_of = |value: &str| -> Option<usize> {
+        if args.regex {
+            patterns.iter().position(|re| re.is_match(value))
+        } else {
+            first_query.get(value).copied()
         }
-        
-        let mut per_root_matches: FxHashMap<u32, FxHashSet<String>> = FxHashMap::default();
+    };
+
+    // A query counts as matched when it resolved to at least one feature
+    let matched_terms = if args.regex {
+        patterns
+            .iter()
+            .filter(|re| attr_to_fids.keys().any(|v| re.is_match(v)))
+            .count()
+    } else {
+        attr_values.iter().filter(|q| attr_to_fids.contains_key(q.value.as_str())).count()
+    };
+    let term_kind = if args.regex { "pattern(s)" } else { "attribute value(s)" };
+    let summary = RunSummary::new()
+        .input(gff_path)
+        .matched(matched_terms, attr_values.len(), term_kind)
+        .count(roots_effective.len(), "model(s)");
+
+    if args.common.dry_run {
+        set_summary(summary);
+        let plan = DryRunPlan {
+            term_kind,
+            terms: attr_values.len(),
+            matched_terms,
+            roots: roots_effective.len(),
+        };
+        return plan.report(gff_path, &blocks, args.common.types.as_ref());
+    }
+
+    if let Some(bed) = &args.common.roots_bed {
+        // Score: the matched features of each model
+        let mut counts: FxHashMap<u32, usize> = FxHashMap::default();
+        for &r in root.iter().filter(|&&r| r != u32::MAX) {
+            *counts.entry(r).or_default() += 1;
+        }
+        let rows = write_roots_bed(gff_path, &blocks, &counts, bed, args.common.types.as_ref(), verbose)?;
+        set_summary(summary.count(rows, "BED row(s)").output(Some(bed.as_path()).filter(|p| *p != Path::new("-"))));
+        return Ok(());
+    }
+
+    let allowed_roots: FxHashSet<u32> = roots_effective.iter().copied().collect();
+    let mut fid_to_roots: FxHashMap<u32, Vec<u32>> = FxHashMap::default();
+    fid_to_roots.reserve(fid_vec.len());
+    for &(fid, r) in &fid_roots {
+        if allowed_roots.contains(&r) {
+            fid_to_roots.entry(fid).or_default().push(r);
+        }
+    }
+    let mut per_root_matches: FxHashMap<u32, FxHashSet<String>> = FxHashMap::default();
+    if !args.common.entire_group {
         per_root_matches.reserve(roots_effective.len());
-        
         for (attr_val, fids) in &attr_to_fids {
             for &fid in fids {
-                if let Some(&r) = fid_to_root.get(&fid) {
-                    per_root_matches.entry(r).or_default().insert(attr_val.clone());
+                for &r in fid_to_roots.get(&fid).into_iter().flatten() {
+                    per_root_matches.entry(r).or_default().insert(attr_val.to_string());
                 }
             }
         }
+    }
+    let selection = LineSelection {
+        per_root_matches: &per_root_matches,
+        attr_keys: &matched_keys,
+        types_filter: args.common.types.as_ref(),
+        lengths: args.common.length_filter(),
+        provenance: args.common.provenance,
+    };
 
-        write_gff_output_filtered(
+    if args.preserve_query_order {
+        // Each model under the first query that matched it
+        let mut root_query: FxHashMap<u32, usize> = FxHashMap::default();
+        for (attr_val, fids) in &attr_to_fids {
+            let Some(q) = query_of(attr_val) else { continue };
+            for &fid in fids {
+                for &r in fid_to_roots.get(&fid).into_iter().flatten() {
+                    root_query.entry(r).and_modify(|first| *first = (*first).min(q)).or_insert(q);
+                }
+            }
+        }
+        let mut groups: Vec<QueryGroup> = attr_values.iter().map(|q| (q.value.clone(), Vec::new())).collect();
+        for &block in &blocks {
+            if let Some(&q) = root_query.get(&block.0) {
+                groups[q].1.push(block);
+            }
+        }
+        let (selection, types) = if args.common.entire_group {
+            (None, args.common.types.as_ref())
+        } else {
+            (Some(selection), None)
+        };
+        write_gff_output_grouped(gff_path, &groups, selection, types, &args.common.output, verbose)?;
+    } else if args.common.roots_only {
+        write_gff_roots_only(
             gff_path,
             &blocks,
-            &per_root_matches,
-            &atn_attr_name,
             &args.common.output,
-            args.common.types.as_deref(),
+            args.common.types.as_ref(),
             verbose,
         )?;
+    } else if !args.common.entire_group {
+        if let (Some(checkpoint), Some(output)) = (&args.checkpoint, &args.common.output) {
+            write_gff_output_resumable(
+                gff_path,
+                &blocks,
+                Some(selection),
+                output,
+                checkpoint,
+                &command_line_key(),
+                verbose,
+            )?;
+        } else {
+            write_gff_output_filtered(gff_path, &blocks, selection, &args.common.output, verbose)?;
+        }
+    } else if let Some(types) = &args.common.types {
+        // Whole models, minus the lines of other types (lengths were applied to the root extent)
+        write_gff_output_typed(gff_path, &blocks, &args.common.output, types, LengthFilter::default(), verbose)?;
+    } else if let (Some(checkpoint), Some(output)) = (&args.checkpoint, &args.common.output) {
+        write_gff_output_resumable(gff_path, &blocks, None, output, checkpoint, &command_line_key(), verbose)?;
     } else {
         let mut fid_to_root: FxHashMap<u32, u32> = FxHashMap::default();
-        for (fid, r) in fid_vec.iter().copied().zip(root.clone().into_iter()) {
+        for (fid, r) in fid_vec.iter().copied().zip(root.iter().copied()) {
             if r != u32::MAX {
                 fid_to_root.insert(fid, r);
             }
@@ -244,9 +702,158 @@ pub fn run(args: &SearchArgs) -> Result<()> {
         )?;
     }
 
+    let mut context = Vec::new();
+    if let Some(n) = args.context {
+        let fts = load_fts(gff_path)?;
+        let index = TreeIndexData::load_tree_index(gff_path)?;
+        context = context_roots(&index, &roots_effective, n)?;
+        let written = write_context_blocks(gff_path, &gof, &fts, &context, &args.common.output)?;
+        if verbose {
+            diag!("[INFO] Wrote {} context model(s) (--context {})", written, n);
+        }
+    }
+    if let Some(bp) = args.pad_models {
+        let fts = load_fts(gff_path)?;
+        let index = TreeIndexData::load_tree_index(gff_path)?;
+        let seq_lens = SeqLengths::load(gff_path, None)?;
+        let (mut padding, n_clamped) = pad_roots(&index, &roots_effective, bp, &seq_lens)?;
+        drop_context_roots(&mut padding, &context);
+        let written = write_padded_blocks(gff_path, &gof, &fts, &padding, &args.common.output)?;
+        if verbose {
+            diag!("[INFO] Wrote {} padding model(s) (--pad-models {})", written, bp);
+            if n_clamped > 0 {
+                diag!("[INFO] {} padding window(s) cut at a sequence end", n_clamped);
+            }
+        }
+    }
+
+    set_summary(summary.output(args.common.output.as_deref()));
+
     if verbose {
-        eprintln!("[timing] Total elapsed: {:?}", overall_start.elapsed());
+        diag!("[timing] Total elapsed: {:?}", overall_start.elapsed());
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::index::{self, IndexArgs};
+    use crate::testutil::{GffSynth, SAMPLE_GFF, ScratchDir, build_fixture, data_lines};
+
+    fn search(gff: &std::path::Path, out: &std::path::Path, extra: &[&str]) -> Result<Vec<String>> {
+        let mut argv = vec!["search", "-i", gff.to_str().unwrap(), "-o", out.to_str().unwrap()];
+        argv.extend_from_slice(extra);
+        run(&SearchArgs::try_parse_from(argv)?)?;
+        data_lines(out)
+    }
+
+    #[test]
+    fn name_index_answers_when_the_primary_key_is_gene_id() {
+        let text = SAMPLE_GFF.replace("ID=g1;", "ID=g1;gene_id=ENSG1;").replace("ID=g2;", "ID=g2;gene_id=ENSG2;");
+        let fx = build_fixture(&text).unwrap();
+        let reindex = |extra: &[&str]| {
+            let mut argv = vec!["index", "-i", fx.gff.to_str().unwrap(), "-a", "gene_id"];
+            argv.extend_from_slice(extra);
+            index::run(&IndexArgs::try_parse_from(argv).unwrap()).unwrap();
+        };
+        reindex(&[]);
+        let out = fx.dir.join("out.gff");
+
+        let by_key = search(&fx.gff, &out, &["-a", "ENSG2"]).unwrap();
+        assert_eq!(by_key.len(), 1);
+        assert!(by_key[0].contains("ID=g2;"));
+        // No gene_id is "beta": the Name index answers instead
+        let fallback = search(&fx.gff, &out, &["-a", "beta"]).unwrap();
+        assert_eq!(fallback, by_key);
+        let explicit = search(&fx.gff, &out, &["-a", "alpha", "--key", "Name"]).unwrap();
+        assert_eq!(explicit.len(), 1);
+        assert!(explicit[0].contains("ID=g1;"));
+        // An explicit primary key does not fall back
+        assert!(search(&fx.gff, &out, &["-a", "beta", "--key", "gene_id"]).is_err());
+        assert!(search(&fx.gff, &out, &["-a", "beta", "--key", "Note"]).is_err());
+
+        reindex(&["--no-name-index"]);
+        assert!(search(&fx.gff, &out, &["-a", "alpha", "--key", "Name"]).is_err());
+    }
+
+    #[test]
+    fn find_roots_fails_with_the_matching_variant() {
+        let fx = build_fixture(SAMPLE_GFF).unwrap();
+        let fts = load_fts(&fx.gff).unwrap();
+        let fid = |id: &str| fts.get_fid(id).unwrap();
+        assert_eq!(find_roots(&fx.gff, None, &["gamma", "alpha", "alpha"]).unwrap(), [fid("g1"), fid("g3")]);
+
+        let err = find_roots(&fx.gff, None, &["delta"]).unwrap_err();
+        assert!(matches!(&err, Error::NoMatches { query } if query == "Name=delta"), "{err:?}");
+        let err = find_roots(&fx.gff, Some("gene_id"), &["alpha"]).unwrap_err();
+        assert!(matches!(err, Error::NoMatches { .. }), "{err:?}");
+
+        let dir = ScratchDir::new("find-roots-unindexed").unwrap();
+        let gff = dir.join("g.gff");
+        std::fs::write(&gff, SAMPLE_GFF).unwrap();
+        let err = find_roots(&gff, None, &["alpha"]).unwrap_err();
+        assert!(matches!(err, Error::IndexMissing { .. }), "{err:?}");
+    }
+
+    fn gene_ids(lines: &[String]) -> Vec<&str> {
+        let mut ids: Vec<&str> = lines.iter().filter_map(|l| l.split("ID=").nth(1)?.split(';').next()).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn anchored_and_fixed_string_patterns() {
+        let fx = build_fixture(
+            "chr1\t.\tgene\t1\t10\t.\t+\t.\tID=g1;Name=BRCA1\n\
+             chr1\t.\tgene\t20\t30\t.\t+\t.\tID=g2;Name=BRCA10\n\
+             chr1\t.\tgene\t40\t50\t.\t+\t.\tID=g3;Name=LOC(1)\n\
+             chr1\t.\tgene\t60\t70\t.\t+\t.\tID=g4;Name=LOC1\n",
+        )
+        .unwrap();
+        let out = fx.dir.join("out.gff");
+        let ids = |extra: &[&str]| gene_ids(&search(&fx.gff, &out, extra).unwrap()).join(",");
+
+        assert_eq!(ids(&["-r", "-a", "BRCA1"]), "g1,g2");
+        assert_eq!(ids(&["-r", "--anchored", "-a", "BRCA1"]), "g1");
+        // Unescaped, "(1)" is a group and matches LOC1
+        assert_eq!(ids(&["-r", "--anchored", "-a", "LOC(1)"]), "g4");
+        assert_eq!(ids(&["-r", "--anchored", "--fixed-strings", "-a", "LOC(1)"]), "g3");
+        assert_eq!(ids(&["-r", "--fixed-strings", "-a", "LOC("]), "g3");
+        assert!(SearchArgs::try_parse_from(["search", "-i", "x", "-a", "BRCA1", "--anchored"]).is_err());
+
+        // Compile errors name the failing input
+        let err = search(&fx.gff, &out, &["-r", "-a", "LOC("]).unwrap_err();
+        assert!(format!("{err:#}").contains("--attr"), "{err:#}");
+        let list = fx.dir.join("patterns.txt");
+        std::fs::write(&list, "BRCA1\n\nLOC(\n").unwrap();
+        let err = search(&fx.gff, &out, &["-r", "-A", list.to_str().unwrap()]).unwrap_err();
+        assert!(format!("{err:#}").contains("line 3 of"), "{err:#}");
+    }
+
+    #[test]
+    fn context_and_padding_write_each_model_once() {
+        let (genome, fx) = GffSynth::new().chromosomes(1).genes_per_chrom(30).seed(43).fixture().unwrap();
+        let (dir, gff) = (&fx.dir, &fx.gff);
+        let m = &genome.genes[10];
+        let bp = (genome.genes[13].start - m.end).to_string();
+        let out = dir.join("out.gff");
+        let (gff, out_s) = (gff.to_str().unwrap(), out.to_str().unwrap());
+        let argv = ["search", "-i", gff, "-e", "-a", &m.id, "--context", "2", "--pad-models", &bp, "-o", out_s];
+        run(&SearchArgs::try_parse_from(argv).unwrap()).unwrap();
+
+        let got = std::fs::read_to_string(&out).unwrap();
+        let mut genes: Vec<&str> = got
+            .lines()
+            .filter(|l| l.split('\t').nth(2) == Some("gene"))
+            .map(|l| l.split("ID=").nth(1).unwrap().split(';').next().unwrap())
+            .collect();
+        assert!(got.lines().any(|l| l.starts_with("# padded-for: ")));
+        let n = genes.len();
+        genes.sort_unstable();
+        genes.dedup();
+        assert_eq!(genes.len(), n, "a model was written twice");
+        assert!(genes.contains(&genome.genes[8].id.as_str()) && genes.contains(&genome.genes[13].id.as_str()));
+    }
+}
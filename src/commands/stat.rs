@@ -0,0 +1,210 @@
+use anyhow::{Context, Result};
+use clap::Parser;
+use rayon::prelude::*;
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::PathBuf,
+    str,
+    time::Instant,
+};
+
+use crate::{
+    BaseCounts, ByteSource, Cell, Column, CountingWriter, Fasta, ModelBlock, RunSummary, TableArgs, TableWriter,
+    TypeFilter, check_gff_present, check_index_matches, fast_id, load_gof, set_index_prefix, set_summary,
+};
+
+/// Skipped features reported one by one; the rest are summarised.
+const MAX_SKIP_REPORTS: usize = 10;
+
+/// Arguments
+#[derive(Parser, Debug)]
+#[command(
+    about = "Per-feature statistics of an indexed GFF against its genome",
+    long_about = "Computes per-feature statistics from the genome sequence under each feature: with \
+                  --per-feature, one table row per root feature (or per feature of the --types types) \
+                  with its length, GC content and N fraction."
+)]
+pub struct StatArgs {
+    /// Input GFF file (indexed)
+    #[arg(short = 'i', long = "input", value_name = "FILE")]
+    pub input: PathBuf,
+
+    /// Genome FASTA (uncompressed); `<FASTA>.fai` is used when present, otherwise the
+    /// FASTA is scanned on start
+    #[arg(long = "fasta", value_name = "FILE")]
+    pub fasta: PathBuf,
+
+    /// Write the per-feature table (seqid, start, end, type, id, length, gc, n_frac;
+    /// optional columns gc_bases, at_bases, n_bases) to FILE
+    #[arg(long = "per-feature", value_name = "FILE")]
+    pub per_feature: PathBuf,
+
+    /// Report every feature of these types instead of the root features (a
+    /// comma-separated list or `@FILE`; a leading `!` excludes the listed types)
+    #[arg(short = 'T', long = "types", value_name = "TYPES", value_parser = TypeFilter::parse)]
+    pub types: Option<TypeFilter>,
+
+    #[command(flatten)]
+    pub table: TableArgs,
+
+    /// Read the index files from PREFIX (see `index --index-prefix`) instead of from
+    /// next to the input
+    #[arg(long = "index-prefix", value_name = "PREFIX")]
+    pub index_prefix: Option<PathBuf>,
+
+    /// Number of threads for parallel processing
+    #[arg(short = 't', long = "threads", default_value_t = 12)]
+    pub threads: usize,
+
+    /// Enable verbose output
+    #[arg(short = 'v', long = "verbose", default_value_t = false)]
+    pub verbose: bool,
+}
+
+/// One row of the per-feature table.
+struct FeatureStat<'a> {
+    seqid: &'a str,
+    /// 1-based, inclusive (as in the GFF)
+    start: u64,
+    end: u64,
+    ftype: &'a str,
+    id: &'a str,
+    counts: BaseCounts,
+}
+
+/// Why a feature got no row.
+enum Skip<'a> {
+    UnknownSeqid { seqid: &'a str },
+    PastEnd { id: &'a str, seqid: &'a str, end: u64, seq_len: u64 },
+}
+
+fn stat_columns<'a>() -> Vec<Column<'a, FeatureStat<'a>>> {
+    vec![
+        Column::new("seqid", |r: &FeatureStat<'a>| Cell::Str(r.seqid)),
+        Column::start("start", |r: &FeatureStat<'a>| r.start - 1),
+        Column::new("end", |r: &FeatureStat<'a>| Cell::UInt(r.end)),
+        Column::new("type", |r: &FeatureStat<'a>| Cell::Str(r.ftype)),
+        Column::new("id", |r: &FeatureStat<'a>| Cell::Str(r.id)),
+        Column::new("length", |r: &FeatureStat<'a>| Cell::UInt(r.end - r.start + 1)),
+        Column::new("gc", |r: &FeatureStat<'a>| r.counts.gc_fraction().map_or(Cell::Null, Cell::Float)),
+        Column::new("n_frac", |r: &FeatureStat<'a>| r.counts.n_fraction().map_or(Cell::Null, Cell::Float)),
+        Column::optional("gc_bases", |r: &FeatureStat<'a>| Cell::UInt(r.counts.gc)),
+        Column::optional("at_bases", |r: &FeatureStat<'a>| Cell::UInt(r.counts.at)),
+        Column::optional("n_bases", |r: &FeatureStat<'a>| Cell::UInt(r.counts.n)),
+    ]
+}
+
+/// Rows of one model: its root line, or every line whose type passes `types`.
+fn model_stats<'a>(
+    model: &ModelBlock<'a>,
+    types: Option<&TypeFilter>,
+    fasta: &Fasta,
+) -> Result<Vec<Result<FeatureStat<'a>, Skip<'a>>>> {
+    let mut out = Vec::new();
+    for line in model.lines() {
+        let Ok(line) = str::from_utf8(line) else { continue };
+        let f: Vec<&str> = line.splitn(9, '\t').collect();
+        if f.len() < 9 {
+            continue;
+        }
+        if types.is_none_or(|t| t.allows(f[2].as_bytes())) {
+            let (Ok(start), Ok(end)) = (f[3].parse::<u64>(), f[4].parse::<u64>()) else { continue };
+            if start == 0 || start > end {
+                continue;
+            }
+            let (seqid, id) = (f[0], fast_id(f[8]).unwrap_or("."));
+            out.push(match fasta.seq_len(seqid) {
+                None => Err(Skip::UnknownSeqid { seqid }),
+                Some(seq_len) if end > seq_len => Err(Skip::PastEnd { id, seqid, end, seq_len }),
+                Some(_) => {
+                    let counts = fasta.base_counts(seqid, start - 1, end)?;
+                    Ok(FeatureStat { seqid, start, end, ftype: f[2], id, counts })
+                }
+            });
+        }
+        if types.is_none() {
+            break;
+        }
+    }
+    Ok(out)
+}
+
+pub fn run(args: &StatArgs) -> Result<()> {
+    let t0 = Instant::now();
+    let _ = rayon::ThreadPoolBuilder::new().num_threads(args.threads).build_global();
+    let gff_path = &args.input;
+    set_index_prefix(gff_path, args.index_prefix.as_deref());
+
+    check_gff_present(gff_path)?;
+    check_index_matches(gff_path)?;
+    let gof = load_gof(gff_path)?;
+    let gff = ByteSource::open_gff(gff_path).with_context(|| format!("Cannot open GFF file: {:?}", gff_path))?;
+    let fasta = Fasta::open(&args.fasta)?;
+    let writer = TableWriter::new(stat_columns(), &args.table)?;
+    if args.verbose {
+        diag!("[TIMER] Loaded index, GFF and FASTA ({} sequence(s)): {:.2?}", fasta.entries().len(), t0.elapsed());
+    }
+
+    let models: Vec<ModelBlock<'_>> = gof.iter_models(&gff).collect::<Result<_, _>>()?;
+    let per_model: Vec<Vec<Result<FeatureStat<'_>, Skip<'_>>>> = models
+        .par_iter()
+        .map(|m| model_stats(m, args.types.as_ref(), &fasta))
+        .collect::<Result<_>>()?;
+
+    let mut skipped = 0usize;
+    let mut unknown_seqids = 0usize;
+    let mut missing: Vec<&str> = Vec::new();
+    let rows = per_model.into_iter().flatten().filter_map(|r| match r {
+        Ok(row) => Some(row),
+        Err(skip) => {
+            skipped += 1;
+            match skip {
+                Skip::UnknownSeqid { seqid } => {
+                    unknown_seqids += 1;
+                    if !missing.contains(&seqid) {
+                        missing.push(seqid);
+                    }
+                }
+                Skip::PastEnd { id, seqid, end, seq_len } if skipped - unknown_seqids <= MAX_SKIP_REPORTS => {
+                    diag!("[WARN] Skipped {}: ends at {} beyond the end of {} ({} bp)", id, end, seqid, seq_len);
+                }
+                Skip::PastEnd { .. } => {}
+            }
+            None
+        }
+    });
+
+    let file = File::create(&args.per_feature)
+        .with_context(|| format!("Cannot create output: {:?}", args.per_feature))?;
+    let mut out = BufWriter::new(CountingWriter(file));
+    let written = writer.write_all(rows, &mut out)?;
+
+    if unknown_seqids > 0 {
+        let shown = missing.iter().take(MAX_SKIP_REPORTS).copied().collect::<Vec<_>>().join(", ");
+        let more = if missing.len() > MAX_SKIP_REPORTS { ", ..." } else { "" };
+        diag!(
+            "[WARN] Skipped {} feature(s) on {} sequence(s) missing from the FASTA: {}{}",
+            unknown_seqids,
+            missing.len(),
+            shown,
+            more
+        );
+    }
+    let past_end = skipped - unknown_seqids;
+    if past_end > MAX_SKIP_REPORTS {
+        diag!("[WARN]   ... and {} more beyond a sequence end", past_end - MAX_SKIP_REPORTS);
+    }
+    if args.verbose {
+        diag!("[TIMER] Wrote {} row(s): {:.2?}", written, t0.elapsed());
+    }
+    set_summary(
+        RunSummary::new()
+            .input(gff_path)
+            .input(&args.fasta)
+            .count(written, "feature(s)")
+            .count(skipped, "skipped")
+            .output(Some(&args.per_feature)),
+    );
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Errors returned by the library API (index building, loading and querying).
+///
+/// The CLI converts these into `anyhow::Error`; library users can match on the
+/// variant instead of parsing messages.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// An index artifact (`.gof`, `.fts`, `.rit`, ...) does not exist
+    #[error("index file {path:?} not found; run `gffx index` first")]
+    IndexMissing { path: PathBuf },
+
+    /// The GFF is gone while its index is still there; index-only operations
+    /// (e.g. `--dry-run`) keep working, anything reading feature lines fails with this
+    #[error("GFF file {path:?} not found; its index is present, but this operation reads feature lines from the GFF")]
+    GffMissing { path: PathBuf },
+
+    /// The index was built from different content than the GFF next to it (`.meta` size
+    /// or sampled hash differs), e.g. indexes copied beside another or an edited file
+    #[error(
+        "index of {gff:?} is stale: built for {built_for} ({detail}); re-run `gffx index -i {gff:?}`, \
+         or pass --skip-index-check to use it anyway"
+    )]
+    IndexMismatch { gff: PathBuf, built_for: String, detail: String },
+
+    /// A gzip/bgzip-compressed GFF whose decompressed copy (`<gff>.raw`, written by
+    /// `gffx index` and read by every query) is missing or older than the compressed file
+    #[error("{gff:?} is gzip-compressed and its decompressed copy {copy:?} {detail}; rebuild it with `gffx index -i {gff:?}`")]
+    DecompressedCopy { gff: PathBuf, copy: PathBuf, detail: &'static str },
+
+    /// An index artifact exists but cannot be parsed
+    #[error("corrupt index {artifact}: {detail}")]
+    IndexCorrupt { artifact: String, detail: String },
+
+    /// A sequence name absent from the `.sqs` table
+    #[error("sequence {name:?} is not in the index")]
+    UnknownSeqid { name: String },
+
+    /// A query resolved to no features
+    #[error("no features matched {query}")]
+    NoMatches { query: String },
+
+    /// Reading or writing `path` failed
+    #[error("I/O error on {path:?}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// A malformed GFF line (1-based line number)
+    #[error("invalid GFF line {line}: {detail}")]
+    Parse { line: u64, detail: String },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub(crate) fn io(path: &Path, source: io::Error) -> Self {
+        Error::Io { path: path.to_path_buf(), source }
+    }
+
+    pub(crate) fn corrupt(artifact: impl Into<String>, detail: impl ToString) -> Self {
+        Error::IndexCorrupt { artifact: artifact.into(), detail: detail.to_string() }
+    }
+
+    /// Treat a failure to open or parse `path` as an index problem: a missing file
+    /// becomes `IndexMissing`, and `IndexCorrupt` names the file instead of its kind.
+    pub(crate) fn for_index_file(self, path: &Path) -> Self {
+        match self {
+            Error::Io { source, .. } if source.kind() == io::ErrorKind::NotFound => {
+                Error::IndexMissing { path: path.to_path_buf() }
+            }
+            Error::IndexCorrupt { detail, .. } => {
+                Error::IndexCorrupt { artifact: path.display().to_string(), detail }
+            }
+            e => e,
+        }
+    }
+}
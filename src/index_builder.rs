@@ -1,2 +1,11 @@
 pub mod core;
-pub use core::{build_index, write_binary_u32, write_gof, write_lines};
+pub mod bundle;
+#[cfg(feature = "cli")]
+pub mod tabix;
+pub use bundle::{INDEX_SUFFIXES, bundle_index, unbundle_index};
+pub use core::{
+    ANON_ID_PREFIX, BuildStats, DEFAULT_SKIP_TYPES, IndexBuilder, NAME_KEY, is_anonymous_id, write_binary_u32,
+    write_gof, write_lines,
+};
+#[cfg(feature = "cli")]
+pub use tabix::write_tabix_companion;
@@ -0,0 +1,81 @@
+use crate::{BUNDLE_SUFFIX, Bundle, ByteSource, Error, index_path, write_bundle};
+use crate::error::Result;
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+/// Every artifact `IndexBuilder::build` may write, in bundle order (the tables of extra
+/// attribute keys, see `attr_index_suffixes`, come last).
+pub const INDEX_SUFFIXES: [&str; 28] = [
+    ".gof", ".fts", ".prt", ".sqs", ".atn", ".a2f", ".rit", ".rix", ".natn", ".na2f", ".fbl", ".meta", ".gcnt",
+    ".mpr", ".atn.1", ".a2f.1", ".atn.2", ".a2f.2", ".atn.3", ".a2f.3", ".atn.4", ".a2f.4", ".atn.5", ".a2f.5", ".atn.6",
+    ".a2f.6", ".atn.7", ".a2f.7",
+];
+
+/// Pack the loose index files of `gff` into `<gff>.gxi` and delete them.
+/// Returns the bundle path.
+pub fn bundle_index(gff: &Path, verbose: bool) -> Result<PathBuf> {
+    let mut sources = Vec::new();
+    for suffix in INDEX_SUFFIXES {
+        let path = index_path(gff, suffix);
+        if path.exists() {
+            sources.push((suffix, ByteSource::open(&path)?));
+        }
+    }
+    if sources.is_empty() {
+        return Err(Error::IndexMissing { path: index_path(gff, ".gof") });
+    }
+
+    let gxi_path = index_path(gff, BUNDLE_SUFFIX);
+    let tmp_path = index_path(gff, ".gxi.tmp");
+    let artifacts: Vec<(&str, &[u8])> = sources.iter().map(|(s, b)| (*s, &b[..])).collect();
+    let write = || -> std::io::Result<()> {
+        let mut out = BufWriter::new(File::create(&tmp_path)?);
+        write_bundle(&mut out, &artifacts)?;
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()
+    };
+    write().map_err(|e| Error::io(&tmp_path, e))?;
+    fs::rename(&tmp_path, &gxi_path).map_err(|e| Error::io(&gxi_path, e))?;
+
+    let total: usize = artifacts.iter().map(|(_, b)| b.len()).sum();
+    drop(artifacts);
+    for (suffix, bytes) in sources {
+        drop(bytes);
+        let path = index_path(gff, suffix);
+        fs::remove_file(&path).map_err(|e| Error::io(&path, e))?;
+    }
+    if verbose {
+        diag!("[INFO] Bundled {} bytes of index files into {}", total, gxi_path.display());
+    }
+    Ok(gxi_path)
+}
+
+/// Check `<gff>.gxi` against its checksums, write its artifacts back as loose files
+/// and delete it. Returns the number of files written.
+pub fn unbundle_index(gff: &Path, verbose: bool) -> Result<usize> {
+    let gxi_path = index_path(gff, BUNDLE_SUFFIX);
+    let bundle = Bundle::open(&gxi_path)?;
+    bundle.verify().map_err(|e| e.for_index_file(&gxi_path))?;
+    // Names become file suffixes, so only accept the ones gffx writes
+    if let Some(e) = bundle.entries().iter().find(|e| !INDEX_SUFFIXES.contains(&e.name.as_str())) {
+        return Err(Error::corrupt(
+            gxi_path.display().to_string(),
+            format!("unknown artifact {:?}", e.name),
+        ));
+    }
+
+    for e in bundle.entries() {
+        let path = index_path(gff, &e.name);
+        let data = bundle.get(&e.name).unwrap_or_default();
+        fs::write(&path, data).map_err(|err| Error::io(&path, err))?;
+        if verbose {
+            diag!("[INFO] Wrote {} ({} bytes)", path.display(), data.len());
+        }
+    }
+    let n = bundle.entries().len();
+    drop(bundle);
+    fs::remove_file(&gxi_path).map_err(|e| Error::io(&gxi_path, e))?;
+    Ok(n)
+}
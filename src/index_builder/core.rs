@@ -1,35 +1,48 @@
-use crate::append_suffix;
-use crate::{Interval, IntervalTree, save_multiple_trees, write_offsets_to_file};
-use anyhow::anyhow;
-use anyhow::{Result, bail};
+use crate::{
+    BUNDLE_SUFFIX, BloomFilter, ByteSource, DECOMPRESSED_SUFFIX, DEFAULT_ID_KEY, DEFAULT_PARENT_KEY, Error, IndexMeta,
+    MAX_ATTR_KEYS, attr_index_suffixes, attr_value, did_you_mean, index_path,
+};
+use crate::{Interval, IntervalTree, save_multiple_trees};
+use crate::utils::seq_lengths::parse_sequence_region;
+use crate::error::Result;
 use byteorder::{LittleEndian, WriteBytesExt};
+use flate2::read::MultiGzDecoder;
 use indexmap::IndexMap;
 use memchr::memchr;
-use memmap2::Mmap;
-use regex::{Regex, escape};
-use std::{fs::File, io::Write, path::PathBuf};
+use rayon::prelude::*;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
 use rustc_hash::{FxHashMap, FxHashSet};
 
 // Writes text lines to a file
-pub fn write_lines(path: PathBuf, lines: &[String]) -> Result<()> {
-    let mut file = File::create(path)?;
-    for line in lines {
-        writeln!(file, "{}", line)?;
-    }
-    Ok(())
+pub fn write_lines<S: AsRef<str>>(path: PathBuf, lines: &[S]) -> Result<()> {
+    let write = || -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(&path)?);
+        for line in lines {
+            writeln!(file, "{}", line.as_ref())?;
+        }
+        file.flush()
+    };
+    write().map_err(|e| Error::io(&path, e))
 }
 
 // Writes u32 values in binary little-endian format
 pub fn write_binary_u32(path: PathBuf, values: &[u32]) -> Result<()> {
-    let mut file = File::create(path)?;
-    for &v in values {
-        file.write_u32::<LittleEndian>(v)?;
-    }
-    Ok(())
+    let write = || -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(&path)?);
+        for &v in values {
+            file.write_u32::<LittleEndian>(v)?;
+        }
+        file.flush()
+    };
+    write().map_err(|e| Error::io(&path, e))
 }
 
 // Writes GFF offset records (gof)
-pub fn write_gof(file: &mut File, id: u32, seq_num: u32, start: u64, end: u64) -> Result<()> {
+pub fn write_gof(file: &mut File, id: u32, seq_num: u32, start: u64, end: u64) -> io::Result<()> {
     file.write_u32::<LittleEndian>(id)?; // root feature id
     file.write_u32::<LittleEndian>(seq_num)?; // seq numeric id
     file.write_u64::<LittleEndian>(start)?; // start offset in GFF file
@@ -37,38 +50,355 @@ pub fn write_gof(file: &mut File, id: u32, seq_num: u32, start: u64, end: u64) -
     Ok(())
 }
 
-/// Builds various index files for a GFF: .fts, .prt, .a2f, .atn, .sqs, .gof, .rit, .rix
-pub fn build_index(gff: &PathBuf, attr_key: &str, skip_types: &str, verbose: bool) -> Result<()> {
-    // Compile regex patterns
-    let id_re = Regex::new(r"ID=([^;\s]+)")?;
-    let parent_re = Regex::new(r"Parent=([^;\s]+)")?;
-    let attr_re = Regex::new(&format!(r"{}=([^;]+)", escape(attr_key)))?;
+/// Malformed lines listed individually in the skip summary.
+const MAX_MALFORMED_REPORTS: usize = 10;
+
+/// Duplicate root groups listed individually before summarising
+const MAX_DUPLICATE_ROOT_REPORTS: usize = 10;
+
+/// Distinct attribute keys recorded in `.meta`; keys first seen after this many are dropped.
+const MAX_OBSERVED_KEYS: usize = 4096;
+
+/// (seqid, start, end, type) of a root line, used to find duplicated models.
+type RootKey<'a> = (&'a str, u32, u32, &'a str);
+
+/// Attribute always indexed into the secondary `.natn`/`.na2f` pair.
+pub const NAME_KEY: &str = "Name";
+
+/// Feature types `gffx index` leaves out by default (`--skip-types`).
+pub const DEFAULT_SKIP_TYPES: &str = "remark,note,comment,region,gap,assembly_gap,contig,scaffold,source";
 
-    let skip_types_set: FxHashSet<&str> = skip_types.split(',').collect();
+/// Prefix of the `.fts` names given to features without an ID (`__gffx_anon_<fid>`).
+/// Loaders treat these names as absent, so they are never looked up or printed.
+pub const ANON_ID_PREFIX: &str = "__gffx_anon_";
 
+/// Whether `id` is an internal name of a feature without an ID (see `ANON_ID_PREFIX`).
+#[inline]
+pub fn is_anonymous_id(id: &str) -> bool {
+    id.starts_with(ANON_ID_PREFIX)
+}
+
+/// Non-empty value of attribute `key` in column 9 (see `attr_value`), trimmed.
+fn attr_str<'a>(col9: &'a str, key: &str) -> Option<&'a str> {
+    // Cut at ASCII bytes only, so the slice stays valid UTF-8
+    attr_value(col9.as_bytes(), key.as_bytes())
+        .and_then(|v| std::str::from_utf8(v).ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+}
+
+/// `attr_str` up to the first whitespace, as for ID and Parent values.
+fn attr_token<'a>(col9: &'a str, key: &str) -> Option<&'a str> {
+    attr_str(col9, key).and_then(|v| v.split_ascii_whitespace().next())
+}
+
+/// The comma-separated values of a raw Parent value (`Parent=mRNA1,mRNA2` for an exon
+/// shared by two transcripts), each up to the first whitespace like `attr_token`.
+fn parent_tokens(raw: Option<&str>) -> impl Iterator<Item = &str> {
+    raw.into_iter().flat_map(|v| v.split(',')).filter_map(|p| p.split_ascii_whitespace().next())
+}
+
+/// Decompress gzip/bgzip-compressed `gff` into `<gff>.raw` (replacing an older copy)
+/// and return the copy's path.
+fn write_decompressed_copy(gff: &Path, verbose: bool) -> Result<PathBuf> {
+    let copy = index_path(gff, DECOMPRESSED_SUFFIX);
+    let tmp = crate::append_suffix(&copy, ".tmp");
+    let decompress = || -> io::Result<u64> {
+        let mut input = MultiGzDecoder::new(File::open(gff)?);
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        let n = io::copy(&mut input, &mut out)?;
+        out.flush()?;
+        Ok(n)
+    };
+    let n = decompress().map_err(|e| {
+        let _ = std::fs::remove_file(&tmp);
+        Error::io(gff, e)
+    })?;
+    std::fs::rename(&tmp, &copy).map_err(|e| Error::io(&copy, e))?;
     if verbose {
-        eprintln!("Building index for {} ...", gff.display());
+        diag!("[INFO] Decompressed {} bytes of {} into {}", n, gff.display(), copy.display());
+    }
+    Ok(copy)
+}
+
+/// Assign attribute IDs for one key: pushes one AID (or `u32::MAX`) per feature
+/// into `a2f` and new distinct values into `atn`.
+///
+/// Values are slices of the mapped GFF, so interning a value never copies it.
+fn push_attr_value<'a>(
+    val: Option<&'a str>,
+    value_to_id: &mut FxHashMap<&'a str, u32>,
+    atn: &mut Vec<&'a str>,
+    a2f: &mut Vec<u32>,
+) {
+    if let Some(val) = val {
+        let aid = *value_to_id.entry(val).or_insert_with(|| {
+            let a = atn.len() as u32;
+            atn.push(val);
+            a
+        });
+        a2f.push(aid);
+    } else {
+        a2f.push(u32::MAX);
+    }
+}
+
+/// What `IndexBuilder::build` indexed, for the caller's run summary.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildStats {
+    /// Features indexed (skipped types and malformed lines excluded; the lines of a
+    /// feature split over several lines with one ID count once)
+    pub features: usize,
+    /// Per attribute key, in `attr_key` order: indexed features with a non-empty value
+    pub with_attribute: Vec<usize>,
+    /// Feature lines dropped because their type is in `skip_types`
+    pub skipped: usize,
+}
+
+/// Settings of an index build (`gffx index`), built up like
+/// `IndexBuilder::new().attr_key("Name").bloom_fpr(0.01).build(gff)?`. The defaults are
+/// those of `gffx index`: attribute key `gene_name`, `DEFAULT_ID_KEY`/`DEFAULT_PARENT_KEY`,
+/// `DEFAULT_SKIP_TYPES`, a Name index, and everything else off.
+#[derive(Debug, Clone)]
+pub struct IndexBuilder {
+    attr_key: String,
+    id_key: String,
+    parent_key: String,
+    skip_types: String,
+    root_types: Option<String>,
+    name_index: bool,
+    strict: bool,
+    strict_ids: bool,
+    dedup_identical: bool,
+    bloom_fpr: Option<f64>,
+    verbose: bool,
+}
+
+impl Default for IndexBuilder {
+    fn default() -> Self {
+        Self {
+            attr_key: "gene_name".to_string(),
+            id_key: DEFAULT_ID_KEY.to_string(),
+            parent_key: DEFAULT_PARENT_KEY.to_string(),
+            skip_types: DEFAULT_SKIP_TYPES.to_string(),
+            root_types: None,
+            name_index: true,
+            strict: false,
+            strict_ids: false,
+            dedup_identical: false,
+            bloom_fpr: None,
+            verbose: false,
+        }
+    }
+}
+
+impl IndexBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Comma-separated attribute keys whose values get a lookup table (`-a`).
+    pub fn attr_key(mut self, keys: &str) -> Self {
+        self.attr_key = keys.to_string();
+        self
+    }
+
+    /// Keys of the feature IDs and parent references (`--id-key`, `--parent-key`).
+    pub fn id_keys(mut self, id_key: &str, parent_key: &str) -> Self {
+        self.id_key = id_key.to_string();
+        self.parent_key = parent_key.to_string();
+        self
+    }
+
+    /// Comma-separated feature types left out like comments (`--skip-types`).
+    pub fn skip_types(mut self, types: &str) -> Self {
+        self.skip_types = types.to_string();
+        self
+    }
+
+    /// Comma-separated feature types that are always roots (`--root-types`).
+    pub fn root_types(mut self, types: Option<&str>) -> Self {
+        self.root_types = types.map(str::to_string);
+        self
     }
 
-    // Memory-map input file
-    let file = File::open(gff)?;
-    let mmap = unsafe { Mmap::map(&file)? };
+    /// Whether to build the secondary `.natn`/`.na2f` Name index.
+    pub fn name_index(mut self, on: bool) -> Self {
+        self.name_index = on;
+        self
+    }
+
+    /// Fail on the first malformed line instead of skipping it (`--strict`).
+    pub fn strict(mut self, on: bool) -> Self {
+        self.strict = on;
+        self
+    }
+
+    /// Fail on the first feature without an ID (`--strict-ids`).
+    pub fn strict_ids(mut self, on: bool) -> Self {
+        self.strict_ids = on;
+        self
+    }
+
+    /// Give only the first of identical roots a block (`--dedup-identical`).
+    pub fn dedup_identical(mut self, on: bool) -> Self {
+        self.dedup_identical = on;
+        self
+    }
+
+    /// False-positive rate of a Bloom filter over the feature IDs (`--bloom-fpr`).
+    pub fn bloom_fpr(mut self, rate: Option<f64>) -> Self {
+        self.bloom_fpr = rate;
+        self
+    }
+
+    pub fn verbose(mut self, on: bool) -> Self {
+        self.verbose = on;
+        self
+    }
+
+    /// Builds the index files of a GFF: .fts, .prt, .a2f, .atn, .sqs, .gof, .gcnt
+    /// (features per root block), .mpr (extra parents, see below), .rit, .meta (size and
+    /// sampled hash of the GFF, see `check_index_matches`) and, unless `name_index` is off or `attr_key` already lists
+    /// `Name`, .natn/.na2f.
+    /// A gzip- or bgzip-compressed `gff` is first decompressed into `<gff>.raw`
+    /// (`DECOMPRESSED_SUFFIX`); the index, its block offsets included, describes that copy,
+    /// which every query reads in place of `gff` (see `gff_text_path`). Keep both files.
+    /// With `bloom_fpr` a Bloom filter over the feature IDs (.fbl) is written as well;
+    /// otherwise a stale .fbl is removed. A `.gxi` bundle of an earlier build is removed
+    /// too; see `bundle_index` to pack the new files.
+    ///
+    /// `attr_key` is a comma-separated list of up to `MAX_ATTR_KEYS` attribute keys (more
+    /// are ignored with a warning); each gets a value table and a2f, named by
+    /// `attr_index_suffixes` (`.atn`/`.a2f` for the first, `.atn.1`/`.a2f.1`, ... after it).
+    /// Tables of an earlier build with more keys are removed.
+    ///
+    /// Feature lines whose type is in the comma-separated `skip_types` (e.g. `chromosome`,
+    /// `biological_region`) are left out like comments: they get no fid, need no ID and
+    /// stay inside the surrounding block. They are counted per type on stderr.
+    ///
+    /// A feature is a root when it has no (resolvable) Parent. Features whose type is listed
+    /// in `root_types` are roots as well; their Parent is ignored when resolving roots.
+    /// A Parent may list several IDs (`Parent=mRNA1,mRNA2`): `.prt` points to the first
+    /// that resolves and `.mpr` records the others. Features whose parents lie in different
+    /// models are reported; their lines stay in the block they lie in.
+    ///
+    /// Lines without exactly 9 tab-separated columns are skipped like comments (they get no
+    /// fid and stay inside the surrounding block) and summarised on stderr; with `strict`
+    /// the first one aborts the build.
+    ///
+    /// IDs and parent references are the values of `id_key` and `parent_key` (`ID` and
+    /// `Parent` in GFF3; other dialects use e.g. `id`/`parent` or `locus`), matched
+    /// case-sensitively and recorded in `.meta`. A feature that lacks `id_key` while another
+    /// value of it is named by some `parent_key` is an error: its children could not be
+    /// attached to it, which usually means the wrong key was chosen.
+    ///
+    /// GFF3 only requires an ID on features that others refer to, so a feature without one
+    /// (e.g. an exon) is still indexed: it gets a fid, a .prt entry and a place in its root's
+    /// block, and `ANON_ID_PREFIX` plus its fid as its `.fts` name. Nothing can name it as a
+    /// Parent. With `strict_ids` a missing ID aborts the build instead.
+    ///
+    /// Lines repeating an ID (GFF3 discontinuous features, e.g. a CDS split into several
+    /// lines with one `ID`) are parts of one feature: they share a fid and one .fts, .prt
+    /// and .a2f entry (attribute values from the first part carrying them, Parent values of
+    /// all parts, extras in .mpr). Each part lies in the block of the model its own Parent
+    /// names, so extraction by the ID emits every part.
+    ///
+    /// A .gof block is a run of consecutive lines of one model (the features resolving to
+    /// one root), so a child listed before its parent still lands in the parent's block. A
+    /// model whose lines are interleaved with another model's gets one .gof record per run
+    /// and is reported; the loaders emit all of them in file order.
+    ///
+    /// Roots with different IDs but identical seqid, start, end and type are reported as
+    /// duplicate groups. With `dedup_identical` only the first root of each group gets a
+    /// .gof block and a tree interval; the lines of the others stay in the GFF but belong
+    /// to no block.
+    pub fn build(&self, gff: &Path) -> Result<BuildStats> {
+        build_index(gff, self)
+    }
+}
+
+fn build_index(gff: &Path, opts: &IndexBuilder) -> Result<BuildStats> {
+    let (attr_key, id_key, parent_key) = (opts.attr_key.as_str(), opts.id_key.as_str(), opts.parent_key.as_str());
+    let (skip_types, root_types) = (opts.skip_types.as_str(), opts.root_types.as_deref());
+    let IndexBuilder { name_index, strict, strict_ids, dedup_identical, bloom_fpr, verbose, .. } = *opts;
+    let mut attr_keys: Vec<&str> = Vec::new();
+    for key in attr_key.split(',').map(str::trim).filter(|k| !k.is_empty()) {
+        if !attr_keys.contains(&key) {
+            attr_keys.push(key);
+        }
+    }
+    if attr_keys.is_empty() {
+        // Still write an (empty) .atn/.a2f: every index has them
+        attr_keys.push(attr_key.trim());
+    }
+    if attr_keys.len() > MAX_ATTR_KEYS {
+        diag!(
+            "[WARN] Only the first {} attribute keys are indexed; ignoring {}",
+            MAX_ATTR_KEYS,
+            attr_keys[MAX_ATTR_KEYS..].join(",")
+        );
+        attr_keys.truncate(MAX_ATTR_KEYS);
+    }
+    let name_index = name_index && !attr_keys.contains(&NAME_KEY);
+
+    let skip_types_set: FxHashSet<&str> = skip_types.split(',').map(str::trim).filter(|t| !t.is_empty()).collect();
+    let root_types_set: FxHashSet<&str> = root_types
+        .map(|s| s.split(',').map(str::trim).filter(|t| !t.is_empty()).collect())
+        .unwrap_or_default();
+
+    if verbose {
+        diag!("Building index for {} ...", gff.display());
+    }
+
+    // The index may go to another directory than the GFF (see `set_index_prefix`)
+    if let Some(dir) = index_path(gff, ".gof").parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|e| Error::io(dir, e))?;
+    }
+
+    // Memory-map input file (read into memory when mmap is unavailable); a compressed
+    // GFF is indexed through its decompressed copy
+    let mmap = if crate::is_gzip(gff) {
+        ByteSource::open(&write_decompressed_copy(gff, verbose)?)?
+    } else {
+        ByteSource::open(gff)?
+    };
     let data = &mmap[..];
 
-    // First pass: parse raw features
-    struct RawFeature {
-        seqid: String,
+    // First pass: parse raw features.
+    // All strings borrow from the mmap; the GFF itself serves as the string arena.
+    struct RawFeature<'a> {
+        seqid: &'a str,
+        ftype: &'a str,
         start: u32,
         end: u32,
         line_offset: u64,
-        id: String,
-        parent: Option<String>,
-        attr: Option<String>,
+        line_no: u64,
+        /// Column 9, for the check of features without an ID
+        col9: &'a str,
+        /// `None` for features without an ID
+        id: Option<&'a str>,
+        /// Raw Parent value, possibly several comma-separated IDs (see `parent_tokens`)
+        parent: Option<&'a str>,
+        name: Option<&'a str>,
+        forced_root: bool,
     }
     let mut raw_features = Vec::new();
+    // Per attribute key, the value of every raw feature (in raw_features order)
+    let mut attr_columns: Vec<Vec<Option<&str>>> = vec![Vec::new(); attr_keys.len()];
     let mut offset = 0;
+    let mut line_no = 0u64;
+    let mut n_malformed = 0usize;
+    let mut malformed_reports: Vec<(u64, usize)> = Vec::new();
+    let mut n_anonymous = 0usize;
+    let mut skipped_types: FxHashMap<&str, usize> = FxHashMap::default();
+    // Column-9 keys seen, for `.meta` and the did-you-mean hint on a missing key
+    let mut observed_keys: FxHashSet<&str> = FxHashSet::default();
+    // `##sequence-region` lengths, for `.meta` (bounds of region queries and padding)
+    let mut seq_lengths: IndexMap<&str, u32> = IndexMap::new();
 
     while offset < data.len() {
+        line_no += 1;
         let nl_pos = memchr(b'\n', &data[offset..])
             .map(|pos| pos + offset)
             .unwrap_or(data.len());
@@ -77,30 +407,59 @@ pub fn build_index(gff: &PathBuf, attr_key: &str, skip_types: &str, verbose: boo
         offset = nl_pos + 1;
 
         if line_bytes.is_empty() || line_bytes[0] == b'#' {
+            if let Some((seq, len)) = parse_sequence_region(line_bytes) {
+                seq_lengths.insert(seq, len);
+            }
             continue;
         }
-        let line = std::str::from_utf8(line_bytes)?.trim();
+        let line = std::str::from_utf8(line_bytes)
+            .map_err(|e| Error::Parse { line: line_no, detail: format!("invalid UTF-8: {e}") })?
+            .trim();
         if line.is_empty() {
             continue;
         }
 
         let fields: Vec<&str> = line.split('\t').collect();
         if fields.len() != 9 {
-            bail!("Invalid GFF line (expected 9 columns): {}", line);
+            if strict {
+                return Err(Error::Parse {
+                    line: line_no,
+                    detail: format!("expected 9 columns, found {}: {}", fields.len(), line),
+                });
+            }
+            n_malformed += 1;
+            if malformed_reports.len() < MAX_MALFORMED_REPORTS {
+                malformed_reports.push((line_no, fields.len()));
+            }
+            continue;
         }
 
-        let seqid = fields[0].to_string();
+        let seqid = fields[0];
         let ftype = fields[2];
+        if observed_keys.len() < MAX_OBSERVED_KEYS {
+            for pair in fields[8].split(';') {
+                if let Some((k, _)) = pair.split_once('=') {
+                    let k = k.trim();
+                    if !k.is_empty() && observed_keys.len() < MAX_OBSERVED_KEYS {
+                        observed_keys.insert(k);
+                    }
+                }
+            }
+        }
 
         if skip_types_set.contains(ftype) {
-            if verbose {
-                    println!("skip comment feature: {}", ftype);
-            }
+            *skipped_types.entry(ftype).or_default() += 1;
             continue;
         }
 
-        let s1 = fields[3].parse::<u32>()?;
-        let e1 = fields[4].parse::<u32>()?;
+        let parse_coord = |col: &str, what: &str| {
+            col.parse::<u32>().map_err(|e| Error::Parse {
+                line: line_no,
+                detail: format!("invalid {} {:?}: {}", what, col, e),
+            })
+        };
+        let s1 = parse_coord(fields[3], "start")?;
+        let e1 = parse_coord(fields[4], "end")?;
         if e1 == 0 {
             continue;
         }
@@ -108,135 +467,578 @@ pub fn build_index(gff: &PathBuf, attr_key: &str, skip_types: &str, verbose: boo
         let start = s1.saturating_sub(1);
         let end   = e1;
         
-        // Extract ID
-        let id = id_re
-            .captures(line)
-            .ok_or_else(|| anyhow!("Missing ID in feature: {}", line))?[1]
-            .to_string();
-        // Extract raw Parent (may refer to unseen ID)
-        let parent = parent_re.captures(line).map(|cap| cap[1].to_string());
-        // Extract attribute value
-        let attr = attr_re.captures(line).map(|cap| {
-            let val = cap[1].to_string();       
-            // GFF3 spec: attribute values must be URL-encoded.
-            // Raw characters such as space, semicolon, or comma are not allowed.
-            if val.contains(' ') || val.contains(';') || val.contains(',') {
-                eprintln!("[WARN] Attribute value contains invalid chars (.,;) (should be URL-encoded): in '{}'", val);
-            }
-            val
-        });
+        // Column-9 values are looked up by exact key, so `gene_ID=` or a value
+        // containing `ID=` is not taken for the ID
+        let col9 = fields[8];
+        // Extract ID (optional unless strict_ids)
+        let id = attr_token(col9, id_key);
+        match id {
+            None if strict_ids => {
+                return Err(Error::Parse { line: line_no, detail: format!("missing {} in feature: {}", id_key, line) });
+            }
+            None => n_anonymous += 1,
+            Some(id) if is_anonymous_id(id) => {
+                return Err(Error::Parse {
+                    line: line_no,
+                    detail: format!("ID {:?} uses the prefix {:?} reserved for features without an ID", id, ANON_ID_PREFIX),
+                });
+            }
+            Some(_) => {}
+        }
+        // Extract raw Parent (may refer to unseen IDs)
+        let parent = attr_str(col9, parent_key);
+        // Extract attribute values
+        // Empty values (e.g. `key= ;`) are treated as absent
+        for (&key, column) in attr_keys.iter().zip(attr_columns.iter_mut()) {
+            let attr = attr_str(col9, key).inspect(|val| {
+                // GFF3 spec: attribute values must be URL-encoded.
+                // Raw characters such as space, semicolon, or comma are not allowed.
+                if val.contains(' ') || val.contains(';') || val.contains(',') {
+                    diag!("[WARN] Attribute value contains invalid chars (.,;) (should be URL-encoded): in '{}'", val);
+                }
+            });
+            column.push(attr);
+        }
+        let name = if name_index { attr_str(col9, NAME_KEY) } else { None };
         
         raw_features.push(RawFeature {
             seqid,
+            ftype,
             start,
             end,
             line_offset,
+            line_no,
+            col9,
             id,
             parent,
-            attr,
+            name,
+            forced_root: root_types_set.contains(ftype),
         });
     }
 
-    // Build feature_map: string ID -> numeric ID
-    let mut feature_map: FxHashMap<String, u32> = FxHashMap::default();
+    // Build feature_map: string ID -> numeric ID. Lines repeating an earlier ID (GFF3
+    // discontinuous features, e.g. a CDS split over several lines) are parts of one
+    // feature: one fid and one .fts/.prt/.a2f entry, taken from the first part
+    let mut feature_map: FxHashMap<&str, u32> = FxHashMap::default();
+    feature_map.reserve(raw_features.len());
+    // Fid of each raw feature line, and raw index of the first line of each fid
+    let mut line_fid: Vec<u32> = Vec::with_capacity(raw_features.len());
+    let mut first_line: Vec<usize> = Vec::with_capacity(raw_features.len());
+    // Fid -> raw indices of its further parts
+    let mut later_parts: FxHashMap<u32, Vec<usize>> = FxHashMap::default();
     for (i, rf) in raw_features.iter().enumerate() {
-        feature_map.insert(rf.id.clone(), i as u32);
+        let fid = match rf.id.and_then(|id| feature_map.get(id)) {
+            Some(&fid) => {
+                later_parts.entry(fid).or_default().push(i);
+                fid
+            }
+            None => {
+                let fid = first_line.len() as u32;
+                first_line.push(i);
+                if let Some(id) = rf.id {
+                    feature_map.insert(id, fid);
+                }
+                fid
+            }
+        };
+        line_fid.push(fid);
+    }
+    let n_features = first_line.len();
+    // Raw indices of every line of a fid, first part first
+    let parts = |fid: u32| {
+        std::iter::once(first_line[fid as usize]).chain(later_parts.get(&fid).into_iter().flatten().copied())
+    };
+    let id_of = |fid: u32| first_line.get(fid as usize).and_then(|&i| raw_features[i].id).unwrap_or(".");
+
+    if n_malformed > 0 {
+        diag!(
+            "[WARN] Skipped {} line(s) without exactly 9 tab-separated columns (use --strict to fail instead):",
+            n_malformed
+        );
+        for (n, cols) in &malformed_reports {
+            diag!("[WARN]   line {}: {} column(s)", n, cols);
+        }
+        if n_malformed > malformed_reports.len() {
+            diag!("[WARN]   ... and {} more", n_malformed - malformed_reports.len());
+        }
+    }
+
+    if n_anonymous > 0 {
+        diag!(
+            "[INFO] Indexed {} feature(s) without an ID; they are extracted with their model but cannot be looked up (use --strict-ids to fail instead)",
+            n_anonymous
+        );
+    }
+
+    if !later_parts.is_empty() {
+        diag!(
+            "[INFO] {} feature(s) span several lines with the same ID ({} lines); each is indexed once, and extract emits every part",
+            later_parts.len(),
+            later_parts.values().map(|v| v.len() + 1).sum::<usize>()
+        );
+    }
+
+    let mut skipped: Vec<(&str, usize)> = skipped_types.into_iter().collect();
+    skipped.sort_unstable();
+    let n_skipped = skipped.iter().map(|&(_, n)| n).sum();
+    if n_skipped > 0 {
+        let per_type: Vec<String> = skipped.iter().map(|(t, n)| format!("{} {}", n, t)).collect();
+        diag!("[INFO] Skipped {} feature line(s) by type (--skip-types): {}", n_skipped, per_type.join(", "));
+    }
+
+    // A feature's attribute value is that of its first part carrying one
+    let n_with_attr: Vec<usize> = attr_columns
+        .iter()
+        .map(|c| (0..n_features as u32).filter(|&f| parts(f).any(|i| c[i].is_some())).count())
+        .collect();
+    for (i, (&key, &n)) in attr_keys.iter().zip(&n_with_attr).enumerate() {
+        diag!("[INFO] Attribute '{}': {} feature(s) with a value, {} without", key, n, n_features - n);
+        if n_features > 0 && n == 0 {
+            diag!(
+                "[WARN] No feature has a '{}' attribute; the attribute index ({}) is empty and searches on it find nothing",
+                key,
+                attr_index_suffixes(i).0
+            );
+            if let Some(k) = did_you_mean(key, observed_keys.iter().copied()) {
+                diag!("[WARN] Did you mean '{}'? Rebuild with -a {}", k, k);
+            }
+        }
+    }
+
+    // A feature without an ID whose other attributes carry a value that children name
+    // as their parent: the ID is most likely under another key than `id_key`
+    let unresolved: FxHashSet<&str> = raw_features
+        .iter()
+        .flat_map(|rf| parent_tokens(rf.parent))
+        .filter(|p| !feature_map.contains_key(p))
+        .collect();
+    if !unresolved.is_empty() {
+        for rf in raw_features.iter().filter(|rf| rf.id.is_none()) {
+            let named = rf.col9.split(';').filter_map(|pair| pair.split_once('=')).find(|(k, v)| {
+                k.trim() != parent_key && v.split(',').any(|v| unresolved.contains(v.trim()))
+            });
+            if let Some((key, value)) = named {
+                return Err(Error::Parse {
+                    line: rf.line_no,
+                    detail: format!(
+                        "feature has children ({}={}) but no {} attribute; index with --id-key {} if that key holds the IDs",
+                        parent_key,
+                        value.trim(),
+                        id_key,
+                        key.trim()
+                    ),
+                });
+            }
+        }
+    }
+
+    // Forced roots nested under other forced roots usually indicate a wrong --root-types list
+    if !root_types_set.is_empty() {
+        let parent_of = |rf: &RawFeature| parent_tokens(rf.parent).find_map(|p| feature_map.get(p)).copied();
+        let mut nested = 0usize;
+        for rf in first_line.iter().map(|&i| &raw_features[i]).filter(|rf| rf.forced_root) {
+            let mut cur = parent_of(rf);
+            // Bounded walk so that Parent cycles cannot hang the build
+            for _ in 0..n_features {
+                let Some(p) = cur else { break };
+                let prf = &raw_features[first_line[p as usize]];
+                if prf.forced_root {
+                    nested += 1;
+                    if verbose {
+                        diag!(
+                            "[WARN] Forced root {} is nested inside forced root {}",
+                            rf.id.unwrap_or("."),
+                            prf.id.unwrap_or(".")
+                        );
+                    }
+                    break;
+                }
+                cur = parent_of(prf);
+            }
+        }
+        if nested > 0 {
+            diag!(
+                "[WARN] {} forced root(s) are nested inside other forced roots (--root-types {})",
+                nested,
+                root_types.unwrap_or_default()
+            );
+        }
     }
 
     // Open output files
-    let mut fts_file = File::create(append_suffix(gff, ".fts"))?;
-    let mut prt_entries = Vec::with_capacity(raw_features.len());
-    let mut a2f_entries = Vec::with_capacity(raw_features.len());
-    let mut atn_entries = Vec::new();
-    let mut attr_value_to_id: FxHashMap<String, u32> = FxHashMap::default();
-    let mut gof_file = File::create(append_suffix(gff, ".gof"))?;
-    let mut seqid_to_num: IndexMap<String, u32> = IndexMap::new();
+    let fts_path = index_path(gff, ".fts");
+    let gof_path = index_path(gff, ".gof");
+    let mut fts_file = BufWriter::new(File::create(&fts_path).map_err(|e| Error::io(&fts_path, e))?);
+    let mut prt_entries = Vec::with_capacity(n_features);
+    // Per attribute key: AID per feature, distinct values, value -> AID
+    let mut a2f_entries: Vec<Vec<u32>> = vec![Vec::with_capacity(n_features); attr_keys.len()];
+    let mut atn_entries: Vec<Vec<&str>> = vec![Vec::new(); attr_keys.len()];
+    let mut attr_value_to_id: Vec<FxHashMap<&str, u32>> = vec![FxHashMap::default(); attr_keys.len()];
+    let mut name_a2f_entries = Vec::with_capacity(if name_index { n_features } else { 0 });
+    let mut name_atn_entries = Vec::new();
+    let mut name_value_to_id: FxHashMap<&str, u32> = FxHashMap::default();
+    let mut seqid_to_num: IndexMap<&str, u32> = IndexMap::new();
     let mut trees_input: IndexMap<u32, Vec<(u32, u32, u32)>> = IndexMap::new();
     let mut next_seqid_num: u32 = 0;
-    let mut current_root: Option<(u32, u64, u32)> = None;
-
-    // Write .fts and build .prt, .a2f, .gof, and seqid intervals
-    for rf in &raw_features {
-        let fid = feature_map[&rf.id];
-        writeln!(fts_file, "{}", rf.id)?;
-        // Resolve parent (fallback to self if missing)
-        let parent_id = rf
-            .parent
-            .as_ref()
-            .and_then(|p| feature_map.get(p).cloned())
-            .unwrap_or(fid);
-        prt_entries.push(parent_id);
-        // Record roots for GOF and intervals
-        if parent_id == fid {
-            let seqid_num = *seqid_to_num.entry(rf.seqid.clone()).or_insert_with(|| {
+    // Sequences whose roots resume after another sequence's roots (interleaved seqids)
+    let mut prev_seqid_num: Option<u32> = None;
+    let mut resumed_seqids: FxHashSet<u32> = FxHashSet::default();
+    // Root fid -> sequence number, for the roots that get blocks (not dropped duplicates)
+    let mut root_seqid: FxHashMap<u32, u32> = FxHashMap::default();
+    // (seqid, start, end, type) -> distinct root fids in file order
+    let mut root_groups: FxHashMap<RootKey, Vec<u32>> = FxHashMap::default();
+    let mut n_dropped_roots = 0usize;
+
+    // Write .fts and build .prt, .a2f (once per feature, at its first part), and seqid
+    // intervals (per root line)
+    for (i, rf) in raw_features.iter().enumerate() {
+        let fid = line_fid[i];
+        if first_line[fid as usize] == i {
+            match rf.id {
+                Some(id) => writeln!(fts_file, "{}", id),
+                None => writeln!(fts_file, "{}{}", ANON_ID_PREFIX, fid),
+            }
+            .map_err(|e| Error::io(&fts_path, e))?;
+            // Resolve parent (the first of several that resolves; fallback to self if missing;
+            // forced roots always point to self)
+            let parent_id = if rf.forced_root {
+                fid
+            } else {
+                parent_tokens(rf.parent)
+                    .find_map(|p| feature_map.get(p).copied())
+                    .unwrap_or(fid)
+            };
+            prt_entries.push(parent_id);
+
+            // Attribute mapping
+            for (k, column) in attr_columns.iter().enumerate() {
+                let value = parts(fid).find_map(|j| column[j]);
+                push_attr_value(value, &mut attr_value_to_id[k], &mut atn_entries[k], &mut a2f_entries[k]);
+            }
+            if name_index {
+                let value = parts(fid).find_map(|j| raw_features[j].name);
+                push_attr_value(value, &mut name_value_to_id, &mut name_atn_entries, &mut name_a2f_entries);
+            }
+        }
+        // Record roots for intervals (and GOF below)
+        if prt_entries[fid as usize] == fid {
+            let seen = seqid_to_num.contains_key(rf.seqid);
+            let seqid_num = *seqid_to_num.entry(rf.seqid).or_insert_with(|| {
                 let id = next_seqid_num;
                 next_seqid_num += 1;
                 id
             });
-            
-            trees_input
-                .entry(seqid_num)
-                .or_default()
-                .push((rf.start, rf.end, fid));
-    
-            if let Some((old_id, old_off, old_seqid_num)) = current_root.take() {
-                write_gof(&mut gof_file, old_id, old_seqid_num, old_off, rf.line_offset)?;
+            if seen && prev_seqid_num != Some(seqid_num) {
+                resumed_seqids.insert(seqid_num);
+            }
+            prev_seqid_num = Some(seqid_num);
+
+            let group = root_groups.entry((rf.seqid, rf.start, rf.end, rf.ftype)).or_default();
+            if !group.contains(&fid) {
+                group.push(fid);
+            }
+            let dropped = dedup_identical && group[0] != fid;
+
+            if dropped {
+                // The lines of its model stay outside every block
+                n_dropped_roots += 1;
+            } else {
+                trees_input
+                    .entry(seqid_num)
+                    .or_default()
+                    .push((rf.start, rf.end, fid));
+                root_seqid.entry(fid).or_insert(seqid_num);
             }
-            current_root = Some((fid, rf.line_offset, seqid_num));
         }
-        
-        // Attribute mapping
-        if let Some(val) = &rf.attr {
-            let aid = *attr_value_to_id.entry(val.clone()).or_insert_with(|| {
-                let a = atn_entries.len() as u32;
-                atn_entries.push(val.clone());
-                a
-            });
-            a2f_entries.push(aid);
+    }
+    fts_file.flush().map_err(|e| Error::io(&fts_path, e))?;
+
+    // Write .fbl (Bloom filter over feature IDs)
+    let fbl_path = index_path(gff, ".fbl");
+    if let Some(fpr) = bloom_fpr {
+        let mut bloom = BloomFilter::with_rate(feature_map.len(), fpr);
+        for id in feature_map.keys() {
+            bloom.insert(id);
+        }
+        let bytes = bloom.to_bytes();
+        std::fs::write(&fbl_path, &bytes).map_err(|e| Error::io(&fbl_path, e))?;
+        if verbose {
+            diag!(
+                "[INFO] Bloom filter: {} IDs, {} bytes (.fbl, target FPR {})",
+                feature_map.len(),
+                bytes.len(),
+                fpr
+            );
+        }
+    } else {
+        let _ = std::fs::remove_file(&fbl_path);
+    }
+
+    // Root of a fid along .prt; `None` for a Parent cycle
+    let root_in_prt = |mut cur: u32| -> Option<u32> {
+        for _ in 0..=prt_entries.len() {
+            let p = prt_entries[cur as usize];
+            if p == cur {
+                return Some(cur);
+            }
+            cur = p;
+        }
+        None
+    };
+
+    // Blocks: runs of consecutive feature lines of the same model (resolved root), each
+    // from its first line to the first line of the next run; '#' and skipped lines go
+    // with the run before them. A model is normally one run starting at its root line,
+    // and children listed before their parent (forward references) join it. A model
+    // whose lines are interleaved with another's gets one .gof record per run.
+    // (root fid, start offset, indexed features)
+    let mut runs: Vec<(u32, u64, u32)> = Vec::new();
+    let mut counted_parts: FxHashSet<(usize, u32)> = FxHashSet::default();
+    for (i, rf) in raw_features.iter().enumerate() {
+        let fid = line_fid[i];
+        let current = runs.last().map(|r| r.0);
+        let own_parent = || parent_tokens(rf.parent).find_map(|p| feature_map.get(p).copied());
+        let root = if prt_entries[fid as usize] == fid {
+            Some(fid)
+        } else if rf.parent.is_some_and(|p| p.contains(','))
+            && parent_tokens(rf.parent).any(|p| feature_map.get(p).and_then(|&f| root_in_prt(f)) == current)
+        {
+            // A feature of several models stays with the one it lies in
+            current
+        } else if first_line[fid as usize] != i
+            && let Some(p) = own_parent()
+        {
+            // A later part goes with the model its own Parent names
+            root_in_prt(p)
         } else {
-            a2f_entries.push(u32::MAX);
+            root_in_prt(prt_entries[fid as usize])
+        };
+        // Lines of a Parent cycle stay where they are
+        let Some(root) = root.or(current) else { continue };
+        if runs.last().is_none_or(|run| run.0 != root) {
+            runs.push((root, rf.line_offset, 0));
+        }
+        // Parts of a split feature count once per block
+        if !later_parts.contains_key(&fid) || counted_parts.insert((runs.len(), fid)) {
+            runs.last_mut().unwrap().2 += 1;
         }
     }
-    // Write final GOF record
-    if let Some((last_id, last_off, last_seqid_num)) = current_root {
-        write_gof(&mut gof_file, last_id, last_seqid_num, last_off, data.len() as u64)?;
+    let mut gof_file = File::create(&gof_path).map_err(|e| Error::io(&gof_path, e))?;
+    // (root fid, indexed features in its block), one per GOF record
+    let mut gcnt: Vec<u32> = Vec::new();
+    let mut runs_per_root: FxHashMap<u32, u32> = FxHashMap::default();
+    for (k, &(root, start, count)) in runs.iter().enumerate() {
+        // Dropped duplicate roots get no block
+        let Some(&seqid_num) = root_seqid.get(&root) else { continue };
+        let end = runs.get(k + 1).map_or(data.len() as u64, |r| r.1);
+        write_gof(&mut gof_file, root, seqid_num, start, end).map_err(|e| Error::io(&gof_path, e))?;
+        gcnt.extend([root, count]);
+        *runs_per_root.entry(root).or_default() += 1;
+    }
+    write_binary_u32(index_path(gff, ".gcnt"), &gcnt)?;
+    let split: Vec<u32> = runs_per_root.into_iter().filter(|&(_, n)| n > 1).map(|(root, _)| root).collect();
+    if !split.is_empty() {
+        let mut ids: Vec<&str> = split.iter().map(|&f| id_of(f)).collect();
+        ids.sort_unstable();
+        let more = ids.len().saturating_sub(MAX_DUPLICATE_ROOT_REPORTS);
+        ids.truncate(MAX_DUPLICATE_ROOT_REPORTS);
+        diag!(
+            "[WARN] {} model(s) have lines interleaved with other models ({}{}); each is extracted from several ranges, \
+             in file order. Sort the GFF by model to keep models contiguous",
+            split.len(),
+            ids.join(", "),
+            if more > 0 { format!(", ... and {} more", more) } else { String::new() }
+        );
     }
 
-    // Build interval trees per seqid
-    let mut trees = Vec::with_capacity(seqid_to_num.len());
-    for (_seqid, seqid_num) in &seqid_to_num {
-        let ivs = &trees_input[seqid_num];
-        let iv_structs: Vec<Interval<_>> = ivs
+    // Features with several Parent values (across their parts, for features split over
+    // several lines): .prt keeps the first that resolves, .mpr (child fid, parent fid)
+    // records the others
+    let mut mpr_entries: Vec<u32> = Vec::new();
+    let mut n_multi_parent = 0usize;
+    let mut cross_model: Vec<(u64, u32, u32)> = Vec::new();
+    for (fid, &i) in first_line.iter().enumerate() {
+        let (fid, rf) = (fid as u32, &raw_features[i]);
+        if rf.forced_root || (!later_parts.contains_key(&fid) && !rf.parent.is_some_and(|p| p.contains(','))) {
+            continue;
+        }
+        let primary = prt_entries[fid as usize];
+        let mut others: Vec<u32> = Vec::new();
+        let tokens = parts(fid).flat_map(|j| parent_tokens(raw_features[j].parent));
+        for p in tokens.filter_map(|p| feature_map.get(p).copied()) {
+            if p != primary && p != fid && !others.contains(&p) {
+                others.push(p);
+            }
+        }
+        if others.is_empty() {
+            continue;
+        }
+        n_multi_parent += 1;
+        let root = root_in_prt(primary);
+        if let Some(other) = others.iter().filter_map(|&p| root_in_prt(p)).find(|&r| Some(r) != root) {
+            cross_model.push((rf.line_no, root.unwrap_or(u32::MAX), other));
+        }
+        mpr_entries.extend(others.into_iter().flat_map(|p| [fid, p]));
+    }
+    write_binary_u32(index_path(gff, ".mpr"), &mpr_entries)?;
+    if verbose && n_multi_parent > 0 {
+        diag!("[INFO] {} feature(s) have several {} values (.mpr)", n_multi_parent, parent_key);
+    }
+    if !cross_model.is_empty() {
+        diag!(
+            "[WARN] {} feature(s) name parents in different models; their lines stay in the block they lie in, \
+             and extract/search match them under every parent's model:",
+            cross_model.len()
+        );
+        for &(line, root, other) in cross_model.iter().take(MAX_DUPLICATE_ROOT_REPORTS) {
+            diag!("[WARN]   line {}: models {} and {}", line, id_of(root), id_of(other));
+        }
+        if cross_model.len() > MAX_DUPLICATE_ROOT_REPORTS {
+            diag!("[WARN]   ... and {} more", cross_model.len() - MAX_DUPLICATE_ROOT_REPORTS);
+        }
+    }
+
+    if !resumed_seqids.is_empty() {
+        let mut names: Vec<&str> = seqid_to_num
             .iter()
-            .map(|&(start, end, fid)| Interval {
-                start,
-                end,
-                root_fid: fid,
-            })
+            .filter(|(_, n)| resumed_seqids.contains(n))
+            .map(|(name, _)| *name)
             .collect();
-        trees.push(IntervalTree::new(iv_structs));
+        let more = names.len().saturating_sub(MAX_DUPLICATE_ROOT_REPORTS);
+        names.truncate(MAX_DUPLICATE_ROOT_REPORTS);
+        diag!(
+            "[INFO] {} sequence(s) are interleaved with others in the GFF ({}{}); models are still indexed per sequence, and outputs keep file order",
+            resumed_seqids.len(),
+            names.join(", "),
+            if more > 0 { format!(", ... and {} more", more) } else { String::new() }
+        );
+    }
+
+    // Report roots sharing seqid/start/end/type under different IDs
+    let mut dup_groups: Vec<(RootKey, Vec<u32>)> =
+        root_groups.into_iter().filter(|(_, fids)| fids.len() > 1).collect();
+    if !dup_groups.is_empty() {
+        dup_groups.sort_unstable_by_key(|(_, fids)| fids[0]);
+        diag!(
+            "[WARN] {} group(s) of root features with identical seqid, start, end and type:",
+            dup_groups.len()
+        );
+        for ((seqid, start, end, ftype), fids) in dup_groups.iter().take(MAX_DUPLICATE_ROOT_REPORTS) {
+            let ids: Vec<&str> = fids.iter().map(|&f| id_of(f)).collect();
+            diag!("[WARN]   {}:{}-{} {}: {}", seqid, start + 1, end, ftype, ids.join(", "));
+        }
+        if dup_groups.len() > MAX_DUPLICATE_ROOT_REPORTS {
+            diag!("[WARN]   ... and {} more", dup_groups.len() - MAX_DUPLICATE_ROOT_REPORTS);
+        }
+        if dedup_identical {
+            diag!(
+                "[WARN] Dropped {} duplicate root(s) from .gof/.rit; their lines remain in the GFF but are not extractable",
+                n_dropped_roots
+            );
+        } else {
+            diag!("[WARN] Use --dedup-identical to index only the first root of each group");
+        }
     }
 
-    // Write .rit and .rix
-    let rit = append_suffix(gff, ".rit");
-    let rix = append_suffix(gff, ".rix");
-    let offsets = save_multiple_trees(&trees, rit.as_path())?;
-    write_offsets_to_file(&offsets, rix.as_path())?;
+
+    // Build interval trees per seqid in parallel. Intervals are sorted by
+    // (start, end, fid) first so the tree shape does not depend on input order;
+    // the ordered collect keeps .rit aligned with .sqs.
+    let per_seqid: Vec<Vec<(u32, u32, u32)>> = seqid_to_num
+        .values()
+        .map(|seqid_num| trees_input.swap_remove(seqid_num).unwrap_or_default())
+        .collect();
+    let trees: Vec<IntervalTree<u32>> = per_seqid
+        .into_par_iter()
+        .map(|mut ivs| {
+            ivs.sort_unstable();
+            let iv_structs: Vec<Interval<_>> = ivs
+                .into_iter()
+                .map(|(start, end, fid)| Interval {
+                    start,
+                    end,
+                    payload: fid,
+                })
+                .collect();
+            IntervalTree::new(iv_structs)
+        })
+        .collect();
+
+    // Write .rit (tree offsets in its header); drop the .rix of an older build
+    save_multiple_trees(&trees, &index_path(gff, ".rit"))?;
+    let _ = std::fs::remove_file(index_path(gff, ".rix"));
 
     // Write .sqs (sequence list)
-    let seqids: Vec<String> = seqid_to_num.keys().cloned().collect();
-    write_lines(append_suffix(gff, ".sqs"), &seqids)?;
+    let seqids: Vec<&str> = seqid_to_num.keys().copied().collect();
+    write_lines(index_path(gff, ".sqs"), &seqids)?;
 
-    // Write .atn, .a2f, .prt
-    let mut atn_out = Vec::with_capacity(atn_entries.len() + 1);
-    atn_out.push(format!("#attribute={}", attr_key));
-    atn_out.extend(atn_entries.clone());
-    write_lines(append_suffix(gff, ".atn"), &atn_out)?;
-    write_binary_u32(append_suffix(gff, ".a2f"), &a2f_entries)?;
-    write_binary_u32(append_suffix(gff, ".prt"), &prt_entries)?;
+    // Write .atn and .a2f per attribute key (.atn.1, .a2f.1, ... after the first)
+    for (i, &key) in attr_keys.iter().enumerate() {
+        let (atn_entries, a2f_entries) = (&atn_entries[i], &a2f_entries[i]);
+        // Values are deduplicated through attr_value_to_id, so the AIDs in .a2f must be
+        // exactly 0..atn_entries.len(); loaders rely on line index == AID.
+        let max_aid = a2f_entries.iter().copied().filter(|&a| a != u32::MAX).max();
+        if max_aid.map_or(0, |m| m as usize + 1) != atn_entries.len() {
+            return Err(Error::corrupt(
+                "ATN",
+                format!("attribute table out of sync: {} values but max AID is {:?}", atn_entries.len(), max_aid),
+            ));
+        }
+        let (atn_suffix, a2f_suffix) = attr_index_suffixes(i);
+        let atn_header = format!("#attribute={}", key);
+        let mut atn_out = Vec::with_capacity(atn_entries.len() + 1);
+        atn_out.push(atn_header.as_str());
+        atn_out.extend_from_slice(atn_entries);
+        write_lines(index_path(gff, atn_suffix), &atn_out)?;
+        write_binary_u32(index_path(gff, a2f_suffix), a2f_entries)?;
+    }
+    // Drop the tables of keys an earlier build indexed beyond these
+    for i in attr_keys.len()..MAX_ATTR_KEYS {
+        let (atn_suffix, a2f_suffix) = attr_index_suffixes(i);
+        let _ = std::fs::remove_file(index_path(gff, atn_suffix));
+        let _ = std::fs::remove_file(index_path(gff, a2f_suffix));
+    }
+
+    // Write .prt
+    write_binary_u32(index_path(gff, ".prt"), &prt_entries)?;
+
+    // Write .natn, .na2f (secondary Name index)
+    if name_index {
+        let natn_header = format!("#attribute={}", NAME_KEY);
+        let mut natn_out = Vec::with_capacity(name_atn_entries.len() + 1);
+        natn_out.push(natn_header.as_str());
+        natn_out.extend(name_atn_entries);
+        write_lines(index_path(gff, ".natn"), &natn_out)?;
+        write_binary_u32(index_path(gff, ".na2f"), &name_a2f_entries)?;
+
+        let natn_bytes: usize = natn_out.iter().map(|l| l.len() + 1).sum();
+        let na2f_bytes = name_a2f_entries.len() * 4;
+        diag!(
+            "[INFO] Name index: {} distinct values, {} bytes (.natn {} + .na2f {})",
+            natn_out.len() - 1,
+            natn_bytes + na2f_bytes,
+            natn_bytes,
+            na2f_bytes
+        );
+    } else {
+        // Drop a stale Name index left by an earlier build so search cannot use it
+        for suffix in [".natn", ".na2f"] {
+            let _ = std::fs::remove_file(index_path(gff, suffix));
+        }
+    }
+    // Write .meta (what the index was built from, checked before reading blocks)
+    let meta_path = index_path(gff, ".meta");
+    let meta = IndexMeta::of_bytes(gff, data)
+        .with_attr_keys(observed_keys.iter().map(|k| k.to_string()).collect())
+        .with_id_keys(id_key, parent_key)
+        .with_seq_lengths(seq_lengths.into_iter().map(|(seq, len)| (seq.to_string(), len)).collect());
+    std::fs::write(&meta_path, meta.to_text()).map_err(|e| Error::io(&meta_path, e))?;
+
+    // Loose files take precedence, but a stale bundle could still supply optional ones
+    let _ = std::fs::remove_file(index_path(gff, BUNDLE_SUFFIX));
 
     if verbose {
-        eprintln!("Index built successfully for {}", gff.display());
+        diag!("Index built successfully for {}", gff.display());
     }
-    Ok(())
+    Ok(BuildStats { features: n_features, with_attribute: n_with_attr, skipped: n_skipped })
 }
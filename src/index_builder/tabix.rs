@@ -0,0 +1,138 @@
+use crate::{ByteSource, index_path};
+use anyhow::{Context, Result, bail};
+use rust_htslib::{bgzf, htslib};
+use std::{
+    ffi::CString,
+    io::Write,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+/// Bytes handed to the BGZF writer per call.
+const BGZF_COPY_CHUNK: usize = 4 * 1024 * 1024;
+
+/// Min shift of the CSI binning scheme (same as `tabix -C`).
+const CSI_MIN_SHIFT: i32 = 14;
+
+/// Write a BGZF copy of `gff` next to it (`<gff>.gz`) and build its tabix index
+/// (`.gz.tbi`, or `.gz.csi` when `csi`) with the GFF preset: sequence column 1,
+/// begin 4, end 5, comment '#'.
+///
+/// tabix requires the records to be sorted by sequence and start; htslib rejects
+/// unsorted input and the error is reported here.
+pub fn write_tabix_companion(gff: &Path, csi: bool, verbose: bool) -> Result<PathBuf> {
+    let gz_path = index_path(gff, ".gz");
+
+    let mmap = ByteSource::open_gff(gff)?;
+    {
+        let mut writer = bgzf::Writer::from_path(&gz_path)
+            .with_context(|| format!("Cannot create BGZF file {:?}", gz_path))?;
+        for chunk in mmap.chunks(BGZF_COPY_CHUNK) {
+            writer
+                .write_all(chunk)
+                .with_context(|| format!("Failed to write {:?}", gz_path))?;
+        }
+        writer.flush()?;
+    }
+
+    let c_path = CString::new(gz_path.as_os_str().as_bytes())
+        .with_context(|| format!("Path contains a NUL byte: {:?}", gz_path))?;
+    let min_shift = if csi { CSI_MIN_SHIFT } else { 0 };
+    // SAFETY: `c_path` is a valid NUL-terminated path and `tbx_conf_gff` is htslib's
+    // static GFF preset; htslib only reads both.
+    let ret = unsafe { htslib::tbx_index_build(c_path.as_ptr(), min_shift, &htslib::tbx_conf_gff) };
+    if ret != 0 {
+        bail!(
+            "tabix index build failed for {:?} (code {}); the GFF must be sorted by seqid and start",
+            gz_path,
+            ret
+        );
+    }
+
+    if verbose {
+        diag!(
+            "[INFO] Wrote {} and its {} index",
+            gz_path.display(),
+            if csi { "CSI" } else { "TBI" }
+        );
+    }
+    Ok(gz_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{GffSynth, ScratchDir};
+    use rust_htslib::tbx::{self, Read};
+
+    /// A synthetic GFF with its feature lines sorted by seqid and start, as tabix needs.
+    fn sorted_gff(dir: &ScratchDir) -> (PathBuf, Vec<(String, u64, u64, String)>) {
+        let raw = dir.join("raw.gff");
+        GffSynth::new().chromosomes(3).genes_per_chrom(40).seed(21).write(&raw).unwrap();
+        let text = std::fs::read_to_string(&raw).unwrap();
+        let (header, body): (Vec<&str>, Vec<&str>) = text.lines().partition(|l| l.starts_with('#'));
+        let mut features: Vec<(String, u64, u64, String)> = body
+            .iter()
+            .map(|l| {
+                let f: Vec<&str> = l.split('\t').collect();
+                (f[0].to_string(), f[3].parse().unwrap(), f[4].parse().unwrap(), l.to_string())
+            })
+            .collect();
+        // chrN names of one digit sort like their numbers
+        features.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+        let gff = dir.join("sorted.gff");
+        let mut out = header.join("\n") + "\n";
+        for (.., line) in &features {
+            out.push_str(line);
+            out.push('\n');
+        }
+        std::fs::write(&gff, out).unwrap();
+        (gff, features)
+    }
+
+    fn fetch(gz: &Path, seq: &str, start: u64, end: u64) -> Vec<String> {
+        let mut reader = tbx::Reader::from_path(gz).unwrap();
+        let tid = reader.tid(seq).unwrap();
+        reader.fetch(tid, start, end).unwrap();
+        reader.records().map(|r| String::from_utf8(r.unwrap()).unwrap()).collect()
+    }
+
+    #[test]
+    fn companion_index_answers_region_queries() {
+        let dir = ScratchDir::new("tabix").unwrap();
+        let (gff, features) = sorted_gff(&dir);
+        for csi in [false, true] {
+            let gz = write_tabix_companion(&gff, csi, false).unwrap();
+            let idx = PathBuf::from(format!("{}.{}", gz.display(), if csi { "csi" } else { "tbi" }));
+            assert!(idx.exists(), "{:?} missing", idx);
+
+            let reader = tbx::Reader::from_path(&gz).unwrap();
+            assert_eq!(reader.seqnames(), ["chr1", "chr2", "chr3"]);
+            // 0-based half-open windows: one base inside a gene, across gene boundaries, the
+            // first base of a sequence
+            let gene = features.iter().filter(|f| f.0 == "chr2" && f.3.contains("\tgene\t")).nth(3).unwrap();
+            let mid = (gene.1 + gene.2) / 2;
+            let mut total = 0;
+            for (seq, start, end) in [("chr2", mid, mid + 1), ("chr1", 5_000, 60_000), ("chr3", 0, 1)] {
+                let want: Vec<&str> = features
+                    .iter()
+                    .filter(|(s, fs, fe, _)| s == seq && fs - 1 < end && *fe > start)
+                    .map(|(.., line)| line.as_str())
+                    .collect();
+                assert_eq!(fetch(&gz, seq, start, end), want, "{}:{}-{} (csi {})", seq, start, end, csi);
+                total += want.len();
+            }
+            assert!(total > 1);
+            std::fs::remove_file(&idx).unwrap();
+        }
+    }
+
+    #[test]
+    fn unsorted_gff_is_rejected() {
+        let dir = ScratchDir::new("tabix-unsorted").unwrap();
+        let gff = dir.join("g.gff");
+        std::fs::write(&gff, "chr1\t.\tgene\t500\t900\t.\t+\t.\tID=b\nchr1\t.\tgene\t100\t200\t.\t+\t.\tID=a\n").unwrap();
+        let err = write_tabix_companion(&gff, false, false).unwrap_err();
+        assert!(err.to_string().contains("must be sorted"), "{}", err);
+    }
+}
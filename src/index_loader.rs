@@ -3,9 +3,140 @@ pub mod gof;
 pub mod fts;
 pub mod prt;
 pub mod a2f;
+pub mod fbl;
+pub mod bundle;
+pub mod atn;
+pub mod meta;
 
-pub use core::{load_atn, load_sqs, safe_mmap_readonly};
-pub use gof::{GofMap, load_gof};
-pub use fts::{FtsMap, load_fts};
-pub use prt::{PrtMap, load_prt};
-pub use a2f::{A2fMap, load_a2f};
+pub use core::{
+    ByteSource, DECOMPRESSED_SUFFIX, RangeSource, apply_index_prefix, artifact_exists, check_gff_present,
+    gff_text_path, index_path, index_prefix, is_gzip, load_sqs, parse_sqs, safe_mmap_readonly, set_index_prefix,
+    set_no_mmap,
+};
+pub use gof::{GofMap, ModelBlock, load_gcnt, load_gof, parse_gcnt, parse_gof, scan_gof};
+pub use fts::{FTS_SCAN_MAX, FtsMap, load_fts, parse_fts, scan_fts};
+pub use prt::{PrtMap, load_mpr, load_prt, parse_mpr, parse_prt};
+pub use a2f::{A2fMap, load_a2f, parse_a2f};
+pub use fbl::{BloomFilter, load_fbl, parse_fbl};
+pub use atn::{
+    AtnTable, MAX_ATTR_KEYS, attr_index_suffixes, indexed_attr_keys, load_atn, load_attr_index, load_name_index,
+    parse_atn,
+};
+pub use bundle::{BUNDLE_SUFFIX, Bundle, BundleEntry, crc32, write_bundle};
+pub use meta::{
+    DEFAULT_ID_KEY, DEFAULT_PARENT_KEY, IndexMeta, check_index_matches, load_id_key, load_meta, load_parent_key,
+    set_force_index_mismatch,
+};
+
+// Loaded indexes are shared read-only across threads (e.g. behind one `Arc` serving
+// concurrent queries): their lazy caches are `OnceLock`s, built once by whichever
+// thread gets there first, and un-mapped GFFs are read with positioned reads. This
+// stops compiling if a loader type loses `Send + Sync`.
+const _: () = {
+    const fn shared<T: Send + Sync>() {}
+    shared::<ByteSource>();
+    shared::<RangeSource>();
+    shared::<GofMap>();
+    shared::<FtsMap>();
+    shared::<PrtMap>();
+    shared::<A2fMap>();
+    shared::<BloomFilter>();
+    shared::<AtnTable>();
+    shared::<Bundle>();
+    shared::<IndexMeta>();
+    shared::<crate::TreeIndexData>();
+    shared::<crate::AnnotationSet>();
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{Fixture, GffSynth};
+    use crate::{Error, RIT_MAGIC, TreeIndexData, parse_rit_header};
+
+    /// Index files of a small synthetic GFF, read back as bytes.
+    fn index_bytes(fx: &Fixture) -> impl Fn(&str) -> Vec<u8> {
+        move |suffix| std::fs::read(index_path(&fx.gff, suffix)).unwrap()
+    }
+
+    fn fixture() -> Fixture {
+        GffSynth::new().chromosomes(3).genes_per_chrom(30).seed(9).fixture().unwrap().1
+    }
+
+    fn is_corrupt<T>(r: crate::error::Result<T>) -> bool {
+        matches!(r, Err(Error::IndexCorrupt { .. }))
+    }
+
+    #[test]
+    fn truncated_or_misaligned_fixed_width_files_are_errors() {
+        let fx = fixture();
+        let read = index_bytes(&fx);
+        let (gof, prt, a2f, gcnt) = (read(".gof"), read(".prt"), read(".a2f"), read(".gcnt"));
+        assert!(parse_gof(&gof).is_ok() && parse_prt(&prt).is_ok() && parse_a2f(&a2f).is_ok());
+        assert!(parse_gcnt(&gcnt).is_ok());
+
+        for cut in 1..24 {
+            assert!(is_corrupt(parse_gof(&gof[..gof.len() - cut])), "gof cut by {}", cut);
+            assert!(is_corrupt(scan_gof(&gof[..gof.len() - cut], &[0])));
+        }
+        for cut in 1..8 {
+            assert!(is_corrupt(parse_gcnt(&gcnt[..gcnt.len() - cut])));
+            assert!(is_corrupt(parse_mpr(&gcnt[..gcnt.len() - cut])));
+        }
+        for cut in 1..4 {
+            assert!(is_corrupt(parse_prt(&prt[..prt.len() - cut])));
+            assert!(is_corrupt(parse_a2f(&a2f[..a2f.len() - cut])));
+            // Shifted by a few bytes: the records no longer line up
+            assert!(is_corrupt(parse_prt(&prt[cut..])));
+            assert!(is_corrupt(parse_a2f(&a2f[cut..])));
+            assert!(is_corrupt(parse_gof(&gof[cut..])));
+        }
+    }
+
+    #[test]
+    fn truncated_or_misaligned_rit_is_an_error() {
+        let rit = index_bytes(&fixture())(".rit");
+        let offsets = parse_rit_header(&rit).unwrap().expect("header");
+        assert_eq!(TreeIndexData::parse_region_index(&rit, &offsets).unwrap().len(), 3);
+
+        // Header and offset table cut short
+        for len in [RIT_MAGIC.len() + 3, RIT_MAGIC.len() + 8 + 4, RIT_MAGIC.len() + 8 + 8 * offsets.len() - 1] {
+            assert!(is_corrupt(parse_rit_header(&rit[..len])), "header cut at {}", len);
+        }
+        // Trees cut short, mid-tree and right after the header
+        for len in [rit.len() - 1, (offsets[1] + offsets[2]) as usize / 2, offsets[0] as usize] {
+            assert!(is_corrupt(TreeIndexData::parse_region_index(&rit[..len], &offsets)), "cut at {}", len);
+        }
+        // Offsets that do not point at a tree, past the end or out of order
+        let shifted: Vec<u64> = offsets.iter().map(|o| o + 1).collect();
+        assert!(is_corrupt(TreeIndexData::parse_region_index(&rit, &shifted)));
+        assert!(is_corrupt(TreeIndexData::parse_region_index(&rit, &[offsets[0], rit.len() as u64 + 1])));
+        assert!(is_corrupt(TreeIndexData::parse_region_index(&rit, &[offsets[1], offsets[0]])));
+        // A count of trees far beyond the file
+        let mut huge = rit[..RIT_MAGIC.len() + 8].to_vec();
+        huge[RIT_MAGIC.len() + 4..].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(is_corrupt(parse_rit_header(&huge)));
+        huge.extend_from_slice(&rit[RIT_MAGIC.len() + 8..]);
+        assert!(is_corrupt(parse_rit_header(&huge)));
+        // A version newer than this build reads
+        let mut newer = rit.clone();
+        newer[RIT_MAGIC.len()..RIT_MAGIC.len() + 4].copy_from_slice(&99u32.to_le_bytes());
+        assert!(is_corrupt(parse_rit_header(&newer)));
+    }
+
+    #[test]
+    fn cyclic_prt_resolves_to_no_root() {
+        // 0 -> 1 -> 2 -> 0 is a cycle, 3 is a root, 4 points past the end, 5 -> 3
+        let words: [u32; 6] = [1, 2, 0, 3, 77, 3];
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        let prt = parse_prt(&bytes).unwrap();
+        assert_eq!((0..7).map(|f| prt.root_of(f)).collect::<Vec<_>>(), [None, None, None, Some(3), None, Some(3), None]);
+        let fids: Vec<u32> = (0..6).chain([u32::MAX]).collect();
+        let want = [u32::MAX, u32::MAX, u32::MAX, 3, u32::MAX, 3, u32::MAX];
+        assert_eq!(prt.map_fids_to_roots(&fids, 1), want);
+        // The parallel path agrees
+        let many: Vec<u32> = fids.iter().copied().cycle().take(7 * 100).collect();
+        let roots = prt.map_fids_to_roots(&many, 4);
+        assert!(roots.chunks(7).all(|c| c == want));
+    }
+}
@@ -1,9 +1,9 @@
-use anyhow::{bail, Context, Result};
 use byteorder::{ByteOrder, LittleEndian};
 use rustc_hash::{FxHashMap, FxHashSet};
 use std::path::Path;
 
-use crate::{append_suffix, safe_mmap_readonly};
+use crate::{ByteSource, index_path};
+use crate::error::{Error, Result};
 
 /// A2fMap stores two indexes:
 /// - `aid_to_fids`: mapping from Attribute ID (AID) to all Feature IDs (FIDs) that reference it.
@@ -42,7 +42,7 @@ impl A2fMap {
             if let Some(fids) = self.aid_to_fids.get(&aid) {
                 out.extend(fids.iter().copied());
             } else {
-                eprintln!("[WARN] AID {} not found (no FIDs).", aid);
+                diag!("[WARN] AID {} not found (no FIDs).", aid);
             }
         }
         out
@@ -57,7 +57,7 @@ impl A2fMap {
             if let Some(fids) = self.aid_to_fids.get(&aid) {
                 out.extend_from_slice(fids);
             } else {
-                eprintln!("[WARN] AID {} not found (no FIDs).", aid);
+                diag!("[WARN] AID {} not found (no FIDs).", aid);
             }
         }
         out
@@ -76,24 +76,26 @@ impl A2fMap {
 /// - In memory: build both `fid -> aid` (vector) and `aid -> fids` (hashmap).
 pub fn load_a2f<P: AsRef<Path>>(gff_path: P) -> Result<A2fMap> {
     let path = gff_path.as_ref();
-    let a2f_path = append_suffix(path, ".a2f");
+    let a2f_path = index_path(path, ".a2f");
 
-    let mmap = safe_mmap_readonly(&a2f_path)
-        .with_context(|| format!("Failed to mmap {}", a2f_path.display()))?;
+    let bytes = ByteSource::open_artifact(path, ".a2f")?;
+    parse_a2f(&bytes).map_err(|e| e.for_index_file(&a2f_path))
+}
 
-    if mmap.len() % 4 != 0 {
-        bail!(
-            "Corrupted A2F ({}): length {} not aligned to u32",
-            a2f_path.display(),
-            mmap.len()
-        );
+/// Parse raw `.a2f` bytes (see `load_a2f`).
+pub fn parse_a2f(bytes: &[u8]) -> Result<A2fMap> {
+    if !bytes.len().is_multiple_of(4) {
+        return Err(Error::corrupt(
+            "A2F",
+            format!("length {} not aligned to u32", bytes.len()),
+        ));
     }
 
-    let n = mmap.len() / 4;
+    let n = bytes.len() / 4;
     let mut fid_to_aid: Vec<Option<u32>> = Vec::with_capacity(n);
     let mut aid_to_fids: FxHashMap<u32, Vec<u32>> = FxHashMap::default();
 
-    for (fid, chunk) in mmap.chunks_exact(4).enumerate() {
+    for (fid, chunk) in bytes.chunks_exact(4).enumerate() {
         let raw = LittleEndian::read_u32(chunk);
         let aid = if raw == u32::MAX { None } else { Some(raw) };
         fid_to_aid.push(aid);
@@ -0,0 +1,235 @@
+use memchr::{memchr, memchr_iter, memrchr};
+use std::{ops::Range, path::Path};
+
+use crate::{A2fMap, ByteSource, artifact_exists, index_path, parse_a2f};
+use crate::error::{Error, Result};
+
+/// Most attribute keys one index holds (`index -a KEY1,KEY2,...`).
+pub const MAX_ATTR_KEYS: usize = 8;
+
+/// Value table and a2f suffixes of the keys after the first, in `-a` order.
+const EXTRA_ATTR_SUFFIXES: [(&str, &str); MAX_ATTR_KEYS - 1] = [
+    (".atn.1", ".a2f.1"),
+    (".atn.2", ".a2f.2"),
+    (".atn.3", ".a2f.3"),
+    (".atn.4", ".a2f.4"),
+    (".atn.5", ".a2f.5"),
+    (".atn.6", ".a2f.6"),
+    (".atn.7", ".a2f.7"),
+];
+
+/// `.atn`/`.a2f` suffixes of the `i`-th indexed attribute key: the plain ones for the
+/// first key (so single-key indexes read as before), `.atn.<i>`/`.a2f.<i>` after it.
+/// Panics when `i >= MAX_ATTR_KEYS`.
+pub fn attr_index_suffixes(i: usize) -> (&'static str, &'static str) {
+    if i == 0 { (".atn", ".a2f") } else { EXTRA_ATTR_SUFFIXES[i - 1] }
+}
+
+/// Attribute value table (`.atn`, `.natn`): the value of AID `i` is line `i + 1` of the file.
+///
+/// Values stay in the mapped (or owned) bytes; only one offset per value is held,
+/// so a table of millions of values costs 8 bytes each instead of an owned `String`.
+/// UTF-8 is validated once by `parse_atn`.
+#[derive(Debug)]
+pub struct AtnTable {
+    bytes: ByteSource,
+    attr_name: String,
+    /// Start of every value line plus one past the end of the last; value `i` spans
+    /// `starts[i]..starts[i + 1] - 1` (the '\n', or a virtual one after the last line)
+    starts: Vec<u64>,
+}
+
+impl AtnTable {
+    /// The attribute key the table was built for (`#attribute=` header).
+    #[inline]
+    pub fn attr_name(&self) -> &str {
+        &self.attr_name
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.starts.len() - 1
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Value of `aid`, read verbatim (a trailing '\r' is stripped). Panics when out of range.
+    #[inline]
+    pub fn value(&self, aid: u32) -> &str {
+        let i = aid as usize;
+        let (start, end) = (self.starts[i] as usize, self.starts[i + 1] as usize - 1);
+        let line = &self.bytes[start..end];
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        // SAFETY: parse_atn checked that the whole value section is UTF-8, and both
+        // bounds sit next to an ASCII '\n' or '\r' (or the end of the data)
+        unsafe { std::str::from_utf8_unchecked(line) }
+    }
+
+    /// Value of `aid`, or `None` when out of range.
+    #[inline]
+    pub fn get(&self, aid: u32) -> Option<&str> {
+        ((aid as usize) < self.len()).then(|| self.value(aid))
+    }
+
+    /// (AID, value) pairs of `aids`, a sub-range of the table (clamped to its length).
+    pub fn range(&self, aids: Range<u32>) -> impl Iterator<Item = (u32, &str)> {
+        let end = aids.end.min(self.len() as u32);
+        (aids.start.min(end)..end).map(move |aid| (aid, self.value(aid)))
+    }
+
+    /// Every (AID, value) pair in AID order.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &str)> {
+        self.range(0..self.len() as u32)
+    }
+}
+
+pub fn load_atn(path: &Path) -> Result<AtnTable> {
+    let bytes = ByteSource::open_artifact(path, ".atn")?;
+    parse_atn(bytes).map_err(|e| e.for_index_file(&index_path(path, ".atn")))
+}
+
+/// The attribute keys with a value table, in `index -a` order (the first is the one
+/// `load_atn` reads). Only the `#attribute=` header of each table is read.
+pub fn indexed_attr_keys(path: &Path) -> Result<Vec<String>> {
+    let mut keys = Vec::new();
+    for i in 0..MAX_ATTR_KEYS {
+        let (atn_suffix, _) = attr_index_suffixes(i);
+        if !artifact_exists(path, atn_suffix) {
+            break;
+        }
+        let bytes = ByteSource::open_artifact(path, atn_suffix)?;
+        keys.push(parse_atn_header(&bytes).map_err(|e| e.for_index_file(&index_path(path, atn_suffix)))?);
+    }
+    Ok(keys)
+}
+
+/// Load the value table and a2f built for `key` (any of `indexed_attr_keys`).
+/// Returns `Ok(None)` when the index has no table for it.
+pub fn load_attr_index(path: &Path, key: &str) -> Result<Option<(AtnTable, A2fMap)>> {
+    let Some(i) = indexed_attr_keys(path)?.iter().position(|k| k == key) else {
+        return Ok(None);
+    };
+    let (atn_suffix, a2f_suffix) = attr_index_suffixes(i);
+    let atn = ByteSource::open_artifact(path, atn_suffix)?;
+    let values = parse_atn(atn).map_err(|e| e.for_index_file(&index_path(path, atn_suffix)))?;
+    let a2f = ByteSource::open_artifact(path, a2f_suffix)?;
+    let a2f = parse_a2f(&a2f[..]).map_err(|e| e.for_index_file(&index_path(path, a2f_suffix)))?;
+    Ok(Some((values, a2f)))
+}
+
+/// Load the secondary Name index (`.natn` + `.na2f`), if it was built.
+/// Returns `Ok(None)` when the files are absent.
+pub fn load_name_index(path: &Path) -> Result<Option<(AtnTable, A2fMap)>> {
+    if !artifact_exists(path, ".natn") || !artifact_exists(path, ".na2f") {
+        return Ok(None);
+    }
+    let natn = ByteSource::open_artifact(path, ".natn")?;
+    let values = parse_atn(natn).map_err(|e| e.for_index_file(&index_path(path, ".natn")))?;
+    let na2f = ByteSource::open_artifact(path, ".na2f")?;
+    let a2f = parse_a2f(&na2f[..]).map_err(|e| e.for_index_file(&index_path(path, ".na2f")))?;
+    Ok(Some((values, a2f)))
+}
+
+/// Parse `.atn` bytes into an `AtnTable`.
+///
+/// The first line must be the `#attribute=` header. Every following line is one value,
+/// read verbatim (only a trailing '\r' is stripped), so values starting with '#' and
+/// empty values keep their AID = line index - 1.
+pub fn parse_atn(bytes: ByteSource) -> Result<AtnTable> {
+    let attr_name = parse_atn_header(&bytes)?;
+    // Skip a UTF-8 BOM if present
+    let data: &[u8] = &bytes;
+    let body_start = if data.starts_with("\u{feff}".as_bytes()) { 3 } else { 0 };
+    let data = &data[body_start..];
+    let header_end = memchr(b'\n', data).unwrap_or(data.len());
+
+    let mut starts: Vec<u64> = Vec::new();
+    if header_end < data.len() {
+        let values = &data[header_end + 1..];
+        if let Err(e) = std::str::from_utf8(values) {
+            // Report the offending line on its own, as a per-line check would
+            let bad = e.valid_up_to();
+            let n = memchr_iter(b'\n', &values[..bad]).count();
+            let start = memrchr(b'\n', &values[..bad]).map_or(0, |i| i + 1);
+            let end = memchr(b'\n', &values[bad..]).map_or(values.len(), |i| bad + i);
+            let line = &values[start..end];
+            let e = std::str::from_utf8(line.strip_suffix(b"\r").unwrap_or(line)).err().unwrap_or(e);
+            return Err(Error::corrupt("ATN", format!("invalid UTF-8 in value {n}: {e}")));
+        }
+        let base = (body_start + header_end + 1) as u64;
+        starts.push(base);
+        starts.extend(memchr_iter(b'\n', values).map(|i| base + i as u64 + 1));
+        // An unterminated last line ends at a virtual '\n' past the data; a terminating
+        // '\n' leaves no trailing value
+        if !values.is_empty() && !values.ends_with(b"\n") {
+            starts.push(base + values.len() as u64 + 1);
+        }
+    }
+    if starts.is_empty() {
+        starts.push(0);
+    }
+
+    Ok(AtnTable { bytes, attr_name, starts })
+}
+
+/// The key of the `#attribute=` header line that starts `.atn` bytes.
+fn parse_atn_header(data: &[u8]) -> Result<String> {
+    let data = data.strip_prefix("\u{feff}".as_bytes()).unwrap_or(data);
+    let header_end = memchr(b'\n', data).unwrap_or(data.len());
+    let header = std::str::from_utf8(&data[..header_end])
+        .map_err(|e| Error::corrupt("ATN", format!("invalid UTF-8 in header: {e}")))?
+        .trim();
+    Ok(header
+        .strip_prefix("#attribute=")
+        .ok_or_else(|| Error::corrupt("ATN", "missing #attribute=... header"))?
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::build_fixture;
+    use crate::{load_a2f, load_fts};
+
+    #[test]
+    fn values_are_read_verbatim() {
+        let atn = |b: &[u8]| parse_atn(ByteSource::from(b.to_vec()));
+        let table = atn(b"#attribute=Dbxref\n#hash\n\nplain\r\n#attribute=x\n").unwrap();
+        assert_eq!(table.attr_name(), "Dbxref");
+        assert_eq!(table.iter().map(|(_, v)| v).collect::<Vec<_>>(), ["#hash", "", "plain", "#attribute=x"]);
+        assert_eq!(table.get(4), None);
+        let table = atn(b"\xef\xbb\xbf#attribute=Name\nlast").unwrap();
+        assert_eq!(table.iter().map(|(_, v)| v).collect::<Vec<_>>(), ["last"]);
+        assert!(atn(b"#attribute=Name").unwrap().is_empty());
+        assert!(atn(b"value\n").is_err());
+        assert!(atn(b"#attribute=Name\nok\nbad \xff\n").is_err());
+    }
+
+    #[test]
+    fn hash_empty_and_duplicate_values_round_trip() {
+        let gff = "\
+chr1\t.\tgene\t1\t10\t.\t+\t.\tID=g1;Name=#first
+chr1\t.\tgene\t20\t30\t.\t+\t.\tID=g2;Name= ;Note=x
+chr1\t.\tgene\t40\t50\t.\t+\t.\tID=g3;Name=dup
+chr1\t.\tgene\t60\t70\t.\t+\t.\tID=g4;Name=dup
+chr1\t.\tgene\t80\t90\t.\t+\t.\tID=g5;Name=#second
+";
+        let fx = build_fixture(gff).unwrap();
+        let table = load_atn(&fx.gff).unwrap();
+        assert_eq!(table.attr_name(), "Name");
+        // Empty values are not indexed; duplicates share one AID
+        assert_eq!(table.iter().map(|(_, v)| v).collect::<Vec<_>>(), ["#first", "dup", "#second"]);
+
+        let a2f = load_a2f(&fx.gff).unwrap();
+        let fts = load_fts(&fx.gff).unwrap();
+        let ids_of = |aid: u32| -> Vec<&str> {
+            a2f.fids_for_aid(aid).unwrap_or_default().iter().map(|&f| fts.get_id(f).unwrap()).collect()
+        };
+        assert_eq!(ids_of(0), ["g1"]);
+        assert_eq!(ids_of(1), ["g3", "g4"]);
+        assert_eq!(ids_of(2), ["g5"]);
+    }
+}
@@ -0,0 +1,173 @@
+use byteorder::{ByteOrder, LittleEndian};
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::ByteSource;
+use crate::error::{Error, Result};
+
+/// Suffix of the single-file index bundle (`<gff>.gxi`).
+pub const BUNDLE_SUFFIX: &str = ".gxi";
+
+const MAGIC: &[u8; 8] = b"GFFXGXI1";
+
+/// Artifacts are stored at 8-byte aligned offsets.
+const ALIGN: u64 = 8;
+
+/// One artifact of a `.gxi` bundle.
+#[derive(Debug, Clone)]
+pub struct BundleEntry {
+    /// Artifact suffix, e.g. `.gof`
+    pub name: String,
+    pub offset: u64,
+    pub len: u64,
+    /// CRC-32 (IEEE) of the artifact bytes
+    pub crc32: u32,
+}
+
+/// All index artifacts of one GFF in a single file (`gffx index --bundle`).
+///
+/// Layout, little-endian: `GFFXGXI1`, entry count (u32), then per entry the name
+/// length (u16), name, offset (u64), length (u64) and CRC-32 (u32); artifact bytes
+/// follow at their offsets. Loaders slice artifacts straight out of the mapping.
+#[derive(Debug)]
+pub struct Bundle {
+    bytes: ByteSource,
+    entries: Vec<BundleEntry>,
+}
+
+impl Bundle {
+    pub fn open(path: &Path) -> Result<Self> {
+        let bytes = ByteSource::open(path)?;
+        Self::parse(bytes).map_err(|e| e.for_index_file(path))
+    }
+
+    /// Read the table of contents; artifact bytes are checked against it only by `verify`.
+    pub fn parse(bytes: ByteSource) -> Result<Self> {
+        let corrupt = |detail: String| Error::corrupt("GXI", detail);
+        if bytes.len() < MAGIC.len() + 4 || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(corrupt("missing GFFXGXI1 header".to_string()));
+        }
+        let n = LittleEndian::read_u32(&bytes[8..12]) as usize;
+        let mut pos = 12usize;
+        let mut entries = Vec::with_capacity(n.min(64));
+        for i in 0..n {
+            let Some(name_len) = bytes.get(pos..pos + 2).map(LittleEndian::read_u16) else {
+                return Err(corrupt(format!("table of contents truncated at entry {}", i)));
+            };
+            let name_end = pos + 2 + name_len as usize;
+            let Some(rec) = bytes.get(name_end..name_end + 20) else {
+                return Err(corrupt(format!("table of contents truncated at entry {}", i)));
+            };
+            let name = std::str::from_utf8(&bytes[pos + 2..name_end])
+                .map_err(|_| corrupt(format!("entry {} has a non-UTF-8 name", i)))?
+                .to_string();
+            let offset = LittleEndian::read_u64(&rec[0..8]);
+            let len = LittleEndian::read_u64(&rec[8..16]);
+            let crc32 = LittleEndian::read_u32(&rec[16..20]);
+            if offset.checked_add(len).is_none_or(|end| end > bytes.len() as u64) {
+                return Err(corrupt(format!(
+                    "{} spans {}+{} beyond the bundle size {}",
+                    name,
+                    offset,
+                    len,
+                    bytes.len()
+                )));
+            }
+            entries.push(BundleEntry { name, offset, len, crc32 });
+            pos = name_end + 20;
+        }
+        Ok(Self { bytes, entries })
+    }
+
+    /// The whole bundle file.
+    #[inline]
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    #[inline]
+    pub fn entries(&self) -> &[BundleEntry] {
+        &self.entries
+    }
+
+    /// Bytes of the artifact with suffix `name` (e.g. `.gof`).
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.entries
+            .iter()
+            .find(|e| e.name == name)
+            .map(|e| &self.bytes[e.offset as usize..(e.offset + e.len) as usize])
+    }
+
+    /// Check every artifact against its CRC-32.
+    pub fn verify(&self) -> Result<()> {
+        for e in &self.entries {
+            let data = &self.bytes[e.offset as usize..(e.offset + e.len) as usize];
+            let crc = crc32(data);
+            if crc != e.crc32 {
+                return Err(Error::corrupt(
+                    "GXI",
+                    format!("checksum mismatch for {}: stored {:08x}, computed {:08x}", e.name, e.crc32, crc),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write a bundle holding `artifacts` as (suffix, bytes) pairs.
+pub fn write_bundle<W: Write>(out: &mut W, artifacts: &[(&str, &[u8])]) -> io::Result<()> {
+    let toc_len: u64 = 12 + artifacts.iter().map(|(name, _)| 2 + name.len() as u64 + 20).sum::<u64>();
+    let mut offsets = Vec::with_capacity(artifacts.len());
+    let mut pos = toc_len.next_multiple_of(ALIGN);
+    for (_, data) in artifacts {
+        offsets.push(pos);
+        pos = (pos + data.len() as u64).next_multiple_of(ALIGN);
+    }
+
+    let mut toc = Vec::with_capacity(toc_len as usize);
+    toc.extend_from_slice(MAGIC);
+    toc.extend_from_slice(&(artifacts.len() as u32).to_le_bytes());
+    for ((name, data), &offset) in artifacts.iter().zip(&offsets) {
+        toc.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        toc.extend_from_slice(name.as_bytes());
+        toc.extend_from_slice(&offset.to_le_bytes());
+        toc.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        toc.extend_from_slice(&crc32(data).to_le_bytes());
+    }
+    out.write_all(&toc)?;
+
+    let mut written = toc_len;
+    for ((_, data), &offset) in artifacts.iter().zip(&offsets) {
+        out.write_all(&[0u8; ALIGN as usize][..(offset - written) as usize])?;
+        out.write_all(data)?;
+        written = offset + data.len() as u64;
+    }
+    out.flush()
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 (IEEE 802.3), as used by gzip and zip.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut c = !0u32;
+    for &b in data {
+        c = CRC32_TABLE[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8);
+    }
+    !c
+}
@@ -1,89 +1,383 @@
-use crate::append_suffix;
-use anyhow::{Context, Result, bail};
+use crate::{BUNDLE_SUFFIX, Bundle, Error, append_suffix};
+use crate::{Counter, record};
+use anyhow::{Context, Result};
 use memmap2::Mmap;
 use rustc_hash::FxHashMap;
 use std::{
-    //    collections::HashMap,
+    borrow::Cow,
     fs::File,
-    io::{BufRead, BufReader},
-    path::Path,
+    io::{self, Read},
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::SystemTime,
 };
 
+/// Suffix of the decompressed copy of a gzip/bgzip-compressed GFF (`<gff>.raw`), written
+/// by `IndexBuilder::build`. Block offsets in the index refer to this copy.
+pub const DECOMPRESSED_SUFFIX: &str = ".raw";
 
+/// Set by `--no-mmap`: read files with buffered/positioned reads instead of mmap.
+static NO_MMAP: AtomicBool = AtomicBool::new(false);
 
-pub fn safe_mmap_readonly(path: &Path) -> Result<Mmap> {
-    let file = File::open(path).with_context(|| format!("Failed to open file: {:?}", path))?;
-    unsafe { Mmap::map(&file) }.with_context(|| format!("Failed to mmap file: {:?}", path))
+/// Disable (or re-enable) memory mapping for every `ByteSource`/`RangeSource` opened
+/// afterwards. Mapping failures fall back to reads automatically; this forces it.
+pub fn set_no_mmap(disabled: bool) {
+    NO_MMAP.store(disabled, Ordering::Relaxed);
 }
 
-pub fn load_sqs<P: AsRef<Path>>(path: P) -> Result<(Vec<String>, FxHashMap<String, u32>)> {
-    let path = path.as_ref();
-    let sqs_path = append_suffix(path, ".sqs");
-    let file = File::open(&sqs_path)
-        .with_context(|| format!("Failed to open SQS file: {:?}", &sqs_path))?;
-    let reader = BufReader::new(file);
+/// Index locations set by `--index-prefix`, as (GFF path, prefix).
+static INDEX_PREFIXES: RwLock<Vec<(PathBuf, PathBuf)>> = RwLock::new(Vec::new());
 
-    let id_to_name: Vec<String> = reader.lines().collect::<Result<_, _>>()?;
-    let name_to_id: FxHashMap<_, _> = id_to_name
-        .iter()
-        .enumerate()
-        .map(|(id, name)| (name.clone(), id as u32))
-        .collect();
+/// Keep the index files of `gff` under `prefix` instead of next to it (e.g. when the
+/// GFF lives on a read-only volume): `<prefix><suffix>` (`/scratch/genome` gives
+/// `/scratch/genome.gof`), or `<prefix>/<gff file name><suffix>` when `prefix` is a
+/// directory (an existing one, or a path ending in `/`). `None` drops the prefix.
+///
+/// `IndexBuilder::build` writes there and every loader reads from there (see `index_path`);
+/// the GFF itself is still read from `gff`.
+pub fn set_index_prefix(gff: &Path, prefix: Option<&Path>) {
+    let mut prefixes = INDEX_PREFIXES.write().unwrap_or_else(|e| e.into_inner());
+    prefixes.retain(|(g, _)| g != gff);
+    if let Some(prefix) = prefix {
+        prefixes.push((gff.to_path_buf(), prefix.to_path_buf()));
+    }
+}
 
-    Ok((id_to_name, name_to_id))
+/// `set_index_prefix` for each of several GFFs (e.g. `coverage -i a.gff -i b.gff`),
+/// which then need a directory prefix: a file prefix would give them one index.
+pub fn apply_index_prefix<P: AsRef<Path>>(gffs: &[P], prefix: Option<&Path>) -> Result<()> {
+    if let Some(prefix) = prefix
+        && gffs.len() > 1
+        && !is_dir_prefix(prefix)
+    {
+        anyhow::bail!(
+            "--index-prefix {:?} must be a directory (or end in '/') when several GFFs are given",
+            prefix
+        );
+    }
+    for gff in gffs {
+        set_index_prefix(gff.as_ref(), prefix);
+    }
+    Ok(())
+}
+
+fn is_dir_prefix(prefix: &Path) -> bool {
+    prefix.is_dir() || prefix.as_os_str().to_string_lossy().ends_with(std::path::is_separator)
+}
+
+/// Path of index artifact `suffix` (e.g. `.gof`) of `gff`: under the prefix set by
+/// `set_index_prefix`, or `<gff><suffix>` next to the GFF.
+pub fn index_path(gff: &Path, suffix: &str) -> PathBuf {
+    let prefixes = INDEX_PREFIXES.read().unwrap_or_else(|e| e.into_inner());
+    let Some((_, prefix)) = prefixes.iter().find(|(g, _)| g == gff) else {
+        return append_suffix(gff, suffix);
+    };
+    if is_dir_prefix(prefix) {
+        append_suffix(&prefix.join(gff.file_name().unwrap_or_default()), suffix)
+    } else {
+        append_suffix(prefix, suffix)
+    }
+}
+
+/// The prefix set for `gff` by `set_index_prefix`, if any.
+pub fn index_prefix(gff: &Path) -> Option<PathBuf> {
+    let prefixes = INDEX_PREFIXES.read().unwrap_or_else(|e| e.into_inner());
+    prefixes.iter().find(|(g, _)| g == gff).map(|(_, prefix)| prefix.clone())
+}
+
+#[inline]
+fn mmap_enabled() -> bool {
+    !cfg!(target_arch = "wasm32") && !NO_MMAP.load(Ordering::Relaxed)
+}
+
+fn warn_mmap_fallback(path: &Path, err: &io::Error) {
+    diag!(
+        "[WARN] mmap failed for {:?} ({}); falling back to buffered reads (use --no-mmap to skip the attempt)",
+        path, err
+    );
+}
+
+/// Bytes of an index file: memory-mapped from disk, owned (read into memory, or
+/// handed over by an embedder such as a browser `ArrayBuffer` under wasm), or a
+/// section of a `.gxi` bundle.
+///
+/// Every `load_*` loader reads through this and parses with the matching
+/// `parse_*(&[u8])`, so callers without a file system only need the parsers.
+#[derive(Debug)]
+pub enum ByteSource {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+    Bundled { bundle: Arc<Bundle>, start: usize, end: usize },
+}
+
+impl ByteSource {
+    /// Map `path` read-only. With `--no-mmap`, on targets without mmap (wasm32) or when
+    /// mapping fails (e.g. some NFS mounts), the file is read into memory instead.
+    pub fn open(path: &Path) -> crate::error::Result<Self> {
+        let file = File::open(path).map_err(|e| Error::io(path, e))?;
+        if mmap_enabled() {
+            match unsafe { Mmap::map(&file) } {
+                Ok(m) => {
+                    record(Counter::BytesMapped, m.len() as u64);
+                    return Ok(ByteSource::Mapped(m));
+                }
+                Err(e) => warn_mmap_fallback(path, &e),
+            }
+        }
+        let bytes = std::fs::read(path).map_err(|e| Error::io(path, e))?;
+        record(Counter::BytesRead, bytes.len() as u64);
+        Ok(ByteSource::Owned(bytes))
+    }
+
+    /// Open index artifact `suffix` (e.g. `.gof`) of `gff`: the loose `<gff><suffix>`
+    /// file, or its section of `<gff>.gxi` when only the bundle exists. A missing
+    /// artifact is reported as `Error::IndexMissing` naming the loose path.
+    pub fn open_artifact(gff: &Path, suffix: &str) -> crate::error::Result<Self> {
+        let path = index_path(gff, suffix);
+        if !path.exists()
+            && let Some(bundle) = open_bundle(gff)?
+            && let Some(e) = bundle.entries().iter().find(|e| e.name == suffix)
+        {
+            let (start, end) = (e.offset as usize, (e.offset + e.len) as usize);
+            return Ok(ByteSource::Bundled { bundle, start, end });
+        }
+        Self::open(&path).map_err(|e| e.for_index_file(&path))
+    }
+
+    /// Open the text of GFF `gff`: the file itself, or the decompressed copy of a
+    /// compressed one (see `gff_text_path`).
+    pub fn open_gff(gff: &Path) -> crate::error::Result<Self> {
+        Self::open(&gff_text_path(gff)?)
+    }
+}
+
+/// Whether `path` starts with the gzip magic bytes (plain gzip or bgzip/BGZF).
+pub fn is_gzip(path: &Path) -> bool {
+    let mut magic = [0u8; 2];
+    File::open(path).and_then(|mut f| f.read_exact(&mut magic)).is_ok() && magic == [0x1f, 0x8b]
+}
+
+/// The file holding the feature lines of `gff`: `gff` itself, or for a gzip/bgzip-
+/// compressed GFF its decompressed copy `<gff>.raw` (`DECOMPRESSED_SUFFIX`), which the
+/// index was built from. `Error::DecompressedCopy` when that copy is missing or older
+/// than `gff`.
+pub fn gff_text_path(gff: &Path) -> crate::error::Result<Cow<'_, Path>> {
+    if !is_gzip(gff) {
+        return Ok(Cow::Borrowed(gff));
+    }
+    let copy = index_path(gff, DECOMPRESSED_SUFFIX);
+    let mtime = |p: &Path| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+    let detail = match (mtime(gff), mtime(&copy)) {
+        (_, None) => "is missing",
+        (Some(g), Some(c)) if g > c => "is older than the compressed file",
+        _ => return Ok(Cow::Owned(copy)),
+    };
+    Err(Error::DecompressedCopy { gff: gff.to_path_buf(), copy, detail })
+}
+
+/// Whether index artifact `suffix` of `gff` exists, loose or inside `<gff>.gxi`.
+pub fn artifact_exists(gff: &Path, suffix: &str) -> bool {
+    index_path(gff, suffix).exists()
+        || matches!(open_bundle(gff), Ok(Some(b)) if b.get(suffix).is_some())
+}
+
+/// `Error::GffMissing` when `gff` does not exist but its index does, so that reading
+/// blocks of a moved or deleted GFF names the problem instead of a bare "not found";
+/// `Error::DecompressedCopy` when a compressed GFF has no usable decompressed copy.
+pub fn check_gff_present(gff: &Path) -> crate::error::Result<()> {
+    if !gff.exists() && artifact_exists(gff, ".gof") {
+        return Err(Error::GffMissing { path: gff.to_path_buf() });
+    }
+    if gff.exists() {
+        gff_text_path(gff)?;
+    }
+    Ok(())
 }
 
+/// Bundles opened so far, keyed by path and reused while size and mtime are unchanged
+/// (every loader of a command reads from the same mapping).
+static OPENED_BUNDLES: Mutex<Vec<OpenedBundle>> = Mutex::new(Vec::new());
 
-pub fn load_atn(path: &Path) -> Result<(String, Vec<String>)> {
-    let atn_path = append_suffix(path, ".atn");
-    let mmap = safe_mmap_readonly(&atn_path)?;
-    let data = &mmap[..];
+/// (path, size, mtime, bundle)
+type OpenedBundle = (PathBuf, u64, SystemTime, Arc<Bundle>);
 
-    let mut values = Vec::new();
-    let mut attr_name: Option<String> = None;
+/// The `<gff>.gxi` bundle, or `None` when there is none.
+fn open_bundle(gff: &Path) -> crate::error::Result<Option<Arc<Bundle>>> {
+    let path = index_path(gff, BUNDLE_SUFFIX);
+    let Ok(meta) = std::fs::metadata(&path) else {
+        return Ok(None);
+    };
+    let (len, mtime) = (meta.len(), meta.modified().unwrap_or(SystemTime::UNIX_EPOCH));
+    let mut opened = OPENED_BUNDLES.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((.., b)) = opened.iter().find(|(p, l, m, _)| *p == path && *l == len && *m == mtime) {
+        return Ok(Some(Arc::clone(b)));
+    }
+    let bundle = Arc::new(Bundle::open(&path)?);
+    opened.retain(|(p, ..)| *p != path);
+    opened.push((path, len, mtime, Arc::clone(&bundle)));
+    Ok(Some(bundle))
+}
+
+impl Deref for ByteSource {
+    type Target = [u8];
 
-    // Helper to process a single line (without trailing '\n')
-    let mut push_line = |bytes: &[u8]| -> Result<()> {
-        if bytes.is_empty() {
-            return Ok(());
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        match self {
+            ByteSource::Mapped(m) => m,
+            ByteSource::Owned(v) => v,
+            ByteSource::Bundled { bundle, start, end } => &bundle.bytes()[*start..*end],
         }
-        let mut line = std::str::from_utf8(bytes)
-            .context("ATN contains invalid UTF-8")?
-            .trim();
+    }
+}
 
-        // Strip UTF-8 BOM if it appears at the start of the very first line
-        if attr_name.is_none() && line.starts_with('\u{feff}') {
-            line = &line['\u{feff}'.len_utf8()..];
+impl From<Vec<u8>> for ByteSource {
+    fn from(bytes: Vec<u8>) -> Self {
+        ByteSource::Owned(bytes)
+    }
+}
+
+/// Block-oriented access to a (possibly huge) GFF: byte ranges are borrowed from an
+/// mmap, or fetched with positioned reads when mapping is disabled or fails, so
+/// files larger than the address space (32-bit builds) still work. A compressed GFF is
+/// read through its decompressed copy (see `gff_text_path`).
+#[derive(Debug)]
+pub enum RangeSource {
+    Mapped(Mmap),
+    File { file: File, len: u64 },
+}
+
+impl RangeSource {
+    pub fn open(path: &Path) -> Result<Self> {
+        check_gff_present(path)?;
+        crate::check_index_matches(path)?;
+        let path = &gff_text_path(path)?;
+        let file = File::open(path).with_context(|| format!("Cannot open GFF file: {:?}", path))?;
+        if mmap_enabled() {
+            match unsafe { Mmap::map(&file) } {
+                Ok(m) => {
+                    record(Counter::BytesMapped, m.len() as u64);
+                    return Ok(RangeSource::Mapped(m));
+                }
+                Err(e) => warn_mmap_fallback(path, &e),
+            }
         }
+        let len = file
+            .metadata()
+            .with_context(|| format!("Cannot stat {:?}", path))?
+            .len();
+        Ok(RangeSource::File { file, len })
+    }
 
-        if let Some(rest) = line.strip_prefix("#attribute=") {
-            // Only a single header is allowed
-            if attr_name.is_some() {
-                bail!("Multiple #attribute= headers found in .atn file");
+    #[inline]
+    pub fn len(&self) -> u64 {
+        match self {
+            RangeSource::Mapped(m) => m.len() as u64,
+            RangeSource::File { len, .. } => *len,
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The whole file when it is mapped.
+    #[inline]
+    pub fn as_mapped(&self) -> Option<&[u8]> {
+        match self {
+            RangeSource::Mapped(m) => Some(m),
+            RangeSource::File { .. } => None,
+        }
+    }
+
+    /// Bytes `[start, end)`, clamped to the file length.
+    pub fn range(&self, start: u64, end: u64) -> Result<Cow<'_, [u8]>> {
+        let end = end.min(self.len());
+        let start = start.min(end);
+        match self {
+            RangeSource::Mapped(m) => Ok(Cow::Borrowed(&m[start as usize..end as usize])),
+            RangeSource::File { file, .. } => {
+                let mut buf = vec![0u8; (end - start) as usize];
+                read_exact_at(file, &mut buf, start)
+                    .with_context(|| format!("Read of bytes {}..{} failed", start, end))?;
+                record(Counter::BytesRead, buf.len() as u64);
+                Ok(Cow::Owned(buf))
             }
-            attr_name = Some(rest.to_string());
-        } else if !line.is_empty() && !line.starts_with('#') {
-            // Collect non-empty, non-comment value lines
-            values.push(line.to_string());
         }
-        Ok(())
-    };
+    }
 
-    // Scan by '\n'
-    let mut start = 0usize;
-    for (i, &b) in data.iter().enumerate() {
-        if b == b'\n' {
-            push_line(&data[start..i])?;
-            start = i + 1;
+    /// Copy bytes `[start, end)` to `w`, in bounded chunks when not mapped.
+    pub fn copy_range<W: io::Write>(&self, start: u64, end: u64, w: &mut W) -> Result<()> {
+        const CHUNK: u64 = 8 * 1024 * 1024;
+        let end = end.min(self.len());
+        let mut pos = start.min(end);
+        while pos < end {
+            let next = match self {
+                RangeSource::Mapped(_) => end,
+                RangeSource::File { .. } => (pos + CHUNK).min(end),
+            };
+            w.write_all(&self.range(pos, next)?)?;
+            pos = next;
         }
+        Ok(())
     }
-    // Handle a trailing line without '\n'
-    if start < data.len() {
-        push_line(&data[start..])?;
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    while !buf.is_empty() {
+        match std::os::windows::fs::FileExt::seek_read(file, buf, offset)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+        }
     }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn read_exact_at(_file: &File, _buf: &mut [u8], _offset: u64) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "positioned reads are not supported on this target"))
+}
+
+pub fn safe_mmap_readonly(path: &Path) -> Result<Mmap> {
+    let file = File::open(path).with_context(|| format!("Failed to open file: {:?}", path))?;
+    let mmap = unsafe { Mmap::map(&file) }.with_context(|| format!("Failed to mmap file: {:?}", path))?;
+    record(Counter::BytesMapped, mmap.len() as u64);
+    Ok(mmap)
+}
+
+pub fn load_sqs<P: AsRef<Path>>(path: P) -> crate::error::Result<(Vec<String>, FxHashMap<String, u32>)> {
+    let path = path.as_ref();
+    let bytes = ByteSource::open_artifact(path, ".sqs")?;
+    parse_sqs(&bytes).map_err(|e| e.for_index_file(&index_path(path, ".sqs")))
+}
 
-    let attr_name = attr_name
-        .ok_or_else(|| anyhow::anyhow!("Missing #attribute=... header in .atn file"))?;
+/// Parse raw `.sqs` bytes (one seqid per line; line index = seqid number) into
+/// (number -> seqid, seqid -> number).
+pub fn parse_sqs(data: &[u8]) -> crate::error::Result<(Vec<String>, FxHashMap<String, u32>)> {
+    let text = std::str::from_utf8(data).map_err(|e| Error::corrupt("SQS", e))?;
+    let id_to_name: Vec<String> = text
+        .lines()
+        .map(|l| l.to_string())
+        .collect();
+    let name_to_id: FxHashMap<_, _> = id_to_name
+        .iter()
+        .enumerate()
+        .map(|(id, name)| (name.clone(), id as u32))
+        .collect();
 
-    Ok((attr_name, values))
-}
\ No newline at end of file
+    Ok((id_to_name, name_to_id))
+}
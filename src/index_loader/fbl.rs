@@ -0,0 +1,109 @@
+use byteorder::{ByteOrder, LittleEndian};
+use std::path::Path;
+
+use crate::{ByteSource, artifact_exists, index_path};
+use crate::error::{Error, Result};
+
+/// `.fbl` header: bit count (u64) + number of hash functions (u32).
+const HEADER_SIZE: usize = 8 + 4;
+
+/// Upper bound on hash functions; `with_rate` never needs more.
+const MAX_HASHES: u32 = 32;
+
+/// Bloom filter over feature IDs (`.fbl`), consulted before the `.fts` hash map so
+/// that absent IDs are rejected without building the forward index.
+///
+/// Layout: `n_bits: u64`, `n_hashes: u32`, then `ceil(n_bits / 64)` u64 words, all
+/// little-endian. Bit positions use double hashing over FNV-1a, which is fixed by
+/// this format (unlike the process-local hashers used for in-memory maps).
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    n_bits: u64,
+    n_hashes: u32,
+    words: Vec<u64>,
+}
+
+impl BloomFilter {
+    /// Empty filter sized for `n_items` keys at false-positive rate `fpr` (0 < fpr < 1).
+    pub fn with_rate(n_items: usize, fpr: f64) -> Self {
+        let n = n_items.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let n_bits = (-(n * fpr.ln()) / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let n_hashes = ((n_bits as f64 / n) * ln2).round().clamp(1.0, MAX_HASHES as f64) as u32;
+        Self { n_bits, n_hashes, words: vec![0; n_bits.div_ceil(64) as usize] }
+    }
+
+    #[inline]
+    fn hashes(key: &[u8]) -> (u64, u64) {
+        let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+        for &b in key {
+            h ^= b as u64;
+            h = h.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        // splitmix64 finaliser for the second hash; odd so strides cover every bit
+        let mut z = h.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        (h, (z ^ (z >> 31)) | 1)
+    }
+
+    /// Bit indices of `key` (Kirsch-Mitzenmacher double hashing).
+    #[inline]
+    fn bit_positions(n_bits: u64, n_hashes: u32, key: &str) -> impl Iterator<Item = u64> {
+        let (h1, h2) = Self::hashes(key.as_bytes());
+        (0..n_hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % n_bits)
+    }
+
+    pub fn insert(&mut self, key: &str) {
+        for bit in Self::bit_positions(self.n_bits, self.n_hashes, key) {
+            self.words[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    /// `false` means `key` is definitely absent; `true` means it may be present.
+    #[inline]
+    pub fn may_contain(&self, key: &str) -> bool {
+        Self::bit_positions(self.n_bits, self.n_hashes, key)
+            .all(|bit| self.words[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Serialize to the `.fbl` layout.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![0u8; HEADER_SIZE + self.words.len() * 8];
+        LittleEndian::write_u64(&mut out[0..8], self.n_bits);
+        LittleEndian::write_u32(&mut out[8..12], self.n_hashes);
+        LittleEndian::write_u64_into(&self.words, &mut out[HEADER_SIZE..]);
+        out
+    }
+}
+
+/// Load `<gff>.fbl` if it was built; `Ok(None)` when the file is absent.
+pub fn load_fbl<P: AsRef<Path>>(gff_path: P) -> Result<Option<BloomFilter>> {
+    let path = gff_path.as_ref();
+    if !artifact_exists(path, ".fbl") {
+        return Ok(None);
+    }
+    let bytes = ByteSource::open_artifact(path, ".fbl")?;
+    parse_fbl(&bytes).map(Some).map_err(|e| e.for_index_file(&index_path(path, ".fbl")))
+}
+
+/// Parse raw `.fbl` bytes (see `BloomFilter`).
+pub fn parse_fbl(bytes: &[u8]) -> Result<BloomFilter> {
+    if bytes.len() < HEADER_SIZE {
+        return Err(Error::corrupt("FBL", format!("length {} shorter than the header", bytes.len())));
+    }
+    let n_bits = LittleEndian::read_u64(&bytes[0..8]);
+    let n_hashes = LittleEndian::read_u32(&bytes[8..12]);
+    let body = &bytes[HEADER_SIZE..];
+    if n_bits == 0
+        || !(1..=MAX_HASHES).contains(&n_hashes)
+        || body.len() as u64 != n_bits.div_ceil(64).saturating_mul(8) {
+        return Err(Error::corrupt(
+            "FBL",
+            format!("{} bits / {} hashes do not match a body of {} bytes", n_bits, n_hashes, body.len()),
+        ));
+    }
+    let mut words = vec![0u64; body.len() / 8];
+    LittleEndian::read_u64_into(body, &mut words);
+    Ok(BloomFilter { n_bits, n_hashes, words })
+}
@@ -1,21 +1,25 @@
-use anyhow::{Context, Result};
 use rustc_hash::{FxHashMap, FxHashSet};
 use std::path::Path;
 use std::sync::OnceLock; // lazy cache
 use rayon::prelude::*;
-use crate::{append_suffix, safe_mmap_readonly};
+use memchr::memchr_iter;
+use crate::{BloomFilter, ByteSource, index_path, is_anonymous_id, load_fbl};
+use crate::error::{Error, Result};
 
 #[derive(Debug)]
 pub struct FtsMap {
+    /// One ID per fid; features without an ID have an internal name (see `is_anonymous_id`)
     pub ids: Vec<String>,
     /// String -> numeric ID (u32)
     index_fwd: OnceLock<FxHashMap<String, u32>>,
+    /// Optional `.fbl` filter; rejects absent IDs before `index_fwd` is built
+    bloom: Option<BloomFilter>,
 }
 
 impl FtsMap {
     fn build_fwd(&self) -> FxHashMap<String, u32> {
         let mut m = FxHashMap::with_capacity_and_hasher(self.ids.len(), Default::default());
-        for (i, s) in self.ids.iter().enumerate() {
+        for (i, s) in self.ids.iter().enumerate().filter(|(_, s)| !is_anonymous_id(s)) {
             m.insert(s.clone(), i as u32);
         }
         m
@@ -26,14 +30,39 @@ impl FtsMap {
         self.index_fwd.get_or_init(|| self.build_fwd())
     }
 
-    /// Convert string ID to numeric fid
+    /// Attach a Bloom filter over `ids` (see `load_fbl`/`parse_fbl`).
+    pub fn with_bloom(mut self, bloom: BloomFilter) -> Self {
+        self.bloom = Some(bloom);
+        self
+    }
+
+    /// Whether a Bloom filter is attached
+    #[inline]
+    pub fn has_bloom(&self) -> bool {
+        self.bloom.is_some()
+    }
+
+    /// Convert string ID to numeric fid. Until the forward index exists, IDs rejected
+    /// by the Bloom filter return `None` without building it; afterwards the hash map
+    /// alone is faster than the filter. Internal names of features without an ID are
+    /// never found
     pub fn get_fid(&self, id: &str) -> Option<u32> {
+        if is_anonymous_id(id) {
+            return None;
+        }
+        if let Some(idx) = self.index_fwd.get() {
+            return idx.get(id).copied();
+        }
+        if self.bloom.as_ref().is_some_and(|b| !b.may_contain(id)) {
+            return None;
+        }
         self.index_fwd().get(id).copied()
     }
 
-    /// Convert numeric fid to string ID (direct from ids vec)
+    /// Convert numeric fid to string ID (direct from ids vec); `None` for a feature
+    /// without an ID
     pub fn get_id(&self, fid: u32) -> Option<&str> {
-        self.ids.get(fid as usize).map(|s| s.as_str())
+        self.ids.get(fid as usize).map(|s| s.as_str()).filter(|s| !is_anonymous_id(s))
     }
 
     /// Map a set of feature names to their fids
@@ -45,10 +74,9 @@ impl FtsMap {
     ) -> (FxHashSet<u32>, Vec<String>) {
         enum Either<L, R> { Left(L), Right(R) }
 
-        let idx = self.index_fwd();
-
+        // get_fid builds the forward index lazily, on the first probable hit
         let mapper = |fname: &String| {
-            if let Some(&fid) = idx.get(fname) {
+            if let Some(fid) = self.get_fid(fname) {
                 Either::Left(fid)
             } else {
                 Either::Right(fname.clone())
@@ -82,7 +110,7 @@ impl FtsMap {
             set.reserve(feature_names.len());
             let mut miss = Vec::new();
             for fname in feature_names {
-                if let Some(&fid) = idx.get(fname) {
+                if let Some(fid) = self.get_fid(fname) {
                     set.insert(fid);
                 } else {
                     miss.push(fname.clone());
@@ -93,15 +121,65 @@ impl FtsMap {
     }
 }
 
+/// Query sets up to this size are resolved by `scan_fts` instead of building the
+/// forward index of every ID.
+pub const FTS_SCAN_MAX: usize = 100;
+
+/// Look up `names` with one pass over raw `.fts` bytes, without materialising the IDs.
+///
+/// Returns name -> fid for the names present; fids number the non-empty lines as in
+/// `parse_fts`, and a repeated ID resolves to its last line, as in the forward index.
+/// Only lines with the length of some name are decoded, so invalid UTF-8 elsewhere
+/// goes unnoticed (`parse_fts` would reject it). Internal names of features without an
+/// ID are never found.
+pub fn scan_fts<'n>(data: &[u8], names: &'n FxHashSet<String>) -> FxHashMap<&'n str, u32> {
+    let mut found: FxHashMap<&'n str, u32> = FxHashMap::default();
+    let Some(max_len) = names.iter().map(String::len).max() else {
+        return found;
+    };
+    let mut wanted_len = vec![false; max_len + 1];
+    for n in names {
+        wanted_len[n.len()] = true;
+    }
+
+    let mut fid = 0u32;
+    let mut start = 0usize;
+    let ends = memchr_iter(b'\n', data).chain((!data.ends_with(b"\n")).then_some(data.len()));
+    for end in ends {
+        let line = &data[start..end];
+        start = end + 1;
+        if line.is_empty() {
+            continue;
+        }
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.len() <= max_len
+            && wanted_len[line.len()]
+            && let Ok(id) = std::str::from_utf8(line)
+            && !is_anonymous_id(id)
+            && let Some(name) = names.get(id)
+        {
+            found.insert(name.as_str(), fid);
+        }
+        fid += 1;
+    }
+    found
+}
+
 /// Load `.fts` file into FtsMap
 pub fn load_fts<P: AsRef<Path>>(gff_path: P) -> Result<FtsMap> {
     let path = gff_path.as_ref();
-    let fts_path = append_suffix(path, ".fts");
+    let fts_path = index_path(path, ".fts");
 
-    let mmap = safe_mmap_readonly(&fts_path)
-        .with_context(|| format!("Failed to mmap {}", fts_path.display()))?;
-    let data = &mmap[..];
+    let bytes = ByteSource::open_artifact(path, ".fts")?;
+    let fts = parse_fts(&bytes).map_err(|e| e.for_index_file(&fts_path))?;
+    Ok(match load_fbl(path)? {
+        Some(bloom) => fts.with_bloom(bloom),
+        None => fts,
+    })
+}
 
+/// Parse raw `.fts` bytes (one feature ID per line) into FtsMap.
+pub fn parse_fts(data: &[u8]) -> Result<FtsMap> {
     let mut lines = Vec::new();
     let mut start = 0;
 
@@ -113,7 +191,7 @@ pub fn load_fts<P: AsRef<Path>>(gff_path: P) -> Result<FtsMap> {
                     slice = &slice[..slice.len() - 1];
                 }
                 let s = std::str::from_utf8(slice)
-                    .with_context(|| format!("FTS contains invalid UTF-8 at byte {}", start))?;
+                    .map_err(|_| Error::corrupt("FTS", format!("invalid UTF-8 at byte {}", start)))?;
                 lines.push(s.to_string());
             }
             start = i + 1;
@@ -126,7 +204,7 @@ pub fn load_fts<P: AsRef<Path>>(gff_path: P) -> Result<FtsMap> {
                 slice = &slice[..slice.len() - 1];
             }
             let s = std::str::from_utf8(slice)
-                .with_context(|| format!("FTS contains invalid UTF-8 at byte {}", start))?;
+                .map_err(|_| Error::corrupt("FTS", format!("invalid UTF-8 at byte {}", start)))?;
             lines.push(s.to_string());
         }
     }
@@ -134,5 +212,6 @@ pub fn load_fts<P: AsRef<Path>>(gff_path: P) -> Result<FtsMap> {
     Ok(FtsMap {
         ids: lines,
         index_fwd: OnceLock::new(),
+        bloom: None,
     })
 }
@@ -1,10 +1,11 @@
-use anyhow::{bail, Context, Result};
 use byteorder::{ByteOrder, LittleEndian};
 use rustc_hash::FxHashMap;
 use std::{path::Path, sync::OnceLock};
-use crate::{append_suffix, safe_mmap_readonly};
+use crate::{ByteSource, artifact_exists, index_path};
+use crate::error::{Error, Result};
 
-const MISSING: u64 = u64::MAX; // Set sentinel value for missing entries
+/// Duplicate feature ids reported individually before summarising
+const MAX_DUPLICATE_WARNINGS: usize = 10;
 
 #[derive(Debug)]
 pub struct GofEntry {
@@ -14,7 +15,24 @@ pub struct GofEntry {
     pub end_offset: u64,
 }
 
+/// One model (a root feature and its descendants) as its raw bytes in the GFF.
+#[derive(Debug, Clone, Copy)]
+pub struct ModelBlock<'a> {
+    pub root: u32,
+    pub seqid_num: u32,
+    /// Whole lines of the model, terminators included
+    pub block: &'a [u8],
+}
 
+impl<'a> ModelBlock<'a> {
+    /// Feature lines of the block ('#' lines and empty lines skipped), without terminators.
+    pub fn lines(&self) -> impl Iterator<Item = &'a [u8]> + 'a {
+        self.block
+            .split(|&b| b == b'\n')
+            .map(|l| l.strip_suffix(b"\r").unwrap_or(l))
+            .filter(|l| !l.is_empty() && !l.starts_with(b"#"))
+    }
+}
 
 #[derive(Debug)]
 pub struct GofMap {
@@ -22,20 +40,83 @@ pub struct GofMap {
     pub entries: Vec<GofEntry>,
     /// Lazy, thread-safe cache: feature_id -> (start, end)
     index_cache: OnceLock<FxHashMap<u32, (u64, u64)>>,
+    /// Lazy cache: feature_id -> every (start, end) in file order (only differs from
+    /// `index_cache` for duplicated feature ids)
+    ranges_cache: OnceLock<FxHashMap<u32, Vec<(u64, u64)>>>,
     pub seqid_index: FxHashMap<u32, Vec<usize>>,       // seqid_num -> entry indices
-
+    /// Whether any feature_id occurs in more than one entry
+    has_duplicates: bool,
+    /// Whether some sequence's roots resume after another sequence's (file order is
+    /// then not grouped by sequence)
+    seqid_interleaved: bool,
+    /// `.gcnt` records (root fid, features in its block), when the index has them
+    feature_counts: Option<Vec<(u32, u32)>>,
+    /// Lazy cache: root fid -> feature count
+    count_cache: OnceLock<FxHashMap<u32, u32>>,
 }
 
 impl GofMap {
     /// Build a transient index (allocates on every call).
     /// Prefer `index_cached()` for hot paths.
+    ///
+    /// If a feature_id occurs more than once, the first entry in file order wins;
+    /// use `all_ranges()` to see every range.
     pub fn index(&self) -> FxHashMap<u32, (u64, u64)> {
         self.entries
             .iter()
+            .rev()
             .map(|e| (e.feature_id, (e.start_offset, e.end_offset)))
             .collect()
     }
 
+    /// Whether any feature_id occurs in more than one GOF entry.
+    #[inline]
+    pub fn has_duplicates(&self) -> bool {
+        self.has_duplicates
+    }
+
+    /// Whether the roots of some sequence are split into several runs in file order
+    /// (e.g. chr1 models appended after chr2). Positional views (trees, `--context`)
+    /// are unaffected; outputs in file order are then not grouped by sequence.
+    #[inline]
+    pub fn is_seqid_interleaved(&self) -> bool {
+        self.seqid_interleaved
+    }
+
+    /// Attach `.gcnt` records (see `parse_gcnt`); `load_gof` does this when the file exists.
+    pub fn with_feature_counts(mut self, counts: Vec<(u32, u32)>) -> Self {
+        self.feature_counts = Some(counts);
+        self.count_cache = OnceLock::new();
+        self
+    }
+
+    /// Whether per-root feature counts are available (indexes built before `.gcnt`
+    /// existed have none).
+    #[inline]
+    pub fn has_feature_counts(&self) -> bool {
+        self.feature_counts.is_some()
+    }
+
+    /// Number of indexed features in the block of root `fid` (the root included), or
+    /// `None` without `.gcnt` or for an unknown root. For a duplicated root the first
+    /// block in file order counts, as in `get`.
+    pub fn feature_count(&self, fid: u32) -> Option<u32> {
+        let counts = self.feature_counts.as_deref()?;
+        self.count_cache.get_or_init(|| counts.iter().rev().copied().collect()).get(&fid).copied()
+    }
+
+    /// Every (start, end) range recorded for `fid`, in file order (empty if absent).
+    pub fn all_ranges(&self, fid: u32) -> &[(u64, u64)] {
+        let ranges = self.ranges_cache.get_or_init(|| {
+            let mut m: FxHashMap<u32, Vec<(u64, u64)>> = FxHashMap::default();
+            for e in &self.entries {
+                m.entry(e.feature_id).or_default().push((e.start_offset, e.end_offset));
+            }
+            m
+        });
+        ranges.get(&fid).map_or(&[], Vec::as_slice)
+    }
+
     /// Get (or build once) the cached index.
     #[inline]
     pub fn index_cached(&self) -> &FxHashMap<u32, (u64, u64)> {
@@ -49,40 +130,58 @@ impl GofMap {
     }
 
     /// Map a list of root IDs to their (start, end) offsets using the cached index.
-    /// Missing roots are silently skipped (consistent with the original free function).
+    ///
+    /// Returns `(blocks, missing)`: `blocks` follows the order of `roots` (independent of
+    /// `threads`), and `missing` lists the roots absent from the index, also in input order.
+    /// A duplicated root yields one block per GOF entry, in file order.
     #[inline]
     pub fn roots_to_offsets(
         &self,
         roots: &[u32],
         threads: usize,
-    ) -> Vec<(u32, u64, u64)> {
+    ) -> (Vec<(u32, u64, u64)>, Vec<u32>) {
         let idx = self.index_cached();
 
         // Simple heuristic: parallelize only for large inputs
         let should_parallel = threads > 1 && roots.len() > 2048;
 
-        if should_parallel {
+        // Index-aligned lookups; `None` marks a missing root
+        let found: Vec<Option<(u64, u64)>> = if should_parallel {
             use rayon::prelude::*;
-            roots
-                .par_iter()
-                .map(|&r| match idx.get(&r) {
-                    Some(&(s, e)) => (r, s, e),
-                    None => (r, MISSING, MISSING), // use u64::MAX as sentinel value
-                })
-                .collect()
+            roots.par_iter().map(|r| idx.get(r).copied()).collect()
         } else {
-            let mut out = Vec::with_capacity(roots.len());
-            for &r in roots {
-                if let Some(&(s, e)) = idx.get(&r) {
-                    out.push((r, s, e));
-                } else {
-                    out.push((r, MISSING, MISSING)); // use u64::MAX as sentinel value
+            roots.iter().map(|r| idx.get(r).copied()).collect()
+        };
+
+        let mut blocks = Vec::with_capacity(roots.len());
+        let mut missing = Vec::new();
+        for (&r, hit) in roots.iter().zip(found) {
+            match hit {
+                Some(_) if self.has_duplicates => {
+                    blocks.extend(self.all_ranges(r).iter().map(|&(s, e)| (r, s, e)));
                 }
+                Some((s, e)) => blocks.push((r, s, e)),
+                None => missing.push(r),
             }
-            out
         }
+        (blocks, missing)
     }
     
+    /// Every model of `gff` (the bytes the GOF was built from) in file order, one block
+    /// per GOF entry. Blocks are sliced lazily; an entry outside `gff` yields an error.
+    pub fn iter_models<'a>(&'a self, gff: &'a [u8]) -> impl Iterator<Item = Result<ModelBlock<'a>>> + 'a {
+        self.entries.iter().map(move |e| {
+            let (s, end) = (e.start_offset, e.end_offset);
+            if s > end || end > gff.len() as u64 {
+                return Err(Error::corrupt(
+                    "GOF",
+                    format!("block of root fid={} ({}..{}) lies outside the GFF ({} bytes)", e.feature_id, s, end, gff.len()),
+                ));
+            }
+            Ok(ModelBlock { root: e.feature_id, seqid_num: e.seqid_num, block: &gff[s as usize..end as usize] })
+        })
+    }
+
     pub fn roots_for_seqid(&self, seqid_num: u32) -> Vec<&GofEntry> {
         match self.seqid_index.get(&seqid_num) {
             Some(indices) => indices.iter().map(|&i| &self.entries[i]).collect(),
@@ -91,38 +190,198 @@ impl GofMap {
     }
 }
 
-/// Load a `.gof` file containing (u32 fid, u32 padding, u64 start, u64 end) records.
+/// Load a `.gof` file containing (u32 fid, u32 padding, u64 start, u64 end) records,
+/// with the per-root feature counts of `.gcnt` when it exists.
 pub fn load_gof<P: AsRef<Path>>(gff_path: P) -> Result<GofMap> {
     let path = gff_path.as_ref();
-    let gof_path = append_suffix(path, ".gof");
-    let mmap = safe_mmap_readonly(&gof_path)
-        .with_context(|| format!("Failed to mmap {}", gof_path.display()))?;
-    let bytes = &mmap[..];
-    const REC_SIZE: usize = 4 + 4 + 8 + 8;
-
-    if bytes.len() % REC_SIZE != 0 {
-        bail!(
-            "Corrupted GOF ({}): length {} not multiple of {}",
-            gof_path.display(),
-            bytes.len(),
-            REC_SIZE
-        );
+    let gof_path = index_path(path, ".gof");
+    let bytes = ByteSource::open_artifact(path, ".gof")?;
+    let gof = parse_gof(&bytes).map_err(|e| e.for_index_file(&gof_path))?;
+    Ok(match load_gcnt(path)? {
+        Some(counts) => gof.with_feature_counts(counts),
+        None => gof,
+    })
+}
+
+/// (u32 root fid, u32 feature count)
+const GCNT_REC_SIZE: usize = 4 + 4;
+
+/// Load `<gff>.gcnt` if it was built; `Ok(None)` for indexes without it.
+pub fn load_gcnt<P: AsRef<Path>>(gff_path: P) -> Result<Option<Vec<(u32, u32)>>> {
+    let path = gff_path.as_ref();
+    if !artifact_exists(path, ".gcnt") {
+        return Ok(None);
+    }
+    let bytes = ByteSource::open_artifact(path, ".gcnt")?;
+    parse_gcnt(&bytes).map(Some).map_err(|e| e.for_index_file(&index_path(path, ".gcnt")))
+}
+
+/// Parse raw `.gcnt` bytes: one (u32 root fid, u32 feature count) record per `.gof`
+/// entry, in the same order. The count covers every indexed feature line in the root's
+/// block, the root included.
+pub fn parse_gcnt(bytes: &[u8]) -> Result<Vec<(u32, u32)>> {
+    if !bytes.len().is_multiple_of(GCNT_REC_SIZE) {
+        return Err(Error::corrupt(
+            "GCNT",
+            format!("length {} not multiple of {}", bytes.len(), GCNT_REC_SIZE),
+        ));
     }
+    Ok(bytes
+        .chunks_exact(GCNT_REC_SIZE)
+        .map(|rec| (LittleEndian::read_u32(&rec[0..4]), LittleEndian::read_u32(&rec[4..8])))
+        .collect())
+}
+
+/// (u32 fid, u32 seqid_num, u64 start, u64 end)
+const REC_SIZE: usize = 4 + 4 + 8 + 8;
+
+fn check_gof_len(bytes: &[u8]) -> Result<()> {
+    if !bytes.len().is_multiple_of(REC_SIZE) {
+        return Err(Error::corrupt(
+            "GOF",
+            format!("length {} not multiple of {}", bytes.len(), REC_SIZE),
+        ));
+    }
+    Ok(())
+}
+
+/// `(blocks, missing roots)` as returned by `GofMap::roots_to_offsets`
+type RootOffsets = (Vec<(u32, u64, u64)>, Vec<u32>);
+
+/// `GofMap::roots_to_offsets` for a few roots, by one pass over raw `.gof` bytes
+/// without building a `GofMap`. Same `(blocks, missing)` result, duplicated roots
+/// included; duplicates are not reported.
+pub fn scan_gof(bytes: &[u8], roots: &[u32]) -> Result<RootOffsets> {
+    check_gof_len(bytes)?;
+    let mut ranges: FxHashMap<u32, Vec<(u64, u64)>> = roots.iter().map(|&r| (r, Vec::new())).collect();
+    for rec in bytes.chunks_exact(REC_SIZE) {
+        if let Some(v) = ranges.get_mut(&LittleEndian::read_u32(&rec[0..4])) {
+            v.push((LittleEndian::read_u64(&rec[8..16]), LittleEndian::read_u64(&rec[16..24])));
+        }
+    }
+
+    let mut blocks = Vec::with_capacity(roots.len());
+    let mut missing = Vec::new();
+    for &r in roots {
+        match ranges.get(&r).map(Vec::as_slice) {
+            Some([]) | None => missing.push(r),
+            Some(found) => blocks.extend(found.iter().map(|&(s, e)| (r, s, e))),
+        }
+    }
+    Ok((blocks, missing))
+}
+
+/// Parse raw `.gof` bytes (see `load_gof`).
+pub fn parse_gof(bytes: &[u8]) -> Result<GofMap> {
+    check_gof_len(bytes)?;
 
     let mut entries = Vec::with_capacity(bytes.len() / REC_SIZE);
     let mut seqid_index: FxHashMap<u32, Vec<usize>> = FxHashMap::default();
+    // fid -> index of its first entry, to report duplicates
+    let mut first_seen: FxHashMap<u32, usize> = FxHashMap::default();
+    let mut n_duplicates = 0usize;
+    let mut seqid_interleaved = false;
     for (i, rec) in bytes.chunks_exact(REC_SIZE).enumerate() {
         let fid   = LittleEndian::read_u32(&rec[0..4]);
         let seqid_num = LittleEndian::read_u32(&rec[4..8]);
         let start = LittleEndian::read_u64(&rec[8..16]);
         let end   = LittleEndian::read_u64(&rec[16..24]);
-        entries.push(GofEntry { feature_id: fid, seqid_num: seqid_num, start_offset: start, end_offset: end });
+        if let Some(&j) = first_seen.get(&fid) {
+            n_duplicates += 1;
+            if n_duplicates <= MAX_DUPLICATE_WARNINGS {
+                let first: &GofEntry = &entries[j];
+                diag!(
+                    "[WARN] Several GOF entries for fid {} (duplicated or interleaved model): {}..{} and {}..{}",
+                    fid, first.start_offset, first.end_offset, start, end
+                );
+            }
+        } else {
+            first_seen.insert(fid, i);
+        }
+        if i > 0 && entries[i - 1].seqid_num != seqid_num && seqid_index.contains_key(&seqid_num) {
+            seqid_interleaved = true;
+        }
+        entries.push(GofEntry { feature_id: fid, seqid_num, start_offset: start, end_offset: end });
         seqid_index.entry(seqid_num).or_default().push(i);
     }
 
+    if n_duplicates > MAX_DUPLICATE_WARNINGS {
+        diag!(
+            "[WARN] {} extra GOF entries in total ({} shown); all ranges of such roots are emitted",
+            n_duplicates, MAX_DUPLICATE_WARNINGS
+        );
+    }
+
     Ok(GofMap {
         entries,
         index_cache: OnceLock::new(),
+        ranges_cache: OnceLock::new(),
         seqid_index,
+        has_duplicates: n_duplicates > 0,
+        seqid_interleaved,
+        feature_counts: None,
+        count_cache: OnceLock::new(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::SynthRng;
+
+    /// `.gof` bytes of `n` roots (fids `0, 2, 4, ...`, so odd fids are missing), each
+    /// listed once except `dup`, which gets a second block at the end.
+    fn gof_bytes(n: u32, dup: Option<u32>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut rec = |fid: u32, start: u64| {
+            bytes.extend_from_slice(&fid.to_le_bytes());
+            bytes.extend_from_slice(&(fid % 7).to_le_bytes());
+            bytes.extend_from_slice(&start.to_le_bytes());
+            bytes.extend_from_slice(&(start + 10).to_le_bytes());
+        };
+        for i in 0..n {
+            rec(2 * i, 100 * u64::from(i));
+        }
+        if let Some(d) = dup {
+            rec(d, 100 * u64::from(n));
+        }
+        bytes
+    }
+
+    fn query_roots(n: usize, seed: u64) -> Vec<u32> {
+        let mut rng = SynthRng::new(seed, 0);
+        (0..n).map(|_| rng.range(0..30_000) as u32).collect()
+    }
+
+    #[test]
+    fn roots_to_offsets_is_deterministic_across_threads() {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(8).build().unwrap();
+        for dup in [None, Some(4)] {
+            let bytes = gof_bytes(12_000, dup);
+            let gof = parse_gof(&bytes).unwrap();
+            let roots = query_roots(10_000, 1);
+            let first = pool.install(|| gof.roots_to_offsets(&roots, 8));
+            let second = pool.install(|| gof.roots_to_offsets(&roots, 8));
+            assert_eq!(first, second);
+            assert_eq!(first, gof.roots_to_offsets(&roots, 1));
+            assert_eq!(first, scan_gof(&bytes, &roots).unwrap());
+        }
+    }
+
+    #[test]
+    fn roots_to_offsets_reports_missing_roots_in_input_order() {
+        let gof = parse_gof(&gof_bytes(100, Some(6))).unwrap();
+        let (blocks, missing) = gof.roots_to_offsets(&[7, 6, 0, 1_000, 7, 198, 200], 1);
+        assert_eq!(blocks, [(6, 300, 310), (6, 10_000, 10_010), (0, 0, 10), (198, 9_900, 9_910)]);
+        assert_eq!(missing, [7, 1_000, 7, 200]);
+
+        let (blocks, missing) = gof.roots_to_offsets(&[], 8);
+        assert!(blocks.is_empty() && missing.is_empty());
+
+        // Every root missing, on the parallel path
+        let odd: Vec<u32> = (0..5_000).map(|i| 2 * i + 1).collect();
+        let (blocks, missing) = gof.roots_to_offsets(&odd, 8);
+        assert!(blocks.is_empty());
+        assert_eq!(missing, odd);
+    }
+}
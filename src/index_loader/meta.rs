@@ -0,0 +1,359 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use crate::{ByteSource, artifact_exists, crc32};
+use crate::error::{Error, Result};
+
+const MAGIC: &str = "#gffx-meta 1";
+
+/// Attribute keys holding feature IDs and parent references unless `index --id-key`
+/// / `--parent-key` chose others.
+pub const DEFAULT_ID_KEY: &str = "ID";
+pub const DEFAULT_PARENT_KEY: &str = "Parent";
+
+/// Bytes hashed at each end of the GFF; files up to twice this are hashed whole.
+const SAMPLE_BYTES: usize = 64 * 1024;
+
+/// Set by `--skip-index-check`: a `.meta` mismatch warns instead of failing.
+static FORCE_MISMATCH: AtomicBool = AtomicBool::new(false);
+
+/// GFFs already checked by `check_index_matches` (checked and warned about once).
+static CHECKED: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Use indexes whose `.meta` does not match the GFF, with a warning instead of
+/// `Error::IndexMismatch`.
+pub fn set_force_index_mismatch(force: bool) {
+    FORCE_MISMATCH.store(force, Ordering::Relaxed);
+}
+
+/// The GFF an index was built from (`.meta`): file name, size and a CRC-32 over its
+/// first and last 64 KiB, plus the attribute keys seen while indexing it, the keys it
+/// took IDs and parents from and the lengths of its `##sequence-region` directives.
+///
+/// Text layout: a `#gffx-meta 1` line, then `key<TAB>value` lines (`file`, `size`,
+/// `sample_crc32`, and optionally `attr_keys` with one tab-separated value per key,
+/// `id_key`, `parent_key` and `seq_lengths` with one tab-separated `<seqid>=<length>`
+/// per sequence); unknown keys are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexMeta {
+    pub file: String,
+    pub size: u64,
+    pub sample_crc32: u32,
+    /// Column-9 attribute keys of the GFF, sorted (capped at the first few thousand
+    /// distinct keys; empty for indexes built before they were recorded)
+    pub attr_keys: Vec<String>,
+    /// Key of the feature IDs (`DEFAULT_ID_KEY` unless recorded otherwise)
+    pub id_key: String,
+    /// Key of the parent references (`DEFAULT_PARENT_KEY` unless recorded otherwise)
+    pub parent_key: String,
+    /// `(seqid, length)` of each `##sequence-region` directive, in file order; `None`
+    /// for indexes built before they were recorded
+    pub seq_lengths: Option<Vec<(String, u32)>>,
+}
+
+/// CRC-32 of the sampled bytes: the whole file when it is short, else head then tail.
+fn sample_crc(head: &[u8], tail: &[u8]) -> u32 {
+    let mut buf = Vec::with_capacity(head.len() + tail.len());
+    buf.extend_from_slice(head);
+    buf.extend_from_slice(tail);
+    crc32(&buf)
+}
+
+impl IndexMeta {
+    /// Describe GFF bytes `data` read from `gff`.
+    pub fn of_bytes(gff: &Path, data: &[u8]) -> Self {
+        let (head, tail) = if data.len() <= 2 * SAMPLE_BYTES {
+            (data, &data[..0])
+        } else {
+            (&data[..SAMPLE_BYTES], &data[data.len() - SAMPLE_BYTES..])
+        };
+        Self {
+            file: file_name(gff),
+            size: data.len() as u64,
+            sample_crc32: sample_crc(head, tail),
+            attr_keys: Vec::new(),
+            id_key: DEFAULT_ID_KEY.to_string(),
+            parent_key: DEFAULT_PARENT_KEY.to_string(),
+            seq_lengths: None,
+        }
+    }
+
+    /// Record the attribute keys observed in the GFF (stored sorted).
+    pub fn with_attr_keys(mut self, mut keys: Vec<String>) -> Self {
+        keys.sort_unstable();
+        keys.dedup();
+        self.attr_keys = keys;
+        self
+    }
+
+    /// Record the keys IDs and parent references were read from.
+    pub fn with_id_keys(mut self, id_key: &str, parent_key: &str) -> Self {
+        self.id_key = id_key.to_string();
+        self.parent_key = parent_key.to_string();
+        self
+    }
+
+    /// Record the sequence lengths from the GFF's `##sequence-region` directives.
+    pub fn with_seq_lengths(mut self, lengths: Vec<(String, u32)>) -> Self {
+        self.seq_lengths = Some(lengths);
+        self
+    }
+
+    /// Describe the GFF at `gff`, reading only the sampled bytes.
+    pub fn of_file(gff: &Path) -> Result<Self> {
+        let read = || -> std::io::Result<Self> {
+            let mut file = File::open(gff)?;
+            let size = file.metadata()?.len();
+            let mut head = Vec::new();
+            let mut tail = Vec::new();
+            if size <= 2 * SAMPLE_BYTES as u64 {
+                file.read_to_end(&mut head)?;
+            } else {
+                head.resize(SAMPLE_BYTES, 0);
+                file.read_exact(&mut head)?;
+                tail.resize(SAMPLE_BYTES, 0);
+                file.seek(SeekFrom::Start(size - SAMPLE_BYTES as u64))?;
+                file.read_exact(&mut tail)?;
+            }
+            Ok(Self {
+                file: file_name(gff),
+                size,
+                sample_crc32: sample_crc(&head, &tail),
+                attr_keys: Vec::new(),
+                id_key: DEFAULT_ID_KEY.to_string(),
+                parent_key: DEFAULT_PARENT_KEY.to_string(),
+                seq_lengths: None,
+            })
+        };
+        read().map_err(|e| Error::io(gff, e))
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "{MAGIC}\nfile\t{}\nsize\t{}\nsample_crc32\t{:08x}\n",
+            self.file.replace(['\t', '\n', '\r'], " "),
+            self.size,
+            self.sample_crc32
+        );
+        if !self.attr_keys.is_empty() {
+            text.push_str("attr_keys");
+            for k in &self.attr_keys {
+                text.push('\t');
+                text.push_str(&k.replace(['\t', '\n', '\r'], " "));
+            }
+            text.push('\n');
+        }
+        text.push_str(&format!("id_key\t{}\nparent_key\t{}\n", self.id_key, self.parent_key));
+        if let Some(lengths) = &self.seq_lengths {
+            text.push_str("seq_lengths");
+            for (seq, len) in lengths {
+                text.push_str(&format!("\t{}={}", seq.replace(['\t', '\n', '\r'], " "), len));
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let text = std::str::from_utf8(bytes).map_err(|e| Error::corrupt("META", e))?;
+        let mut lines = text.lines();
+        if lines.next() != Some(MAGIC) {
+            return Err(Error::corrupt("META", "missing #gffx-meta header"));
+        }
+        let (mut file, mut size, mut crc) = (None, None, None);
+        let mut attr_keys = Vec::new();
+        let mut id_key = DEFAULT_ID_KEY.to_string();
+        let mut parent_key = DEFAULT_PARENT_KEY.to_string();
+        let mut seq_lengths = None;
+        for line in lines {
+            if line == "seq_lengths" {
+                seq_lengths = Some(Vec::new());
+                continue;
+            }
+            match line.split_once('\t') {
+                Some(("file", v)) => file = Some(v.to_string()),
+                Some(("size", v)) => size = v.parse::<u64>().ok(),
+                Some(("sample_crc32", v)) => crc = u32::from_str_radix(v, 16).ok(),
+                Some(("attr_keys", v)) => attr_keys = v.split('\t').map(str::to_string).collect(),
+                Some(("id_key", v)) if !v.is_empty() => id_key = v.to_string(),
+                Some(("parent_key", v)) if !v.is_empty() => parent_key = v.to_string(),
+                Some(("seq_lengths", v)) => {
+                    let mut lengths = Vec::new();
+                    for entry in v.split('\t') {
+                        let len = entry.rsplit_once('=').and_then(|(seq, len)| Some((seq, len.parse::<u32>().ok()?)));
+                        let Some((seq, len)) = len else {
+                            return Err(Error::corrupt("META", format!("bad seq_lengths entry {:?}", entry)));
+                        };
+                        lengths.push((seq.to_string(), len));
+                    }
+                    seq_lengths = Some(lengths);
+                }
+                _ => {}
+            }
+        }
+        match (file, size, crc) {
+            (Some(file), Some(size), Some(sample_crc32)) => {
+                Ok(Self { file, size, sample_crc32, attr_keys, id_key, parent_key, seq_lengths })
+            }
+            _ => Err(Error::corrupt("META", "expected file, size and sample_crc32 lines")),
+        }
+    }
+}
+
+fn file_name(gff: &Path) -> String {
+    gff.file_name().map_or_else(|| gff.display().to_string(), |n| n.to_string_lossy().into_owned())
+}
+
+/// The `.meta` of `gff`'s index (loose or bundled), or `None` for an index built
+/// before `.meta` existed.
+pub fn load_meta(gff: &Path) -> Result<Option<IndexMeta>> {
+    if !artifact_exists(gff, ".meta") {
+        return Ok(None);
+    }
+    let bytes = ByteSource::open_artifact(gff, ".meta")?;
+    IndexMeta::parse(&bytes).map(Some).map_err(|e| e.for_index_file(&crate::index_path(gff, ".meta")))
+}
+
+/// Key holding the feature IDs of `gff`'s index: the recorded `--id-key`, or
+/// `DEFAULT_ID_KEY` (also for indexes without `.meta`).
+pub fn load_id_key(gff: &Path) -> Result<String> {
+    Ok(load_meta(gff)?.map_or_else(|| DEFAULT_ID_KEY.to_string(), |m| m.id_key))
+}
+
+/// Key holding the parent IDs of `gff`'s index: the recorded `--parent-key`, or
+/// `DEFAULT_PARENT_KEY`.
+pub fn load_parent_key(gff: &Path) -> Result<String> {
+    Ok(load_meta(gff)?.map_or_else(|| DEFAULT_PARENT_KEY.to_string(), |m| m.parent_key))
+}
+
+/// `Error::IndexMismatch` when the index of `gff` was built from different content:
+/// the size or the sampled CRC-32 recorded in `.meta` differs from the file on disk
+/// (e.g. indexes copied next to another or an edited GFF). A renamed but identical GFF
+/// passes; a compressed GFF is compared through its decompressed copy. Indexes without
+/// `.meta` and a missing GFF are not checked here. With `--skip-index-check` the
+/// mismatch is a warning.
+///
+/// Each GFF is checked once per process; threads opening the same GFF concurrently wait
+/// for the first check instead of repeating it.
+pub fn check_index_matches(gff: &Path) -> Result<()> {
+    let mut checked = CHECKED.lock().unwrap_or_else(|e| e.into_inner());
+    if checked.iter().any(|p| p == gff) {
+        return Ok(());
+    }
+    if !gff.exists() {
+        return Ok(());
+    }
+    let Some(meta) = load_meta(gff)? else {
+        checked.push(gff.to_path_buf());
+        return Ok(());
+    };
+    // A compressed GFF was indexed (and is read) through its decompressed copy
+    let actual = IndexMeta::of_file(&crate::gff_text_path(gff)?)?;
+    let detail = if meta.size != actual.size {
+        Some(format!("{} bytes; this file has {}", meta.size, actual.size))
+    } else if meta.sample_crc32 != actual.sample_crc32 {
+        Some(format!(
+            "same size, but content hash {:08x}; this file has {:08x}",
+            meta.sample_crc32, actual.sample_crc32
+        ))
+    } else {
+        None
+    };
+    if let Some(detail) = detail {
+        if !FORCE_MISMATCH.load(Ordering::Relaxed) {
+            return Err(Error::IndexMismatch { gff: gff.to_path_buf(), built_for: meta.file, detail });
+        }
+        diag!(
+            "[WARN] Index of {:?} was built for {} ({}); using it anyway (--skip-index-check), output may be wrong",
+            gff, meta.file, detail
+        );
+    }
+    checked.push(gff.to_path_buf());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{SAMPLE_GFF, build_fixture};
+    use crate::{INDEX_SUFFIXES, append_suffix};
+
+    #[test]
+    fn text_round_trip() {
+        let meta = IndexMeta::of_bytes(Path::new("dir/a.gff"), b"##gff-version 3\n")
+            .with_attr_keys(vec!["Name".into(), "ID".into(), "Name".into()])
+            .with_id_keys("locus", "parent")
+            .with_seq_lengths(vec![("chr1".into(), 1000), ("HLA=A:01".into(), 7)]);
+        assert_eq!(meta.file, "a.gff");
+        let parsed = IndexMeta::parse(meta.to_text().as_bytes()).unwrap();
+        assert_eq!(parsed, meta);
+        assert_eq!(parsed.attr_keys, ["ID", "Name"]);
+        assert_eq!((parsed.id_key.as_str(), parsed.parent_key.as_str()), ("locus", "parent"));
+
+        let none = IndexMeta::of_bytes(Path::new("a.gff"), b"").with_seq_lengths(Vec::new());
+        assert_eq!(IndexMeta::parse(none.to_text().as_bytes()).unwrap().seq_lengths, Some(Vec::new()));
+    }
+
+    #[test]
+    fn older_meta_has_no_lengths() {
+        let text = "#gffx-meta 1\nfile\ta.gff\nsize\t10\nsample_crc32\t0000abcd\n";
+        let meta = IndexMeta::parse(text.as_bytes()).unwrap();
+        assert_eq!((meta.size, meta.sample_crc32, meta.seq_lengths), (10, 0xabcd, None));
+        assert_eq!((meta.id_key.as_str(), meta.parent_key.as_str()), (DEFAULT_ID_KEY, DEFAULT_PARENT_KEY));
+        let bad = format!("{text}seq_lengths\tchr1=12\tchr2\n");
+        assert!(IndexMeta::parse(bad.as_bytes()).is_err());
+        assert!(IndexMeta::parse(b"file\ta.gff\n").is_err());
+        assert!(IndexMeta::parse(b"#gffx-meta 1\nfile\ta.gff\nsize\t10\n").is_err());
+    }
+
+    #[test]
+    fn index_of_another_gff_is_rejected() {
+        // Large enough that only the first and last 64 KiB are hashed
+        let big = SAMPLE_GFF.repeat(300);
+        assert!(big.len() > 2 * SAMPLE_BYTES);
+
+        let same_size = build_fixture(&big).unwrap();
+        check_index_matches(&same_size.gff).unwrap();
+        let edited = big.replacen("Name=alpha", "Name=omega", 1);
+        std::fs::write(&same_size.gff, edited).unwrap();
+        // Already checked in this process
+        check_index_matches(&same_size.gff).unwrap();
+
+        let fresh = build_fixture(&big).unwrap();
+        std::fs::write(&fresh.gff, big.replacen("Name=alpha", "Name=omega", 1)).unwrap();
+        match check_index_matches(&fresh.gff) {
+            Err(Error::IndexMismatch { built_for, detail, .. }) => {
+                assert_eq!(built_for, "fixture.gff");
+                assert!(detail.starts_with("same size"), "{detail}");
+            }
+            other => panic!("expected IndexMismatch, got {:?}", other),
+        }
+
+        let longer = build_fixture(SAMPLE_GFF).unwrap();
+        std::fs::write(&longer.gff, format!("{SAMPLE_GFF}# trailing comment\n")).unwrap();
+        assert!(matches!(check_index_matches(&longer.gff), Err(Error::IndexMismatch { .. })));
+
+        // An edit in the unsampled middle of a large file is not seen
+        let middle = build_fixture(&big).unwrap();
+        let at = big.len() / 2 + big[big.len() / 2..].find("Name=alpha").unwrap();
+        std::fs::write(&middle.gff, format!("{}Name=omega{}", &big[..at], &big[at + 10..])).unwrap();
+        check_index_matches(&middle.gff).unwrap();
+
+        // A GFF renamed together with its index passes
+        let renamed = build_fixture(SAMPLE_GFF).unwrap();
+        let to = renamed.dir.join("renamed.gff");
+        std::fs::rename(&renamed.gff, &to).unwrap();
+        for suffix in INDEX_SUFFIXES {
+            let _ = std::fs::rename(append_suffix(&renamed.gff, suffix), append_suffix(&to, suffix));
+        }
+        assert_eq!(load_meta(&to).unwrap().unwrap().file, "fixture.gff");
+        check_index_matches(&to).unwrap();
+    }
+}
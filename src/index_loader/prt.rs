@@ -1,10 +1,10 @@
-use anyhow::{bail, Result};
 use byteorder::{ByteOrder, LittleEndian};
 use rustc_hash::FxHashMap;
 use std::{path::Path, sync::OnceLock};
 use rayon::prelude::*; // Parallel iteration (no feature gate)
 
-use crate::{append_suffix, safe_mmap_readonly};
+use crate::{ByteSource, artifact_exists, index_path};
+use crate::error::{Error, Result};
 
 #[derive(Debug, Clone, Copy)]
 pub struct PrtEntry {
@@ -20,6 +20,8 @@ pub struct PrtMap {
     pub entries: Vec<PrtEntry>,
     /// Lazy, thread-safe cache for child->parent index
     index_cache: OnceLock<FxHashMap<u32, u32>>,
+    /// Parents beyond `entries` of features with several Parent values (`.mpr`)
+    extra_parents: FxHashMap<u32, Vec<u32>>,
 }
 
 impl PrtMap {
@@ -28,9 +30,80 @@ impl PrtMap {
         Self {
             entries,
             index_cache: OnceLock::new(),
+            extra_parents: FxHashMap::default(),
         }
     }
 
+    /// Attach `.mpr` records (see `parse_mpr`); `load_prt` does this when the file exists.
+    pub fn with_extra_parents(mut self, pairs: Vec<(u32, u32)>) -> Self {
+        self.extra_parents.clear();
+        for (child, parent) in pairs {
+            self.extra_parents.entry(child).or_default().push(parent);
+        }
+        self
+    }
+
+    /// Whether some feature has more than one parent.
+    #[inline]
+    pub fn has_multi_parents(&self) -> bool {
+        !self.extra_parents.is_empty()
+    }
+
+    /// Every parent of `fid`: the `.prt` one first, then the others of a feature with
+    /// several Parent values. Empty for roots and out-of-range ids.
+    pub fn parents_of(&self, fid: u32) -> Vec<u32> {
+        let mut parents: Vec<u32> = match self.entries.get(fid as usize) {
+            Some(e) if e.parent != fid => vec![e.parent],
+            _ => return Vec::new(),
+        };
+        parents.extend(self.extra_parents.get(&fid).into_iter().flatten().copied());
+        parents
+    }
+
+    /// Roots of `fid` other than `root_of(fid)`: those reached through its extra parents
+    /// (and theirs), when they lie in other models. Usually empty.
+    pub fn other_roots(&self, fid: u32) -> Vec<u32> {
+        if self.extra_parents.is_empty() {
+            return Vec::new();
+        }
+        let first = self.root_of(fid);
+        let mut roots: Vec<u32> = Vec::new();
+        let mut stack = vec![fid];
+        let mut seen: Vec<u32> = Vec::new();
+        while let Some(cur) = stack.pop() {
+            if seen.contains(&cur) {
+                continue;
+            }
+            seen.push(cur);
+            match self.parents_of(cur).as_slice() {
+                [] => {
+                    if Some(cur) != first && !roots.contains(&cur) {
+                        roots.push(cur);
+                    }
+                }
+                parents => stack.extend(parents.iter().filter(|&&p| (p as usize) < self.entries.len())),
+            }
+        }
+        roots.sort_unstable();
+        roots
+    }
+
+    /// `(fid, root)` of each of `fids` with its root in `roots` (as from
+    /// `map_fids_to_roots`; `u32::MAX` roots are left out), plus one pair per
+    /// `other_roots` model of a feature with parents in several models.
+    pub fn fid_root_pairs(&self, fids: &[u32], roots: &[u32]) -> Vec<(u32, u32)> {
+        let mut pairs: Vec<(u32, u32)> =
+            fids.iter().copied().zip(roots.iter().copied()).filter(|&(_, r)| r != u32::MAX).collect();
+        if self.has_multi_parents() {
+            let extra: Vec<(u32, u32)> = pairs
+                .iter()
+                .flat_map(|&(fid, _)| self.other_roots(fid).into_iter().map(move |r| (fid, r)))
+                .collect();
+            pairs.extend(extra);
+        }
+        pairs
+    }
+
     /// Build a child -> parent hashmap for O(1) lookups (allocates every call).
     pub fn index(&self) -> FxHashMap<u32, u32> {
         self.entries.iter().map(|e| (e.child, e.parent)).collect()
@@ -50,12 +123,13 @@ impl PrtMap {
     }
 
     /// Resolve the root of a node by following parent pointers via *array access* (fast path).
+    /// A chain longer than the number of entries can only be a cycle and is reported as invalid.
     #[inline]
     fn resolve_root(&self, start: u32) -> (u32, bool) {
         let n = self.entries.len() as u32;
         let mut cur = start;
 
-        loop {
+        for _ in 0..=n {
             if cur >= n {
                 return (u32::MAX, true);
             }
@@ -69,13 +143,23 @@ impl PrtMap {
             }
             cur = p;
         }
+        (u32::MAX, true)
+    }
+
+    /// Root of a single feature; `None` for out-of-range ids and broken or cyclic chains.
+    #[inline]
+    pub fn root_of(&self, fid: u32) -> Option<u32> {
+        match self.resolve_root(fid) {
+            (root, false) => Some(root),
+            _ => None,
+        }
     }
 
     #[inline]
-    /// Map a Vec<FID> to a Vec<ROOT> using the fast resolver.
+    /// Map a slice of FIDs to a Vec<ROOT> using the fast resolver.
     /// - If a FID equals `u32::MAX`, keep it as-is (sentinel).
     /// - Output order matches the input order.
-    pub fn map_fids_to_roots(&self, fids: &Vec<u32>, threads: usize) -> Vec<u32> {
+    pub fn map_fids_to_roots(&self, fids: &[u32], threads: usize) -> Vec<u32> {
         let should_parallel = threads > 1 && fids.len() > 256; // tune threshold as needed
 
         if should_parallel {
@@ -105,16 +189,49 @@ impl PrtMap {
 /// Load a `.prt` file that encodes parent pointers as a u32 array.
 /// Each 4-byte little-endian word is the parent id of the child at the same index.
 /// For child i, parent = data[i].
+///
+/// The extra parents of `.mpr` are attached when it exists.
 pub fn load_prt<P: AsRef<Path>>(gff_path: P) -> Result<PrtMap> {
     let path = gff_path.as_ref();
-    let prt_path = append_suffix(path, ".prt");
-    let mmap = safe_mmap_readonly(&prt_path)?;
-    if mmap.len() % 4 != 0 {
-        bail!("Corrupted PRT: not aligned to u32");
+    let prt_path = index_path(path, ".prt");
+    let bytes = ByteSource::open_artifact(path, ".prt")?;
+    let prt = parse_prt(&bytes).map_err(|e| e.for_index_file(&prt_path))?;
+    Ok(match load_mpr(path)? {
+        Some(pairs) => prt.with_extra_parents(pairs),
+        None => prt,
+    })
+}
+
+/// Load `<gff>.mpr` if it was built; `Ok(None)` for indexes without it.
+pub fn load_mpr<P: AsRef<Path>>(gff_path: P) -> Result<Option<Vec<(u32, u32)>>> {
+    let path = gff_path.as_ref();
+    if !artifact_exists(path, ".mpr") {
+        return Ok(None);
+    }
+    let bytes = ByteSource::open_artifact(path, ".mpr")?;
+    parse_mpr(&bytes).map(Some).map_err(|e| e.for_index_file(&index_path(path, ".mpr")))
+}
+
+/// Parse raw `.mpr` bytes: one (u32 child fid, u32 parent fid) record per parent of a
+/// multi-parent feature beyond its `.prt` one, in fid order.
+pub fn parse_mpr(bytes: &[u8]) -> Result<Vec<(u32, u32)>> {
+    if !bytes.len().is_multiple_of(8) {
+        return Err(Error::corrupt("MPR", format!("length {} not multiple of 8", bytes.len())));
+    }
+    Ok(bytes
+        .chunks_exact(8)
+        .map(|rec| (LittleEndian::read_u32(&rec[0..4]), LittleEndian::read_u32(&rec[4..8])))
+        .collect())
+}
+
+/// Parse raw `.prt` bytes (see `load_prt`).
+pub fn parse_prt(bytes: &[u8]) -> Result<PrtMap> {
+    if !bytes.len().is_multiple_of(4) {
+        return Err(Error::corrupt("PRT", format!("length {} not aligned to u32", bytes.len())));
     }
 
-    let mut entries = Vec::with_capacity(mmap.len() / 4);
-    for (i, chunk) in mmap.chunks_exact(4).enumerate() {
+    let mut entries = Vec::with_capacity(bytes.len() / 4);
+    for (i, chunk) in bytes.chunks_exact(4).enumerate() {
         let parent = LittleEndian::read_u32(chunk);
         entries.push(PrtEntry {
             child: i as u32,
@@ -1,22 +1,78 @@
 // src/lib.rs
+// First, so that `diag!` is in scope in every other module
+#[macro_use]
+pub mod utils;
+#[cfg(feature = "cli")]
 pub mod commands;
+pub mod error;
 pub mod index_builder;
 pub mod index_loader;
-pub mod utils;
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
 
-pub use index_builder::core::build_index;
+pub use error::Error;
+pub use index_builder::bundle::{INDEX_SUFFIXES, bundle_index, unbundle_index};
+pub use index_builder::core::{
+    ANON_ID_PREFIX, BuildStats, DEFAULT_SKIP_TYPES, IndexBuilder, NAME_KEY, is_anonymous_id,
+};
+#[cfg(feature = "cli")]
+pub use index_builder::tabix::write_tabix_companion;
 pub use index_loader::{
-    core::{load_atn, load_sqs, safe_mmap_readonly},
-    gof::{GofMap, load_gof},
-    fts::{FtsMap, load_fts},
-    prt::{PrtMap, load_prt},
-    a2f::{A2fMap, load_a2f}
+    core::{
+        ByteSource, DECOMPRESSED_SUFFIX, RangeSource, apply_index_prefix, artifact_exists, check_gff_present,
+        gff_text_path, index_path, index_prefix, is_gzip, load_sqs, parse_sqs, safe_mmap_readonly, set_index_prefix,
+        set_no_mmap,
+    },
+    gof::{GofMap, ModelBlock, load_gcnt, load_gof, parse_gcnt, parse_gof, scan_gof},
+    fts::{FTS_SCAN_MAX, FtsMap, load_fts, parse_fts, scan_fts},
+    prt::{PrtMap, load_mpr, load_prt, parse_mpr, parse_prt},
+    a2f::{A2fMap, load_a2f, parse_a2f},
+    fbl::{BloomFilter, load_fbl, parse_fbl},
+    atn::{
+        AtnTable, MAX_ATTR_KEYS, attr_index_suffixes, indexed_attr_keys, load_atn, load_attr_index, load_name_index,
+        parse_atn,
+    },
+    bundle::{BUNDLE_SUFFIX, Bundle, BundleEntry, crc32, write_bundle},
+    meta::{
+        DEFAULT_ID_KEY, DEFAULT_PARENT_KEY, IndexMeta, check_index_matches, load_id_key, load_meta, load_parent_key,
+        set_force_index_mismatch,
+    },
 };
 
 
 pub use utils::common::{
-    CommonArgs, append_suffix, check_index_files_exist, write_gff_output, write_gff_output_filtered,
+    CommonArgs, DryRunPlan, LengthFilter, LineIndex, LineSelection, Provenance, ReportFormat, ScorePhase, TypeFilter,
+    align_blocks_to_lines, append_suffix, check_index_files_exist, context_roots, did_you_mean, drop_context_roots,
+    edit_distance, attr_value, deterministic, ensure_index, fast_id, missing_index_files, pad_roots,
+    push_line_with_attributes, push_line_with_provenance, gff_header_len, read_feature_names, resolve_feature_roots,
+    resolve_feature_roots_scan, set_coalesce_gaps, set_deterministic, set_strict_offsets, set_write_header,
+    sorted_root_intervals, write_header_enabled, write_source_header,
+    write_gff_output, write_context_blocks, write_padded_blocks, write_gff_output_grouped, QueryGroup,
+    write_gff_output_filtered, write_gff_output_resumable, write_gff_output_typed, write_gff_roots_only, write_roots_bed,
 };
-pub use utils::tree_io::{save_multiple_trees, write_offsets_to_file};
-pub use utils::tree::{Interval, IntervalTree};
+pub use utils::tree_io::{RIT_MAGIC, RIT_VERSION, parse_rit_header, rit_format_version, save_multiple_trees};
+pub use utils::tree::{Interval, IntervalIter, IntervalTree};
 pub use utils::tree_index::TreeIndexData;
+pub use utils::seq_lengths::{Clamp, SeqLengths};
+pub use utils::annotation_set::{AnnotationSet, UnknownContigs, parse_fraction};
+pub use utils::bed_chunks::{
+    BED_CHUNK_SIZE, BedChunks, BedpeEnd, BedpeMode, BedpeSkips, parse_bed_chunk, parse_bed_region, parse_bedpe_chunk,
+    parse_bedpe_pair, source_extension,
+};
+#[cfg(feature = "cli")]
+pub use utils::bed_chunks::BedReader;
+pub use utils::utr::{derive_block_utrs, write_gff_with_derived_utrs};
+pub use utils::longest::{TranscriptMetric, select_longest_transcript, write_gff_longest_transcripts};
+pub use utils::subtree::{block_descendants, expand_to_descendants};
+pub use utils::stats::{
+    Counter, CountingWriter, counter_value, peak_rss_bytes, record, report_stats, set_stats, stats_enabled,
+};
+pub use utils::table::{Cell, Column, Coords, TableArgs, TableFormat, TableWriter};
+pub use utils::attr_edit::{AttrEditor, escape_attr_value};
+pub use utils::log::{
+    RunSummary, color_default, color_enabled, report_error, report_summary, set_color, set_summary, set_summary_only,
+    summary_only,
+};
+pub use utils::checkpoint::{Checkpoint, PlanEntry, command_line_key, write_resumable};
+pub use utils::fasta::{BaseCounts, FaiEntry, Fasta, build_fai, parse_fai};
+pub use utils::fsname::{MAX_NAME_BYTES, path_for, sanitize, sanitize_all, unsanitize};
@@ -1,6 +1,7 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
 use gffx::commands::*;
+use std::time::Instant;
 
 #[derive(Parser)]
 #[command(
@@ -12,6 +13,43 @@ use gffx::commands::*;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Read the GFF and index files with buffered/positioned reads instead of mmap
+    /// (used automatically when mapping fails)
+    #[arg(long = "no-mmap", global = true, default_value_t = false)]
+    no_mmap: bool,
+
+    /// Report peak RSS, bytes mmapped/read/written, tree queries and block scans on
+    /// stderr when the command finishes
+    #[arg(long = "stats", global = true, default_value_t = false)]
+    stats: bool,
+
+    /// Fail when an indexed block does not start and end on a line boundary (e.g. the
+    /// GFF changed after indexing) instead of widening it to whole lines with a warning
+    #[arg(long = "strict-offsets", global = true, default_value_t = false)]
+    strict_offsets: bool,
+
+    /// When writing whole blocks, also join blocks separated by less than 64 bytes of
+    /// whole `#` comment lines (the comment lines are then part of the output)
+    #[arg(long = "coalesce-gaps", global = true, default_value_t = false)]
+    coalesce_gaps: bool,
+
+    /// Use a stale index, whose `.meta` says it was built from a different GFF (size or
+    /// content hash differs, e.g. the GFF was edited after indexing), with a warning
+    /// instead of failing; output may be wrong
+    #[arg(long = "skip-index-check", alias = "force-index-mismatch", global = true, default_value_t = false)]
+    skip_index_check: bool,
+
+    /// Print only the one-line run summary (and errors) on stderr; warnings, info and
+    /// verbose lines are suppressed
+    #[arg(long = "summary-only", global = true, default_value_t = false)]
+    summary_only: bool,
+
+    /// Make outputs byte-identical across runs and thread counts: per-root results are
+    /// merged in root order, depth/coverage tables are sorted by position, and `sample`
+    /// uses seed 0 unless --seed is given
+    #[arg(long = "deterministic", global = true, default_value_t = false)]
+    deterministic: bool,
 }
 
 #[derive(Subcommand)]
@@ -22,13 +60,62 @@ enum Commands {
     Search(SearchArgs),
     Coverage(CoverageArgs),
     Depth(DepthArgs),
-    Sample(SampleArgs)
+    Sample(SampleArgs),
+    Debug(DebugArgs),
+    DiffIds(DiffIdsArgs),
+    Stat(StatArgs),
+    Grep(GrepArgs),
+    Completions(CompletionsArgs),
+    Mangen(MangenArgs),
 }
 
-fn main() -> Result<()> {
+impl Commands {
+    fn name(&self) -> &'static str {
+        match self {
+            Commands::Index(_) => "index",
+            Commands::Intersect(_) => "intersect",
+            Commands::Extract(_) => "extract",
+            Commands::Search(_) => "search",
+            Commands::Coverage(_) => "coverage",
+            Commands::Depth(_) => "depth",
+            Commands::Sample(_) => "sample",
+            Commands::Debug(_) => "debug",
+            Commands::DiffIds(_) => "diff-ids",
+            Commands::Stat(_) => "stat",
+            Commands::Grep(_) => "grep",
+            Commands::Completions(_) => "completions",
+            Commands::Mangen(_) => "mangen",
+        }
+    }
+}
+
+fn main() {
     let cli = Cli::parse();
+    gffx::set_no_mmap(cli.no_mmap);
+    gffx::set_stats(cli.stats);
+    gffx::set_strict_offsets(cli.strict_offsets);
+    gffx::set_force_index_mismatch(cli.skip_index_check);
+    gffx::set_coalesce_gaps(cli.coalesce_gaps);
+    gffx::set_summary_only(cli.summary_only);
+    gffx::set_deterministic(cli.deterministic);
+    gffx::set_color(gffx::color_default());
+
+    let name = cli.command.name();
+    // Completion scripts and man pages are sourced or installed, not read
+    let summarize = !matches!(cli.command, Commands::Completions(_) | Commands::Mangen(_));
+    let start = Instant::now();
+    if let Err(e) = run(cli.command) {
+        gffx::report_error(&e);
+        std::process::exit(1);
+    }
+    gffx::report_stats();
+    if summarize {
+        gffx::report_summary(name, start.elapsed());
+    }
+}
 
-    match cli.command {
+fn run(command: Commands) -> Result<()> {
+    match command {
         Commands::Index(args) => run_index(&args)?,
         Commands::Intersect(args) => run_intersect(&args)?,
         Commands::Extract(args) => run_extract(&args)?,
@@ -36,7 +123,156 @@ fn main() -> Result<()> {
         Commands::Coverage(args) => run_coverage(&args)?,
         Commands::Depth(args) => run_depth(&args)?,
         Commands::Sample(args) => run_sample(&args)?,
+        Commands::Debug(args) => run_debug(&args)?,
+        Commands::DiffIds(args) => run_diff_ids(&args)?,
+        Commands::Stat(args) => run_stat(&args)?,
+        Commands::Grep(args) => run_grep(&args)?,
+        // Generated from the full command tree, so new flags are always covered
+        Commands::Completions(args) => run_completions(&args, &mut Cli::command())?,
+        Commands::Mangen(args) => run_mangen(&args, Cli::command())?,
     }
-
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap_complete::{Shell, generate};
+    use gffx::commands::debug::DebugCommand;
+
+    #[test]
+    fn skip_index_check_keeps_the_old_spelling() {
+        for flag in ["--skip-index-check", "--force-index-mismatch"] {
+            let cli = Cli::try_parse_from(["gffx", "extract", "-i", "a.gff", "-f", "g1", flag]).unwrap();
+            assert!(cli.skip_index_check, "{flag}");
+        }
+        let cli = Cli::try_parse_from(["gffx", "extract", "-i", "a.gff", "-f", "g1"]).unwrap();
+        assert!(!cli.skip_index_check);
+    }
+
+    fn parse(args: &[&str]) -> Result<Commands, clap::Error> {
+        Cli::try_parse_from(["gffx"].iter().chain(args)).map(|cli| cli.command)
+    }
+
+    #[test]
+    fn every_subcommand_parses_its_flags() {
+        let argv = ["index", "-i", "a.gff", "-a", "Name,gene_id", "--strict", "--bloom-fpr", "0.1"];
+        let Ok(Commands::Index(a)) = parse(&argv) else {
+            panic!("index")
+        };
+        assert_eq!((a.attribute.as_str(), a.strict, a.bloom_fpr), ("Name,gene_id", true, Some(0.1)));
+        let Ok(Commands::Intersect(a)) = parse(&["intersect", "-i", "a.gff", "-r", "chr1:1-100", "-c", "-I"]) else {
+            panic!("intersect")
+        };
+        assert!(a.contained && a.invert && !a.overlap && a.region.as_deref() == Some("chr1:1-100"));
+        let Ok(Commands::Extract(a)) = parse(&["extract", "-i", "a.gff", "-f", "t1", "-d", "-T", "exon"]) else {
+            panic!("extract")
+        };
+        assert!(a.descendants && a.common.types.is_some() && a.common.feature_only());
+        let Ok(Commands::Search(a)) = parse(&["search", "-i", "a.gff", "-a", "BRCA", "-r", "--provenance", "lines"])
+        else {
+            panic!("search")
+        };
+        assert!(a.common.provenance.is_some() && a.common.feature_only());
+        let Ok(Commands::Coverage(a)) = parse(&["coverage", "-i", "a.gff", "-i", "b.gff", "-s", "r.bam", "-f", "g1"])
+        else {
+            panic!("coverage")
+        };
+        assert_eq!((a.input.len(), a.feature_id.as_deref()), (2, Some("g1")));
+        let Ok(Commands::Depth(a)) = parse(&["depth", "-i", "a.gff", "-s", "r.bam", "--uncovered-only"]) else {
+            panic!("depth")
+        };
+        assert!(a.uncovered_only && a.rollup.is_none());
+        let Ok(Commands::Sample(a)) = parse(&["sample", "-i", "a.gff", "-r", "0.5", "--seed", "7"]) else {
+            panic!("sample")
+        };
+        assert_eq!((a.ratio, a.seed), (Some(0.5), Some(7)));
+        let Ok(Commands::Debug(a)) = parse(&["debug", "blocks", "-i", "a.gff", "--offsets"]) else {
+            panic!("debug")
+        };
+        let DebugCommand::Blocks(blocks) = a.command;
+        assert!(blocks.offsets);
+        let Ok(Commands::DiffIds(a)) = parse(&["diff-ids", "-a", "a.gff", "-b", "b.gff", "--attributes"]) else {
+            panic!("diff-ids")
+        };
+        assert!(a.attributes);
+        let Ok(Commands::Stat(a)) = parse(&["stat", "-i", "a.gff", "--fasta", "g.fa", "--per-feature", "s.tsv"]) else {
+            panic!("stat")
+        };
+        assert_eq!(a.fasta, std::path::Path::new("g.fa"));
+        let Ok(Commands::Grep(a)) = parse(&["grep", "-i", "a.gff", "-p", "kinase", "-x", "-e"]) else {
+            panic!("grep")
+        };
+        assert!(a.whole_value && a.entire_group && !a.regex);
+        let Ok(Commands::Completions(a)) = parse(&["completions", "zsh"]) else {
+            panic!("completions")
+        };
+        assert_eq!(a.shell, Shell::Zsh);
+        let Ok(Commands::Mangen(a)) = parse(&["mangen"]) else {
+            panic!("mangen")
+        };
+        assert_eq!(a.out_dir, std::path::Path::new("man"));
+    }
+
+    #[test]
+    fn entire_group_combines_with_types() {
+        for cmd in ["extract", "search"] {
+            let query = if cmd == "extract" { "-f" } else { "-a" };
+            for e in ["-e", "--entire-group", "--entire_group"] {
+                let common = match parse(&[cmd, "-i", "a.gff", query, "g1", e, "-T", "mRNA"]).unwrap() {
+                    Commands::Extract(a) => a.common,
+                    Commands::Search(a) => a.common,
+                    _ => unreachable!(),
+                };
+                assert!(common.entire_group && common.types.is_some() && !common.feature_only(), "{cmd} {e}");
+            }
+            // Per-feature only: provenance and --roots-only still conflict with -e
+            for flag in [&["--roots-only"][..], &["--provenance", "both"]] {
+                let argv = [&[cmd, "-i", "a.gff", query, "g1", "-e"][..], flag].concat();
+                let err = parse(&argv).err().unwrap();
+                assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict, "{cmd} {flag:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn removed_flags_are_rejected() {
+        for argv in [
+            ["extract", "-i", "a.gff", "-f", "g1", "--match-only"],
+            ["search", "-i", "a.gff", "-a", "g1", "--match-only"],
+            ["intersect", "-i", "a.gff", "-r", "chr1:1-9", "--match-only"],
+            ["search", "-i", "a.gff", "-a", "g1", "-d"],
+            ["intersect", "-i", "a.gff", "-r", "chr1:1-9", "-d"],
+        ] {
+            let err = parse(&argv).err().unwrap();
+            assert_eq!(err.kind(), clap::error::ErrorKind::UnknownArgument, "{}", argv.join(" "));
+        }
+    }
+
+    #[test]
+    fn command_tree_is_consistent() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn bash_completion_covers_subcommand_flags() {
+        let mut buf = Vec::new();
+        generate(Shell::Bash, &mut Cli::command(), "gffx", &mut buf);
+        let script = String::from_utf8(buf).unwrap();
+        for word in ["extract", "search", "depth", "--entire-group", "--preserve-query-order", "--no-mmap"] {
+            assert!(script.contains(word), "bash completion lacks {}", word);
+        }
+    }
+
+    #[test]
+    fn mangen_writes_a_page_per_subcommand() {
+        let out_dir = std::env::temp_dir().join(format!("gffx-test-mangen-{}", std::process::id()));
+        run_mangen(&MangenArgs { out_dir: out_dir.clone() }, Cli::command()).unwrap();
+        let page = std::fs::read_to_string(out_dir.join("gffx-extract.1")).unwrap();
+        let top = out_dir.join("gffx.1").exists();
+        std::fs::remove_dir_all(&out_dir).unwrap();
+        assert!(top);
+        assert!(page.contains(".TH gffx-extract"));
+        assert!(page.contains("entire\\-group"));
+    }
+}
@@ -0,0 +1,623 @@
+//! Test fixtures: scratch directories and small indexed GFFs for the crate's own unit
+//! tests, and seeded synthetic GFF/BED/BAM generators (`testutil` feature).
+
+use crate::IndexBuilder;
+use anyhow::{Context, Result, bail};
+#[cfg(any(feature = "cli", feature = "testutil"))]
+use rust_htslib::bam::{
+    self,
+    header::{Header, HeaderRecord},
+    record::{Cigar, CigarString},
+};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A directory under the system temp dir for one test's files, unique within the
+/// process, removed with its contents on drop.
+#[derive(Debug)]
+pub struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    pub fn new(name: &str) -> Result<Self> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("gffx-{}-{}-{}", name, std::process::id(), n));
+        fs::create_dir_all(&dir).with_context(|| format!("Cannot create {:?}", dir))?;
+        Ok(Self(dir))
+    }
+
+    /// `file` inside the directory.
+    pub fn join<P: AsRef<Path>>(&self, file: P) -> PathBuf {
+        self.0.join(file)
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Two genes on chr1 (one with two transcripts) and one on chr2; features carry `ID`,
+/// genes also `Name`. A comment line sits between the two chr1 models.
+pub const SAMPLE_GFF: &str = "\
+##gff-version 3
+##sequence-region chr1 1 10000
+##sequence-region chr2 1 5000
+chr1\tsrc\tgene\t100\t900\t.\t+\t.\tID=g1;Name=alpha
+chr1\tsrc\tmRNA\t100\t900\t.\t+\t.\tID=t1;Parent=g1
+chr1\tsrc\texon\t100\t300\t.\t+\t.\tID=e1;Parent=t1
+chr1\tsrc\tCDS\t150\t300\t.\t+\t0\tID=c1;Parent=t1
+chr1\tsrc\texon\t500\t900\t.\t+\t.\tID=e2;Parent=t1
+chr1\tsrc\tmRNA\t100\t700\t.\t+\t.\tID=t2;Parent=g1
+chr1\tsrc\texon\t100\t700\t.\t+\t.\tID=e3;Parent=t2
+# second model
+chr1\tsrc\tgene\t2000\t3000\t.\t-\t.\tID=g2;Name=beta
+chr1\tsrc\tmRNA\t2000\t3000\t.\t-\t.\tID=t3;Parent=g2
+chr1\tsrc\texon\t2000\t3000\t.\t-\t.\tID=e4;Parent=t3
+chr2\tsrc\tgene\t10\t400\t.\t+\t.\tID=g3;Name=gamma
+chr2\tsrc\tmRNA\t10\t400\t.\t+\t.\tID=t4;Parent=g3
+chr2\tsrc\texon\t10\t400\t.\t+\t.\tID=e5;Parent=t4
+";
+
+/// A GFF written to a scratch directory and indexed there.
+#[derive(Debug)]
+pub struct Fixture {
+    pub gff: PathBuf,
+    pub dir: ScratchDir,
+}
+
+/// Write `gff_text` to a fresh scratch directory and index it like `gffx index -a Name`.
+pub fn build_fixture(gff_text: &str) -> Result<Fixture> {
+    build_fixture_with(gff_text, &IndexBuilder::new().attr_key("Name"))
+}
+
+/// `build_fixture` with other index settings (keys, `--strict`, ...).
+pub fn build_fixture_with(gff_text: &str, builder: &IndexBuilder) -> Result<Fixture> {
+    let dir = ScratchDir::new("fixture")?;
+    let gff = dir.join("fixture.gff");
+    fs::write(&gff, gff_text)?;
+    builder.build(&gff)?;
+    Ok(Fixture { gff, dir })
+}
+
+/// The non-comment lines of a GFF/TSV output file.
+pub fn data_lines(path: &Path) -> Result<Vec<String>> {
+    let text = fs::read_to_string(path).with_context(|| format!("Cannot read {:?}", path))?;
+    Ok(text.lines().filter(|l| !l.starts_with('#')).map(str::to_string).collect())
+}
+
+/// SplitMix64: small, fast and stable across platforms and crate versions, so a seed
+/// always yields the same fixture. Also used directly by tests that draw their own data.
+#[derive(Debug, Clone)]
+pub struct SynthRng(u64);
+
+impl SynthRng {
+    /// Generator for `seed`; different `stream`s of one seed are independent.
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = Self(seed ^ stream.wrapping_mul(0xA076_1D64_78BD_642F));
+        rng.next_u64();
+        rng
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `r` (`r.start` when `r` is empty).
+    pub fn range(&mut self, r: Range<u64>) -> u64 {
+        let n = r.end.saturating_sub(r.start);
+        if n == 0 { r.start } else { r.start + self.next_u64() % n }
+    }
+
+    pub fn coin(&mut self) -> bool {
+        self.next_u64() & 1 == 1
+    }
+
+    /// Fisher-Yates shuffle of `items`.
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.range(0..i as u64 + 1) as usize);
+        }
+    }
+}
+
+/// One transcript of a synthetic gene; coordinates are 1-based, inclusive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SynthTranscript {
+    pub id: String,
+    /// Exons in ascending order, non-overlapping
+    pub exons: Vec<(u64, u64)>,
+}
+
+/// One synthetic gene; coordinates are 1-based, inclusive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SynthGene {
+    pub id: String,
+    /// Index into `SynthGenome::chromosomes`
+    pub chrom: usize,
+    pub start: u64,
+    pub end: u64,
+    /// '+' or '-'
+    pub strand: char,
+    pub transcripts: Vec<SynthTranscript>,
+}
+
+/// Layout drawn by `GffSynth`: sequences with their lengths and the genes on them.
+///
+/// `RegionSynth` and `BamSynth` place their regions and reads on the same layout, so
+/// generated BED and BAM inputs hit the generated genes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SynthGenome {
+    /// (name, length)
+    pub chromosomes: Vec<(String, u64)>,
+    /// Sorted by chromosome, then start
+    pub genes: Vec<SynthGene>,
+}
+
+impl SynthGenome {
+    /// Write the annotation as GFF3: a `##sequence-region` per chromosome, then per gene
+    /// its gene line and, per transcript, an mRNA line with its exon lines and CDS lines
+    /// (every exon but the first and last, so each multi-exon transcript has UTRs).
+    pub fn write_gff_to<W: Write>(&self, out: &mut W) -> Result<()> {
+        let mut buf = Vec::with_capacity(1 << 20);
+        writeln!(buf, "##gff-version 3")?;
+        for (name, len) in &self.chromosomes {
+            writeln!(buf, "##sequence-region {} 1 {}", name, len)?;
+        }
+        for g in &self.genes {
+            let seqid = &self.chromosomes[g.chrom].0;
+            writeln!(
+                buf,
+                "{seqid}\tgffx\tgene\t{}\t{}\t.\t{}\t.\tID={};Name={}",
+                g.start, g.end, g.strand, g.id, g.id
+            )?;
+            for t in &g.transcripts {
+                let (ts, te) = (t.exons[0].0, t.exons[t.exons.len() - 1].1);
+                let (tid, strand) = (&t.id, g.strand);
+                writeln!(buf, "{seqid}\tgffx\tmRNA\t{ts}\t{te}\t.\t{strand}\t.\tID={tid};Parent={}", g.id)?;
+                for (k, &(s, e)) in t.exons.iter().enumerate() {
+                    let k = k + 1;
+                    writeln!(buf, "{seqid}\tgffx\texon\t{s}\t{e}\t.\t{strand}\t.\tID={tid}.exon{k};Parent={tid}")?;
+                }
+                let n = t.exons.len();
+                for (k, &(s, e)) in t.exons.iter().enumerate().take(n.saturating_sub(1)).skip(1) {
+                    writeln!(buf, "{seqid}\tgffx\tCDS\t{s}\t{e}\t.\t{strand}\t0\tID={tid}.cds{k};Parent={tid}")?;
+                }
+            }
+            if buf.len() >= 1 << 20 {
+                out.write_all(&buf)?;
+                buf.clear();
+            }
+        }
+        out.write_all(&buf)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// `write_gff_to` a new file at `path`.
+    pub fn write_gff(&self, path: &Path) -> Result<()> {
+        let file = File::create(path).with_context(|| format!("Cannot create {:?}", path))?;
+        self.write_gff_to(&mut BufWriter::new(file))
+    }
+}
+
+/// Seeded builder of a synthetic GFF3 annotation (`testutil` feature).
+///
+/// Each chromosome (`chr1`, `chr2`, ...) holds `genes_per_chrom` genes in order,
+/// separated by intergenic gaps; each gene has a number of transcripts drawn from
+/// `transcripts`, each with a number of exons drawn from `exons`. The same settings
+/// and seed always give the same bytes, e.g.
+/// `GffSynth::new().chromosomes(5).genes_per_chrom(1000).seed(42).write(path)?`.
+#[derive(Debug, Clone)]
+pub struct GffSynth {
+    chromosomes: usize,
+    genes_per_chrom: usize,
+    transcripts: Range<u32>,
+    exons: Range<u32>,
+    gene_len: Range<u64>,
+    gap: Range<u64>,
+    seed: u64,
+}
+
+impl Default for GffSynth {
+    fn default() -> Self {
+        Self {
+            chromosomes: 1,
+            genes_per_chrom: 100,
+            transcripts: 1..4,
+            exons: 2..12,
+            gene_len: 1_000..20_000,
+            gap: 500..5_000,
+            seed: 0,
+        }
+    }
+}
+
+impl GffSynth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn chromosomes(mut self, n: usize) -> Self {
+        self.chromosomes = n;
+        self
+    }
+
+    pub fn genes_per_chrom(mut self, n: usize) -> Self {
+        self.genes_per_chrom = n;
+        self
+    }
+
+    /// Transcripts per gene (half-open, e.g. `1..4` for one to three).
+    pub fn transcripts(mut self, r: Range<u32>) -> Self {
+        self.transcripts = r;
+        self
+    }
+
+    /// Exons per transcript (half-open).
+    pub fn exons(mut self, r: Range<u32>) -> Self {
+        self.exons = r;
+        self
+    }
+
+    /// Gene span in bp (half-open).
+    pub fn gene_len(mut self, r: Range<u64>) -> Self {
+        self.gene_len = r;
+        self
+    }
+
+    /// Intergenic gap in bp (half-open), also before the first and after the last gene.
+    pub fn gap(mut self, r: Range<u64>) -> Self {
+        self.gap = r;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Draw the layout.
+    pub fn build(&self) -> Result<SynthGenome> {
+        if self.transcripts.is_empty() || self.transcripts.start == 0 {
+            bail!("transcripts range {:?} must be non-empty and start at 1 or more", self.transcripts);
+        }
+        if self.exons.is_empty() || self.exons.start == 0 {
+            bail!("exons range {:?} must be non-empty and start at 1 or more", self.exons);
+        }
+        // Every exon needs at least 2 bp of its slot, plus 1 bp between slots
+        let min_len = 3 * u64::from(self.exons.end - 1);
+        if self.gene_len.is_empty() || self.gene_len.start < min_len {
+            bail!("gene_len range {:?} must be non-empty and start at {} or more", self.gene_len, min_len);
+        }
+
+        let mut chromosomes = Vec::with_capacity(self.chromosomes);
+        let mut genes = Vec::with_capacity(self.chromosomes * self.genes_per_chrom);
+        for c in 0..self.chromosomes {
+            // One stream per chromosome: adding chromosomes keeps the earlier ones
+            let mut rng = SynthRng::new(self.seed, c as u64);
+            let mut pos = 1 + rng.range(self.gap.clone());
+            for g in 0..self.genes_per_chrom {
+                let len = rng.range(self.gene_len.clone());
+                let strand = if rng.coin() { '+' } else { '-' };
+                let id = format!("gene{}_{}", c + 1, g + 1);
+                let n_tx = rng.range(u64::from(self.transcripts.start)..u64::from(self.transcripts.end));
+                let mut transcripts = Vec::with_capacity(n_tx as usize);
+                for t in 0..n_tx {
+                    let n_ex = rng.range(u64::from(self.exons.start)..u64::from(self.exons.end));
+                    // Exon k lies inside slot k of the gene span; the first transcript
+                    // spans the whole gene
+                    let slot = len / n_ex;
+                    let mut exons = Vec::with_capacity(n_ex as usize);
+                    for k in 0..n_ex {
+                        let slot_start = pos + k * slot;
+                        let slot_end = if k + 1 == n_ex { pos + len - 1 } else { slot_start + slot - 2 };
+                        let (s, e) = if t == 0 && n_ex == 1 {
+                            (slot_start, slot_end)
+                        } else if t == 0 && (k == 0 || k + 1 == n_ex) {
+                            let mid = slot_start + (slot_end - slot_start) / 2;
+                            if k == 0 {
+                                (slot_start, rng.range(mid..slot_end + 1))
+                            } else {
+                                (rng.range(slot_start..mid + 1), slot_end)
+                            }
+                        } else {
+                            let s = rng.range(slot_start..slot_end);
+                            (s, rng.range(s + 1..slot_end + 1))
+                        };
+                        exons.push((s, e));
+                    }
+                    transcripts.push(SynthTranscript { id: format!("{}.t{}", id, t + 1), exons });
+                }
+                genes.push(SynthGene { id, chrom: c, start: pos, end: pos + len - 1, strand, transcripts });
+                pos += len + rng.range(self.gap.clone());
+            }
+            chromosomes.push((format!("chr{}", c + 1), pos - 1));
+        }
+        Ok(SynthGenome { chromosomes, genes })
+    }
+
+    /// Draw the layout and write it as GFF3 to `path`; returns the layout for
+    /// `RegionSynth` / `BamSynth`.
+    pub fn write(&self, path: &Path) -> Result<SynthGenome> {
+        let genome = self.build()?;
+        genome.write_gff(path)?;
+        Ok(genome)
+    }
+
+    /// Draw the layout and index it as a `build_fixture` (the synthetic features carry
+    /// a `Name`); returns the layout for `RegionSynth` / `BamSynth` with the fixture.
+    pub fn fixture(&self) -> Result<(SynthGenome, Fixture)> {
+        let genome = self.build()?;
+        let mut gff = Vec::new();
+        genome.write_gff_to(&mut gff)?;
+        let fx = build_fixture(std::str::from_utf8(&gff)?)?;
+        Ok((genome, fx))
+    }
+}
+
+/// Seeded builder of BED regions over a `SynthGenome` (`testutil` feature).
+///
+/// A `hit_fraction` of the regions lies inside a gene (drawn uniformly over genes),
+/// the rest anywhere on a chromosome. Regions are written sorted, BED 0-based half-open,
+/// with a `r<n>` name column.
+#[derive(Debug, Clone)]
+pub struct RegionSynth<'g> {
+    genome: &'g SynthGenome,
+    count: usize,
+    width: Range<u64>,
+    hit_fraction: f64,
+    seed: u64,
+}
+
+impl<'g> RegionSynth<'g> {
+    pub fn new(genome: &'g SynthGenome) -> Self {
+        Self { genome, count: 1000, width: 100..5_000, hit_fraction: 0.8, seed: 0 }
+    }
+
+    pub fn count(mut self, n: usize) -> Self {
+        self.count = n;
+        self
+    }
+
+    /// Region width in bp (half-open).
+    pub fn width(mut self, r: Range<u64>) -> Self {
+        self.width = r;
+        self
+    }
+
+    /// Share of regions placed inside genes, in [0, 1].
+    pub fn hit_fraction(mut self, f: f64) -> Self {
+        self.hit_fraction = f.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Draw the regions: (chromosome index, 0-based start, end), sorted.
+    pub fn build(&self) -> Result<Vec<(usize, u64, u64)>> {
+        let genome = self.genome;
+        if genome.chromosomes.is_empty() || (genome.genes.is_empty() && self.hit_fraction > 0.0) {
+            bail!("the genome has no chromosomes or no genes to place regions on");
+        }
+        if self.width.is_empty() || self.width.start == 0 {
+            bail!("width range {:?} must be non-empty and start at 1 or more", self.width);
+        }
+        let mut rng = SynthRng::new(self.seed, u64::MAX);
+        let threshold = (self.hit_fraction * u64::MAX as f64) as u64;
+        let mut regions = Vec::with_capacity(self.count);
+        for _ in 0..self.count {
+            let width = rng.range(self.width.clone());
+            let (chrom, lo, hi) = if rng.next_u64() < threshold {
+                let g = &genome.genes[rng.range(0..genome.genes.len() as u64) as usize];
+                (g.chrom, g.start - 1, g.end)
+            } else {
+                let c = rng.range(0..genome.chromosomes.len() as u64) as usize;
+                (c, 0, genome.chromosomes[c].1)
+            };
+            let width = width.min(hi - lo);
+            let start = rng.range(lo..hi - width + 1);
+            regions.push((chrom, start, start + width));
+        }
+        regions.sort_unstable();
+        Ok(regions)
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> Result<()> {
+        let mut buf = Vec::with_capacity(1 << 20);
+        for (i, (c, s, e)) in self.build()?.into_iter().enumerate() {
+            writeln!(buf, "{}\t{}\t{}\tr{}", self.genome.chromosomes[c].0, s, e, i + 1)?;
+            if buf.len() >= 1 << 20 {
+                out.write_all(&buf)?;
+                buf.clear();
+            }
+        }
+        out.write_all(&buf)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let file = File::create(path).with_context(|| format!("Cannot create {:?}", path))?;
+        self.write_to(&mut BufWriter::new(file))
+    }
+}
+
+/// Seeded builder of a coordinate-sorted BAM with reads over the genes of a
+/// `SynthGenome` (`testutil` feature).
+///
+/// Each gene receives about `depth * gene length / read_len` single-end reads, placed
+/// uniformly over its span, fully matched (`<read_len>M`), MAPQ 60, on a random strand.
+/// Bases are random; qualities are all 40.
+#[cfg(any(feature = "cli", feature = "testutil"))]
+#[derive(Debug, Clone)]
+pub struct BamSynth<'g> {
+    genome: &'g SynthGenome,
+    depth: f64,
+    read_len: u32,
+    seed: u64,
+}
+
+#[cfg(any(feature = "cli", feature = "testutil"))]
+impl<'g> BamSynth<'g> {
+    pub fn new(genome: &'g SynthGenome) -> Self {
+        Self { genome, depth: 10.0, read_len: 100, seed: 0 }
+    }
+
+    /// Mean coverage over gene spans.
+    pub fn depth(mut self, depth: f64) -> Self {
+        self.depth = depth.max(0.0);
+        self
+    }
+
+    pub fn read_len(mut self, len: u32) -> Self {
+        self.read_len = len;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Draw the read placements: (chromosome index, 0-based start, reverse), sorted.
+    pub fn build(&self) -> Result<Vec<(usize, u64, bool)>> {
+        if self.read_len == 0 {
+            bail!("read_len must be at least 1");
+        }
+        let read_len = u64::from(self.read_len);
+        let mut rng = SynthRng::new(self.seed, u64::MAX - 1);
+        let mut reads = Vec::new();
+        for g in &self.genome.genes {
+            let chrom_len = self.genome.chromosomes[g.chrom].1;
+            if chrom_len < read_len {
+                continue;
+            }
+            let n = (self.depth * (g.end - g.start + 1) as f64 / read_len as f64).round() as u64;
+            let lo = (g.start - 1).min(chrom_len - read_len);
+            let hi = g.end.saturating_sub(read_len).clamp(lo, chrom_len - read_len);
+            for _ in 0..n {
+                reads.push((g.chrom, rng.range(lo..hi + 1), rng.coin()));
+            }
+        }
+        reads.sort_unstable();
+        Ok(reads)
+    }
+
+    /// Write the BAM to `path` (`@HD SO:coordinate`, one `@SQ` per chromosome).
+    /// Returns the number of reads.
+    pub fn write(&self, path: &Path) -> Result<usize> {
+        let mut header = Header::new();
+        header.push_record(HeaderRecord::new(b"HD").push_tag(b"VN", "1.6").push_tag(b"SO", "coordinate"));
+        for (name, len) in &self.genome.chromosomes {
+            header.push_record(HeaderRecord::new(b"SQ").push_tag(b"SN", name).push_tag(b"LN", len));
+        }
+        let mut writer = bam::Writer::from_path(path, &header, bam::Format::Bam)
+            .with_context(|| format!("Cannot create BAM {:?}", path))?;
+
+        let reads = self.build()?;
+        let mut rng = SynthRng::new(self.seed, u64::MAX - 2);
+        let cigar = CigarString(vec![Cigar::Match(self.read_len)]);
+        let qual = vec![40u8; self.read_len as usize];
+        let mut seq = vec![b'A'; self.read_len as usize];
+        let mut record = bam::Record::new();
+        for (i, &(chrom, start, reverse)) in reads.iter().enumerate() {
+            for b in seq.iter_mut() {
+                *b = b"ACGT"[(rng.next_u64() & 3) as usize];
+            }
+            record.set(format!("read{}", i + 1).as_bytes(), Some(&cigar), &seq, &qual);
+            record.set_tid(chrom as i32);
+            record.set_pos(start as i64);
+            record.set_mapq(60);
+            record.set_mtid(-1);
+            record.set_mpos(-1);
+            record.set_insert_size(0);
+            record.set_flags(if reverse { 0x10 } else { 0 });
+            writer.write(&record).with_context(|| format!("Cannot write to BAM {:?}", path))?;
+        }
+        Ok(reads.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn genome(seed: u64) -> SynthGenome {
+        GffSynth::new().chromosomes(3).genes_per_chrom(40).seed(seed).build().unwrap()
+    }
+
+    #[test]
+    fn gff_synth_is_deterministic() {
+        let dir = ScratchDir::new("synth-gff").unwrap();
+        let synth = GffSynth::new().chromosomes(3).genes_per_chrom(40);
+        let a = synth.clone().seed(7).write(&dir.join("a.gff")).unwrap();
+        let b = synth.clone().seed(7).write(&dir.join("b.gff")).unwrap();
+        let c = synth.seed(8).write(&dir.join("c.gff")).unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        let read = |f: &str| fs::read(dir.join(f)).unwrap();
+        assert_eq!(read("a.gff"), read("b.gff"));
+        assert_ne!(read("a.gff"), read("c.gff"));
+    }
+
+    #[test]
+    fn adding_chromosomes_keeps_earlier_ones() {
+        let small = GffSynth::new().chromosomes(2).genes_per_chrom(10).seed(3).build().unwrap();
+        let large = GffSynth::new().chromosomes(4).genes_per_chrom(10).seed(3).build().unwrap();
+        assert_eq!(small.chromosomes[..], large.chromosomes[..2]);
+        assert_eq!(small.genes[..], large.genes[..20]);
+    }
+
+    #[test]
+    fn region_synth_is_deterministic() {
+        let dir = ScratchDir::new("synth-bed").unwrap();
+        let g = genome(1);
+        RegionSynth::new(&g).count(500).seed(11).write(&dir.join("a.bed")).unwrap();
+        RegionSynth::new(&g).count(500).seed(11).write(&dir.join("b.bed")).unwrap();
+        RegionSynth::new(&g).count(500).seed(12).write(&dir.join("c.bed")).unwrap();
+        let read = |f: &str| fs::read(dir.join(f)).unwrap();
+        assert_eq!(read("a.bed"), read("b.bed"));
+        assert_ne!(read("a.bed"), read("c.bed"));
+        assert_eq!(read("a.bed").iter().filter(|&&b| b == b'\n').count(), 500);
+    }
+
+    #[cfg(any(feature = "cli", feature = "testutil"))]
+    #[test]
+    fn bam_synth_is_deterministic() {
+        let dir = ScratchDir::new("synth-bam").unwrap();
+        let g = genome(2);
+        let n = BamSynth::new(&g).depth(2.0).seed(5).write(&dir.join("a.bam")).unwrap();
+        assert_eq!(BamSynth::new(&g).depth(2.0).seed(5).write(&dir.join("b.bam")).unwrap(), n);
+        BamSynth::new(&g).depth(2.0).seed(6).write(&dir.join("c.bam")).unwrap();
+        let read = |f: &str| fs::read(dir.join(f)).unwrap();
+        assert!(n > 0);
+        assert_eq!(read("a.bam"), read("b.bam"));
+        assert_ne!(read("a.bam"), read("c.bam"));
+
+        // The records read back are the drawn placements
+        use rust_htslib::bam::Read;
+        let mut reader = bam::Reader::from_path(dir.join("a.bam")).unwrap();
+        let placements: Vec<(usize, u64, bool)> = reader
+            .records()
+            .map(|r| {
+                let r = r.unwrap();
+                (r.tid() as usize, r.pos() as u64, r.is_reverse())
+            })
+            .collect();
+        assert_eq!(placements, BamSynth::new(&g).depth(2.0).seed(5).build().unwrap());
+    }
+}
@@ -1,7 +1,42 @@
+#[macro_use]
+pub mod log;
 pub mod common;
 pub mod tree;
 pub mod tree_io;
 pub mod tree_index;
+pub mod seq_lengths;
+pub mod annotation_set;
+pub mod bed_chunks;
+pub mod utr;
+pub mod longest;
+pub mod subtree;
+pub mod stats;
+pub mod table;
+pub mod attr_edit;
+pub mod checkpoint;
+pub mod fasta;
+pub mod fsname;
 
-pub use tree::{Interval, IntervalTree};
-pub use tree_index::TreeIndexData;
\ No newline at end of file
+pub use tree::{Interval, IntervalIter, IntervalTree};
+pub use tree_index::TreeIndexData;
+pub use seq_lengths::{Clamp, SeqLengths};
+pub use annotation_set::{AnnotationSet, UnknownContigs, parse_fraction};
+pub use bed_chunks::{
+    BED_CHUNK_SIZE, BedChunks, BedpeEnd, BedpeMode, BedpeSkips, parse_bed_chunk, parse_bed_region, parse_bedpe_chunk,
+    parse_bedpe_pair, source_extension,
+};
+#[cfg(feature = "cli")]
+pub use bed_chunks::BedReader;
+pub use utr::{derive_block_utrs, write_gff_with_derived_utrs};
+pub use longest::{TranscriptMetric, select_longest_transcript, write_gff_longest_transcripts};
+pub use subtree::{block_descendants, expand_to_descendants};
+pub use stats::{Counter, CountingWriter, counter_value, peak_rss_bytes, record, report_stats, set_stats, stats_enabled};
+pub use table::{Cell, Column, Coords, TableArgs, TableFormat, TableWriter};
+pub use attr_edit::{AttrEditor, escape_attr_value};
+pub use log::{
+    RunSummary, color_default, color_enabled, report_error, report_summary, set_color, set_summary, set_summary_only,
+    summary_only,
+};
+pub use checkpoint::{Checkpoint, PlanEntry, command_line_key, write_resumable};
+pub use fasta::{BaseCounts, FaiEntry, Fasta, build_fai, parse_fai};
+pub use fsname::{MAX_NAME_BYTES, path_for, sanitize, sanitize_all, unsanitize};
@@ -0,0 +1,253 @@
+use anyhow::{Context, Result, bail};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ByteSource, GofMap, RangeSource, TreeIndexData, TypeFilter, check_gff_present, check_index_matches, fast_id,
+    load_fts, load_gof, load_prt, push_line_with_attributes, resolve_feature_roots, write_source_header,
+};
+
+/// One indexed GFF queried during a shared pass over a BAM/BED source.
+///
+/// Each set keeps its own sequence-ID map, so the same source record may map
+/// to different (or no) sequences in different annotations.
+#[derive(Debug)]
+pub struct AnnotationSet {
+    /// Label used in the `annotation` output column (the GFF file name)
+    pub name: String,
+    pub path: PathBuf,
+    pub index_data: TreeIndexData,
+    pub gof: GofMap,
+    pub gff_mmap: ByteSource,
+    /// Roots whose features are reported; `None` reports every root
+    pub root_filter: Option<FxHashSet<u32>>,
+    /// Feature types whose rows are reported (`-T`); `None` reports every type
+    pub type_filter: Option<TypeFilter>,
+}
+
+impl AnnotationSet {
+    /// Load the GOF and tree index of `gff_path` and mmap the GFF itself.
+    pub fn load(gff_path: &Path) -> Result<Self> {
+        let gof = load_gof(gff_path)?;
+        check_gff_present(gff_path)?;
+        check_index_matches(gff_path)?;
+        let gff_mmap = ByteSource::open_gff(gff_path)
+            .with_context(|| format!("Cannot open GFF file: {:?}", gff_path))?;
+        let index_data = TreeIndexData::load_tree_index(gff_path)?;
+        let name = gff_path
+            .file_name()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| gff_path.display().to_string());
+        Ok(Self {
+            name,
+            path: gff_path.to_path_buf(),
+            index_data,
+            gof,
+            gff_mmap,
+            root_filter: None,
+            type_filter: None,
+        })
+    }
+
+    /// Restrict reporting to the models containing `feature_names` (resolved via
+    /// `.fts`/`.prt` as in `extract`). Returns the number of allowed roots.
+    pub fn restrict_to_features(
+        &mut self,
+        feature_names: &FxHashSet<String>,
+        threads: usize,
+    ) -> Result<usize> {
+        let fts = load_fts(&self.path)?;
+        let prt = load_prt(&self.path)?;
+        let (_, roots) = resolve_feature_roots(&fts, &prt, feature_names, threads);
+        let allowed: FxHashSet<u32> = roots.into_iter().filter(|&r| r != u32::MAX).collect();
+        let n = allowed.len();
+        self.root_filter = Some(allowed);
+        Ok(n)
+    }
+
+    /// Whether hits on `root` should be reported.
+    #[inline]
+    pub fn allows_root(&self, root: u32) -> bool {
+        self.root_filter.as_ref().is_none_or(|f| f.contains(&root))
+    }
+
+    /// Whether features of type `ftype` (column 3) should be reported.
+    #[inline]
+    pub fn allows_type(&self, ftype: &[u8]) -> bool {
+        self.type_filter.as_ref().is_none_or(|t| t.allows(ftype))
+    }
+
+    /// Re-emit the header and every model containing a feature in `attrs` (in file
+    /// order), appending `attrs[ID]` to column 9 of each line with that ID. Feature
+    /// lines of types outside `type_filter` are left out. Returns the number of
+    /// rewritten lines.
+    pub fn write_annotated_gff<W: Write>(
+        &self,
+        attrs: &FxHashMap<String, String>,
+        out: &mut W,
+        threads: usize,
+    ) -> Result<usize> {
+        let ids: FxHashSet<String> = attrs.keys().cloned().collect();
+        let fts = load_fts(&self.path)?;
+        let prt = load_prt(&self.path)?;
+        let (_, roots) = resolve_feature_roots(&fts, &prt, &ids, threads);
+        let mut roots: Vec<u32> = roots.into_iter().filter(|&r| r != u32::MAX).collect();
+        roots.sort_unstable();
+        roots.dedup();
+
+        let (mut blocks, missing) = self.gof.roots_to_offsets(&roots, threads);
+        if !missing.is_empty() {
+            diag!(
+                "[WARN] {} of {} roots not found in the GOF index of {}; their blocks are skipped.",
+                missing.len(),
+                roots.len(),
+                self.name
+            );
+        }
+        blocks.sort_unstable_by_key(|&(_, s, _)| s);
+
+        let data = &self.gff_mmap[..];
+        let header_end = write_source_header(&RangeSource::open(&self.path)?, out)? as usize;
+        let mut rewritten = 0usize;
+        let mut buf: Vec<u8> = Vec::with_capacity(1024);
+        for &(_, s, e) in &blocks {
+            let (s, e) = ((s as usize).max(header_end), (e as usize).min(data.len()));
+            if s >= e {
+                continue;
+            }
+            let block = &data[s..e];
+            for line in block.split_inclusive(|&b| b == b'\n') {
+                let body = line.strip_suffix(b"\n").unwrap_or(line);
+                let body = body.strip_suffix(b"\r").unwrap_or(body);
+                if let Some(types) = &self.type_filter
+                    && !body.starts_with(b"#")
+                    && !types.allows_line(body)
+                {
+                    continue;
+                }
+                let tag = (!body.starts_with(b"#"))
+                    .then(|| body.iter().rposition(|&b| b == b'\t'))
+                    .flatten()
+                    .and_then(|i| std::str::from_utf8(&body[i + 1..]).ok())
+                    .and_then(fast_id)
+                    .and_then(|id| attrs.get(id));
+                match tag {
+                    Some(tag) => {
+                        buf.clear();
+                        push_line_with_attributes(&mut buf, line, tag.as_bytes());
+                        out.write_all(&buf)?;
+                        rewritten += 1;
+                    }
+                    None => out.write_all(line)?,
+                }
+            }
+        }
+        out.flush()?;
+        Ok(rewritten)
+    }
+
+    /// Map source target names (e.g. BAM header tids) to this annotation's seqid numbers.
+    pub fn map_target_names<'a, I>(&self, names: I) -> Vec<Option<u32>>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        names
+            .into_iter()
+            .map(|n| self.index_data.seqid_to_num.get(n).copied())
+            .collect()
+    }
+}
+
+/// Unknown sequences listed one by one in the report; the rest are summarised.
+const MAX_CONTIG_REPORTS: usize = 20;
+
+/// Source records (BAM reads or BED regions) on sequences that no annotation set
+/// knows, counted per sequence for the end-of-pass report (`--strict-contigs`).
+#[derive(Debug, Default)]
+pub struct UnknownContigs {
+    /// Records considered: mapped reads or parsed BED regions
+    pub total: u64,
+    skipped: FxHashMap<String, u64>,
+}
+
+impl UnknownContigs {
+    /// Count `n` records on `contig` as skipped.
+    pub fn skip(&mut self, contig: &str, n: u64) {
+        if n == 0 {
+            return;
+        }
+        match self.skipped.get_mut(contig) {
+            Some(c) => *c += n,
+            None => {
+                self.skipped.insert(contig.to_owned(), n);
+            }
+        }
+    }
+
+    pub fn n_skipped(&self) -> u64 {
+        self.skipped.values().sum()
+    }
+
+    /// Skipped share of all records (0 when there were none).
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 { 0.0 } else { self.n_skipped() as f64 / self.total as f64 }
+    }
+
+    /// Print the skipped records per sequence (most first) to stderr; `what` names
+    /// the records ("read", "BED region"). With `max_fraction` (`--strict-contigs`),
+    /// fail when a larger share of the records was skipped.
+    pub fn report(&self, what: &str, max_fraction: Option<f64>) -> Result<()> {
+        let n_skipped = self.n_skipped();
+        if n_skipped == 0 {
+            return Ok(());
+        }
+        let pct = self.fraction() * 100.0;
+        diag!(
+            "[WARN] {} of {} {}(s) ({:.2}%) lie on {} sequence(s) absent from the annotation and were not counted:",
+            n_skipped,
+            self.total,
+            what,
+            pct,
+            self.skipped.len()
+        );
+        let mut rows: Vec<(&str, u64)> = self.skipped.iter().map(|(c, &n)| (c.as_str(), n)).collect();
+        rows.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        let width = rows.iter().take(MAX_CONTIG_REPORTS).map(|r| r.0.len()).max().unwrap_or(0).max(8);
+        diag!("[WARN]   {:<width$}  {}s skipped", "sequence", what);
+        for (contig, n) in rows.iter().take(MAX_CONTIG_REPORTS) {
+            diag!("[WARN]   {:<width$}  {}", contig, n);
+        }
+        if rows.len() > MAX_CONTIG_REPORTS {
+            let rest: u64 = rows[MAX_CONTIG_REPORTS..].iter().map(|r| r.1).sum();
+            diag!(
+                "[WARN]   ... and {} more sequence(s) with {} {}(s)",
+                rows.len() - MAX_CONTIG_REPORTS,
+                rest,
+                what
+            );
+        }
+        if let Some(max) = max_fraction
+            && self.fraction() > max
+        {
+            bail!(
+                "{:.2}% of {}s lie on sequences absent from the annotation, above --strict-contigs {}",
+                pct,
+                what,
+                max
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Value parser for `--strict-contigs` and `sample --ratio`: a fraction in [0, 1].
+pub fn parse_fraction(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(r) if (0.0..=1.0).contains(&r) => Ok(r),
+        Ok(_) => Err("must be between 0 and 1".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
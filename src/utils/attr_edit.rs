@@ -0,0 +1,283 @@
+use memchr::{memchr, memchr_iter};
+use std::{borrow::Cow, ops::Range};
+
+/// One `key=value` attribute of column 9; ranges index into the line.
+#[derive(Debug, Clone)]
+struct Attr {
+    key: Range<usize>,
+    /// After the '='; `None` for a bare key without '='
+    value: Option<Range<usize>>,
+}
+
+impl Attr {
+    #[inline]
+    fn start(&self) -> usize {
+        self.key.start
+    }
+
+    #[inline]
+    fn end(&self) -> usize {
+        self.value.as_ref().map_or(self.key.end, |v| v.end)
+    }
+}
+
+/// Pending change of one parsed attribute.
+#[derive(Debug, Clone, Default)]
+struct Patch {
+    key: Option<Vec<u8>>,
+    value: Option<Vec<u8>>,
+    removed: bool,
+}
+
+/// Span-based editor of column 9 of one GFF line.
+///
+/// Attributes are located once (split on ';', keys up to the first '='); edits are
+/// recorded against those spans and spliced into the original bytes on output, so
+/// everything not edited keeps its exact bytes: other attributes, their order,
+/// percent-encodings, separators and spacing, columns 1-8 and the line terminator.
+/// Keys and values are compared and written raw (percent-encoded, as in the file);
+/// see `escape_attr_value`.
+///
+/// Lines without 9 columns and '#' lines are not editable: edits are ignored and the
+/// line is returned unchanged. An editor without edits returns the input slice itself.
+#[derive(Debug, Clone)]
+pub struct AttrEditor<'a> {
+    line: &'a [u8],
+    /// Column 9 (terminator excluded); `None` when the line is not editable
+    col9: Option<Range<usize>>,
+    attrs: Vec<Attr>,
+    patches: Vec<Patch>,
+    /// Raw `key=value;...` text added after the existing attributes
+    appended: Vec<u8>,
+}
+
+impl<'a> AttrEditor<'a> {
+    /// Locate the attributes of `line` (with or without its '\n' / '\r\n').
+    pub fn new(line: &'a [u8]) -> Self {
+        let mut body_end = line.len();
+        if line[..body_end].ends_with(b"\n") {
+            body_end -= 1;
+        }
+        if line[..body_end].ends_with(b"\r") {
+            body_end -= 1;
+        }
+        let body = &line[..body_end];
+
+        let col9 = if body.starts_with(b"#") || memchr_iter(b'\t', body).count() < 8 {
+            None
+        } else {
+            let start = body.iter().rposition(|&b| b == b'\t').map_or(0, |i| i + 1);
+            Some(start..body_end)
+        };
+
+        let mut attrs = Vec::new();
+        if let Some(col9) = &col9
+            && &line[col9.clone()] != b"."
+        {
+            let mut pos = col9.start;
+            while pos < col9.end {
+                let end = memchr(b';', &line[pos..col9.end]).map_or(col9.end, |i| pos + i);
+                let mut start = pos;
+                while start < end && line[start] == b' ' {
+                    start += 1;
+                }
+                if start < end {
+                    attrs.push(match memchr(b'=', &line[start..end]) {
+                        Some(i) => Attr { key: start..start + i, value: Some(start + i + 1..end) },
+                        None => Attr { key: start..end, value: None },
+                    });
+                }
+                pos = end + 1;
+            }
+        }
+
+        let patches = vec![Patch::default(); attrs.len()];
+        Self { line, col9, attrs, patches, appended: Vec::new() }
+    }
+
+    /// Whether the line has a column 9 that edits apply to.
+    #[inline]
+    pub fn is_editable(&self) -> bool {
+        self.col9.is_some()
+    }
+
+    /// Column 9 as read (without edits); `None` when the line is not editable.
+    pub fn attributes(&self) -> Option<&'a [u8]> {
+        self.col9.clone().map(|r| &self.line[r])
+    }
+
+    /// Original value of the first attribute named `key` (empty for a bare key).
+    pub fn get(&self, key: &[u8]) -> Option<&'a [u8]> {
+        let line = self.line;
+        self.attrs
+            .iter()
+            .find(|a| &line[a.key.clone()] == key)
+            .map(|a| a.value.clone().map_or(&line[0..0], |v| &line[v]))
+    }
+
+    /// Original (key, value) pairs in column order; the value is `None` for a bare key.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a [u8], Option<&'a [u8]>)> + '_ {
+        let line = self.line;
+        self.attrs.iter().map(move |a| (&line[a.key.clone()], a.value.clone().map(|v| &line[v])))
+    }
+
+    /// Indices of the live (not removed) attributes whose current key is `key`.
+    fn matching(&self, key: &[u8]) -> Vec<usize> {
+        (0..self.attrs.len())
+            .filter(|&i| {
+                let p = &self.patches[i];
+                !p.removed && p.key.as_deref().unwrap_or(&self.line[self.attrs[i].key.clone()]) == key
+            })
+            .collect()
+    }
+
+    /// Set the value of every attribute named `key`; when there is none, `key=value`
+    /// is appended. Returns the number of existing attributes changed.
+    pub fn set(&mut self, key: &[u8], value: &[u8]) -> usize {
+        if self.col9.is_none() {
+            return 0;
+        }
+        let hits = self.matching(key);
+        for &i in &hits {
+            let unchanged = self.attrs[i].value.clone().is_some_and(|v| &self.line[v] == value);
+            self.patches[i].value = (!unchanged).then(|| value.to_vec());
+        }
+        if hits.is_empty() {
+            self.append_pair(key, value);
+        }
+        hits.len()
+    }
+
+    /// Remove every attribute named `key` (and its separator). Returns the number removed.
+    pub fn remove(&mut self, key: &[u8]) -> usize {
+        let hits = self.matching(key);
+        for &i in &hits {
+            self.patches[i].removed = true;
+        }
+        hits.len()
+    }
+
+    /// Rename every attribute named `from` to `to`, keeping its value bytes.
+    /// Returns the number renamed.
+    pub fn rename(&mut self, from: &[u8], to: &[u8]) -> usize {
+        let hits = self.matching(from);
+        for &i in &hits {
+            let unchanged = &self.line[self.attrs[i].key.clone()] == to;
+            self.patches[i].key = (!unchanged).then(|| to.to_vec());
+        }
+        hits.len()
+    }
+
+    /// Add `key=value` after the existing attributes (no check for an existing `key`).
+    pub fn append_pair(&mut self, key: &[u8], value: &[u8]) {
+        if self.col9.is_none() {
+            return;
+        }
+        if !self.appended.is_empty() {
+            self.appended.push(b';');
+        }
+        self.appended.extend_from_slice(key);
+        self.appended.push(b'=');
+        self.appended.extend_from_slice(value);
+    }
+
+    /// Add raw attribute text (`key=value;k2=v2`) after the existing attributes.
+    pub fn append_raw(&mut self, attrs: &[u8]) {
+        if self.col9.is_none() || attrs.is_empty() {
+            return;
+        }
+        if !self.appended.is_empty() {
+            self.appended.push(b';');
+        }
+        self.appended.extend_from_slice(attrs);
+    }
+
+    /// Whether any recorded edit changes the line.
+    pub fn is_modified(&self) -> bool {
+        !self.appended.is_empty()
+            || self.patches.iter().any(|p| p.removed || p.key.is_some() || p.value.is_some())
+    }
+
+    /// The edited line; borrows the input when nothing changed.
+    pub fn finish(&self) -> Cow<'a, [u8]> {
+        if !self.is_modified() {
+            return Cow::Borrowed(self.line);
+        }
+        let mut out = Vec::with_capacity(self.line.len() + self.appended.len() + 16);
+        self.write_to(&mut out);
+        Cow::Owned(out)
+    }
+
+    /// Append the edited line to `out`.
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        let Some(col9) = self.col9.clone().filter(|_| self.is_modified()) else {
+            out.extend_from_slice(self.line);
+            return;
+        };
+        let line = self.line;
+        out.extend_from_slice(&line[..col9.start]);
+        let attrs_start = out.len();
+
+        // Kept attributes with the separator that preceded them; the first one kept
+        // takes the column's leading bytes instead
+        let lead = &line[col9.start..self.attrs.first().map_or(col9.end, Attr::start)];
+        let mut emitted = false;
+        let mut prev_end = col9.start;
+        for (a, p) in self.attrs.iter().zip(&self.patches) {
+            let gap = &line[prev_end..a.start()];
+            prev_end = a.end();
+            if p.removed {
+                continue;
+            }
+            if emitted {
+                out.extend_from_slice(gap);
+            } else {
+                out.extend_from_slice(lead);
+                emitted = true;
+            }
+            out.extend_from_slice(p.key.as_deref().unwrap_or(&line[a.key.clone()]));
+            match (&p.value, &a.value) {
+                (Some(v), _) => {
+                    out.push(b'=');
+                    out.extend_from_slice(v);
+                }
+                (None, Some(v)) => out.extend_from_slice(&line[v.start - 1..v.end]),
+                (None, None) => {}
+            }
+        }
+        if emitted {
+            out.extend_from_slice(&line[prev_end..col9.end]);
+        } else if self.attrs.is_empty() && lead != b"." {
+            // Separators only (e.g. `;`): nothing to drop
+            out.extend_from_slice(lead);
+        }
+
+        if !self.appended.is_empty() {
+            if out.len() > attrs_start && !out.ends_with(b";") {
+                out.push(b';');
+            }
+            out.extend_from_slice(&self.appended);
+        } else if out.len() == attrs_start {
+            out.push(b'.');
+        }
+        out.extend_from_slice(&line[col9.end..]);
+    }
+}
+
+/// Percent-encode the characters GFF3 reserves in attribute values
+/// (`;`, `=`, `&`, `,`, `%`, tab, newlines and other control characters).
+pub fn escape_attr_value(value: &str) -> Cow<'_, str> {
+    let reserved = |c: char| matches!(c, ';' | '=' | '&' | ',' | '%') || c.is_ascii_control();
+    if !value.contains(reserved) {
+        return Cow::Borrowed(value);
+    }
+    let mut out = String::with_capacity(value.len() + 8);
+    for c in value.chars() {
+        if reserved(c) {
+            out.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            out.push(c);
+        }
+    }
+    Cow::Owned(out)
+}
@@ -0,0 +1,273 @@
+use rayon::prelude::*;
+use std::{path::Path, str};
+#[cfg(feature = "cli")]
+use {
+    crate::ByteSource,
+    anyhow::{Context, Result},
+    flate2::read::MultiGzDecoder,
+    std::{
+        fs::File,
+        io::{BufRead, BufReader, Read},
+    },
+};
+
+/// Target size of one newline-aligned BED chunk.
+pub const BED_CHUNK_SIZE: usize = 64 * 1024 * 1024;
+
+/// Iterator over newline-aligned chunks of about `chunk_size` bytes, so a BED file
+/// can be processed in bounded memory without a per-line offset table.
+pub struct BedChunks<'a> {
+    data: &'a [u8],
+    pos: usize,
+    chunk_size: usize,
+}
+
+impl<'a> BedChunks<'a> {
+    pub fn new(data: &'a [u8], chunk_size: usize) -> Self {
+        Self { data, pos: 0, chunk_size: chunk_size.max(1) }
+    }
+}
+
+impl<'a> Iterator for BedChunks<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        if self.pos >= self.data.len() {
+            return None;
+        }
+        let start = self.pos;
+        let target = start.saturating_add(self.chunk_size);
+        let end = if target >= self.data.len() {
+            self.data.len()
+        } else {
+            // Extend to the end of the line containing `target`
+            memchr::memchr(b'\n', &self.data[target..])
+                .map(|i| target + i + 1)
+                .unwrap_or(self.data.len())
+        };
+        self.pos = end;
+        Some(&self.data[start..end])
+    }
+}
+
+/// Lowercase extension of `path`, looking past a trailing `.gz` (`x.bed.gz` -> `bed`).
+pub fn source_extension(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+    let name = name.strip_suffix(".gz").unwrap_or(&name);
+    Path::new(name).extension()?.to_str().map(str::to_owned)
+}
+
+/// A BED (or other line-based region) file: memory-mapped when plain, streamed through
+/// a multi-member gzip decoder (so BGZF works too) when it starts with the gzip magic
+/// bytes. Both variants yield the same newline-aligned chunks, so line parsing is
+/// identical for compressed and plain input.
+#[cfg(feature = "cli")]
+pub enum BedReader {
+    Plain(ByteSource),
+    /// Boxed: the decoder state is large next to a `ByteSource`
+    Gzip(Box<BufReader<MultiGzDecoder<File>>>),
+}
+
+#[cfg(feature = "cli")]
+impl BedReader {
+    const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file = File::open(path).with_context(|| format!("Cannot open {:?}", path))?;
+        let mut magic = [0u8; 2];
+        let is_gzip = file.read_exact(&mut magic).is_ok() && magic == Self::GZIP_MAGIC;
+        if is_gzip {
+            // The decoder needs the stream from the start
+            let file = File::open(path).with_context(|| format!("Cannot open {:?}", path))?;
+            Ok(BedReader::Gzip(Box::new(BufReader::new(MultiGzDecoder::new(file)))))
+        } else {
+            Ok(BedReader::Plain(ByteSource::open(path)?))
+        }
+    }
+
+    #[inline]
+    pub fn is_gzip(&self) -> bool {
+        matches!(self, BedReader::Gzip(_))
+    }
+
+    /// Call `f` on newline-aligned chunks of about `chunk_size` bytes, in file order.
+    pub fn for_each_chunk(
+        &mut self,
+        chunk_size: usize,
+        mut f: impl FnMut(&[u8]) -> Result<()>,
+    ) -> Result<()> {
+        match self {
+            BedReader::Plain(data) => {
+                for chunk in BedChunks::new(data, chunk_size) {
+                    f(chunk)?;
+                }
+            }
+            BedReader::Gzip(reader) => {
+                let mut buf = Vec::with_capacity(chunk_size.min(BED_CHUNK_SIZE));
+                loop {
+                    let n = reader
+                        .read_until(b'\n', &mut buf)
+                        .context("Failed to decompress gzip input")?;
+                    if n == 0 || buf.len() >= chunk_size {
+                        if !buf.is_empty() {
+                            f(&buf)?;
+                            buf.clear();
+                        }
+                        if n == 0 {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The first `n` lines (fewer in a shorter file), without reading the rest.
+    pub fn head(self, n: usize) -> Result<Vec<u8>> {
+        match self {
+            BedReader::Plain(data) => {
+                let end = memchr::memchr_iter(b'\n', &data).nth(n.saturating_sub(1)).map_or(data.len(), |i| i + 1);
+                Ok(if n == 0 { Vec::new() } else { data[..end].to_vec() })
+            }
+            BedReader::Gzip(mut reader) => {
+                let mut buf = Vec::new();
+                for _ in 0..n {
+                    if reader.read_until(b'\n', &mut buf).context("Failed to decompress gzip input")? == 0 {
+                        break;
+                    }
+                }
+                Ok(buf)
+            }
+        }
+    }
+
+    /// The whole (decompressed) contents, for callers that keep line offsets.
+    pub fn into_bytes(self) -> Result<ByteSource> {
+        match self {
+            BedReader::Plain(data) => Ok(data),
+            BedReader::Gzip(mut reader) => {
+                let mut buf = Vec::new();
+                reader
+                    .read_to_end(&mut buf)
+                    .context("Failed to decompress gzip input")?;
+                Ok(ByteSource::from(buf))
+            }
+        }
+    }
+}
+
+/// Parse one BED line into (chrom, start, end).
+/// Comments, lines with fewer than 3 fields and empty intervals yield `None`.
+#[inline]
+pub fn parse_bed_region(line: &[u8]) -> Option<(&str, u32, u32)> {
+    if line.is_empty() || line[0] == b'#' {
+        return None;
+    }
+    let mut fields = line
+        .split(|&b| b == b'\t' || b == b' ')
+        .filter(|f| !f.is_empty());
+    let chrom = str::from_utf8(fields.next()?).ok()?;
+    let s = str::from_utf8(fields.next()?).ok()?.parse::<u32>().ok()?;
+    let e = str::from_utf8(fields.next()?).ok()?.trim_end().parse::<u32>().ok()?;
+    if s >= e {
+        return None;
+    }
+    Some((chrom, s, e))
+}
+
+/// Parse every line of a chunk in parallel, keeping file order.
+pub fn parse_bed_chunk(chunk: &[u8]) -> Vec<(&str, u32, u32)> {
+    chunk
+        .par_split(|&b| b == b'\n')
+        .filter_map(parse_bed_region)
+        .collect()
+}
+
+/// One end of a BEDPE pair; `None` when unmapped (chrom `.`, negative or empty interval).
+pub type BedpeEnd<'a> = Option<(&'a str, u32, u32)>;
+
+/// Parse the first six fields of a BEDPE line (chrom1, start1, end1, chrom2, start2, end2).
+/// Comments, lines with fewer than 6 fields and pairs with no mapped end yield `None`.
+#[inline]
+pub fn parse_bedpe_pair(line: &[u8]) -> Option<(BedpeEnd<'_>, BedpeEnd<'_>)> {
+    if line.is_empty() || line[0] == b'#' {
+        return None;
+    }
+    let mut fields = line
+        .split(|&b| b == b'\t' || b == b' ')
+        .filter(|f| !f.is_empty());
+    let (a, b) = (bedpe_end(&mut fields)?, bedpe_end(&mut fields)?);
+    (a.is_some() || b.is_some()).then_some((a, b))
+}
+
+/// Next three fields as one BEDPE end; `None` when they are missing.
+#[inline]
+fn bedpe_end<'a>(fields: &mut impl Iterator<Item = &'a [u8]>) -> Option<BedpeEnd<'a>> {
+    let chrom = str::from_utf8(fields.next()?).ok()?;
+    let s = str::from_utf8(fields.next()?).ok()?.trim_end().parse::<u32>();
+    let e = str::from_utf8(fields.next()?).ok()?.trim_end().parse::<u32>();
+    Some(match (s, e) {
+        (Ok(s), Ok(e)) if chrom != "." && s < e => Some((chrom, s, e)),
+        _ => None,
+    })
+}
+
+/// Parse every BEDPE line of a chunk in parallel, keeping file order.
+pub fn parse_bedpe_chunk(chunk: &[u8]) -> Vec<(BedpeEnd<'_>, BedpeEnd<'_>)> {
+    chunk
+        .par_split(|&b| b == b'\n')
+        .filter_map(parse_bedpe_pair)
+        .collect()
+}
+
+/// How a BEDPE pair becomes regions (`--bedpe-mode`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum BedpeMode {
+    /// One region from the leftmost start to the rightmost end; cis pairs only
+    #[default]
+    Span,
+    /// Each end is a region, but a pair counts once per feature
+    Ends,
+}
+
+/// BEDPE pairs left out by `--bedpe-mode span`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BedpeSkips {
+    /// Ends on different sequences
+    pub trans: u64,
+    /// Only one end mapped
+    pub unpaired: u64,
+}
+
+impl BedpeMode {
+    /// Regions of one pair: the fragment span (`Span`; `[None, None]` for trans and
+    /// unpaired pairs, counted in `skips`) or the mapped ends (`Ends`).
+    #[inline]
+    pub fn regions<'a>(self, pair: (BedpeEnd<'a>, BedpeEnd<'a>), skips: &mut BedpeSkips) -> [BedpeEnd<'a>; 2] {
+        match (self, pair) {
+            (BedpeMode::Ends, (a, b)) => [a, b],
+            (BedpeMode::Span, (Some(a), Some(b))) if a.0 == b.0 => [Some((a.0, a.1.min(b.1), a.2.max(b.2))), None],
+            (BedpeMode::Span, (Some(_), Some(_))) => {
+                skips.trans += 1;
+                [None, None]
+            }
+            (BedpeMode::Span, _) => {
+                skips.unpaired += 1;
+                [None, None]
+            }
+        }
+    }
+}
+
+impl BedpeSkips {
+    /// Report skipped pairs on stderr (nothing when none were skipped).
+    pub fn report(&self) {
+        if self.trans > 0 || self.unpaired > 0 {
+            diag!(
+                "[INFO] --bedpe-mode span skipped {} trans pair(s) and {} pair(s) with one unmapped end",
+                self.trans, self.unpaired
+            );
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use anyhow::{Context, Result, bail};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{Seek, SeekFrom, Write},
+    path::Path,
+    time::UNIX_EPOCH,
+};
+
+use crate::{CountingWriter, crc32};
+
+const MAGIC: &str = "#gffx-checkpoint 1";
+
+/// Progress is committed (output synced, record appended) after this many block
+/// bytes or plan entries, whichever comes first.
+const COMMIT_BYTES: u64 = 64 * 1024 * 1024;
+const COMMIT_ENTRIES: usize = 4096;
+
+/// One unit of a resumable write: (root fid, start, end) of a GFF range, in output order.
+pub type PlanEntry = (u32, u64, u64);
+
+/// Identity of the current command line (`--checkpoint` key): CRC-32 of the
+/// arguments after the program name, NUL-joined.
+pub fn command_line_key() -> String {
+    let args: Vec<String> = std::env::args_os().skip(1).map(|a| a.to_string_lossy().into_owned()).collect();
+    format!("{:08x}", crc32(args.join("\0").as_bytes()))
+}
+
+/// Checkpoint of a resumable output (`--checkpoint FILE`).
+///
+/// The file is text: a header naming the run (argument key, GFF size and mtime), the
+/// block plan (one `fid start end` line per entry, in output order), then one
+/// `done <entries> <last root> <output bytes>` line per committed chunk. The last
+/// complete `done` line is the state to resume from; a torn last line is ignored.
+#[derive(Debug)]
+pub struct Checkpoint {
+    file: File,
+    /// Plan entries fully written to the output
+    done: usize,
+    /// Output length after those entries
+    out_len: u64,
+}
+
+impl Checkpoint {
+    /// Open the checkpoint at `path` for `plan`, or create it when absent.
+    ///
+    /// An existing checkpoint must have been written for the same arguments (`key`),
+    /// the same GFF (size and mtime) and the same plan; anything else is an error,
+    /// since resuming would splice two different outputs.
+    pub fn open(path: &Path, key: &str, gff_path: &Path, plan: &[PlanEntry]) -> Result<Self> {
+        let meta = fs::metadata(gff_path).with_context(|| format!("Cannot stat {:?}", gff_path))?;
+        let mtime = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos());
+        let key_line = format!("key\t{}", key);
+        let gff_line = format!("gff\t{}\t{}", meta.len(), mtime);
+        let plan_line = format!("plan\t{}", plan.len());
+
+        if !path.exists() {
+            let args: Vec<String> = std::env::args_os().skip(1).map(|a| a.to_string_lossy().into_owned()).collect();
+            let command = args.join(" ").replace(['\n', '\r'], " ");
+            let mut header = format!("{MAGIC}\n# command: {command}\n{key_line}\n{gff_line}\n{plan_line}\n");
+            for &(fid, s, e) in plan {
+                header.push_str(&format!("{fid}\t{s}\t{e}\n"));
+            }
+            // Header and plan appear at once, so a crash here leaves no checkpoint
+            let tmp = crate::append_suffix(path, ".tmp");
+            fs::write(&tmp, header).with_context(|| format!("Cannot write checkpoint {:?}", tmp))?;
+            File::open(&tmp)?.sync_all()?;
+            fs::rename(&tmp, path).with_context(|| format!("Cannot write checkpoint {:?}", path))?;
+            let file = OpenOptions::new().append(true).open(path)?;
+            return Ok(Self { file, done: 0, out_len: 0 });
+        }
+
+        let text = fs::read_to_string(path).with_context(|| format!("Cannot read checkpoint {:?}", path))?;
+        let mut lines = text.split_inclusive('\n');
+        let mut next = || lines.next().map(|l| l.trim_end_matches('\n'));
+        if next() != Some(MAGIC) {
+            bail!("{:?} is not a gffx checkpoint", path);
+        }
+        let mut line = next();
+        while line.is_some_and(|l| l.starts_with('#')) {
+            line = next();
+        }
+        let stale = |what: &str| {
+            anyhow::anyhow!(
+                "Checkpoint {:?} was written for {}; delete it (and the partial output) to start over",
+                path,
+                what
+            )
+        };
+        if line != Some(key_line.as_str()) {
+            return Err(stale("different arguments"));
+        }
+        if next() != Some(gff_line.as_str()) {
+            return Err(stale("a different version of the GFF file (size or modification time changed)"));
+        }
+        if next() != Some(plan_line.as_str()) {
+            return Err(stale("a different block plan (the index changed)"));
+        }
+        for &(fid, s, e) in plan {
+            if next() != Some(format!("{fid}\t{s}\t{e}").as_str()) {
+                return Err(stale("a different block plan (the index changed)"));
+            }
+        }
+
+        // Only complete lines count; a torn last record is dropped
+        let complete = &text[..text.rfind('\n').map_or(0, |i| i + 1)];
+        let (mut done, mut out_len) = (0usize, 0u64);
+        for l in complete.lines().filter(|l| l.starts_with("done\t")) {
+            let fields: Vec<&str> = l.split('\t').collect();
+            if let [_, k, _, bytes] = fields.as_slice()
+                && let (Ok(k), Ok(bytes)) = (k.parse::<usize>(), bytes.parse::<u64>())
+                && k <= plan.len()
+            {
+                (done, out_len) = (k, bytes);
+            }
+        }
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Self { file, done, out_len })
+    }
+
+    /// Plan entries already written.
+    #[inline]
+    pub fn done(&self) -> usize {
+        self.done
+    }
+
+    /// Output length after the written entries.
+    #[inline]
+    pub fn output_len(&self) -> u64 {
+        self.out_len
+    }
+
+    /// Record that the first `done` plan entries (the last with root `root`) are in
+    /// the output, which is now `out_len` bytes long. The output must be synced first.
+    pub fn record(&mut self, done: usize, root: u32, out_len: u64) -> Result<()> {
+        self.file.write_all(format!("done\t{done}\t{root}\t{out_len}\n").as_bytes())?;
+        self.file.sync_data()?;
+        self.done = done;
+        self.out_len = out_len;
+        Ok(())
+    }
+}
+
+/// Write `plan` to `output` chunk by chunk, resumable through the checkpoint at
+/// `checkpoint_path`.
+///
+/// `render` appends the output bytes of a run of consecutive plan entries to the buffer;
+/// it must depend only on the entries. After each chunk the output is synced and the
+/// checkpoint records it; on a rerun the output is cut back to the last recorded length
+/// (bytes past it belong to a chunk that was not recorded) and writing resumes with the
+/// next entry. An output shorter than recorded is an error. The checkpoint is removed
+/// once the whole plan is written.
+pub fn write_resumable(
+    output: &Path,
+    checkpoint_path: &Path,
+    key: &str,
+    gff_path: &Path,
+    plan: &[PlanEntry],
+    mut render: impl FnMut(&[PlanEntry], &mut Vec<u8>) -> Result<()>,
+    verbose: bool,
+) -> Result<()> {
+    let mut checkpoint = Checkpoint::open(checkpoint_path, key, gff_path, plan)?;
+    let start = checkpoint.done();
+
+    let file = if start == 0 && checkpoint.output_len() == 0 {
+        File::create(output).with_context(|| format!("Cannot create output: {:?}", output))?
+    } else {
+        let file = OpenOptions::new()
+            .write(true)
+            .open(output)
+            .with_context(|| format!("Cannot reopen partial output {:?} to resume", output))?;
+        let have = file.metadata()?.len();
+        let want = checkpoint.output_len();
+        if have < want {
+            bail!(
+                "Partial output {:?} has {} bytes but checkpoint {:?} records {}; it was modified since, \
+                 delete both to start over",
+                output,
+                have,
+                checkpoint_path,
+                want
+            );
+        }
+        file.set_len(want)?;
+        diag!(
+            "[INFO] Resuming {:?} after {} of {} block(s) ({} bytes already written)",
+            output,
+            start,
+            plan.len(),
+            want
+        );
+        file
+    };
+    let mut out = CountingWriter(file);
+    out.0.seek(SeekFrom::End(0))?;
+
+    let mut buf = Vec::new();
+    let mut i = start;
+    let mut chunks = 0usize;
+    while i < plan.len() {
+        let mut j = i;
+        let mut bytes = 0u64;
+        while j < plan.len() && j - i < COMMIT_ENTRIES && bytes < COMMIT_BYTES {
+            bytes += plan[j].2.saturating_sub(plan[j].1);
+            j += 1;
+        }
+        buf.clear();
+        render(&plan[i..j], &mut buf)?;
+        out.write_all(&buf)?;
+        out.0.sync_data()?;
+        checkpoint.record(j, plan[j - 1].0, checkpoint.output_len() + buf.len() as u64)?;
+        i = j;
+        chunks += 1;
+    }
+
+    drop(checkpoint);
+    fs::remove_file(checkpoint_path).with_context(|| format!("Cannot remove checkpoint {:?}", checkpoint_path))?;
+    if verbose {
+        diag!("[INFO] Wrote {} block(s) in {} checkpointed chunk(s)", plan.len() - start, chunks);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::ScratchDir;
+
+    /// 10 000 one-line entries: three commit chunks
+    fn setup(dir: &ScratchDir) -> (std::path::PathBuf, Vec<u8>, Vec<PlanEntry>) {
+        let gff = dir.join("in.gff");
+        let mut data = Vec::new();
+        let mut plan = Vec::new();
+        for i in 0..10_000u32 {
+            let start = data.len() as u64;
+            data.extend_from_slice(format!("chr1\t.\tgene\t{}\t{}\t.\t+\t.\tID=g{}\n", i + 1, i + 2, i).as_bytes());
+            plan.push((i, start, data.len() as u64));
+        }
+        fs::write(&gff, &data).unwrap();
+        (gff, data, plan)
+    }
+
+    fn copy_ranges<'a>(data: &'a [u8]) -> impl FnMut(&[PlanEntry], &mut Vec<u8>) -> Result<()> + 'a {
+        move |entries, buf| {
+            for &(_, s, e) in entries {
+                buf.extend_from_slice(&data[s as usize..e as usize]);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn resuming_after_a_failed_chunk_matches_an_uninterrupted_run() {
+        let dir = ScratchDir::new("checkpoint-resume").unwrap();
+        let (gff, data, plan) = setup(&dir);
+        let (out, ckpt) = (dir.join("out.gff"), dir.join("out.ckpt"));
+
+        let mut calls = 0;
+        let mut copy = copy_ranges(&data);
+        let failing = |entries: &[PlanEntry], buf: &mut Vec<u8>| {
+            calls += 1;
+            if calls == 2 {
+                bail!("interrupted");
+            }
+            copy(entries, buf)
+        };
+        assert!(write_resumable(&out, &ckpt, "k", &gff, &plan, failing, false).is_err());
+        let state = Checkpoint::open(&ckpt, "k", &gff, &plan).unwrap();
+        assert_eq!(state.done(), COMMIT_ENTRIES);
+        let committed = state.output_len();
+        drop(state);
+        assert_eq!(fs::read(&out).unwrap().len() as u64, committed);
+
+        // Bytes of an unrecorded chunk are cut; a torn record is ignored
+        OpenOptions::new().append(true).open(&out).unwrap().write_all(b"garbage").unwrap();
+        OpenOptions::new().append(true).open(&ckpt).unwrap().write_all(b"done\t9999\t9").unwrap();
+        write_resumable(&out, &ckpt, "k", &gff, &plan, copy_ranges(&data), false).unwrap();
+        assert_eq!(fs::read(&out).unwrap(), data);
+        assert!(!ckpt.exists());
+    }
+
+    #[test]
+    fn checkpoints_for_another_run_are_rejected() {
+        let dir = ScratchDir::new("checkpoint-stale").unwrap();
+        let (gff, _, plan) = setup(&dir);
+        let ckpt = dir.join("out.ckpt");
+        drop(Checkpoint::open(&ckpt, "k", &gff, &plan).unwrap());
+
+        let err = |key: &str, plan: &[PlanEntry]| Checkpoint::open(&ckpt, key, &gff, plan).unwrap_err().to_string();
+        assert!(err("other", &plan).contains("different arguments"));
+        assert!(err("k", &plan[1..]).contains("different block plan"));
+        let mut moved = plan.clone();
+        moved[5].2 += 1;
+        assert!(err("k", &moved).contains("different block plan"));
+
+        // An output shorter than recorded was modified after the checkpoint
+        let mut state = Checkpoint::open(&ckpt, "k", &gff, &plan).unwrap();
+        state.record(1, 0, 100).unwrap();
+        drop(state);
+        let out = dir.join("out.gff");
+        fs::write(&out, "short").unwrap();
+        let e = write_resumable(&out, &ckpt, "k", &gff, &plan, |_, _| Ok(()), false).unwrap_err();
+        assert!(e.to_string().contains("has 5 bytes"));
+
+        fs::write(&ckpt, "not a checkpoint\n").unwrap();
+        assert!(Checkpoint::open(&ckpt, "k", &gff, &plan).is_err());
+    }
+}
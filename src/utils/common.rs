@@ -1,19 +1,46 @@
-use anyhow::{Result, Context};
-use clap::{Parser, CommandFactory};
-use clap::error::ErrorKind;
-use memchr::{memchr, memmem};
-use memmap2::Mmap;
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use anyhow::{Result, Context, bail};
+use clap::Parser;
+use memchr::memchr;
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use rustc_hash::{FxHashMap, FxHashSet};
 use std::{
-    fs::File,
-    io::{BufWriter, IoSlice, Write, stdout},
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, IoSlice, Write, stdout},
     path::{Path, PathBuf},
     str,
+    sync::atomic::{AtomicBool, Ordering},
 };
 
+use crate::{
+    AttrEditor, ByteSource, DEFAULT_ID_KEY, DEFAULT_PARENT_KEY, Error, FtsMap, GofMap, IndexBuilder, Interval, PrtMap,
+    RangeSource, SeqLengths, TreeIndexData, artifact_exists, check_index_matches, index_prefix, indexed_attr_keys,
+    load_gcnt, load_id_key, load_parent_key, scan_fts, set_index_prefix,
+};
+use crate::{Counter, CountingWriter, PlanEntry, record, write_resumable};
+
 const MISSING: u64 = u64::MAX; // Set sentinel value for missing entries
 
+/// Set by `--strict-offsets`: blocks that do not fall on line boundaries are an error.
+static STRICT_OFFSETS: AtomicBool = AtomicBool::new(false);
+
+/// Set by `--coalesce-gaps`: `write_gff_output` writes short gaps of comment lines
+/// between blocks through instead of splitting the output there.
+static COALESCE_GAPS: AtomicBool = AtomicBool::new(false);
+
+/// Set by `--deterministic`: outputs whose order would otherwise follow thread
+/// scheduling or hash-map iteration are produced in a fixed order.
+static DETERMINISTIC: AtomicBool = AtomicBool::new(false);
+
+/// Cleared by `--no-header`: GFF writers start their output with the leading '#'
+/// lines of the source GFF.
+static WRITE_HEADER: AtomicBool = AtomicBool::new(true);
+
+/// Longest gap `--coalesce-gaps` writes through (cheaper than another slice).
+const COALESCE_GAP_BYTES: u64 = 64;
+
+/// Misaligned blocks reported one by one; the rest are summarised.
+const MAX_MISALIGNED_REPORTS: usize = 10;
+
 #[derive(Debug, Clone, Parser)]
 pub struct CommonArgs {
     /// Input GFF file path
@@ -25,12 +52,75 @@ pub struct CommonArgs {
     pub output: Option<PathBuf>,
 
     /// Return the entire feature group for each match (entire-group mode); default: only the matched feature (per-feature mode).
-    #[arg(short = 'e', long = "entire_group", default_value_t = false)]
+    #[arg(
+        short = 'e',
+        long = "entire-group",
+        alias = "entire_group",
+        default_value_t = false
+    )]
     pub entire_group: bool,
 
-    /// Comma-separated feature types to retain (e.g. exon,gene); only effective in feature-only mode
-    #[arg(short = 'T', long = "types", value_name = "TYPES")]
-    pub types: Option<String>,
+    /// Feature types to retain: a comma-separated list (e.g. exon,gene) or `@FILE` with one
+    /// type per line; a leading `!` excludes the listed types instead. With -e, models are
+    /// still selected whole and only their lines of these types are written (-e -T was
+    /// rejected by earlier versions)
+    #[arg(short = 'T', long = "types", value_name = "TYPES", value_parser = TypeFilter::parse)]
+    pub types: Option<TypeFilter>,
+
+    /// Emit only the root feature line (e.g. the gene row) of each matched model
+    #[arg(long = "roots-only", default_value_t = false, conflicts_with = "entire_group")]
+    pub roots_only: bool,
+
+    /// Minimum feature length (column 5 - column 4 + 1) to keep; applied per line in
+    /// feature-only mode and to the root extent otherwise. Combined with --types by AND.
+    #[arg(long = "min-length", value_name = "BP")]
+    pub min_length: Option<u32>,
+
+    /// Maximum feature length to keep (see --min-length)
+    #[arg(long = "max-length", value_name = "BP")]
+    pub max_length: Option<u32>,
+
+    /// Tag each emitted line with its source location (`gffx_src=offset:N,line:M` in column 9);
+    /// only effective in feature-only mode
+    #[arg(
+        long = "provenance",
+        value_enum,
+        value_name = "MODE",
+        conflicts_with_all = ["roots_only", "entire_group"]
+    )]
+    pub provenance: Option<Provenance>,
+
+    /// Write one BED6 row per matched model (seqid, root extent, root ID, number of
+    /// matches as score, strand), sorted by position, to FILE (`-` for stdout) instead
+    /// of GFF lines
+    #[arg(
+        long = "roots-bed",
+        value_name = "FILE",
+        conflicts_with_all = ["output", "roots_only", "entire_group", "provenance", "dry_run"]
+    )]
+    pub roots_bed: Option<PathBuf>,
+
+    /// Leave out the leading '#' lines of the input (`##gff-version 3`,
+    /// `##sequence-region`, ...) that GFF output otherwise starts with, e.g. to
+    /// concatenate outputs
+    #[arg(long = "no-header", default_value_t = false)]
+    pub no_header: bool,
+
+    /// Resolve matches and plan the output blocks, then report counts and sizes on
+    /// stderr instead of writing anything (no output file is created)
+    #[arg(long = "dry-run", default_value_t = false)]
+    pub dry_run: bool,
+
+    /// Read the index files from PREFIX (as written by `index --index-prefix`) instead
+    /// of from next to the input
+    #[arg(long = "index-prefix", value_name = "PREFIX")]
+    pub index_prefix: Option<PathBuf>,
+
+    /// Build the index first when any index file is missing or the index is stale (with
+    /// the keys an older index recorded, or the `gffx index` defaults) instead of
+    /// stopping with an error
+    #[arg(long = "auto-index", default_value_t = false)]
+    pub auto_index: bool,
 
     /// Number of threads for parallel processing
     #[arg(
@@ -66,6 +156,18 @@ impl CommonArgs {
         }
     }
 
+    /// Whether output is filtered per line (feature-only mode) rather than per block
+    #[inline]
+    pub fn feature_only(&self) -> bool {
+        !self.roots_only && !self.entire_group && self.roots_bed.is_none()
+    }
+
+    /// Feature length bounds from `--min-length` / `--max-length`
+    #[inline]
+    pub fn length_filter(&self) -> LengthFilter {
+        LengthFilter { min: self.min_length, max: self.max_length }
+    }
+
     /// Initialize rayon global thread pool
     /// - Uses `effective_threads()` to decide the number of threads
     /// - Prints info/warning if verbose mode is enabled
@@ -74,43 +176,34 @@ impl CommonArgs {
         match rayon::ThreadPoolBuilder::new().num_threads(n).build_global() {
             Ok(()) => {
                 if self.verbose {
-                    eprintln!("[INFO] rayon threads = {}", n);
+                    diag!("[INFO] rayon threads = {}", n);
                 }
             }
             Err(e) => {
                 if self.verbose {
-                    eprintln!("[WARN] rayon global pool already initialized: {e}");
+                    diag!("[WARN] rayon global pool already initialized: {e}");
                 }
             }
         }
     }
 
-    /// Post-parse hook:
-    /// - Validate argument combinations
-    /// - Print info messages
-    /// - Initialize rayon
+    /// Post-parse hook, applying the common options to process-wide state:
+    /// - Size the rayon global pool from `--threads` (see `init_rayon`)
+    /// - Turn the source header of GFF output on or off (`--no-header`)
+    /// - Point the index loaders at `--index-prefix`
+    ///
+    /// Argument combinations are checked by clap while parsing; this never fails and
+    /// keeps the `Result` for callers that chain it after parsing.
     pub fn post_parse(&self) -> Result<(), clap::Error> {
-        // Custom conflict error: entire-group mode cannot be combined with --types
-        if self.entire_group && self.types.is_some() {
-            return Err(
-                clap::Error::raw(
-                    ErrorKind::ArgumentConflict,
-                    "Full-model mode does not support filtering by feature types (-T/--types).",
-                )
-                .with_cmd(&Self::command())
-            );
-        }
-
-        // Initialize rayon after validation
         self.init_rayon();
+        set_write_header(!self.no_header);
+        set_index_prefix(&self.input, self.index_prefix.as_deref());
 
         Ok(())
     }
 
-    /// Combined parse + post-parse helper:
-    /// - Parses CLI arguments
-    /// - Runs `post_parse()`
-    /// - Exits with error if validation fails
+    /// Combined parse + post-parse helper: parses the CLI arguments (exiting with
+    /// clap's message when they are invalid), then runs `post_parse()`.
     pub fn parse_and_init() -> Self {
         let args = Self::parse();
         if let Err(e) = args.post_parse() {
@@ -126,61 +219,738 @@ pub fn append_suffix(path: &Path, suffix: &str) -> PathBuf {
     parent.join(format!("{filename}{suffix}"))
 }
 
-/// Write GFF header lines (starting with '#') to output
-/// Returns the byte position after the header
-pub fn write_gff_header<W: Write>(writer: &mut W, gff_buf: &[u8]) -> Result<usize> {
-    let mut pos = 0;
-    while pos < gff_buf.len() && gff_buf[pos] == b'#' {
-        if let Some(nl) = gff_buf[pos..].iter().position(|&b| b == b'\n') {
-            let end = pos + nl + 1;
-            writer.write_all(&gff_buf[pos..end])?;
-            pos = end;
-        } else {
-            break;
+/// Score (column 6) and phase (column 8) of a feature line.
+///
+/// '.' and invalid values (a non-numeric or non-finite score, a phase other than
+/// 0, 1 or 2) are `None` alike.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ScorePhase {
+    pub score: Option<f64>,
+    pub phase: Option<u8>,
+}
+
+impl ScorePhase {
+    pub fn parse(score: &str, phase: &str) -> Self {
+        let score = score.trim().parse::<f64>().ok().filter(|v| v.is_finite());
+        let phase = match phase.trim() {
+            "0" => Some(0),
+            "1" => Some(1),
+            "2" => Some(2),
+            _ => None,
+        };
+        Self { score, phase }
+    }
+}
+
+/// Value of attribute `key` in column 9 `attrs` (raw bytes, as in the file).
+///
+/// The column is split on ';' and each pair's key, up to its first '=' and without
+/// surrounding spaces, must equal `key` exactly: `ID=` inside another key (`gene_ID=`)
+/// or inside a value (`Note=see ID=x`) does not match. The first matching pair wins;
+/// its value has trailing spaces removed. One pass over `attrs`, however many
+/// key-like substrings it holds.
+#[inline]
+pub fn attr_value<'a>(attrs: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    let mut rest = attrs;
+    loop {
+        let (pair, tail) = match memchr(b';', rest) {
+            Some(i) => (&rest[..i], Some(&rest[i + 1..])),
+            None => (rest, None),
+        };
+        if let Some(value) = pair.trim_ascii_start().strip_prefix(key).and_then(|v| v.strip_prefix(b"=")) {
+            return Some(value.trim_ascii_end());
         }
+        rest = tail?;
     }
-    Ok(pos)
 }
 
-/// Check if all expected index files for a given GFF exist.
+/// The `ID` of a GFF attribute column (see `attr_value`), cut at the first whitespace
+/// as the index builder does.
+#[inline]
+pub fn fast_id(attrs: &str) -> Option<&str> {
+    let value = attr_value(attrs.as_bytes(), b"ID")?;
+    let end = value.iter().position(|b| b.is_ascii_whitespace()).unwrap_or(value.len());
+    std::str::from_utf8(&value[..end]).ok()
+}
+
+/// Levenshtein distance between `a` and `b`, ignoring ASCII case.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().map(|c| c.to_ascii_lowercase()).collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().map(|c| c.to_ascii_lowercase()).enumerate() {
+        let mut diag = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let next = (row[j] + 1).min(row[j + 1] + 1).min(diag + usize::from(ca != cb));
+            diag = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+/// The candidate closest to `word` (other than `word` itself) by `edit_distance`, if
+/// it is close enough to be a likely typo: at most 2 edits, or a third of the length
+/// for longer words. Ties go to the first candidate.
+pub fn did_you_mean<'a>(word: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (word.chars().count() / 3).max(2);
+    candidates
+        .into_iter()
+        .filter(|c| *c != word)
+        .map(|c| (edit_distance(word, c), c))
+        .filter(|&(d, _)| d <= limit)
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, c)| c)
+}
+
+/// Read feature IDs from a list file (one per line) or take a single ID.
+pub fn read_feature_names(
+    feature_file: Option<&Path>,
+    feature_id: Option<&str>,
+) -> Result<FxHashSet<String>> {
+    if let Some(file_path) = feature_file {
+        let file = File::open(file_path)
+            .with_context(|| format!("Cannot open feature list: {:?}", file_path))?;
+        let reader = BufReader::new(file);
+        let set = reader.lines().try_fold(
+            FxHashSet::default(),
+            |mut set, line| -> Result<FxHashSet<String>, std::io::Error> {
+                let s = line?;
+                let s = s.trim();
+                if !s.is_empty() {
+                    set.insert(s.to_owned());
+                }
+                Ok(set)
+            },
+        )?;
+        Ok(set)
+    } else if let Some(single_id) = feature_id {
+        Ok([single_id.to_owned()].into_iter().collect())
+    } else {
+        bail!("Either a feature ID or a feature list file must be specified");
+    }
+}
+
+/// Resolve feature IDs to numeric fids and their roots.
 ///
-/// Expected suffixes: `.gof`, `.fts`, `.prt`, `.sqs`, `.atn`, `.a2f`, `.rit`, `.rix`.
+/// Returns `(fids, roots)`, index-aligned; a root of `u32::MAX` marks an invalid
+/// parent chain. Unknown IDs and invalid chains are reported as warnings.
+pub fn resolve_feature_roots(
+    fts: &FtsMap,
+    prt: &PrtMap,
+    feature_names: &FxHashSet<String>,
+    threads: usize,
+) -> (Vec<u32>, Vec<u32>) {
+    // Map feature names to numeric fids
+    let (fids_set, missing) = fts.map_fnames_to_fids(feature_names, threads);
+    if !missing.is_empty() {
+        diag!("[WARN] {} feature IDs not found: {:?}", missing.len(), missing);
+    }
+
+    // Convert set to vec for alignment with roots
+    let fid_vec: Vec<u32> = fids_set.iter().copied().collect();
+    let roots_vec = fids_to_roots(prt, &fid_vec, threads);
+    (fid_vec, roots_vec)
+}
+
+/// `(fids, roots, fid -> ID)` of `resolve_feature_roots_scan`
+type ScannedRoots = (Vec<u32>, Vec<u32>, FxHashMap<u32, String>);
+
+/// `resolve_feature_roots` for small query sets (up to `FTS_SCAN_MAX` IDs): one scan
+/// of the `.fts` file instead of loading every ID into an `FtsMap`.
 ///
-/// If any are missing:
-/// - Otherwise, return `Ok(false)`.
-pub fn check_index_files_exist(gff: &PathBuf) -> Result<bool> {
-    let expected_suffixes = [
-        ".gof", ".fts", ".prt", ".sqs", ".atn", ".a2f", ".rit", ".rix",
-    ];
-    let mut missing = Vec::new();
+/// Also returns the ID of each found fid.
+pub fn resolve_feature_roots_scan(
+    gff_path: &Path,
+    prt: &PrtMap,
+    feature_names: &FxHashSet<String>,
+    threads: usize,
+) -> Result<ScannedRoots> {
+    let fts = ByteSource::open_artifact(gff_path, ".fts")?;
+    let found = scan_fts(&fts, feature_names);
+    let missing: Vec<&String> = feature_names.iter().filter(|n| !found.contains_key(n.as_str())).collect();
+    if !missing.is_empty() {
+        diag!("[WARN] {} feature IDs not found: {:?}", missing.len(), missing);
+    }
+
+    let mut fid_vec: Vec<u32> = found.values().copied().collect();
+    fid_vec.sort_unstable();
+    fid_vec.dedup();
+    let roots_vec = fids_to_roots(prt, &fid_vec, threads);
+    let ids = found.into_iter().map(|(name, fid)| (fid, name.to_string())).collect();
+    Ok((fid_vec, roots_vec, ids))
+}
+
+/// Map fids to their roots (`u32::MAX` = invalid), reporting invalid fids once.
+fn fids_to_roots(prt: &PrtMap, fid_vec: &[u32], threads: usize) -> Vec<u32> {
+    // Use PrtMap fast resolver to map fid -> root (u32::MAX = invalid)
+    let roots_vec: Vec<u32> = prt.map_fids_to_roots(fid_vec, threads);
+
+    // Collect invalid fids (print once)
+    let mut invalid_fids: Vec<u32> = fid_vec.iter()
+        .zip(roots_vec.iter())
+        .filter_map(|(&fid, &r)| if r == u32::MAX { Some(fid) } else { None })
+        .collect();
+    invalid_fids.sort_unstable();
+    invalid_fids.dedup();
+    if !invalid_fids.is_empty() {
+        diag!(
+            "[WARN] {} numeric feature IDs are invalid (out-of-range child or parent), skipped: {:?}",
+            invalid_fids.len(), invalid_fids
+        );
+    }
+    roots_vec
+}
+
+/// Inclusive feature length bounds (`--min-length` / `--max-length`).
+/// Length is `end - start + 1` in GFF (1-based, inclusive) coordinates.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LengthFilter {
+    pub min: Option<u32>,
+    pub max: Option<u32>,
+}
+
+impl LengthFilter {
+    #[inline]
+    pub fn is_active(&self) -> bool {
+        self.min.is_some() || self.max.is_some()
+    }
+
+    #[inline]
+    pub fn accepts(&self, len: u32) -> bool {
+        self.min.is_none_or(|m| len >= m) && self.max.is_none_or(|m| len <= m)
+    }
+
+    /// Check columns 4-5 of a GFF line; lines without parsable coordinates are
+    /// rejected while the filter is active.
+    pub fn accepts_line(&self, line: &[u8]) -> bool {
+        if !self.is_active() {
+            return true;
+        }
+        let mut cols = line.split(|&b| b == b'\t').skip(3);
+        let (Some(s), Some(e)) = (cols.next(), cols.next()) else {
+            return false;
+        };
+        let parse = |b: &[u8]| str::from_utf8(b).ok()?.trim().parse::<u32>().ok();
+        let (Some(s), Some(e)) = (parse(s), parse(e)) else {
+            return false;
+        };
+        self.accepts(s.abs_diff(e) + 1)
+    }
+
+    /// Keep only blocks whose root (first feature line) passes the filter.
+    pub fn filter_blocks(
+        &self,
+        gff_path: &Path,
+        blocks: Vec<(u32, u64, u64)>,
+    ) -> Result<Vec<(u32, u64, u64)>> {
+        if !self.is_active() {
+            return Ok(blocks);
+        }
+        let gff = RangeSource::open(gff_path)?;
+        let file_len = gff.len();
+        let mut kept = Vec::with_capacity(blocks.len());
+        for (root, s, e) in blocks {
+            if s == MISSING || s >= e || e > file_len {
+                continue;
+            }
+            let window = gff.range(s, e)?;
+            if window
+                .split(|&b| b == b'\n')
+                .find(|l| !l.is_empty() && l[0] != b'#')
+                .is_some_and(|l| self.accepts_line(l))
+            {
+                kept.push((root, s, e));
+            }
+        }
+        Ok(kept)
+    }
+}
+
+/// Feature type (column 3) filter parsed from `--types`.
+///
+/// Accepts `a,b,c` or `@FILE` (one type per line, `#` comments allowed). A leading
+/// `!` turns the list into an exclusion list (`!exon,CDS`, `!@FILE`); mixing
+/// excluded and included types is rejected.
+#[derive(Debug, Clone)]
+pub struct TypeFilter {
+    types: FxHashSet<Vec<u8>>,
+    negated: bool,
+}
+
+impl TypeFilter {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        let (negated, list) = match spec.strip_prefix('!') {
+            Some(rest) => (true, rest.trim_start()),
+            None => (false, spec),
+        };
+        let items: Vec<String> = match list.strip_prefix('@') {
+            Some(path) => {
+                let reader = BufReader::new(
+                    File::open(path).with_context(|| format!("Cannot open types file: {}", path))?,
+                );
+                let mut items = Vec::new();
+                for line in reader.lines() {
+                    let line = line.with_context(|| format!("Failed to read types file: {}", path))?;
+                    let t = line.trim();
+                    if !t.is_empty() && !t.starts_with('#') {
+                        items.push(t.to_string());
+                    }
+                }
+                items
+            }
+            None => list
+                .split(',')
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect(),
+        };
+        if items.iter().any(|t| t.starts_with('!')) {
+            bail!(
+                "Cannot mix excluded ('!') and included types in '{}'; prefix the whole list with '!' to exclude",
+                spec
+            );
+        }
+        if items.is_empty() {
+            bail!("No feature types given in '{}'", spec);
+        }
+        Ok(Self { types: items.into_iter().map(String::into_bytes).collect(), negated })
+    }
+
+    /// Whether a feature of type `ty` passes the filter.
+    #[inline]
+    pub fn allows(&self, ty: &[u8]) -> bool {
+        self.types.contains(ty) != self.negated
+    }
+
+    /// Check column 3 of a GFF line; lines with fewer than 4 columns are rejected.
+    #[inline]
+    pub fn allows_line(&self, line: &[u8]) -> bool {
+        let mut cols = line.splitn(4, |&b| b == b'\t').skip(2);
+        match (cols.next(), cols.next()) {
+            (Some(ty), Some(_)) => self.allows(ty),
+            _ => false,
+        }
+    }
+}
+
+/// Which source location to record in the `gffx_src` attribute
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Provenance {
+    /// Byte offset of the line in the source GFF
+    Offsets,
+    /// 1-based line number in the source GFF
+    Lines,
+    /// Both offset and line number
+    Both,
+}
+
+impl Provenance {
+    #[inline]
+    pub fn needs_lines(self) -> bool {
+        matches!(self, Provenance::Lines | Provenance::Both)
+    }
+}
+
+/// Layout of per-feature metric output (`depth`, `coverage`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// Tab-separated table with a header line
+    Tsv,
+    /// One BED row per feature, metric in the score column
+    Bed,
+    /// The matched GFF models with the metric added to column 9 of each feature line
+    Gff,
+}
+
+/// What `--dry-run` reports in place of writing output.
+pub struct DryRunPlan<'a> {
+    /// What the input terms are, e.g. "feature ID(s)"
+    pub term_kind: &'a str,
+    pub terms: usize,
+    pub matched_terms: usize,
+    pub roots: usize,
+}
+
+impl DryRunPlan<'_> {
+    /// Print the plan for `blocks` to stderr only, as `[INFO]` diagnostics (dropped
+    /// under `--summary-only`). Block bytes are the size of the
+    /// whole-block output and an upper bound in per-feature mode; so is the feature
+    /// line count, taken from `.gcnt` when the index has it. With
+    /// `types_filter` the retained lines are also counted per type, which is the
+    /// only case that reads the GFF.
+    pub fn report(
+        &self,
+        gff_path: &Path,
+        blocks: &[(u32, u64, u64)],
+        types_filter: Option<&TypeFilter>,
+    ) -> Result<()> {
+        let mut spans: Vec<(u64, u64)> = blocks
+            .iter()
+            .filter(|&&(_, s, e)| s != MISSING && s < e)
+            .map(|&(_, s, e)| (s, e))
+            .collect();
+        spans.sort_unstable();
+        spans.dedup();
+        let bytes: u64 = spans.iter().map(|&(s, e)| e - s).sum();
+
+        diag!(
+            "[INFO] Dry run: {} of {} {} matched",
+            self.matched_terms, self.terms, self.term_kind
+        );
+        diag!("[INFO] Dry run: {} root(s), {} block(s), {} block byte(s)", self.roots, spans.len(), bytes);
+        if let Some(counts) = load_gcnt(gff_path)? {
+            let counts: FxHashMap<u32, u32> = counts.into_iter().rev().collect();
+            let mut roots: Vec<u32> = blocks.iter().filter(|&&(_, s, e)| s != MISSING && s < e).map(|b| b.0).collect();
+            roots.sort_unstable();
+            roots.dedup();
+            let features: u64 = roots.iter().filter_map(|r| counts.get(r)).map(|&n| u64::from(n)).sum();
+            diag!("[INFO] Dry run: {} feature line(s) in those blocks (upper bound in per-feature mode)", features);
+        }
+
+        let Some(types) = types_filter else {
+            return Ok(());
+        };
+        let gff = RangeSource::open(gff_path)?;
+        let per_block = spans
+            .par_iter()
+            .map(|&(s, e)| {
+                let block = gff.range(s, e)?;
+                let mut counts: FxHashMap<Vec<u8>, u64> = FxHashMap::default();
+                for line in block.split(|&b| b == b'\n') {
+                    let line = line.strip_suffix(b"\r").unwrap_or(line);
+                    if line.is_empty() || line[0] == b'#' || !types.allows_line(line) {
+                        continue;
+                    }
+                    if let Some(ftype) = line.split(|&b| b == b'\t').nth(2) {
+                        *counts.entry(ftype.to_vec()).or_default() += 1;
+                    }
+                }
+                Ok(counts)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut totals: FxHashMap<Vec<u8>, u64> = FxHashMap::default();
+        for counts in per_block {
+            for (ftype, n) in counts {
+                *totals.entry(ftype).or_default() += n;
+            }
+        }
+        let mut totals: Vec<(Vec<u8>, u64)> = totals.into_iter().collect();
+        totals.sort_unstable();
+        diag!("[INFO] Dry run: lines passing --types (upper bound in per-feature mode):");
+        for (ftype, n) in totals {
+            diag!("[INFO]   {}\t{}", String::from_utf8_lossy(&ftype), n);
+        }
+        Ok(())
+    }
+}
+
+/// Sparse newline index: cumulative newline counts at fixed byte strides,
+/// so line numbers can be computed without rescanning the whole file.
+pub struct LineIndex<'a> {
+    src: &'a RangeSource,
+    /// Newlines before each stride
+    cum: Vec<u64>,
+}
+
+impl<'a> LineIndex<'a> {
+    const STRIDE: u64 = 64 * 1024;
+
+    pub fn new(src: &'a RangeSource) -> Result<Self> {
+        let counts: Vec<u64> = (0..src.len().div_ceil(Self::STRIDE))
+            .into_par_iter()
+            .map(|i| {
+                let chunk = src.range(i * Self::STRIDE, (i + 1) * Self::STRIDE)?;
+                Ok(memchr::memchr_iter(b'\n', &chunk).count() as u64)
+            })
+            .collect::<Result<_>>()?;
+        let mut cum = Vec::with_capacity(counts.len() + 1);
+        let mut acc = 0u64;
+        cum.push(0);
+        for c in counts {
+            acc += c;
+            cum.push(acc);
+        }
+        Ok(Self { src, cum })
+    }
+
+    /// 1-based line number of the line containing byte `offset`.
+    pub fn line_of(&self, offset: u64) -> Result<u64> {
+        let offset = offset.min(self.src.len());
+        let chunk = offset / Self::STRIDE;
+        let head = self.src.range(chunk * Self::STRIDE, offset)?;
+        Ok(self.cum[chunk as usize] + memchr::memchr_iter(b'\n', &head).count() as u64 + 1)
+    }
+}
+
+/// Append `line` (as read from the GFF, with or without '\n') to `out`, adding
+/// `gffx_src=offset:<N>,line:<M>` to column 9. `offset` is the absolute byte offset
+/// of the line; `lines` is required when `prov` needs line numbers.
+pub fn push_line_with_provenance(
+    out: &mut Vec<u8>,
+    line: &[u8],
+    offset: u64,
+    lines: Option<&LineIndex>,
+    prov: Provenance,
+) -> Result<()> {
+    let mut tag = String::from("gffx_src=");
+    if matches!(prov, Provenance::Offsets | Provenance::Both) {
+        tag.push_str(&format!("offset:{offset}"));
+    }
+    if prov.needs_lines() && let Some(idx) = lines {
+        if prov == Provenance::Both {
+            tag.push(',');
+        }
+        tag.push_str(&format!("line:{}", idx.line_of(offset)?));
+    }
+    push_line_with_attributes(out, line, tag.as_bytes());
+    Ok(())
+}
+
+/// Append `line` (with or without '\n') to `out` with `attrs` (e.g. `key=value;k2=v2`)
+/// added to column 9, splicing through `AttrEditor` so the rest of the line keeps its
+/// bytes (including a '\r\n' terminator). Lines with fewer than 9 columns are copied
+/// unchanged.
+pub fn push_line_with_attributes(out: &mut Vec<u8>, line: &[u8], attrs_to_add: &[u8]) {
+    let mut editor = AttrEditor::new(line);
+    editor.append_raw(attrs_to_add);
+    editor.write_to(out);
+    if !line.ends_with(b"\n") {
+        out.push(b'\n');
+    }
+}
+
+/// Length of the leading run of '#' lines (directives and comments) of a GFF.
+pub fn gff_header_len(gff: &RangeSource) -> Result<u64> {
+    let mut pos = 0;
+    while pos < gff.len() && gff.range(pos, pos + 1)?[0] == b'#' {
+        pos = line_end_at_or_after(gff, pos)?;
+    }
+    Ok(pos)
+}
 
-    for ext in &expected_suffixes {
-        let path = append_suffix(gff, ext);
-        if !path.exists() {
-            missing.push(ext.to_string());
+/// Write the leading '#' lines of `gff` (`##gff-version`, `##sequence-region`, ...) to
+/// `writer`, unless `set_write_header(false)`. Returns the offset where they end (0 when
+/// not written); writers start their blocks there, so the header is never repeated.
+pub fn write_source_header<W: Write>(gff: &RangeSource, writer: &mut W) -> Result<u64> {
+    if !write_header_enabled() {
+        return Ok(0);
+    }
+    let len = gff_header_len(gff)?;
+    gff.copy_range(0, len, writer)?;
+    Ok(len)
+}
+
+/// Make `align_blocks_to_lines` fail instead of repairing misaligned blocks.
+pub fn set_strict_offsets(strict: bool) {
+    STRICT_OFFSETS.store(strict, Ordering::Relaxed);
+}
+
+/// Let `write_gff_output` join blocks separated by fewer than 64 bytes of whole `#`
+/// comment lines; the output then includes those comment lines.
+pub fn set_coalesce_gaps(enabled: bool) {
+    COALESCE_GAPS.store(enabled, Ordering::Relaxed);
+}
+
+/// Let the GFF writers start their output with the source's leading '#' lines (see
+/// `write_source_header`); on by default, off with `--no-header`.
+pub fn set_write_header(enabled: bool) {
+    WRITE_HEADER.store(enabled, Ordering::Relaxed);
+}
+
+#[inline]
+pub fn write_header_enabled() -> bool {
+    WRITE_HEADER.load(Ordering::Relaxed)
+}
+
+/// Make every output byte-identical across thread counts and runs: per-root results
+/// are merged in root order, table rows are sorted, and `sample` uses a fixed seed.
+pub fn set_deterministic(enabled: bool) {
+    DETERMINISTIC.store(enabled, Ordering::Relaxed);
+}
+
+#[inline]
+pub fn deterministic() -> bool {
+    DETERMINISTIC.load(Ordering::Relaxed)
+}
+
+/// Offset just after the last '\n' before `pos` (0 if there is none).
+fn line_start_at_or_before(gff: &RangeSource, pos: u64) -> Result<u64> {
+    const CHUNK: u64 = 4096;
+    let mut hi = pos;
+    while hi > 0 {
+        let lo = hi.saturating_sub(CHUNK);
+        if let Some(i) = memchr::memrchr(b'\n', &gff.range(lo, hi)?) {
+            return Ok(lo + i as u64 + 1);
+        }
+        hi = lo;
+    }
+    Ok(0)
+}
+
+/// Offset just after the first '\n' at or after `pos` (file length if there is none).
+fn line_end_at_or_after(gff: &RangeSource, pos: u64) -> Result<u64> {
+    const CHUNK: u64 = 4096;
+    let mut lo = pos;
+    while lo < gff.len() {
+        let hi = (lo + CHUNK).min(gff.len());
+        if let Some(i) = memchr(b'\n', &gff.range(lo, hi)?) {
+            return Ok(lo + i as u64 + 1);
         }
+        lo = hi;
     }
+    Ok(gff.len())
+}
 
+/// Guard against GOF offsets that no longer fall on line boundaries (e.g. the GFF was
+/// edited after indexing): every block must start at 0 or after a '\n' and end with a
+/// '\n' or at end of file. Misaligned blocks are widened to whole lines with a warning
+/// naming the root, or rejected under `--strict-offsets`. Blocks with the missing
+/// sentinel or beyond the end of the file are passed through for the caller to handle.
+pub fn align_blocks_to_lines(gff: &RangeSource, blocks: &[(u32, u64, u64)]) -> Result<Vec<(u32, u64, u64)>> {
+    align_blocks(gff, blocks, STRICT_OFFSETS.load(Ordering::Relaxed))
+}
+
+/// `align_blocks_to_lines`, failing on a misaligned block when `strict`.
+fn align_blocks(gff: &RangeSource, blocks: &[(u32, u64, u64)], strict: bool) -> Result<Vec<(u32, u64, u64)>> {
+    let file_len = gff.len();
+    let byte_at = |pos: u64| -> Result<u8> { Ok(gff.range(pos, pos + 1)?[0]) };
+
+    let mut out = Vec::with_capacity(blocks.len());
+    let mut misaligned = 0usize;
+    for &(root, s, e) in blocks {
+        if s == MISSING || s >= e || e > file_len {
+            out.push((root, s, e));
+            continue;
+        }
+        let start_ok = s == 0 || byte_at(s - 1)? == b'\n';
+        let end_ok = e == file_len || byte_at(e - 1)? == b'\n';
+        if start_ok && end_ok {
+            out.push((root, s, e));
+            continue;
+        }
+        if strict {
+            bail!(
+                "Block of root fid={} ({}..{}) does not fall on line boundaries; the GFF may have changed since indexing (rebuild with `gffx index`)",
+                root,
+                s,
+                e
+            );
+        }
+        let ns = if start_ok { s } else { line_start_at_or_before(gff, s)? };
+        let ne = if end_ok { e } else { line_end_at_or_after(gff, e)? };
+        misaligned += 1;
+        record(Counter::MisalignedBlocks, 1);
+        if misaligned <= MAX_MISALIGNED_REPORTS {
+            diag!(
+                "[WARN] Block of root fid={} ({}..{}) is not line-aligned; widened to {}..{}",
+                root, s, e, ns, ne
+            );
+        }
+        out.push((root, ns, ne));
+    }
+    if misaligned > MAX_MISALIGNED_REPORTS {
+        diag!("[WARN]   ... and {} more", misaligned - MAX_MISALIGNED_REPORTS);
+    }
+    if misaligned > 0 {
+        diag!(
+            "[WARN] {} block(s) widened to whole lines; the GFF may have changed since indexing (rebuild with `gffx index`)",
+            misaligned
+        );
+    }
+    Ok(out)
+}
+
+/// Index files the query commands need: `.gof`, `.fts`, `.prt`, `.sqs`, `.atn`, `.a2f`
+/// and `.rit` (plus the `.rix` of a legacy `.rit`, checked when the trees are loaded).
+const REQUIRED_INDEX_SUFFIXES: [&str; 7] = [".gof", ".fts", ".prt", ".sqs", ".atn", ".a2f", ".rit"];
+
+/// Suffixes of the required index files of `gff` found neither as loose files nor in
+/// a `.gxi` bundle (under its `--index-prefix`, when set); empty for a complete index.
+pub fn missing_index_files(gff: &Path) -> Vec<&'static str> {
+    REQUIRED_INDEX_SUFFIXES.into_iter().filter(|ext| !artifact_exists(gff, ext)).collect()
+}
+
+/// Check if all expected index files for a given GFF exist (see `missing_index_files`).
+///
+/// Returns `Ok(false)` and lists the missing suffixes on stderr if any are missing.
+pub fn check_index_files_exist(gff: &Path) -> Result<bool> {
+    let missing = missing_index_files(gff);
     if !missing.is_empty() {
-        eprintln!("Missing index file(s): {:?}", missing);
+        diag!("Missing index file(s): {:?}", missing);
         Ok(false)
     } else {
         Ok(true)
     }
 }
 
+/// Make sure the index of `gff` is complete and current before a query runs.
+///
+/// A missing index file, or a `.meta` recording another size or content hash than the
+/// GFF on disk (`check_index_matches`; e.g. the GFF was edited after indexing), stops
+/// the run with the command to fix it. With `auto_index` the index is rebuilt instead
+/// (after a one-line notice) with the attribute, ID and Parent keys still recorded in
+/// it, or the `gffx index` defaults.
+pub fn ensure_index(gff: &Path, auto_index: bool, verbose: bool) -> Result<()> {
+    let missing = missing_index_files(gff);
+    let prefix = index_prefix(gff);
+    let reason = if missing.is_empty() {
+        match check_index_matches(gff) {
+            Err(Error::IndexMismatch { built_for, detail, .. }) if auto_index => {
+                format!("stale (built for {}: {})", built_for, detail)
+            }
+            checked => return checked.map_err(Into::into),
+        }
+    } else if !auto_index {
+        let prefix_arg = prefix.map(|p| format!(" --index-prefix {}", p.display())).unwrap_or_default();
+        bail!(
+            "Index of {} is incomplete (missing {}); run `gffx index -i {}{}` first, or pass --auto-index",
+            gff.display(),
+            missing.join(", "),
+            gff.display(),
+            prefix_arg
+        );
+    } else {
+        format!("incomplete (missing {})", missing.join(", "))
+    };
+
+    // Whatever survives of the old index tells which keys it was built with
+    let attr_keys = indexed_attr_keys(gff).unwrap_or_default();
+    let attr_key = if attr_keys.is_empty() { "gene_name".to_string() } else { attr_keys.join(",") };
+    let id_key = load_id_key(gff).unwrap_or_else(|_| DEFAULT_ID_KEY.to_string());
+    let parent_key = load_parent_key(gff).unwrap_or_else(|_| DEFAULT_PARENT_KEY.to_string());
+    diag!(
+        "[INFO] Index of {} is {}; building it with -a {}{}",
+        gff.display(),
+        reason,
+        attr_key,
+        prefix.map(|p| format!(" under {}", p.display())).unwrap_or_default()
+    );
+    IndexBuilder::new()
+        .attr_key(&attr_key)
+        .id_keys(&id_key, &parent_key)
+        .verbose(verbose)
+        .build(gff)
+        .with_context(|| format!("--auto-index: failed to index {}", gff.display()))?;
+    Ok(())
+}
+
 /// Write selected byte ranges ("blocks") of a GFF file to an output file or stdout.
 ///
 /// Features:
-/// - Uses memory-mapped I/O for efficiency.
-/// - Merges adjacent or overlapping ranges before writing.
-/// - Uses vectored I/O (`write_vectored`) to minimize syscalls.
+/// - Uses memory-mapped I/O for efficiency (chunked positioned reads when mmap is
+///   disabled or fails, see `RangeSource`).
+/// - Merges adjacent or overlapping ranges in one sorted pass while writing, without
+///   building a merged list; with `--coalesce-gaps` (`set_coalesce_gaps`) ranges
+///   separated by under 64 bytes of whole `#` comment lines are joined too.
+/// - Uses vectored I/O (`write_vectored`) in batches of 1024 slices to minimize syscalls.
+/// - Starts with the leading '#' lines of the GFF (`write_source_header`).
 ///
 /// # Arguments
 /// - `gff_path`: Path to the source GFF file.
 /// - `blocks`: A list of `(start, end)` byte ranges to extract.
 /// - `output_path`: Output file path. If `None`, writes to stdout.
-/// - `_allowed_types`: Reserved for future filtering by feature type (currently unused).
 /// - `verbose`: Whether to print diagnostic output.
 ///
 /// # Errors
@@ -191,272 +961,551 @@ pub fn write_gff_output(
     output_path: &Option<std::path::PathBuf>,
     verbose: bool,
 ) -> Result<()> {
-    let file = File::open(gff_path)?;
-    let mmap = unsafe { Mmap::map(&file)? };
-    let file_len = mmap.len();
-
-    // sort and merge blocks
-    let mut sorted: Vec<(u64, u64)> = {
-        let mut v = Vec::with_capacity(blocks.len());
-        for &(fid, s, e) in blocks {
-            if s == MISSING {
-                eprintln!("[WARN] skipped fid={} due to sentinel start offset", fid);
-                continue;
-            }
-            v.push((s, e));
+    let gff = RangeSource::open(gff_path)?;
+    let file_len = gff.len();
+    let mut sorted = align_blocks_to_lines(&gff, blocks)?;
+    sorted.retain(|&(fid, s, _)| {
+        if s == MISSING {
+            diag!("[WARN] skipped fid={} due to sentinel start offset", fid);
+        }
+        s != MISSING
+    });
+    sorted.sort_unstable_by_key(|&(_, s, _)| s);
+
+    let mut writer: Box<dyn Write> = match output_path {
+        Some(p) => Box::new(BufWriter::new(CountingWriter(File::create(p)?))),
+        None => Box::new(BufWriter::new(CountingWriter(stdout()))),
+    };
+    let header_end = write_source_header(&gff, &mut writer)?;
+
+    // Merged on the fly: the sorted blocks are the only per-block allocation
+    let coalesce = COALESCE_GAPS.load(Ordering::Relaxed);
+    let gap_is_comments = |ce: u64, s: u64| -> Result<bool> {
+        Ok(coalesce && s - ce < COALESCE_GAP_BYTES && is_comment_lines(&gff.range(ce, s)?))
+    };
+    let merged = MergedRanges::new(sorted.iter().map(|&(_, s, e)| (s.max(header_end), e)), gap_is_comments)
+        .filter(|r| r.as_ref().map_or(true, |&(so, eo)| so < eo && eo <= file_len));
+    let mut n_merged = 0usize;
+
+    // Without a mapping, stream each range through positioned reads
+    let Some(mmap) = gff.as_mapped() else {
+        for range in merged {
+            let (so, eo) = range?;
+            gff.copy_range(so, eo, &mut writer)?;
+            n_merged += 1;
+        }
+        writer.flush()?;
+        if verbose {
+            diag!("Wrote {} merged GFF block(s) with buffered reads", n_merged);
         }
-        v
+        return Ok(());
     };
-    sorted.sort_unstable_by_key(|&(s, _)| s);
-
-    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(sorted.len());
-    let mut it = sorted.into_iter();
-    if let Some((mut cs, mut ce)) = it.next() {
-        for (s, e) in it {
-            if s <= ce {
-                ce = ce.max(e);
-            } else {
-                if cs < ce {
-                    merged.push((cs, ce));
+
+    // Write in batches of at most MAX_IOV slices
+    const MAX_IOV: usize = 1024;
+    let mut batch: Vec<IoSlice<'_>> = Vec::with_capacity(MAX_IOV);
+    for range in merged {
+        let (so, eo) = range?;
+        batch.push(IoSlice::new(&mmap[so as usize..eo as usize]));
+        n_merged += 1;
+        if batch.len() == MAX_IOV {
+            write_all_vectored(&mut writer, &batch)?;
+            batch.clear();
+        }
+    }
+    write_all_vectored(&mut writer, &batch)?;
+    writer.flush()?;
+
+    if verbose {
+        diag!(
+            "Wrote {} merged GFF block(s) with vectored I/O",
+            n_merged
+        );
+    }
+    Ok(())
+}
+
+/// `write_vectored` the whole batch, finishing partial writes with `write_all`.
+fn write_all_vectored<W: Write + ?Sized>(writer: &mut W, batch: &[IoSlice<'_>]) -> Result<()> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    let mut remaining = writer.write_vectored(batch)?;
+    let mut i = 0;
+    while i < batch.len() && remaining >= batch[i].len() {
+        remaining -= batch[i].len();
+        i += 1;
+    }
+    if i < batch.len() && remaining > 0 {
+        writer.write_all(&batch[i][remaining..])?;
+        i += 1;
+    }
+    for s in &batch[i..] {
+        writer.write_all(s)?;
+    }
+    Ok(())
+}
+
+/// Whether `bytes` is one or more whole `#` comment lines (not `##` directives).
+fn is_comment_lines(bytes: &[u8]) -> bool {
+    bytes.ends_with(b"\n")
+        && bytes
+            .split_inclusive(|&b| b == b'\n')
+            .all(|l| l.starts_with(b"#") && !l.starts_with(b"##"))
+}
+
+/// Ranges sorted by start, merged as they are read: a range overlapping or touching the
+/// current one extends it, and so does one after a gap that `join_gap(end, start)`
+/// accepts. Empty merged ranges are skipped.
+struct MergedRanges<I, F> {
+    ranges: I,
+    next: Option<(u64, u64)>,
+    join_gap: F,
+}
+
+impl<I, F> MergedRanges<I, F>
+where
+    I: Iterator<Item = (u64, u64)>,
+    F: FnMut(u64, u64) -> Result<bool>,
+{
+    fn new(mut ranges: I, join_gap: F) -> Self {
+        let next = ranges.next();
+        Self { ranges, next, join_gap }
+    }
+}
+
+impl<I, F> Iterator for MergedRanges<I, F>
+where
+    I: Iterator<Item = (u64, u64)>,
+    F: FnMut(u64, u64) -> Result<bool>,
+{
+    type Item = Result<(u64, u64)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (cs, mut ce) = self.next.take()?;
+            for (s, e) in self.ranges.by_ref() {
+                let join = s <= ce
+                    || match (self.join_gap)(ce, s) {
+                        Ok(j) => j,
+                        Err(err) => return Some(Err(err)),
+                    };
+                if join {
+                    ce = ce.max(e);
+                } else {
+                    self.next = Some((s, e));
+                    break;
                 }
-                cs = s;
-                ce = e;
             }
-        }
-        if cs < ce {
-            merged.push((cs, ce));
+            if cs < ce {
+                return Some(Ok((cs, ce)));
+            }
         }
     }
+}
 
-    // build IoSlice list
-    let mut slices: Vec<IoSlice<'_>> = Vec::with_capacity(merged.len());
-    for &(so, eo) in &merged {
-        if so >= eo {
+/// Write only the root line of each model (see `root_lines`).
+///
+/// Models are emitted in file order. When `types_filter` is given, root lines whose
+/// type (column 3) it rejects are skipped.
+pub fn write_gff_roots_only(
+    gff_path: &Path,
+    blocks: &[(u32, u64, u64)],
+    output_path: &Option<PathBuf>,
+    types_filter: Option<&TypeFilter>,
+    verbose: bool,
+) -> Result<()> {
+    let gff = RangeSource::open(gff_path)?;
+    let blocks = &align_blocks_to_lines(&gff, blocks)?;
+
+    let raw: Box<dyn Write> = match output_path {
+        Some(p) => Box::new(CountingWriter(File::create(p).with_context(|| format!("Cannot create output: {:?}", p))?)),
+        None => Box::new(CountingWriter(stdout())),
+    };
+    let mut writer = BufWriter::new(raw);
+    write_source_header(&gff, &mut writer)?;
+    let mut written = 0usize;
+
+    for (_, _, line) in root_lines(&gff, blocks, &load_parent_key(gff_path)?)? {
+        if types_filter.is_some_and(|t| !t.allows_line(&line)) {
             continue;
         }
-        let (start, end) = (so as usize, eo as usize);
-        if end > file_len {
-            continue;
+        writer.write_all(&line)?;
+        if !line.ends_with(b"\n") {
+            writer.write_all(b"\n")?;
         }
-        slices.push(IoSlice::new(&mmap[start..end]));
+        written += 1;
     }
+    writer.flush()?;
 
-    // Write in batches
-    let mut writer: Box<dyn Write> = match output_path {
-        Some(p) => Box::new(BufWriter::new(File::create(p)?)),
-        None => Box::new(BufWriter::new(stdout())),
-    };
-
-    const MAX_IOV: usize = 1024;
-    let mut base = 0;
-    while base < slices.len() {
-        let end = (base + MAX_IOV).min(slices.len());
-        let batch = &slices[base..end];
+    if verbose {
+        diag!("Wrote {} root line(s)", written);
+    }
+    Ok(())
+}
 
-        let nw = writer.write_vectored(batch)?;
-        let mut remaining = nw;
-        let mut i = 0;
+/// The root line of each model in `blocks`, terminator included, as (root, block start,
+/// line) in file order. A model's blocks normally start with its root line, but children
+/// listed before their parent, or lines interleaved with another model, come first in
+/// some; so the root line is the first line without a `parent_key` attribute (the
+/// index's `--parent-key`), and only the first feature line of the model's first block
+/// when no line lacks one (`--root-types`). Duplicated roots (several root lines for
+/// one fid) yield one each.
+fn root_lines(gff: &RangeSource, blocks: &[(u32, u64, u64)], parent_key: &str) -> Result<Vec<(u32, u64, Vec<u8>)>> {
+    let file_len = gff.len();
+    let mut sorted: Vec<(u32, u64, u64)> = blocks
+        .iter()
+        .copied()
+        .filter(|&(_, s, e)| s != MISSING && s < e && e <= file_len)
+        .collect();
+    sorted.sort_unstable_by_key(|&(_, s, e)| (s, e));
+    sorted.dedup_by_key(|b| (b.1, b.2));
 
-        while i < batch.len() && remaining >= batch[i].len() {
-            remaining -= batch[i].len();
-            i += 1;
+    let no_parent = |line: &[u8]| {
+        line.splitn(9, |&b| b == b'\t')
+            .nth(8)
+            .is_none_or(|attrs| attr_value(attrs, parent_key.as_bytes()).is_none())
+    };
+    let mut found: Vec<(u32, u64, Vec<u8>)> = Vec::new();
+    let mut fallback: FxHashMap<u32, (u64, Vec<u8>)> = FxHashMap::default();
+    for (root, s, e) in sorted {
+        let window = gff.range(s, e)?;
+        record(Counter::BlockScans, 1);
+        let mut lines = window
+            .split_inclusive(|&b| b == b'\n')
+            .filter(|line| !line.is_empty() && line[0] != b'#' && line != b"\n" && line != b"\r\n");
+        let Some(first) = lines.next() else { continue };
+        match std::iter::once(first).chain(lines).find(|line| no_parent(line)) {
+            Some(line) => found.push((root, s, line.to_vec())),
+            None => {
+                fallback.entry(root).or_insert_with(|| (s, first.to_vec()));
+            }
         }
+    }
+    let with_root: FxHashSet<u32> = found.iter().map(|r| r.0).collect();
+    found.extend(fallback.into_iter().filter(|(root, _)| !with_root.contains(root)).map(|(r, (s, l))| (r, s, l)));
+    found.sort_unstable_by_key(|r| r.1);
+    Ok(found)
+}
 
-        if i < batch.len() && remaining > 0 {
-            let cur = &batch[i];
-            writer.write_all(&cur[remaining..])?;
-            i += 1;
-        }
+/// Write one BED6 row per model, taken from its root line like `write_gff_roots_only`:
+/// seqid, 0-based start, end, root ID (`.` without one), `counts[root]` as score (0
+/// when absent) and strand. Rows are sorted by seqid, start and end, as `sort -k1,1
+/// -k2,2n` would; blocks whose root line fails `types_filter` are skipped. `-` writes
+/// to stdout. Returns the number of rows.
+pub fn write_roots_bed(
+    gff_path: &Path,
+    blocks: &[(u32, u64, u64)],
+    counts: &FxHashMap<u32, usize>,
+    bed_path: &Path,
+    types_filter: Option<&TypeFilter>,
+    verbose: bool,
+) -> Result<usize> {
+    let gff = RangeSource::open(gff_path)?;
+    let blocks = &align_blocks_to_lines(&gff, blocks)?;
+    let lines = root_lines(&gff, blocks, &load_parent_key(gff_path)?)?;
 
-        for s in &batch[i..] {
-            writer.write_all(s)?;
+    // (seqid, start0, end, name, score, strand)
+    let mut rows: Vec<(String, u64, u64, String, usize, char)> = Vec::with_capacity(lines.len());
+    for (root, s, line) in lines {
+        if types_filter.is_some_and(|t| !t.allows_line(&line)) {
+            continue;
         }
-
-        base = end;
+        let text = String::from_utf8_lossy(&line);
+        let f: Vec<&str> = text.trim_end_matches(['\n', '\r']).splitn(9, '\t').collect();
+        if f.len() < 9 {
+            diag!("[WARN] Root line at offset {} has fewer than 9 columns; skipped in --roots-bed", s);
+            continue;
+        }
+        let (Ok(start), Ok(end)) = (f[3].trim().parse::<u64>(), f[4].trim().parse::<u64>()) else {
+            diag!("[WARN] Root line at offset {} has invalid coordinates; skipped in --roots-bed", s);
+            continue;
+        };
+        let strand = match f[6] {
+            "+" => '+',
+            "-" => '-',
+            _ => '.',
+        };
+        rows.push((
+            f[0].to_string(),
+            start.saturating_sub(1),
+            end,
+            fast_id(f[8]).unwrap_or(".").to_string(),
+            counts.get(&root).copied().unwrap_or(0),
+            strand,
+        ));
     }
+    rows.sort_unstable_by(|a, b| (&a.0, a.1, a.2, &a.3).cmp(&(&b.0, b.1, b.2, &b.3)));
 
+    let raw: Box<dyn Write> = if bed_path == Path::new("-") {
+        Box::new(CountingWriter(stdout()))
+    } else {
+        Box::new(CountingWriter(
+            File::create(bed_path).with_context(|| format!("Cannot create output: {:?}", bed_path))?,
+        ))
+    };
+    let mut writer = BufWriter::new(raw);
+    for (seqid, start, end, name, score, strand) in &rows {
+        writeln!(writer, "{seqid}\t{start}\t{end}\t{name}\t{score}\t{strand}")?;
+    }
     writer.flush()?;
 
     if verbose {
-        eprintln!(
-            "Wrote {} merged GFF block(s) with vectored I/O",
-            merged.len()
-        );
+        diag!("[INFO] Wrote {} root extent(s) to {:?}", rows.len(), bed_path);
     }
-    Ok(())
+    Ok(rows.len())
 }
 
-pub fn write_gff_output_filtered(
-    gff_path: &PathBuf,
+/// Write whole blocks in file order, keeping only the feature lines that pass
+/// `types_filter` and `lengths`; '#' lines are passed through. Unlike
+/// `write_gff_output_filtered` there is no ID matching.
+pub fn write_gff_output_typed(
+    gff_path: &Path,
     blocks: &[(u32, u64, u64)],
-    per_root_matches: &FxHashMap<u32, FxHashSet<String>>,
-    atn_attr_name: &str,
     output_path: &Option<PathBuf>,
-    types_filter: Option<&str>,
+    types_filter: &TypeFilter,
+    lengths: LengthFilter,
     verbose: bool,
 ) -> Result<()> {
-    // mmap GFF
-    let file =
-        File::open(gff_path).with_context(|| format!("Cannot open GFF file: {:?}", gff_path))?;
-    let mmap =
-        unsafe { Mmap::map(&file) }.with_context(|| format!("mmap failed for {:?}", gff_path))?;
-    let file_len = mmap.len();
-
-    // parse optional type filter: comma-separated into a HashSet<String>
-    let type_allow: Option<FxHashSet<String>> = types_filter.map(|s| {
-        s.split(',')
-            .map(|t| t.trim().to_string())
-            .filter(|t| !t.is_empty())
-            .collect()
-    });
+    let gff = RangeSource::open(gff_path)?;
+    let file_len = gff.len();
+    let blocks = &align_blocks_to_lines(&gff, blocks)?;
+
+    let mut sorted: Vec<(u64, u64)> = blocks
+        .iter()
+        .filter(|&&(_, s, e)| s != MISSING && s < e && e <= file_len)
+        .map(|&(_, s, e)| (s, e))
+        .collect();
+    sorted.sort_unstable();
+    sorted.dedup();
 
-    let bkey: Vec<u8> = {
-        let mut k = atn_attr_name.as_bytes().to_vec();
-        k.push(b'=');
-        k
+    let raw: Box<dyn Write> = match output_path {
+        Some(p) => Box::new(CountingWriter(File::create(p).with_context(|| format!("Cannot create output: {:?}", p))?)),
+        None => Box::new(CountingWriter(stdout())),
     };
-    let bkey_finder = memmem::Finder::new(&bkey);
-    
-    // Process blocks in parallel; each task returns (block_start, matched_bytes)
-    let mut parts: Vec<(u64, Vec<u8>)> = blocks
-        .par_iter()
-        .filter_map(|&(root, start, end)| {
-            // root -> set of string IDs to keep
-            let keep: &FxHashSet<String> = per_root_matches.get(&root)?;
-            if keep.is_empty() {
-                return None;
-            }
+    let mut writer = BufWriter::with_capacity(16 * 1024 * 1024, raw);
+    let mut written = 0usize;
+    // Blocks may overlap (duplicated roots); never emit a byte twice
+    let mut written_end = write_source_header(&gff, &mut writer)?;
+
+    for (s, e) in sorted {
+        let s = s.max(written_end);
+        if s >= e {
+            continue;
+        }
+        let window = gff.range(s, e)?;
+        record(Counter::BlockScans, 1);
+        written += write_typed_lines(&window, types_filter, lengths, &mut writer)?;
+        written_end = e;
+    }
+    writer.flush()?;
 
-            let s = start as usize;
-            let e = end.min(file_len as u64) as usize;
-            if s >= e || e > file_len {
+    if verbose {
+        diag!("[INFO] Wrote {} line(s) passing --types", written);
+    }
+    Ok(())
+}
+
+/// Write the lines of `window` that are '#' lines or pass `types_filter` and `lengths`;
+/// returns how many were written.
+fn write_typed_lines<W: Write>(
+    window: &[u8],
+    types_filter: &TypeFilter,
+    lengths: LengthFilter,
+    writer: &mut W,
+) -> std::io::Result<usize> {
+    let mut written = 0usize;
+    let mut pos = 0usize;
+    while pos < window.len() {
+        let end = memchr(b'\n', &window[pos..])
+            .map(|i| pos + i + 1)
+            .unwrap_or(window.len());
+        let line = &window[pos..end];
+        pos = end;
+        let body = line.strip_suffix(b"\n").unwrap_or(line);
+        let body = body.strip_suffix(b"\r").unwrap_or(body);
+        let keep = line[0] == b'#' || (types_filter.allows_line(body) && lengths.accepts_line(body));
+        if keep {
+            writer.write_all(line)?;
+            written += 1;
+        }
+    }
+    Ok(written)
+}
+
+/// Lines kept by the per-feature output: those whose `attr_keys` value is one of the
+/// IDs matched in their block (`per_root_matches`), and that pass `types_filter` and
+/// `lengths`; `provenance` adds the `gffx_src` attribute.
+#[derive(Debug, Clone, Copy)]
+pub struct LineSelection<'a> {
+    pub per_root_matches: &'a FxHashMap<u32, FxHashSet<String>>,
+    pub attr_keys: &'a [&'a str],
+    pub types_filter: Option<&'a TypeFilter>,
+    pub lengths: LengthFilter,
+    pub provenance: Option<Provenance>,
+}
+
+/// Per-block scan of `LineSelection`, shared by the plain and the resumable writers.
+struct BlockFilter<'a> {
+    gff: &'a RangeSource,
+    selection: LineSelection<'a>,
+    line_index: Option<LineIndex<'a>>,
+    /// Root fid -> features in its block (`.gcnt`), to size the output buffers
+    feature_counts: Option<FxHashMap<u32, u32>>,
+    verbose: bool,
+}
+
+impl<'a> BlockFilter<'a> {
+    fn new(gff: &'a RangeSource, gff_path: &Path, selection: LineSelection<'a>, verbose: bool) -> Result<Self> {
+        let line_index = selection
+            .provenance
+            .filter(|p| p.needs_lines())
+            .map(|_| LineIndex::new(gff))
+            .transpose()?;
+        let feature_counts = load_gcnt(gff_path)?.map(|c| c.into_iter().rev().collect());
+        Ok(Self { gff, selection, line_index, feature_counts, verbose })
+    }
+
+    /// Matched lines of block `[start, end)` of `root`; `None` when nothing matches.
+    fn apply(&self, root: u32, start: u64, end: u64) -> Result<Option<Vec<u8>>> {
+        let LineSelection { per_root_matches, types_filter, lengths, provenance, .. } = self.selection;
+        // root -> set of string IDs to keep
+        let Some(keep) = per_root_matches.get(&root).filter(|k| !k.is_empty()) else {
+            return Ok(None);
+        };
+
+        let e = end.min(self.gff.len());
+        if start >= e {
+            return Ok(None);
+        }
+        let s = start as usize;
+        let block = self.gff.range(start, e)?;
+        record(Counter::BlockScans, 1);
+        let window = &block[..];
+
+        // Output buffer for this block: at most one line per kept ID is expected, at the
+        // block's average line length when its feature count is known
+        let capacity = match self.feature_counts.as_ref().and_then(|c| c.get(&root)) {
+            Some(&n) if n > 0 => window.len() / n as usize * keep.len().min(n as usize),
+            _ => 1024,
+        };
+        let mut out = Vec::<u8>::with_capacity(capacity);
+        let mut pos = 0usize;
+
+        // Iterate lines in [s, e)
+        let next_line = |from: usize| -> Option<(usize, usize, usize)> {
+            if from >= window.len() {
                 return None;
             }
-            let window = &mmap[s..e];
-
-            // Output buffer for this block
-            let mut out = Vec::<u8>::with_capacity(1024);
-            let mut pos = 0usize;
+            // '\n' inclusive; rel is the index after '\n' or end-of-window
+            let rel = memchr(b'\n', &window[from..])
+                .map(|i| from + i + 1)
+                .unwrap_or(window.len());
+            // strip trailing '\n' and optional '\r'
+            let mut end_no_nl = rel;
+            if end_no_nl > from && window[end_no_nl - 1] == b'\n' {
+                end_no_nl -= 1;
+            }
+            if end_no_nl > from && window[end_no_nl - 1] == b'\r' {
+                end_no_nl -= 1;
+            }
+            Some((from, rel, end_no_nl))
+        };
 
-            // Iterate lines in [s, e)
-            let next_line = |from: usize| -> Option<(usize, usize, usize)> {
-                if from >= window.len() {
-                    return None;
-                }
-                // '\n' inclusive; rel is the index after '\n' or end-of-window
-                let rel = memchr(b'\n', &window[from..])
-                    .map(|i| from + i + 1)
-                    .unwrap_or(window.len());
-                // strip trailing '\n' and optional '\r'
-                let mut end_no_nl = rel;
-                if end_no_nl > from && window[end_no_nl - 1] == b'\n' {
-                    end_no_nl -= 1;
-                }
-                if end_no_nl > from && window[end_no_nl - 1] == b'\r' {
-                    end_no_nl -= 1;
-                }
-                Some((from, rel, end_no_nl))
-            };
-
-            // If a type filter is supplied, check column-3 equals one of allowed types
-            let type_ok = |line: &[u8]| -> bool {
-                if let Some(allow) = &type_allow {
-                    // find first three tabs
-                    let i1 = match memchr(b'\t', line) {
-                        Some(i) => i,
-                        None => return false,
-                    };
-                    let i2 = match memchr(b'\t', &line[i1 + 1..]) {
-                        Some(x) => i1 + 1 + x,
-                        None => return false,
-                    };
-                    let i3 = match memchr(b'\t', &line[i2 + 1..]) {
-                        Some(x) => i2 + 1 + x,
-                        None => return false,
-                    };
-                    let ty = &line[i2 + 1..i3];
-                    if let Ok(ty_str) = std::str::from_utf8(ty) {
-                        allow.contains(ty_str)
-                    } else {
-                        false
-                    }
-                } else {
-                    true
-                }
-            };
-
-            // Return true if attributes contain `ID=<value>` and value ∈ keep
-            let id_hits_keep = |line_no_crlf: &[u8]| -> bool {
-                // move to 9th field (attributes)
-                let mut off = 0usize;
-                let mut tabs = 0u8;
-                while tabs < 8 {
-                    match memchr(b'\t', &line_no_crlf[off..]) {
-                        Some(i) => {
-                            off += i + 1;
-                            tabs += 1;
-                        }
-                        None => return false,
-                    }
-                }
-                let attr = &line_no_crlf[off..];
-                if let Some(p) = bkey_finder.find(attr) {
-                    let vstart = p + bkey.len();
-                    // value ends at ';' or end-of-line
-                    let vend = memchr(b';', &attr[vstart..])
-                        .map(|i| vstart + i)
-                        .unwrap_or(attr.len());
-                    let id_slice = &attr[vstart..vend];
-                    if let Ok(id_str) = std::str::from_utf8(id_slice) {
-                        return keep.contains(id_str);
+        // Return true if attributes contain `<key>=<value>` for any key and value ∈ keep
+        let id_hits_keep = |line_no_crlf: &[u8]| -> bool {
+            // move to 9th field (attributes)
+            let mut off = 0usize;
+            let mut tabs = 0u8;
+            while tabs < 8 {
+                match memchr(b'\t', &line_no_crlf[off..]) {
+                    Some(i) => {
+                        off += i + 1;
+                        tabs += 1;
                     }
-                }
-                false
-            };
-
-            // Scan lines in this block window
-            while let Some((ls, le, ln_end)) = next_line(pos) {
-                pos = le;
-                let line = &window[ls..le];
-                if !line.is_empty() && line[0] == b'#' {
-                    continue; // skip comments
-                }
-                let line_no_crlf = &window[ls..ln_end];
-
-                if !type_ok(line_no_crlf) {
-                    continue;
-                }
-                if id_hits_keep(line_no_crlf) {
-                    out.extend_from_slice(line);
+                    None => return false,
                 }
             }
+            let attr = &line_no_crlf[off..];
+            self.selection.attr_keys.iter().any(|key| {
+                attr_value(attr, key.as_bytes())
+                    .and_then(|v| std::str::from_utf8(v).ok())
+                    .is_some_and(|v| keep.contains(v))
+            })
+        };
 
-            if verbose {
-                let matched_lines = out.iter().filter(|&&b| b == b'\n').count();
-                eprintln!(
-                    "[filter] root={} block=[{}..{}] keep_ids={} matched_lines={}",
-                    root, start, end, keep.len(), matched_lines
-                );
+        // Scan lines in this block window
+        while let Some((ls, le, ln_end)) = next_line(pos) {
+            pos = le;
+            let line = &window[ls..le];
+            if !line.is_empty() && line[0] == b'#' {
+                continue; // skip comments
             }
+            let line_no_crlf = &window[ls..ln_end];
 
-            if out.is_empty() {
-                None
-            } else {
-                Some((start, out))
+            if types_filter.is_some_and(|t| !t.allows_line(line_no_crlf))
+                || !lengths.accepts_line(line_no_crlf)
+            {
+                continue;
             }
-        })
-        .collect();
+            if id_hits_keep(line_no_crlf) {
+                match provenance {
+                    Some(prov) => push_line_with_provenance(
+                        &mut out,
+                        line,
+                        (s + ls) as u64,
+                        self.line_index.as_ref(),
+                        prov,
+                    )?,
+                    None => out.extend_from_slice(line),
+                }
+            }
+        }
+
+        if self.verbose {
+            let matched_lines = out.iter().filter(|&&b| b == b'\n').count();
+            diag!(
+                "[filter] root={} block=[{}..{}] keep_ids={} matched_lines={}",
+                root, start, end, keep.len(), matched_lines
+            );
+        }
 
-    // Keep original block order
-    parts.sort_unstable_by_key(|(s, _)| *s);
+        Ok((!out.is_empty()).then_some(out))
+    }
+}
+
+/// Write the lines of `blocks` that `selection` keeps, in file order, after the GFF's
+/// header lines.
+pub fn write_gff_output_filtered(
+    gff_path: &Path,
+    blocks: &[(u32, u64, u64)],
+    selection: LineSelection<'_>,
+    output_path: &Option<PathBuf>,
+    verbose: bool,
+) -> Result<()> {
+    // mmap GFF (or positioned reads per block)
+    let gff = RangeSource::open(gff_path)?;
+    let blocks = &align_blocks_to_lines(&gff, blocks)?;
+    let filter = BlockFilter::new(&gff, gff_path, selection, verbose)?;
+
+    // Process blocks in parallel; each task returns (block_start, matched_bytes)
+    let parts: Vec<Option<(u64, Vec<u8>)>> = blocks
+        .par_iter()
+        .map(|&(root, start, end)| Ok(filter.apply(root, start, end)?.map(|out| (start, out))))
+        .collect::<Result<_>>()?;
+    let mut parts: Vec<(u64, Vec<u8>)> = parts.into_iter().flatten().collect();
+
+    // File order; blocks sharing a start keep their input order
+    parts.sort_by_key(|(s, _)| *s);
 
     // Write output (stdout or file)
     let raw: Box<dyn Write> = match output_path {
-        Some(p) => Box::new(File::create(p).with_context(|| format!("Cannot create output: {:?}", p))?),
-        None => Box::new(std::io::stdout()),
+        Some(p) => Box::new(CountingWriter(File::create(p).with_context(|| format!("Cannot create output: {:?}", p))?)),
+        None => Box::new(CountingWriter(std::io::stdout())),
     };
     // Bigger buffer reduces syscalls; tune as needed
     let mut writer = BufWriter::with_capacity(16 * 1024 * 1024, raw);
+    write_source_header(&gff, &mut writer)?;
     for (_, buf) in parts {
         writer.write_all(&buf)?;
     }
@@ -464,4 +1513,567 @@ pub fn write_gff_output_filtered(
     Ok(())
 }
 
+/// One group of `write_gff_output_grouped`: its label and its `(root, start, end)` blocks.
+pub type QueryGroup = (String, Vec<(u32, u64, u64)>);
 
+/// `--preserve-query-order`: the blocks of each group in `groups` (label, blocks), the
+/// groups in the order given and each under `# query: <label>`; within a group blocks
+/// go in file order. With `selection` only its lines are written, as by
+/// `write_gff_output_filtered`; without, whole blocks minus the feature lines
+/// `types_filter` rejects. Groups and blocks with nothing to write are left out.
+/// Returns the number of blocks written.
+pub fn write_gff_output_grouped(
+    gff_path: &Path,
+    groups: &[QueryGroup],
+    selection: Option<LineSelection<'_>>,
+    types_filter: Option<&TypeFilter>,
+    output_path: &Option<PathBuf>,
+    verbose: bool,
+) -> Result<usize> {
+    let gff = RangeSource::open(gff_path)?;
+    let file_len = gff.len();
+    let filter = selection.map(|sel| BlockFilter::new(&gff, gff_path, sel, verbose)).transpose()?;
+
+    let raw: Box<dyn Write> = match output_path {
+        Some(p) => Box::new(CountingWriter(File::create(p).with_context(|| format!("Cannot create output: {:?}", p))?)),
+        None => Box::new(CountingWriter(stdout())),
+    };
+    let mut writer = BufWriter::with_capacity(16 * 1024 * 1024, raw);
+    let header_end = write_source_header(&gff, &mut writer)?;
+
+    let mut written = 0usize;
+    for (label, blocks) in groups {
+        let mut blocks = align_blocks_to_lines(&gff, blocks)?;
+        blocks.retain(|&(_, s, e)| s != MISSING && s.max(header_end) < e && e <= file_len);
+        blocks.sort_by_key(|&(root, s, e)| (s, e, root));
+        blocks.dedup_by_key(|b| (b.1, b.2));
+
+        // One group at a time: its blocks are rendered in parallel, then written in order
+        let parts: Vec<Option<Vec<u8>>> = blocks
+            .par_iter()
+            .map(|&(root, s, e)| {
+                let s = s.max(header_end);
+                match &filter {
+                    Some(filter) => filter.apply(root, s, e),
+                    None => {
+                        let window = gff.range(s, e)?;
+                        record(Counter::BlockScans, 1);
+                        let mut out = Vec::with_capacity(window.len());
+                        match types_filter {
+                            Some(types) => {
+                                write_typed_lines(&window, types, LengthFilter::default(), &mut out)?;
+                            }
+                            None => out.extend_from_slice(&window),
+                        }
+                        Ok((!out.is_empty()).then_some(out))
+                    }
+                }
+            })
+            .collect::<Result<_>>()?;
+        let parts: Vec<Vec<u8>> = parts.into_iter().flatten().collect();
+        if parts.is_empty() {
+            continue;
+        }
+        writeln!(writer, "# query: {}", label)?;
+        for part in &parts {
+            writer.write_all(part)?;
+            if !part.ends_with(b"\n") {
+                writer.write_all(b"\n")?;
+            }
+        }
+        written += parts.len();
+    }
+    writer.flush()?;
+
+    if verbose {
+        diag!("[INFO] Wrote {} block(s) in {} query group(s)", written, groups.len());
+    }
+    Ok(written)
+}
+
+/// `--checkpoint`: the output of `write_gff_output` (`selection` is `None`) or of
+/// `write_gff_output_filtered` (`Some`), written in chunks through `write_resumable`
+/// so that a rerun with the same `key` continues an interrupted write.
+///
+/// The plan is the blocks in file order; whole blocks that overlap are merged first,
+/// so the final output is byte-identical to the one-shot writers.
+pub fn write_gff_output_resumable(
+    gff_path: &Path,
+    blocks: &[(u32, u64, u64)],
+    selection: Option<LineSelection<'_>>,
+    output_path: &Path,
+    checkpoint_path: &Path,
+    key: &str,
+    verbose: bool,
+) -> Result<()> {
+    let gff = RangeSource::open(gff_path)?;
+    let file_len = gff.len();
+    let mut blocks = align_blocks_to_lines(&gff, blocks)?;
+    blocks.retain(|&(fid, s, _)| {
+        if s == MISSING && selection.is_none() {
+            diag!("[WARN] skipped fid={} due to sentinel start offset", fid);
+        }
+        s != MISSING
+    });
+    blocks.sort_by_key(|&(_, s, _)| s);
+
+    // The header is the first plan entry, rendered as is
+    let header_end = if write_header_enabled() { gff_header_len(&gff)? } else { 0 };
+    for b in &mut blocks {
+        b.1 = b.1.max(header_end);
+    }
+    blocks.retain(|&(_, s, e)| s < e);
+    if header_end > 0 {
+        blocks.insert(0, (u32::MAX, 0, header_end));
+    }
+
+    let Some(selection) = selection else {
+        let mut plan: Vec<PlanEntry> = Vec::with_capacity(blocks.len());
+        for (fid, s, e) in blocks {
+            match plan.last_mut() {
+                Some(last) if s < last.2 => last.2 = last.2.max(e),
+                _ => plan.push((fid, s, e)),
+            }
+        }
+        plan.retain(|&(_, s, e)| s < e && e <= file_len);
+        let render = |chunk: &[PlanEntry], buf: &mut Vec<u8>| -> Result<()> {
+            for &(_, s, e) in chunk {
+                buf.extend_from_slice(&gff.range(s, e)?);
+            }
+            Ok(())
+        };
+        return write_resumable(output_path, checkpoint_path, key, gff_path, &plan, render, verbose);
+    };
+
+    let filter = BlockFilter::new(&gff, gff_path, selection, verbose)?;
+    let render = |chunk: &[PlanEntry], buf: &mut Vec<u8>| -> Result<()> {
+        let parts: Vec<Option<Vec<u8>>> = chunk
+            .par_iter()
+            .map(|&(root, s, e)| {
+                if s < header_end { Ok(Some(gff.range(s, e)?.into_owned())) } else { filter.apply(root, s, e) }
+            })
+            .collect::<Result<_>>()?;
+        for part in parts.into_iter().flatten() {
+            buf.extend_from_slice(&part);
+        }
+        Ok(())
+    };
+    write_resumable(output_path, checkpoint_path, key, gff_path, &blocks, render, verbose)
+}
+
+
+
+/// A sequence number and its root intervals.
+type SeqIntervals<'a> = (u32, Vec<&'a Interval<u32>>);
+
+/// Root intervals of every sequence, sorted by `(start, end, root)`, with sequences in
+/// sequence-number order; the positional view shared by `--context` and `--pad-models`
+/// (which decodes every tree).
+pub fn sorted_root_intervals(index: &TreeIndexData) -> Result<Vec<SeqIntervals<'_>>> {
+    Ok(index.all_trees()?.into_iter().map(|(c, tree)| (c, tree.sorted_intervals())).collect())
+}
+
+/// root -> (sequence rank, position along it) in `sorted_root_intervals` order; a root
+/// listed more than once keeps its first position.
+fn root_positions(sorted: &[(u32, Vec<&Interval<u32>>)]) -> FxHashMap<u32, (usize, usize)> {
+    let mut pos: FxHashMap<u32, (usize, usize)> = FxHashMap::default();
+    for (ci, (_, ivs)) in sorted.iter().enumerate() {
+        for (i, iv) in ivs.iter().enumerate() {
+            pos.entry(iv.payload).or_insert((ci, i));
+        }
+    }
+    pos
+}
+
+/// Neighbouring models for `--context N`: the `n` roots before and after each matched
+/// root on the same sequence, in interval-tree (start) order. Matched roots are never
+/// context, and a root next to several matches is listed once.
+/// Returns `(context root, matched roots it neighbours)` in sequence/position order.
+pub fn context_roots(index: &TreeIndexData, matched: &[u32], n: usize) -> Result<Vec<(u32, Vec<u32>)>> {
+    if n == 0 || matched.is_empty() {
+        return Ok(Vec::new());
+    }
+    let sorted = sorted_root_intervals(index)?;
+    let order: Vec<Vec<u32>> = sorted.iter().map(|(_, ivs)| ivs.iter().map(|iv| iv.payload).collect()).collect();
+    let pos = root_positions(&sorted);
+
+    let is_match: FxHashSet<u32> = matched.iter().copied().collect();
+    let mut neighbours: FxHashMap<u32, Vec<u32>> = FxHashMap::default();
+    for &m in matched {
+        let Some(&(ci, i)) = pos.get(&m) else { continue };
+        let roots = &order[ci];
+        let window = i.saturating_sub(n)..(i + n + 1).min(roots.len());
+        for &r in &roots[window] {
+            if !is_match.contains(&r) {
+                let of = neighbours.entry(r).or_default();
+                if !of.contains(&m) {
+                    of.push(m);
+                }
+            }
+        }
+    }
+
+    let mut out: Vec<(u32, Vec<u32>)> = neighbours.into_iter().collect();
+    out.sort_unstable_by_key(|(r, _)| pos[r]);
+    Ok(out)
+}
+
+/// `(padding root, matched roots it is near)` list and the number of windows cut at a
+/// sequence end, from `pad_roots`.
+type PaddingRoots = (Vec<(u32, Vec<u32>)>, usize);
+
+/// Padding models for `--pad-models BP`: other roots on the same sequence as a matched
+/// root that lie at most `bp` bases from it (overlapping roots included), found through
+/// the interval trees. Matched roots are never padding, and a root near several matches
+/// is listed once. The window around each match is clamped to its sequence's length
+/// from `seq_lens` (and to coordinate 0).
+/// Returns `(padding root, matched roots it is near)` in sequence/position order, and
+/// the number of windows cut at a sequence end.
+pub fn pad_roots(index: &TreeIndexData, matched: &[u32], bp: u32, seq_lens: &SeqLengths) -> Result<PaddingRoots> {
+    if matched.is_empty() {
+        return Ok((Vec::new(), 0));
+    }
+    let sorted = sorted_root_intervals(index)?;
+    let pos = root_positions(&sorted);
+
+    let is_match: FxHashSet<u32> = matched.iter().copied().collect();
+    let mut near: FxHashMap<u32, Vec<u32>> = FxHashMap::default();
+    let mut hits = Vec::new();
+    let mut n_clamped = 0usize;
+    for &m in matched {
+        let Some(&(ci, i)) = pos.get(&m) else { continue };
+        let (chr, ivs) = &sorted[ci];
+        let iv = ivs[i];
+        // Half-open extents: a gap of at most `bp` means overlapping the extent widened
+        // by `bp + 1` on each side
+        let seqid = &index.num_to_seqid[*chr as usize];
+        let reach = bp.saturating_add(1);
+        let (lo, hi, clamped) = seq_lens.clamp_window(seqid, iv.start, iv.end, reach, reach);
+        n_clamped += usize::from(clamped);
+        hits.clear();
+        if let Some(tree) = index.tree_for(*chr)? {
+            tree.query_interval(lo, hi, &mut hits);
+        }
+        for r in hits.iter().map(|iv| iv.payload) {
+            if !is_match.contains(&r) {
+                let of = near.entry(r).or_default();
+                if !of.contains(&m) {
+                    of.push(m);
+                }
+            }
+        }
+    }
+
+    let mut out: Vec<(u32, Vec<u32>)> = near.into_iter().collect();
+    out.sort_unstable_by_key(|(r, _)| pos[r]);
+    Ok((out, n_clamped))
+}
+
+/// Drop from `padding` (from `pad_roots`) the roots listed in `context` (from
+/// `context_roots`), so that with both `--context` and `--pad-models` a model is
+/// written once, as context. Returns the number of roots dropped.
+pub fn drop_context_roots(padding: &mut Vec<(u32, Vec<u32>)>, context: &[(u32, Vec<u32>)]) -> usize {
+    let written: FxHashSet<u32> = context.iter().map(|(r, _)| *r).collect();
+    let before = padding.len();
+    padding.retain(|(r, _)| !written.contains(r));
+    before - padding.len()
+}
+
+/// Append the context models from `context_roots` to `output_path` (after the matched
+/// models), each block preceded by `# context-for: <matched IDs>`.
+/// Returns the number of blocks written.
+pub fn write_context_blocks(
+    gff_path: &Path,
+    gof: &GofMap,
+    fts: &FtsMap,
+    context: &[(u32, Vec<u32>)],
+    output_path: &Option<PathBuf>,
+) -> Result<usize> {
+    write_neighbour_blocks(gff_path, gof, fts, context, output_path, "context-for")
+}
+
+/// Append the padding models from `pad_roots` to `output_path` (after the matched
+/// models), each block preceded by `# padded-for: <matched IDs>`.
+/// Returns the number of blocks written.
+pub fn write_padded_blocks(
+    gff_path: &Path,
+    gof: &GofMap,
+    fts: &FtsMap,
+    padding: &[(u32, Vec<u32>)],
+    output_path: &Option<PathBuf>,
+) -> Result<usize> {
+    write_neighbour_blocks(gff_path, gof, fts, padding, output_path, "padded-for")
+}
+
+/// Append the blocks of `roots` to the output, each under `# <label>: <matched IDs>`.
+fn write_neighbour_blocks(
+    gff_path: &Path,
+    gof: &GofMap,
+    fts: &FtsMap,
+    roots: &[(u32, Vec<u32>)],
+    output_path: &Option<PathBuf>,
+    label: &str,
+) -> Result<usize> {
+    let gff = RangeSource::open(gff_path)?;
+    let raw: Box<dyn Write> = match output_path {
+        Some(p) => Box::new(CountingWriter(
+            OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(p)
+                .with_context(|| format!("Cannot append to output: {:?}", p))?,
+        )),
+        None => Box::new(CountingWriter(stdout())),
+    };
+    let mut writer = BufWriter::new(raw);
+    let mut written = 0usize;
+    for (root, of) in roots {
+        let ids: Vec<&str> = of.iter().filter_map(|&m| fts.get_id(m)).collect();
+        let ranges: Vec<(u32, u64, u64)> = gof.all_ranges(*root).iter().map(|&(s, e)| (*root, s, e)).collect();
+        for (_, s, e) in align_blocks_to_lines(&gff, &ranges)? {
+            if s == MISSING || e <= s {
+                continue;
+            }
+            let block = gff.range(s, e)?;
+            writeln!(writer, "# {}: {}", label, ids.join(","))?;
+            writer.write_all(&block)?;
+            if !block.ends_with(b"\n") {
+                writer.write_all(b"\n")?;
+            }
+            written += 1;
+        }
+    }
+    writer.flush()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{GffSynth, SAMPLE_GFF, ScratchDir, build_fixture, build_fixture_with, data_lines};
+    use crate::{load_atn, load_gof, load_meta};
+
+    #[test]
+    fn roots_only_writes_the_first_data_line_of_each_block() {
+        let dir = ScratchDir::new("roots-only").unwrap();
+        let gff = dir.join("g.gff");
+        let text = "# header\n# more\nchr1\t.\tgene\t1\t9\t.\t+\t.\tID=g1\n\
+                    chr1\t.\tmRNA\t1\t9\t.\t+\t.\tID=t1;Parent=g1\n\
+                    chr1\t.\tmRNA\t20\t30\t.\t+\t.\tID=t2\nchr1\t.\texon\t20\t30\t.\t+\t.\tID=x;Parent=t2";
+        std::fs::write(&gff, text).unwrap();
+        let second = text.find("chr1\t.\tmRNA\t20").unwrap() as u64;
+        // The output starts with the header comment lines; the second block has no trailing newline.
+        // A duplicate block is written once.
+        let blocks = [(0, 0, second), (1, second, text.len() as u64), (0, 0, second)];
+        let out = Some(dir.join("out.gff"));
+
+        write_gff_roots_only(&gff, &blocks, &out, None, false).unwrap();
+        let written = std::fs::read_to_string(out.as_ref().unwrap()).unwrap();
+        assert_eq!(
+            written,
+            "# header\n# more\nchr1\t.\tgene\t1\t9\t.\t+\t.\tID=g1\nchr1\t.\tmRNA\t20\t30\t.\t+\t.\tID=t2\n"
+        );
+
+        // Roots of other types are skipped, not replaced by a later line of the block
+        let genes = TypeFilter::parse("gene").unwrap();
+        write_gff_roots_only(&gff, &blocks, &out, Some(&genes), false).unwrap();
+        let written = std::fs::read_to_string(out.as_ref().unwrap()).unwrap();
+        assert_eq!(written, "# header\n# more\nchr1\t.\tgene\t1\t9\t.\t+\t.\tID=g1\n");
+    }
+
+    #[test]
+    fn root_lines_follow_the_index_parent_key() {
+        // Each model starts with a child line; only `parent` tells it from the root
+        let text = "chr1\t.\tmRNA\t1\t9\t.\t+\t.\tid=t1;parent=g1\n\
+                    chr1\t.\tgene\t1\t9\t.\t+\t.\tid=g1\n\
+                    chr1\t.\texon\t20\t30\t.\t+\t.\tid=x;parent=g2\n\
+                    chr1\t.\tgene\t20\t30\t.\t+\t.\tid=g2\n";
+        let fx = build_fixture_with(text, &IndexBuilder::new().id_keys("id", "parent")).unwrap();
+        let blocks: Vec<(u32, u64, u64)> =
+            load_gof(&fx.gff).unwrap().entries.iter().map(|e| (e.feature_id, e.start_offset, e.end_offset)).collect();
+        assert_eq!(blocks.len(), 2);
+        let out = Some(fx.dir.join("roots.gff"));
+        write_gff_roots_only(&fx.gff, &blocks, &out, None, false).unwrap();
+        let roots = data_lines(out.as_ref().unwrap()).unwrap();
+        assert_eq!(roots, [text.lines().nth(1).unwrap(), text.lines().nth(3).unwrap()]);
+    }
+
+    #[test]
+    fn header_is_written_once_by_every_writer() {
+        let fx = build_fixture(SAMPLE_GFF).unwrap();
+        let header: String = SAMPLE_GFF.lines().take_while(|l| l.starts_with('#')).map(|l| format!("{l}\n")).collect();
+        let second = SAMPLE_GFF.find("# second model").unwrap() as u64;
+        // A block starting at offset 0 still gets the header only once
+        let out = Some(fx.dir.join("regions.gff"));
+        write_gff_output(&fx.gff, &[(0, 0, second)], &out, false).unwrap();
+        let written = std::fs::read_to_string(out.as_ref().unwrap()).unwrap();
+        assert_eq!(written, SAMPLE_GFF[..second as usize]);
+        assert_eq!(gff_header_len(&RangeSource::open(&fx.gff).unwrap()).unwrap(), header.len() as u64);
+
+        let set = crate::AnnotationSet::load(&fx.gff).unwrap();
+        let attrs: FxHashMap<String, String> = [("g1".to_string(), "hit=1".to_string())].into_iter().collect();
+        let mut annotated = Vec::new();
+        set.write_annotated_gff(&attrs, &mut annotated, 1).unwrap();
+        let annotated = String::from_utf8(annotated).unwrap();
+        assert!(annotated.starts_with(&header));
+        assert_eq!(annotated.matches("##gff-version").count(), 1);
+        assert!(annotated[header.len()..].starts_with("chr1\tsrc\tgene\t100\t900\t.\t+\t.\tID=g1;Name=alpha;hit=1\n"));
+    }
+
+    #[test]
+    fn type_filter_lists_files_and_negation() {
+        let f = TypeFilter::parse(" exon, CDS ,").unwrap();
+        assert!(f.allows(b"exon") && f.allows(b"CDS"));
+        assert!(!f.allows(b"gene"));
+        assert!(f.allows_line(b"chr1\t.\texon\t1\t9\t.\t+\t.\tID=a"));
+        assert!(!f.allows_line(b"chr1\t.\texon"));
+
+        let not = TypeFilter::parse("!exon,CDS").unwrap();
+        assert!(!not.allows(b"exon") && !not.allows(b"CDS"));
+        assert!(not.allows(b"gene"));
+
+        let dir = ScratchDir::new("type-filter").unwrap();
+        let list = dir.join("types.txt");
+        std::fs::write(&list, "# wanted\ngene\n\n  mRNA  \n").unwrap();
+        let from_file = TypeFilter::parse(&format!("@{}", list.display())).unwrap();
+        assert!(from_file.allows(b"gene") && from_file.allows(b"mRNA"));
+        assert!(!from_file.allows(b"# wanted"));
+        let not_file = TypeFilter::parse(&format!("!@{}", list.display())).unwrap();
+        assert!(!not_file.allows(b"mRNA") && not_file.allows(b"exon"));
+
+        assert!(TypeFilter::parse("exon,!CDS").is_err());
+        assert!(TypeFilter::parse("!").is_err());
+        assert!(TypeFilter::parse(&format!("@{}", dir.join("missing").display())).is_err());
+    }
+
+    #[test]
+    fn length_bounds_are_inclusive() {
+        let f = LengthFilter { min: Some(10), max: Some(20) };
+        assert!(!f.accepts(9));
+        assert!(f.accepts(10));
+        assert!(f.accepts(20));
+        assert!(!f.accepts(21));
+        // Columns 4-5 are 1-based inclusive, in either order
+        assert!(f.accepts_line(b"chr1\t.\texon\t11\t20\t.\t+\t.\tID=a"));
+        assert!(!f.accepts_line(b"chr1\t.\texon\t11\t19\t.\t+\t.\tID=a"));
+        assert!(f.accepts_line(b"chr1\t.\texon\t30\t11\t.\t+\t.\tID=a"));
+        assert!(!f.accepts_line(b"chr1\t.\texon\t30\t10\t.\t+\t.\tID=a"));
+        assert!(!f.accepts_line(b"chr1\t.\texon\tx\t20"));
+        assert!(LengthFilter::default().accepts_line(b"not a gff line"));
+    }
+
+    #[test]
+    fn padding_windows_stay_on_the_sequence() {
+        let (_, fx) = GffSynth::new().chromosomes(2).genes_per_chrom(5).seed(4).fixture().unwrap();
+        let seq_lens = SeqLengths::load(&fx.gff, None).unwrap();
+        let index = TreeIndexData::load_tree_index(&fx.gff).unwrap();
+        let sorted = sorted_root_intervals(&index).unwrap();
+        let roots: Vec<u32> = sorted[0].1.iter().map(|iv| iv.payload).collect();
+        assert_eq!(roots.len(), 5);
+
+        // A middle gene with a small pad stays inside chr1
+        let (padding, cut) = pad_roots(&index, &roots[2..3], 100, &seq_lens).unwrap();
+        assert_eq!((padding.len(), cut), (0, 0));
+        // Pads past coordinate 0 and past the end of chr1 are cut at both, and never
+        // reach chr2; the matches themselves are not padding
+        let (padding, cut) = pad_roots(&index, &[roots[0], roots[4]], 10_000_000, &seq_lens).unwrap();
+        assert_eq!(cut, 2);
+        let padded: Vec<u32> = padding.iter().map(|(r, _)| *r).collect();
+        assert_eq!(padded, roots[1..4]);
+        assert!(padding.iter().all(|(_, near)| near == &[roots[0], roots[4]]));
+        assert_eq!(pad_roots(&index, &[], 100, &seq_lens).unwrap(), (Vec::new(), 0));
+    }
+
+    #[test]
+    fn stale_index_stops_the_run_unless_rebuilt() {
+        let truncated = &SAMPLE_GFF[..SAMPLE_GFF.rfind("chr2").unwrap()];
+
+        let stale = build_fixture(SAMPLE_GFF).unwrap();
+        std::fs::write(&stale.gff, truncated).unwrap();
+        let err = ensure_index(&stale.gff, false, false).unwrap_err();
+        assert!(matches!(err.downcast_ref::<Error>(), Some(Error::IndexMismatch { .. })), "{err}");
+        assert!(err.to_string().contains("--skip-index-check"), "{err}");
+
+        // --auto-index rebuilds it with the recorded attribute key
+        let rebuilt = build_fixture(SAMPLE_GFF).unwrap();
+        std::fs::write(&rebuilt.gff, truncated).unwrap();
+        ensure_index(&rebuilt.gff, true, false).unwrap();
+        assert_eq!(load_meta(&rebuilt.gff).unwrap().unwrap().size, truncated.len() as u64);
+        assert_eq!(load_atn(&rebuilt.gff).unwrap().attr_name(), "Name");
+
+        let current = build_fixture(SAMPLE_GFF).unwrap();
+        ensure_index(&current.gff, false, false).unwrap();
+    }
+
+    /// Both kinds of `RangeSource` over `path`.
+    fn sources(path: &Path) -> [RangeSource; 2] {
+        let file = File::open(path).unwrap();
+        let len = file.metadata().unwrap().len();
+        [RangeSource::open(path).unwrap(), RangeSource::File { file, len }]
+    }
+
+    #[test]
+    fn offsets_off_by_one_are_widened_to_whole_lines() {
+        let (_, fx) = GffSynth::new().chromosomes(2).genes_per_chrom(20).seed(17).fixture().unwrap();
+        let gff = &fx.gff;
+        let text = std::fs::read(gff).unwrap();
+        let lines: FxHashSet<&[u8]> = text.split_inclusive(|&b| b == b'\n').collect();
+        let blocks: Vec<(u32, u64, u64)> =
+            load_gof(gff).unwrap().entries.iter().map(|e| (e.feature_id, e.start_offset, e.end_offset)).collect();
+        let len = text.len() as u64;
+
+        for src in sources(gff) {
+            assert_eq!(align_blocks(&src, &blocks, true).unwrap(), blocks);
+            for &(root, s, e) in &blocks {
+                let mut shifted = vec![(root, s + 1, e), (root, s, e - 1)];
+                shifted.extend((s > 0).then_some((root, s - 1, e)));
+                shifted.extend((e < len).then_some((root, s, e + 1)));
+                for (i, block) in shifted.iter().enumerate() {
+                    let (_, ns, ne) = align_blocks(&src, std::slice::from_ref(block), false).unwrap()[0];
+                    assert!(ns <= block.1 && ne >= block.2);
+                    if i < 2 {
+                        // Cut into the block's own first or last line: widened back to it
+                        assert_eq!((ns, ne), (s, e));
+                    }
+                    let out = &text[ns as usize..ne as usize];
+                    assert!(
+                        out.split_inclusive(|&b| b == b'\n').all(|l| lines.contains(l)),
+                        "{:?} gave a partial line",
+                        block
+                    );
+                }
+                // Under --strict-offsets each of them is an error instead
+                for block in &shifted {
+                    let err = align_blocks(&src, std::slice::from_ref(block), true).unwrap_err();
+                    assert!(err.to_string().contains("does not fall on line boundaries"), "{}", err);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn strict_offsets_flag_reaches_the_aligner() {
+        let dir = ScratchDir::new("align-strict").unwrap();
+        let gff = dir.join("g.gff");
+        std::fs::write(&gff, "a\tb\nc\td\n").unwrap();
+        let src = RangeSource::open(&gff).unwrap();
+        // Aligned blocks never fail, so other tests running meanwhile are unaffected
+        set_strict_offsets(true);
+        let strict = align_blocks_to_lines(&src, &[(1, 1, 4)]);
+        set_strict_offsets(false);
+        assert!(strict.is_err());
+        assert_eq!(align_blocks_to_lines(&src, &[(1, 1, 4)]).unwrap(), [(1, 0, 4)]);
+    }
+
+    #[test]
+    fn unusable_blocks_pass_through() {
+        let dir = ScratchDir::new("align-pass").unwrap();
+        let gff = dir.join("g.gff");
+        std::fs::write(&gff, "a\tb\nc\td\n").unwrap();
+        let blocks = [(1, MISSING, MISSING), (2, 5, 5), (3, 6, 4), (4, 2, 99)];
+        for src in sources(&gff) {
+            assert_eq!(align_blocks(&src, &blocks, true).unwrap(), blocks);
+            assert_eq!(align_blocks(&src, &[(5, 1, 7)], false).unwrap(), [(5, 0, 8)]);
+        }
+    }
+}
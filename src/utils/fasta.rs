@@ -0,0 +1,236 @@
+use anyhow::{Context, Result, bail};
+use memchr::memchr;
+use rustc_hash::FxHashMap;
+use std::path::Path;
+
+use crate::{ByteSource, append_suffix};
+
+/// One `.fai` record (samtools faidx layout).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaiEntry {
+    pub name: String,
+    /// Bases in the sequence
+    pub len: u64,
+    /// Byte offset of the first base
+    pub offset: u64,
+    /// Bases per line
+    pub line_bases: u64,
+    /// Bytes per line, terminator included
+    pub line_width: u64,
+}
+
+/// Parse a `.fai` file: `name\tlength\toffset\tline_bases\tline_width` per line.
+pub fn parse_fai(text: &str) -> Result<Vec<FaiEntry>> {
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate().filter(|(_, l)| !l.is_empty()) {
+        let f: Vec<&str> = line.split('\t').collect();
+        let num = |k: usize| f.get(k).and_then(|v| v.parse::<u64>().ok());
+        let (Some(len), Some(offset), Some(line_bases), Some(line_width)) = (num(1), num(2), num(3), num(4)) else {
+            bail!("Malformed .fai line {}: {:?}", i + 1, line);
+        };
+        if line_bases == 0 || line_width < line_bases {
+            bail!("Malformed .fai line {}: bad line lengths in {:?}", i + 1, line);
+        }
+        entries.push(FaiEntry { name: f[0].to_string(), len, offset, line_bases, line_width });
+    }
+    Ok(entries)
+}
+
+/// Index FASTA bytes as `samtools faidx` would. Every line of a sequence but the last
+/// must have the same length, otherwise positions cannot be computed and this fails.
+pub fn build_fai(data: &[u8]) -> Result<Vec<FaiEntry>> {
+    let mut entries: Vec<FaiEntry> = Vec::new();
+    // Line lengths of the current sequence: (bases, bytes) of its first line, and
+    // whether a shorter line has been seen (which must be the last)
+    let mut shape: Option<(u64, u64)> = None;
+    let mut short_seen = false;
+    let mut pos = 0usize;
+    while pos < data.len() {
+        let end = memchr(b'\n', &data[pos..]).map_or(data.len(), |i| pos + i + 1);
+        let line = &data[pos..end];
+        let body = line.strip_suffix(b"\n").unwrap_or(line);
+        let body = body.strip_suffix(b"\r").unwrap_or(body);
+        if let Some(header) = body.strip_prefix(b">") {
+            let name = header.split(|b| b.is_ascii_whitespace()).next().unwrap_or_default();
+            entries.push(FaiEntry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                len: 0,
+                offset: end as u64,
+                line_bases: 0,
+                line_width: 0,
+            });
+            shape = None;
+            short_seen = false;
+        } else if let Some(entry) = entries.last_mut() {
+            let (bases, bytes) = (body.len() as u64, line.len() as u64);
+            if bases > 0 {
+                let name = &entry.name;
+                match shape {
+                    None => {
+                        shape = Some((bases, bytes));
+                        entry.line_bases = bases;
+                        entry.line_width = bytes;
+                    }
+                    // A full line without terminator can only be the file's last line
+                    Some((b, w)) if short_seen || bases > b || (bases == b && bytes != w && line.ends_with(b"\n")) => {
+                        bail!("FASTA sequence {:?} has lines of different lengths; rewrap it to one line length", name)
+                    }
+                    Some((b, _)) => short_seen = bases < b,
+                }
+                entry.len += bases;
+            } else {
+                short_seen = shape.is_some();
+            }
+        } else if !body.is_empty() {
+            bail!("FASTA does not start with a '>' header line");
+        }
+        pos = end;
+    }
+    for e in &mut entries {
+        if e.line_bases == 0 {
+            (e.line_bases, e.line_width) = (1, 1);
+        }
+    }
+    Ok(entries)
+}
+
+/// Per-base counts of a sequence stretch; lowercase (soft-masked) bases count like
+/// uppercase ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BaseCounts {
+    /// G and C
+    pub gc: u64,
+    /// A, T and U
+    pub at: u64,
+    /// N
+    pub n: u64,
+    /// Other IUPAC codes and unexpected bytes
+    pub other: u64,
+}
+
+/// Class of each byte: 0 other, 1 GC, 2 AT, 3 N
+const BASE_CLASS: [u8; 256] = {
+    let mut t = [0u8; 256];
+    t[b'G' as usize] = 1;
+    t[b'C' as usize] = 1;
+    t[b'g' as usize] = 1;
+    t[b'c' as usize] = 1;
+    t[b'A' as usize] = 2;
+    t[b'T' as usize] = 2;
+    t[b'U' as usize] = 2;
+    t[b'a' as usize] = 2;
+    t[b't' as usize] = 2;
+    t[b'u' as usize] = 2;
+    t[b'N' as usize] = 3;
+    t[b'n' as usize] = 3;
+    t
+};
+
+impl BaseCounts {
+    pub fn add(&mut self, seq: &[u8]) {
+        let mut by_class = [0u64; 4];
+        for &b in seq {
+            by_class[BASE_CLASS[b as usize] as usize] += 1;
+        }
+        self.other += by_class[0];
+        self.gc += by_class[1];
+        self.at += by_class[2];
+        self.n += by_class[3];
+    }
+
+    pub fn total(&self) -> u64 {
+        self.gc + self.at + self.n + self.other
+    }
+
+    /// G+C over A+C+G+T(+U); `None` without any such base (e.g. all N).
+    pub fn gc_fraction(&self) -> Option<f64> {
+        let acgt = self.gc + self.at;
+        (acgt > 0).then(|| self.gc as f64 / acgt as f64)
+    }
+
+    /// N over all bases; `None` for an empty stretch.
+    pub fn n_fraction(&self) -> Option<f64> {
+        let total = self.total();
+        (total > 0).then(|| self.n as f64 / total as f64)
+    }
+}
+
+/// Random access to an uncompressed FASTA through its `.fai`.
+///
+/// `<fasta>.fai` is used when present; otherwise the FASTA is scanned once on open
+/// (nothing is written next to it). The FASTA itself is mapped (or read, see
+/// `ByteSource`), so fetching a range copies nothing.
+#[derive(Debug)]
+pub struct Fasta {
+    data: ByteSource,
+    entries: Vec<FaiEntry>,
+    by_name: FxHashMap<String, usize>,
+}
+
+impl Fasta {
+    pub fn open(path: &Path) -> Result<Self> {
+        if path.extension().is_some_and(|e| e == "gz" || e == "bgz") {
+            bail!("Compressed FASTA {:?} is not supported; decompress it first", path);
+        }
+        let data = ByteSource::open(path).with_context(|| format!("Cannot open FASTA {:?}", path))?;
+        let fai_path = append_suffix(path, ".fai");
+        let entries = if fai_path.exists() {
+            let text = std::fs::read_to_string(&fai_path).with_context(|| format!("Cannot read {:?}", fai_path))?;
+            parse_fai(&text).with_context(|| format!("Invalid FASTA index {:?}", fai_path))?
+        } else {
+            let entries = build_fai(&data).with_context(|| format!("Cannot index FASTA {:?}", path))?;
+            diag!("[INFO] No {:?}; scanned the FASTA ({} sequence(s))", fai_path, entries.len());
+            entries
+        };
+        let by_name = entries.iter().enumerate().map(|(i, e)| (e.name.clone(), i)).collect();
+        Ok(Self { data, entries, by_name })
+    }
+
+    pub fn entries(&self) -> &[FaiEntry] {
+        &self.entries
+    }
+
+    /// Length of sequence `name`, if the FASTA has it.
+    pub fn seq_len(&self, name: &str) -> Option<u64> {
+        self.by_name.get(name).map(|&i| self.entries[i].len)
+    }
+
+    /// Bases `start..end` (0-based, half-open) of `name` as line-sized slices of the file,
+    /// in order. Fails when the sequence is unknown, the range passes its end, or the
+    /// `.fai` points past the end of the file.
+    pub fn segments(&self, name: &str, start: u64, end: u64) -> Result<Vec<&[u8]>> {
+        let Some(e) = self.by_name.get(name).map(|&i| &self.entries[i]) else {
+            bail!("Sequence {:?} is not in the FASTA", name);
+        };
+        if start > end || end > e.len {
+            bail!("Range {}..{} is outside {:?} ({} bp)", start, end, name, e.len);
+        }
+        let mut out = Vec::with_capacity(((end - start) / e.line_bases + 2) as usize);
+        let mut p = start;
+        while p < end {
+            let (line, col) = (p / e.line_bases, p % e.line_bases);
+            let n = (e.line_bases - col).min(end - p);
+            let at = e.offset + line * e.line_width + col;
+            let Some(bytes) = self.data.get(at as usize..(at + n) as usize) else {
+                bail!("FASTA index entry of {:?} points past the end of the file; rebuild the .fai", name);
+            };
+            out.push(bytes);
+            p += n;
+        }
+        Ok(out)
+    }
+
+    /// Bases `start..end` (0-based, half-open) of `name`, copied.
+    pub fn fetch(&self, name: &str, start: u64, end: u64) -> Result<Vec<u8>> {
+        Ok(self.segments(name, start, end)?.concat())
+    }
+
+    /// Base counts of `start..end` (0-based, half-open) of `name`.
+    pub fn base_counts(&self, name: &str, start: u64, end: u64) -> Result<BaseCounts> {
+        let mut counts = BaseCounts::default();
+        for s in self.segments(name, start, end)? {
+            counts.add(s);
+        }
+        Ok(counts)
+    }
+}
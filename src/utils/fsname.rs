@@ -0,0 +1,166 @@
+use anyhow::{Result, bail};
+use rustc_hash::FxHashMap;
+use std::{
+    fmt::Write as FmtWrite,
+    path::{Path, PathBuf},
+};
+
+/// Longest name `sanitize` returns, in bytes. File systems allow 255 bytes (or UTF-16
+/// units, which are never more than bytes); the rest is left for the caller's extension.
+pub const MAX_NAME_BYTES: usize = 200;
+
+/// Clashing IDs listed individually in the `sanitize_all` error.
+const MAX_CLASH_REPORTS: usize = 10;
+
+/// Base names Windows reserves for devices, with or without an extension.
+const RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1",
+    "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Characters that are escaped wherever they occur: path separators, the characters
+/// Windows forbids in names, and `%` itself (so escaping stays reversible).
+#[inline]
+fn is_escaped(c: char) -> bool {
+    c.is_ascii_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '%')
+}
+
+fn push_escape(out: &mut String, c: char) {
+    let mut utf8 = [0u8; 4];
+    for b in c.encode_utf8(&mut utf8).bytes() {
+        // Writing to a String cannot fail
+        let _ = write!(out, "%{b:02X}");
+    }
+}
+
+/// 64-bit FNV-1a of `bytes`, for the suffix of truncated names.
+fn fnv1a64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |h, &b| (h ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
+/// A file name for `id` that is valid on Unix and Windows alike, without extension.
+///
+/// Replacement map (percent-encoding of the UTF-8 bytes, upper-case hex):
+/// - control characters and `/ \ : * ? " < > | %` anywhere, e.g. `a|b` -> `a%7Cb`;
+/// - a leading `.` (no hidden files, `.` or `..`) and a trailing `.` or space, which
+///   Windows drops;
+/// - the last character of a Windows device name (`CON`, `NUL`, `COM1`, ... in any
+///   case, also before a `.`), e.g. `nul.txt` -> `nu%6C.txt`;
+/// - the empty ID becomes `%`.
+///
+/// Everything else, non-ASCII included, is kept, so distinct IDs get distinct names
+/// (see `unsanitize`) up to `MAX_NAME_BYTES`. Longer names are cut at a character
+/// boundary and end in `%~` and 16 hex digits of a hash of the whole ID; `%~` never
+/// occurs in an uncut name.
+pub fn sanitize(id: &str) -> String {
+    if id.is_empty() {
+        return "%".to_string();
+    }
+    let stem = id.split('.').next().unwrap_or_default();
+    let reserved_at = RESERVED.iter().any(|r| stem.eq_ignore_ascii_case(r)).then(|| stem.len() - 1);
+    let last = id.len() - id.chars().next_back().map_or(0, char::len_utf8);
+
+    let mut out = String::with_capacity(id.len());
+    for (i, c) in id.char_indices() {
+        let escape = is_escaped(c)
+            || (i == 0 && c == '.')
+            || (i == last && matches!(c, '.' | ' '))
+            || reserved_at == Some(i);
+        if escape {
+            push_escape(&mut out, c);
+        } else {
+            out.push(c);
+        }
+    }
+    if out.len() <= MAX_NAME_BYTES {
+        return out;
+    }
+
+    // Cut before an escape or a multi-byte character that would straddle the limit
+    let suffix = format!("%~{:016x}", fnv1a64(id.as_bytes()));
+    let mut cut = MAX_NAME_BYTES - suffix.len();
+    while !out.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    if let Some(p) = out.as_bytes()[cut - 2..cut].iter().position(|&b| b == b'%') {
+        cut = cut - 2 + p;
+    }
+    // A trailing '.' or ' ' would be dropped by Windows
+    while out[..cut].ends_with(['.', ' ']) {
+        cut -= 1;
+    }
+    out.truncate(cut);
+    out.push_str(&suffix);
+    out
+}
+
+/// The ID `sanitize` turned into `name`, or `None` when `name` is not such a name
+/// (invalid escape) or was truncated (ends in a hash: the ID cannot be recovered).
+pub fn unsanitize(name: &str) -> Option<String> {
+    if name == "%" {
+        return Some(String::new());
+    }
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut rest = name.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = tail.get(..2)?;
+            if !hex.iter().all(|h| h.is_ascii_digit() || (b'A'..=b'F').contains(h)) {
+                return None;
+            }
+            bytes.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// `sanitize` every ID, in order, failing when two distinct IDs would share a file.
+///
+/// Names are compared case-insensitively, as on Windows and macOS file systems, so
+/// `Gene1` and `gene1` clash; so do two long IDs whose truncated names happen to be
+/// equal. The error lists the clashing IDs.
+pub fn sanitize_all<'a>(ids: impl IntoIterator<Item = &'a str>) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    // case-folded name -> first ID given it
+    let mut seen: FxHashMap<String, &str> = FxHashMap::default();
+    let mut clashes: Vec<(&str, &str, String)> = Vec::new();
+    for id in ids {
+        let name = sanitize(id);
+        match seen.get(&name.to_lowercase()) {
+            Some(&first) if first != id => clashes.push((first, id, name.clone())),
+            Some(_) => {}
+            None => {
+                seen.insert(name.to_lowercase(), id);
+            }
+        }
+        names.push(name);
+    }
+    if !clashes.is_empty() {
+        let mut listed: Vec<String> = clashes
+            .iter()
+            .take(MAX_CLASH_REPORTS)
+            .map(|(a, b, name)| format!("{a:?} and {b:?} (file name {name:?})"))
+            .collect();
+        if clashes.len() > MAX_CLASH_REPORTS {
+            listed.push(format!("... and {} more", clashes.len() - MAX_CLASH_REPORTS));
+        }
+        bail!(
+            "{} feature ID(s) would share a file name with another ID (names are case-insensitive on \
+             Windows and macOS): {}",
+            clashes.len(),
+            listed.join("; ")
+        );
+    }
+    Ok(names)
+}
+
+/// `<dir>/<sanitize(id)><ext>`, e.g. `path_for(dir, "a|b", ".gff")` -> `dir/a%7Cb.gff`.
+pub fn path_for(dir: &Path, id: &str, ext: &str) -> PathBuf {
+    let mut name = sanitize(id);
+    name.push_str(ext);
+    dir.join(name)
+}
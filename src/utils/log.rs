@@ -0,0 +1,136 @@
+use std::{
+    fmt,
+    io::{self, IsTerminal, Write},
+    path::Path,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+/// Set by `--summary-only`: diagnostics are dropped, only the run summary is printed.
+static SUMMARY_ONLY: AtomicBool = AtomicBool::new(false);
+/// Color `[WARN]` prefixes and errors on stderr.
+static COLOR: AtomicBool = AtomicBool::new(false);
+/// Summary set by the command that ran, printed by `report_summary`.
+static SUMMARY: Mutex<Option<RunSummary>> = Mutex::new(None);
+
+/// `eprintln!` for diagnostics (`[WARN]`, `[INFO]`, `[DEBUG]`, `[TIMER]` lines): dropped
+/// under `--summary-only`, and the `[WARN]` prefix is colored when color is on.
+macro_rules! diag {
+    ($($arg:tt)*) => {
+        $crate::utils::log::diag_line(format_args!($($arg)*))
+    };
+}
+
+#[doc(hidden)]
+pub fn diag_line(args: fmt::Arguments<'_>) {
+    if summary_only() {
+        return;
+    }
+    let line = args.to_string();
+    let mut err = io::stderr().lock();
+    let _ = match line.strip_prefix("[WARN]") {
+        Some(rest) if color_enabled() => writeln!(err, "\x1b[33m[WARN]\x1b[0m{}", rest),
+        _ => writeln!(err, "{}", line),
+    };
+}
+
+/// Suppress every diagnostic but the end-of-run summary.
+pub fn set_summary_only(enabled: bool) {
+    SUMMARY_ONLY.store(enabled, Ordering::Relaxed);
+}
+
+#[inline]
+pub fn summary_only() -> bool {
+    SUMMARY_ONLY.load(Ordering::Relaxed)
+}
+
+pub fn set_color(enabled: bool) {
+    COLOR.store(enabled, Ordering::Relaxed);
+}
+
+#[inline]
+pub fn color_enabled() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+/// Whether stderr should be colored: it is a terminal and `NO_COLOR` is unset or empty.
+pub fn color_default() -> bool {
+    std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && io::stderr().is_terminal()
+}
+
+/// Print a fatal error (`Error: ...`, red when color is on); never suppressed.
+pub fn report_error(err: &anyhow::Error) {
+    if color_enabled() {
+        eprintln!("\x1b[1;31mError:\x1b[0m {:?}", err);
+    } else {
+        eprintln!("Error: {:?}", err);
+    }
+}
+
+/// One-line end-of-run summary of a command: inputs, what matched, what was written.
+///
+/// Commands fill one in and hand it to `set_summary`; the CLI prints it with the
+/// elapsed time when the command succeeds.
+#[derive(Debug, Clone, Default)]
+pub struct RunSummary {
+    inputs: Vec<String>,
+    counts: Vec<String>,
+    output: Option<String>,
+}
+
+impl RunSummary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn input(mut self, path: impl AsRef<Path>) -> Self {
+        self.inputs.push(path.as_ref().display().to_string());
+        self
+    }
+
+    /// `matched N of M <what>`, e.g. `matched 0 of 1200 feature ID(s)`.
+    pub fn matched(mut self, matched: usize, of: usize, what: &str) -> Self {
+        self.counts.push(format!("matched {} of {} {}", matched, of, what));
+        self
+    }
+
+    /// `N <what>`, e.g. `34 model(s)`.
+    pub fn count(mut self, n: usize, what: &str) -> Self {
+        self.counts.push(format!("{} {}", n, what));
+        self
+    }
+
+    /// Where the output went; `None` is stdout.
+    pub fn output(mut self, path: Option<&Path>) -> Self {
+        self.output = Some(path.map_or_else(|| "stdout".to_string(), |p| p.display().to_string()));
+        self
+    }
+
+    /// `[SUMMARY] <command>: in <inputs> | <counts> | out <output> | <elapsed>`
+    pub fn render(&self, command: &str, elapsed: Duration) -> String {
+        let mut parts: Vec<String> = Vec::with_capacity(self.counts.len() + 3);
+        if !self.inputs.is_empty() {
+            parts.push(format!("in {}", self.inputs.join(",")));
+        }
+        parts.extend(self.counts.iter().cloned());
+        if let Some(out) = &self.output {
+            parts.push(format!("out {}", out));
+        }
+        parts.push(format!("{:.2?}", elapsed));
+        format!("[SUMMARY] {}: {}", command, parts.join(" | "))
+    }
+}
+
+/// Record the summary of the running command (the last call wins).
+pub fn set_summary(summary: RunSummary) {
+    *SUMMARY.lock().unwrap_or_else(|e| e.into_inner()) = Some(summary);
+}
+
+/// Print the summary line of `command` to stderr (also under `--summary-only`).
+pub fn report_summary(command: &str, elapsed: Duration) {
+    let summary = SUMMARY.lock().unwrap_or_else(|e| e.into_inner()).take().unwrap_or_default();
+    eprintln!("{}", summary.render(command, elapsed));
+}
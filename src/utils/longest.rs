@@ -0,0 +1,235 @@
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::{
+    fs::File,
+    io::{BufWriter, Write, stdout},
+    path::{Path, PathBuf},
+    str,
+};
+
+use crate::{CountingWriter, RangeSource, TypeFilter, align_blocks_to_lines, fast_id, write_source_header};
+
+/// How `--longest-transcript` measures the transcripts of a model
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TranscriptMetric {
+    /// Total length of the CDS children (0 for non-coding transcripts)
+    Cds,
+    /// Total length of the exon children (the transcript's span when it has none)
+    Exonic,
+    /// Genomic span of the transcript line
+    Span,
+}
+
+/// Columns of one feature line that transcript selection needs.
+struct BlockFeature<'a> {
+    ftype: &'a str,
+    start: u32,
+    end: u32,
+    id: Option<&'a str>,
+    parents: Vec<&'a str>,
+}
+
+fn parse_feature(line: &str) -> Option<BlockFeature<'_>> {
+    let mut cols = line.split('\t');
+    let ftype = cols.nth(2)?;
+    let start = cols.next()?.trim().parse().ok()?;
+    let end = cols.next()?.trim().parse().ok()?;
+    let attrs = cols.nth(3)?;
+    let parents = attrs
+        .split(';')
+        .find_map(|kv| kv.trim().strip_prefix("Parent="))
+        .map(|v| v.split(',').map(str::trim).filter(|p| !p.is_empty()).collect())
+        .unwrap_or_default();
+    Some(BlockFeature { ftype, start, end, id: fast_id(attrs), parents })
+}
+
+/// Bases covered by 1-based inclusive intervals, overlaps counted once.
+fn covered_length(mut spans: Vec<(u32, u32)>) -> u64 {
+    spans.sort_unstable();
+    let mut total = 0u64;
+    let mut cur: Option<(u32, u32)> = None;
+    for (s, e) in spans {
+        match cur {
+            Some((cs, ce)) if s <= ce.saturating_add(1) => cur = Some((cs, ce.max(e))),
+            _ => {
+                if let Some((cs, ce)) = cur {
+                    total += u64::from(ce - cs) + 1;
+                }
+                cur = Some((s, e));
+            }
+        }
+    }
+    total + cur.map_or(0, |(cs, ce)| u64::from(ce - cs) + 1)
+}
+
+/// Append the lines of one block to `out` with every transcript but the longest
+/// removed, and return the number of transcripts removed.
+///
+/// Transcripts are the children of the root (the block's first feature) of type
+/// `mRNA` or `transcript`, or with exon children. The one with the largest `metric`
+/// wins, ties going to the smaller ID; the others are dropped with every feature all
+/// of whose parents are dropped. Blocks with fewer than two transcripts (e.g. a
+/// single-exon gene without a transcript line) are copied unchanged, as are comment
+/// lines and other children of the root. `types_filter` applies to the kept lines.
+pub fn select_longest_transcript(
+    block: &[u8],
+    metric: TranscriptMetric,
+    types_filter: Option<&TypeFilter>,
+    out: &mut Vec<u8>,
+) -> usize {
+    let lines: Vec<(&[u8], Option<BlockFeature>)> = block
+        .split_inclusive(|&b| b == b'\n')
+        .map(|raw| {
+            let body = raw.strip_suffix(b"\n").unwrap_or(raw);
+            let body = body.strip_suffix(b"\r").unwrap_or(body);
+            let feature = (!body.is_empty() && body[0] != b'#')
+                .then(|| str::from_utf8(body).ok())
+                .flatten()
+                .and_then(parse_feature);
+            (raw, feature)
+        })
+        .collect();
+    let features = || lines.iter().filter_map(|(_, f)| f.as_ref());
+
+    let mut children: FxHashMap<&str, Vec<&BlockFeature>> = FxHashMap::default();
+    for f in features() {
+        for &p in &f.parents {
+            children.entry(p).or_default().push(f);
+        }
+    }
+    let child_spans = |id: &str, ftype: &str| -> Vec<(u32, u32)> {
+        children.get(id).map_or_else(Vec::new, |c| {
+            c.iter().filter(|f| f.ftype == ftype).map(|f| (f.start, f.end)).collect()
+        })
+    };
+
+    // Transcripts of the root, once per ID (a transcript may span several lines)
+    let root = features().next().and_then(|f| f.id);
+    let mut spans: FxHashMap<&str, (u32, u32)> = FxHashMap::default();
+    let mut transcripts: Vec<&str> = Vec::new();
+    for f in features() {
+        let Some(id) = f.id else { continue };
+        let is_transcript = matches!(f.ftype, "mRNA" | "transcript") || !child_spans(id, "exon").is_empty();
+        if root.is_none_or(|r| !f.parents.contains(&r)) || !is_transcript {
+            continue;
+        }
+        match spans.get_mut(id) {
+            Some(span) => *span = (span.0.min(f.start), span.1.max(f.end)),
+            None => {
+                spans.insert(id, (f.start, f.end));
+                transcripts.push(id);
+            }
+        }
+    }
+    if transcripts.len() < 2 {
+        for (raw, f) in &lines {
+            if f.as_ref().is_none_or(|_| types_filter.is_none_or(|t| t.allows_line(raw))) {
+                out.extend_from_slice(raw);
+            }
+        }
+        return 0;
+    }
+
+    let length = |id: &str| -> u64 {
+        let (s, e) = spans[id];
+        match metric {
+            TranscriptMetric::Span => u64::from(e - s) + 1,
+            TranscriptMetric::Cds => covered_length(child_spans(id, "CDS")),
+            TranscriptMetric::Exonic => match child_spans(id, "exon") {
+                exons if exons.is_empty() => u64::from(e - s) + 1,
+                exons => covered_length(exons),
+            },
+        }
+    };
+    let mut measured: Vec<(u64, &str)> = transcripts.iter().map(|&id| (length(id), id)).collect();
+    measured.sort_unstable_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+
+    // Dropped: the losing transcripts and, transitively, features whose parents all are
+    let mut dropped: FxHashSet<&str> = measured[1..].iter().map(|&(_, id)| id).collect();
+    let orphaned = |f: &BlockFeature, dropped: &FxHashSet<&str>| {
+        !f.parents.is_empty() && f.parents.iter().all(|p| dropped.contains(p))
+    };
+    loop {
+        let before = dropped.len();
+        for f in features() {
+            if let Some(id) = f.id
+                && orphaned(f, &dropped)
+            {
+                dropped.insert(id);
+            }
+        }
+        if dropped.len() == before {
+            break;
+        }
+    }
+
+    for (raw, f) in &lines {
+        let keep = match f {
+            None => true,
+            Some(f) => {
+                !f.id.is_some_and(|id| dropped.contains(id))
+                    && !orphaned(f, &dropped)
+                    && types_filter.is_none_or(|t| t.allows_line(raw))
+            }
+        };
+        if keep {
+            out.extend_from_slice(raw);
+        }
+    }
+    measured.len() - 1
+}
+
+/// Write whole blocks reduced to their longest transcript (see
+/// `select_longest_transcript`), in file order.
+pub fn write_gff_longest_transcripts(
+    gff_path: &Path,
+    blocks: &[(u32, u64, u64)],
+    metric: TranscriptMetric,
+    output_path: &Option<PathBuf>,
+    types_filter: Option<&TypeFilter>,
+    verbose: bool,
+) -> Result<()> {
+    let gff = RangeSource::open(gff_path)?;
+    let file_len = gff.len();
+    let blocks = &align_blocks_to_lines(&gff, blocks)?;
+
+    let mut sorted: Vec<(u32, u64, u64)> = blocks
+        .iter()
+        .copied()
+        .filter(|&(_, s, e)| s < e && e <= file_len)
+        .collect();
+    sorted.sort_unstable_by_key(|&(_, s, _)| s);
+    sorted.dedup_by_key(|b| b.1);
+
+    let parts: Vec<(Vec<u8>, usize)> = sorted
+        .par_iter()
+        .map(|&(_, s, e)| -> Result<(Vec<u8>, usize)> {
+            let block = gff.range(s, e)?;
+            let mut out = Vec::<u8>::with_capacity(block.len());
+            let n = select_longest_transcript(&block, metric, types_filter, &mut out);
+            if out.last().is_some_and(|&b| b != b'\n') {
+                out.push(b'\n');
+            }
+            Ok((out, n))
+        })
+        .collect::<Result<_>>()?;
+
+    let raw: Box<dyn Write> = match output_path {
+        Some(p) => Box::new(CountingWriter(File::create(p).with_context(|| format!("Cannot create output: {:?}", p))?)),
+        None => Box::new(CountingWriter(stdout())),
+    };
+    let mut writer = BufWriter::with_capacity(16 * 1024 * 1024, raw);
+    write_source_header(&gff, &mut writer)?;
+    let mut removed = 0usize;
+    for (buf, n) in &parts {
+        writer.write_all(buf)?;
+        removed += n;
+    }
+    writer.flush()?;
+
+    if verbose {
+        diag!("[INFO] Removed {} shorter transcript(s) from {} block(s)", removed, parts.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,219 @@
+use anyhow::{Context, Result, bail};
+use memchr::memchr;
+use rustc_hash::FxHashMap;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use crate::{ByteSource, check_gff_present, load_meta};
+
+/// Result of clamping a half-open region `[start, end)` to a sequence length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clamp {
+    /// Region lies fully inside the sequence (or the length is unknown).
+    Inside(u32, u32),
+    /// Region extended past the end of the sequence and was truncated.
+    Clamped(u32, u32),
+    /// Region starts at or beyond the end of the sequence.
+    Outside,
+}
+
+/// Sequence lengths used to bound user-supplied regions.
+///
+/// Lengths come either from a `chrom.sizes` file (`--genome`) or from the
+/// `##sequence-region` directives of the GFF, as recorded in the index `.meta` (or read
+/// from the GFF header for indexes built before they were recorded).
+#[derive(Debug, Default, Clone)]
+pub struct SeqLengths {
+    lens: FxHashMap<String, u32>,
+}
+
+impl SeqLengths {
+    /// Load from a two-column `chrom.sizes` file (`<seqid>\t<length>`).
+    pub fn from_chrom_sizes(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Cannot open genome file: {:?}", path))?;
+        let mut lens = FxHashMap::default();
+        for (n, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_ascii_whitespace();
+            let (Some(seq), Some(len)) = (parts.next(), parts.next()) else {
+                bail!("Invalid genome file line {} (expected '<seqid> <length>'): {}", n + 1, line);
+            };
+            let len = len
+                .parse::<u32>()
+                .with_context(|| format!("Invalid length on genome file line {}: {}", n + 1, line))?;
+            lens.insert(seq.to_string(), len);
+        }
+        Ok(Self { lens })
+    }
+
+    /// Collect lengths from `##sequence-region <seqid> <start> <end>` directives
+    /// in the leading header block of a GFF file. A GFF that is gone while its index
+    /// is present yields no lengths (with a warning), so index-only queries still run.
+    pub fn from_gff_header(gff_path: &Path) -> Result<Self> {
+        if check_gff_present(gff_path).is_err() {
+            diag!(
+                "[WARN] GFF file {:?} not found; region ends are not checked against sequence lengths (pass --genome)",
+                gff_path
+            );
+            return Ok(Self::default());
+        }
+        let mmap = ByteSource::open_gff(gff_path)?;
+        let data = &mmap[..];
+        let mut lens = FxHashMap::default();
+        let mut pos = 0usize;
+        while pos < data.len() && data[pos] == b'#' {
+            let nl = memchr(b'\n', &data[pos..]).map(|i| pos + i).unwrap_or(data.len());
+            let line = &data[pos..nl];
+            pos = nl + 1;
+            if let Some((seq, end)) = parse_sequence_region(line) {
+                lens.insert(seq.to_string(), end);
+            }
+        }
+        Ok(Self { lens })
+    }
+
+    /// The lengths recorded in the `.meta` of `gff_path`'s index at `gffx index` time,
+    /// or `None` when the index has no `.meta` or one written before they were recorded.
+    pub fn from_index_meta(gff_path: &Path) -> Result<Option<Self>> {
+        let Some(lengths) = load_meta(gff_path)?.and_then(|m| m.seq_lengths) else {
+            return Ok(None);
+        };
+        Ok(Some(Self { lens: lengths.into_iter().collect() }))
+    }
+
+    /// Prefer an explicit `chrom.sizes` file; otherwise the lengths recorded in the
+    /// index, falling back to the GFF header for older indexes.
+    pub fn load(gff_path: &Path, genome: Option<&Path>) -> Result<Self> {
+        if let Some(g) = genome {
+            return Self::from_chrom_sizes(g);
+        }
+        match Self::from_index_meta(gff_path)? {
+            Some(lens) => Ok(lens),
+            None => Self::from_gff_header(gff_path),
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, seqid: &str) -> Option<u32> {
+        self.lens.get(seqid).copied()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.lens.is_empty()
+    }
+
+    /// Clamp a window `[start - left, end + right)` around a half-open region to
+    /// `[0, seq_len)` (to `[0, u32::MAX]` when the length is unknown). Returns the
+    /// window and whether it was cut at either end.
+    pub fn clamp_window(&self, seqid: &str, start: u32, end: u32, left: u32, right: u32) -> (u32, u32, bool) {
+        let lo = start.checked_sub(left);
+        let hi = end.checked_add(right);
+        let len = self.get(seqid).unwrap_or(u32::MAX);
+        let hi_in = hi.filter(|&h| h <= len);
+        (lo.unwrap_or(0), hi_in.unwrap_or(len), lo.is_none() || hi_in.is_none())
+    }
+
+    /// Clamp a half-open region `[start, end)` on `seqid` to `[0, seq_len)`.
+    /// Sequences with unknown length are returned unchanged.
+    pub fn clamp(&self, seqid: &str, start: u32, end: u32) -> Clamp {
+        match self.get(seqid) {
+            None => Clamp::Inside(start, end),
+            Some(len) if start >= len => Clamp::Outside,
+            Some(len) if end > len => Clamp::Clamped(start, len),
+            Some(_) => Clamp::Inside(start, end),
+        }
+    }
+}
+
+/// `(seqid, end)` of a `##sequence-region <seqid> <start> <end>` directive line.
+pub(crate) fn parse_sequence_region(line: &[u8]) -> Option<(&str, u32)> {
+    let rest = std::str::from_utf8(line.strip_prefix(b"##sequence-region")?).ok()?;
+    let mut parts = rest.split_ascii_whitespace();
+    let (seq, _, end) = (parts.next()?, parts.next()?, parts.next()?);
+    Some((seq, end.parse::<u32>().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{SAMPLE_GFF, ScratchDir, build_fixture};
+
+    fn lens(pairs: &[(&str, u32)]) -> SeqLengths {
+        SeqLengths { lens: pairs.iter().map(|&(s, l)| (s.to_string(), l)).collect() }
+    }
+
+    #[test]
+    fn clamp_near_zero_and_past_end() {
+        let l = lens(&[("chr1", 1000)]);
+        assert_eq!(l.clamp("chr1", 0, 1), Clamp::Inside(0, 1));
+        assert_eq!(l.clamp("chr1", 0, 1000), Clamp::Inside(0, 1000));
+        assert_eq!(l.clamp("chr1", 990, 1001), Clamp::Clamped(990, 1000));
+        assert_eq!(l.clamp("chr1", 0, u32::MAX), Clamp::Clamped(0, 1000));
+        assert_eq!(l.clamp("chr1", 999, 1010), Clamp::Clamped(999, 1000));
+        assert_eq!(l.clamp("chr1", 1000, 1010), Clamp::Outside);
+        // Unknown sequences are not bounded
+        assert_eq!(l.clamp("chrX", 5000, 6000), Clamp::Inside(5000, 6000));
+    }
+
+    #[test]
+    fn clamp_window_cuts_at_either_end() {
+        let l = lens(&[("chr1", 1000)]);
+        assert_eq!(l.clamp_window("chr1", 100, 200, 50, 50), (50, 250, false));
+        assert_eq!(l.clamp_window("chr1", 50, 200, 50, 0), (0, 200, false));
+        assert_eq!(l.clamp_window("chr1", 10, 200, 50, 0), (0, 200, true));
+        assert_eq!(l.clamp_window("chr1", 0, 10, 1, 0), (0, 10, true));
+        assert_eq!(l.clamp_window("chr1", 900, 950, 0, 50), (900, 1000, false));
+        assert_eq!(l.clamp_window("chr1", 900, 990, 0, 11), (900, 1000, true));
+        assert_eq!(l.clamp_window("chrX", 10, u32::MAX - 5, 5, 20), (5, u32::MAX, true));
+    }
+
+    #[test]
+    fn sequence_region_lines() {
+        assert_eq!(parse_sequence_region(b"##sequence-region chr1 1 5000"), Some(("chr1", 5000)));
+        assert_eq!(parse_sequence_region(b"##sequence-region  ctg.2\t1\t77 "), Some(("ctg.2", 77)));
+        assert_eq!(parse_sequence_region(b"##sequence-region chr1 1"), None);
+        assert_eq!(parse_sequence_region(b"##sequence-region chr1 1 -3"), None);
+        assert_eq!(parse_sequence_region(b"##gff-version 3"), None);
+    }
+
+    #[test]
+    fn lengths_from_the_gff_header_and_chrom_sizes() {
+        let dir = ScratchDir::new("seq-lengths").unwrap();
+        let gff = dir.join("g.gff");
+        std::fs::write(
+            &gff,
+            "##gff-version 3\n##sequence-region chr1 1 1000\n##sequence-region chr2 1 20\n\
+             chr1\t.\tgene\t1\t100\t.\t+\t.\tID=g1\n##sequence-region chr3 1 5\n",
+        )
+        .unwrap();
+        let l = SeqLengths::load(&gff, None).unwrap();
+        // Only the leading header block is read
+        assert_eq!((l.get("chr1"), l.get("chr2"), l.get("chr3")), (Some(1000), Some(20), None));
+
+        let sizes = dir.join("g.sizes");
+        std::fs::write(&sizes, "# sizes\nchr1\t500\n\nchr4 7\n").unwrap();
+        let l = SeqLengths::load(&gff, Some(&sizes)).unwrap();
+        assert_eq!((l.get("chr1"), l.get("chr2"), l.get("chr4")), (Some(500), None, Some(7)));
+        std::fs::write(&sizes, "chr1\tlong\n").unwrap();
+        assert!(SeqLengths::from_chrom_sizes(&sizes).is_err());
+    }
+
+    #[test]
+    fn lengths_recorded_at_index_time() {
+        let fx = build_fixture(SAMPLE_GFF).unwrap();
+        let recorded = SeqLengths::from_index_meta(&fx.gff).unwrap().expect("lengths recorded in .meta");
+        assert_eq!((recorded.get("chr1"), recorded.get("chr2")), (Some(10000), Some(5000)));
+        // Without --genome the recorded lengths are used; the header is not re-read
+        std::fs::write(&fx.gff, "##sequence-region chr1 1 5\n").unwrap();
+        assert_eq!(SeqLengths::load(&fx.gff, None).unwrap().get("chr1"), Some(10000));
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    io::{self, Write},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+/// Set by `--stats`; counters stay untouched (one relaxed load per call) otherwise.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Per-run I/O and query counters reported by `--stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Counter {
+    /// Bytes of files memory-mapped by `ByteSource`/`RangeSource`
+    BytesMapped,
+    /// Bytes read into memory when a file is not mapped
+    BytesRead,
+    /// Bytes written to output files or stdout
+    BytesWritten,
+    /// Interval-tree queries
+    TreeQueries,
+    /// GFF blocks scanned line by line for output
+    BlockScans,
+    /// Blocks widened to line boundaries by `align_blocks_to_lines`
+    MisalignedBlocks,
+}
+
+const N_COUNTERS: usize = 6;
+
+static COUNTERS: [AtomicU64; N_COUNTERS] = [const { AtomicU64::new(0) }; N_COUNTERS];
+
+/// Enable (or disable) counting for the rest of the run.
+pub fn set_stats(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+#[inline]
+pub fn stats_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Add `n` to `counter` when `--stats` is on.
+#[inline]
+pub fn record(counter: Counter, n: u64) {
+    if stats_enabled() {
+        COUNTERS[counter as usize].fetch_add(n, Ordering::Relaxed);
+    }
+}
+
+#[inline]
+pub fn counter_value(counter: Counter) -> u64 {
+    COUNTERS[counter as usize].load(Ordering::Relaxed)
+}
+
+/// Peak resident set size of this process in bytes (Linux `VmHWM`); `None` where
+/// it is not available.
+pub fn peak_rss_bytes() -> Option<u64> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kb = status
+        .lines()
+        .find_map(|l| l.strip_prefix("VmHWM:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+/// Print the counters and peak RSS to stderr (`[STATS]` lines, after the `[TIMER]` ones).
+pub fn report_stats() {
+    if !stats_enabled() {
+        return;
+    }
+    let rss = peak_rss_bytes().map_or_else(|| "n/a".to_string(), |b| b.to_string());
+    eprintln!("[STATS] Peak RSS (bytes):     {}", rss);
+    eprintln!("[STATS] Bytes mmapped:        {}", counter_value(Counter::BytesMapped));
+    eprintln!("[STATS] Bytes read:           {}", counter_value(Counter::BytesRead));
+    eprintln!("[STATS] Bytes written:        {}", counter_value(Counter::BytesWritten));
+    eprintln!("[STATS] Tree queries:         {}", counter_value(Counter::TreeQueries));
+    eprintln!("[STATS] Block scans:          {}", counter_value(Counter::BlockScans));
+    eprintln!("[STATS] Misaligned blocks:    {}", counter_value(Counter::MisalignedBlocks));
+}
+
+/// Output sink that adds every byte written to `Counter::BytesWritten`.
+/// Wrap the raw file/stdout (under any `BufWriter`) so it counts once per flush.
+#[derive(Debug)]
+pub struct CountingWriter<W>(pub W);
+
+impl<W: Write> Write for CountingWriter<W> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.0.write(buf)?;
+        record(Counter::BytesWritten, n as u64);
+        Ok(n)
+    }
+
+    #[inline]
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        let n = self.0.write_vectored(bufs)?;
+        record(Counter::BytesWritten, n as u64);
+        Ok(n)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
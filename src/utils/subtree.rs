@@ -0,0 +1,89 @@
+use anyhow::Result;
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::{path::Path, str};
+
+use crate::{RangeSource, attr_value};
+
+const MISSING: u64 = u64::MAX;
+
+/// The values that select the subtrees of `seeds` in one root block: the seed IDs, the
+/// IDs of their transitive children (following `parent_key`, comma-separated values
+/// included), and the raw `parent_key` value of each ID-less line below them.
+///
+/// Lines are then kept when their `id_key` or `parent_key` value is in the set (as
+/// `LineSelection` with both keys does): every line of the subtrees, and no sibling or
+/// ancestor of a seed.
+pub fn block_descendants(
+    block: &[u8],
+    seeds: &FxHashSet<String>,
+    id_key: &str,
+    parent_key: &str,
+) -> FxHashSet<String> {
+    // Features as (ID, raw parent value), and parent ID -> child features
+    let mut feats: Vec<(Option<&str>, &str)> = Vec::new();
+    let mut children: FxHashMap<&str, Vec<usize>> = FxHashMap::default();
+    for raw in block.split(|&b| b == b'\n') {
+        let line = raw.strip_suffix(b"\r").unwrap_or(raw);
+        if line.is_empty() || line[0] == b'#' {
+            continue;
+        }
+        let Some(attrs) = line.splitn(9, |&b| b == b'\t').nth(8) else { continue };
+        let value = |key: &str| attr_value(attrs, key.as_bytes()).and_then(|v| str::from_utf8(v).ok());
+        let Some(parents) = value(parent_key) else { continue };
+        for p in parents.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            children.entry(p).or_default().push(feats.len());
+        }
+        feats.push((value(id_key), parents));
+    }
+
+    let mut keep: FxHashSet<String> = seeds.clone();
+    let mut queue: Vec<&str> = seeds.iter().map(String::as_str).collect();
+    while let Some(id) = queue.pop() {
+        for &i in children.get(id).map_or(&[][..], Vec::as_slice) {
+            match feats[i] {
+                (Some(child), _) => {
+                    if keep.insert(child.to_owned()) {
+                        queue.push(child);
+                    }
+                }
+                (None, parents) => {
+                    keep.insert(parents.to_owned());
+                }
+            }
+        }
+    }
+    keep
+}
+
+/// Replace the matched IDs of each root in `per_root_matches` by `block_descendants`
+/// of its blocks in `blocks` (taken together when a model spans several); roots
+/// without a block keep their IDs.
+pub fn expand_to_descendants(
+    gff_path: &Path,
+    blocks: &[(u32, u64, u64)],
+    per_root_matches: &mut FxHashMap<u32, FxHashSet<String>>,
+    id_key: &str,
+    parent_key: &str,
+) -> Result<()> {
+    let gff = RangeSource::open(gff_path)?;
+    let file_len = gff.len();
+    let mut ranges: FxHashMap<u32, Vec<(u64, u64)>> = FxHashMap::default();
+    for &(root, s, e) in blocks {
+        if s != MISSING && s < e && e <= file_len && per_root_matches.contains_key(&root) {
+            ranges.entry(root).or_default().push((s, e));
+        }
+    }
+    let expanded: Vec<(u32, FxHashSet<String>)> = ranges
+        .par_iter()
+        .map(|(&root, ranges)| {
+            let mut block = Vec::new();
+            for &(s, e) in ranges {
+                block.extend_from_slice(&gff.range(s, e)?);
+            }
+            Ok((root, block_descendants(&block, &per_root_matches[&root], id_key, parent_key)))
+        })
+        .collect::<Result<_>>()?;
+    per_root_matches.extend(expanded);
+    Ok(())
+}
@@ -0,0 +1,306 @@
+use anyhow::{Result, bail};
+use std::{
+    fmt::Write as FmtWrite,
+    io::Write,
+};
+
+/// Flush the row buffer to the sink once it grows past this size.
+const FLUSH_AT: usize = 8 * 1024 * 1024;
+
+/// Text layout of a `TableWriter` (`--table-format`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum TableFormat {
+    /// Tab-separated, header line first
+    #[default]
+    Tsv,
+    /// Comma-separated (RFC 4180 quoting), header line first
+    Csv,
+    /// One JSON object per line keyed by column name; no header line
+    Jsonl,
+}
+
+/// Coordinate convention of the `start` column of table outputs (`--coords`). The end
+/// is the same number in both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Coords {
+    /// 1-based start, inclusive end (as in GFF)
+    #[default]
+    Gff,
+    /// 0-based start, exclusive end (as in BED)
+    Bed,
+}
+
+impl Coords {
+    /// Written start of a feature whose 0-based start is `start0`.
+    #[inline]
+    pub fn start(self, start0: u64) -> u64 {
+        match self {
+            Coords::Gff => start0 + 1,
+            Coords::Bed => start0,
+        }
+    }
+
+    /// Header comment naming the convention.
+    pub fn header_comment(self) -> &'static str {
+        match self {
+            Coords::Gff => "# coords: gff (1-based start, inclusive end)",
+            Coords::Bed => "# coords: bed (0-based start, exclusive end)",
+        }
+    }
+}
+
+/// Column selection and layout of table outputs (`depth`, `coverage` with `--format tsv`, `stat`).
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct TableArgs {
+    /// Columns to write, in this order (e.g. id,depth); default: all columns except the
+    /// optional ones (score, phase). Unknown names are rejected with the list of available columns
+    #[arg(long = "columns", value_name = "LIST", value_delimiter = ',')]
+    pub columns: Option<Vec<String>>,
+
+    /// Omit the header line (tsv/csv)
+    #[arg(long = "no-header", default_value_t = false)]
+    pub no_header: bool,
+
+    /// Table layout: `tsv`, `csv` or `jsonl`
+    #[arg(long = "table-format", value_enum, value_name = "FORMAT", default_value_t = TableFormat::Tsv)]
+    pub table_format: TableFormat,
+
+    /// Coordinates of the start/end columns: `gff` (1-based, end inclusive) or `bed`
+    /// (0-based, end exclusive); named in a `# coords:` line before the header
+    #[arg(long = "coords", value_enum, value_name = "STYLE", default_value_t = Coords::Gff)]
+    pub coords: Coords,
+}
+
+impl TableArgs {
+    /// Whether any option differs from the default table.
+    pub fn is_set(&self) -> bool {
+        self.columns.is_some() || self.no_header || self.table_format != TableFormat::Tsv || self.coords != Coords::Gff
+    }
+}
+
+/// One cell value; typed so that JSON keeps numbers unquoted.
+#[derive(Debug, Clone, Copy)]
+pub enum Cell<'a> {
+    Str(&'a str),
+    UInt(u64),
+    /// Written with 6 decimals
+    Float(f64),
+    /// Written in the shortest form that round-trips (`2.5`, `5`)
+    Num(f64),
+    /// No value: '.' in tsv/csv (as in GFF), `null` in JSON
+    Null,
+    /// 0-based start coordinate, written per `--coords` (see `Column::start`)
+    Start0(u64),
+}
+
+impl<'a> Cell<'a> {
+    /// `Num(v)`, or `Null` when there is no value.
+    #[inline]
+    pub fn opt_num(v: Option<f64>) -> Self {
+        v.map_or(Cell::Null, Cell::Num)
+    }
+}
+
+type Formatter<'f, R> = Box<dyn for<'r> Fn(&'r R) -> Cell<'r> + 'f>;
+
+/// A named column of rows `R`.
+pub struct Column<'f, R> {
+    name: String,
+    get: Formatter<'f, R>,
+    /// Part of the default selection (all columns but the `optional` ones)
+    default: bool,
+    /// Holds a start coordinate converted per `--coords`
+    coord: bool,
+}
+
+impl<'f, R> Column<'f, R> {
+    pub fn new(name: impl Into<String>, get: impl for<'r> Fn(&'r R) -> Cell<'r> + 'f) -> Self {
+        Self { name: name.into(), get: Box::new(get), default: true, coord: false }
+    }
+
+    /// A start coordinate, given 0-based by `start0` and written per `--coords`. End
+    /// columns need no conversion and use `new`.
+    pub fn start(name: impl Into<String>, start0: impl Fn(&R) -> u64 + 'f) -> Self {
+        Self { coord: true, ..Self::new(name, move |r: &R| Cell::Start0(start0(r))) }
+    }
+
+    /// A column written only when `--columns` lists it.
+    pub fn optional(name: impl Into<String>, get: impl for<'r> Fn(&'r R) -> Cell<'r> + 'f) -> Self {
+        Self { default: false, ..Self::new(name, get) }
+    }
+
+    /// A `start` column written only when `--columns` lists it.
+    pub fn optional_start(name: impl Into<String>, start0: impl Fn(&R) -> u64 + 'f) -> Self {
+        Self { default: false, ..Self::start(name, start0) }
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Writes rows through a registry of columns, selected and ordered by `TableArgs`.
+pub struct TableWriter<'f, R> {
+    columns: Vec<Column<'f, R>>,
+    format: TableFormat,
+    header: bool,
+    coords: Coords,
+}
+
+impl<'f, R> TableWriter<'f, R> {
+    /// Select `args.columns` (by default all of `registry` except the optional columns)
+    /// from the registered columns.
+    pub fn new(registry: Vec<Column<'f, R>>, args: &TableArgs) -> Result<Self> {
+        let columns = match &args.columns {
+            None => registry.into_iter().filter(|c| c.default).collect(),
+            Some(names) => {
+                let available: Vec<String> = registry.iter().map(|c| c.name.clone()).collect();
+                let mut registry: Vec<Option<Column<'f, R>>> = registry.into_iter().map(Some).collect();
+                let mut selected = Vec::with_capacity(names.len());
+                for name in names {
+                    let Some(slot) = registry.iter_mut().find(|c| c.as_ref().is_some_and(|c| c.name == *name)) else {
+                        if names.iter().filter(|n| *n == name).count() > 1 {
+                            bail!("Column '{}' is listed more than once in --columns", name);
+                        }
+                        bail!("Unknown column '{}' (available: {})", name, available.join(","));
+                    };
+                    selected.extend(slot.take());
+                }
+                selected
+            }
+        };
+        if columns.is_empty() {
+            bail!("--columns selects no columns");
+        }
+        Ok(Self { columns, format: args.table_format, header: !args.no_header, coords: args.coords })
+    }
+
+    pub fn column_names(&self) -> impl Iterator<Item = &str> {
+        self.columns.iter().map(|c| c.name())
+    }
+
+    fn separator(&self) -> char {
+        match self.format {
+            TableFormat::Csv => ',',
+            TableFormat::Tsv | TableFormat::Jsonl => '\t',
+        }
+    }
+
+    /// Append the header line, preceded by the `# coords:` line when a start column is
+    /// selected (nothing for JSON lines or with `--no-header`).
+    pub fn write_header(&self, buf: &mut String) {
+        if !self.header || self.format == TableFormat::Jsonl {
+            return;
+        }
+        if self.columns.iter().any(|c| c.coord) {
+            buf.push_str(self.coords.header_comment());
+            buf.push('\n');
+        }
+        for (i, c) in self.columns.iter().enumerate() {
+            if i > 0 {
+                buf.push(self.separator());
+            }
+            self.push_str(buf, c.name());
+        }
+        buf.push('\n');
+    }
+
+    /// Append one row.
+    pub fn write_row(&self, buf: &mut String, row: &R) {
+        if self.format == TableFormat::Jsonl {
+            buf.push('{');
+            for (i, c) in self.columns.iter().enumerate() {
+                if i > 0 {
+                    buf.push(',');
+                }
+                push_json_str(buf, c.name());
+                buf.push(':');
+                match self.resolve((c.get)(row)) {
+                    Cell::Str(s) => push_json_str(buf, s),
+                    Cell::Null => buf.push_str("null"),
+                    cell => push_number(buf, cell),
+                }
+            }
+            buf.push_str("}\n");
+            return;
+        }
+        for (i, c) in self.columns.iter().enumerate() {
+            if i > 0 {
+                buf.push(self.separator());
+            }
+            match self.resolve((c.get)(row)) {
+                Cell::Str(s) => self.push_str(buf, s),
+                cell => push_number(buf, cell),
+            }
+        }
+        buf.push('\n');
+    }
+
+    /// Write the header and every row to `out`; returns the number of rows.
+    pub fn write_all<W: Write>(&self, rows: impl IntoIterator<Item = R>, out: &mut W) -> Result<usize> {
+        let mut buf = String::with_capacity(64 * 1024);
+        self.write_header(&mut buf);
+        let mut n = 0usize;
+        for row in rows {
+            self.write_row(&mut buf, &row);
+            n += 1;
+            if buf.len() >= FLUSH_AT {
+                out.write_all(buf.as_bytes())?;
+                buf.clear();
+            }
+        }
+        out.write_all(buf.as_bytes())?;
+        out.flush()?;
+        Ok(n)
+    }
+
+    /// Apply `--coords` to start cells.
+    #[inline]
+    fn resolve<'r>(&self, cell: Cell<'r>) -> Cell<'r> {
+        match cell {
+            Cell::Start0(v) => Cell::UInt(self.coords.start(v)),
+            cell => cell,
+        }
+    }
+
+    /// A text cell; CSV quotes values containing ',', '"' or line breaks.
+    fn push_str(&self, buf: &mut String, s: &str) {
+        if self.format == TableFormat::Csv && s.contains([',', '"', '\n', '\r']) {
+            buf.push('"');
+            buf.push_str(&s.replace('"', "\"\""));
+            buf.push('"');
+        } else {
+            buf.push_str(s);
+        }
+    }
+}
+
+fn push_number(buf: &mut String, cell: Cell<'_>) {
+    // Writing to a String cannot fail
+    let _ = match cell {
+        Cell::UInt(v) | Cell::Start0(v) => write!(buf, "{v}"),
+        Cell::Float(v) => write!(buf, "{v:.6}"),
+        Cell::Num(v) => write!(buf, "{v}"),
+        Cell::Str(s) => write!(buf, "{s}"),
+        Cell::Null => write!(buf, "."),
+    };
+}
+
+fn push_json_str(buf: &mut String, s: &str) {
+    buf.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(buf, "\\u{:04x}", c as u32);
+            }
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+}
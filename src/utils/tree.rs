@@ -1,122 +1,430 @@
-use serde::{Deserialize, Serialize};
+use crate::{Counter, record};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::io::Read;
 
 /// Closed interval on [start, end] for point queries.
 /// For range queries we use half-open logic.
+///
+/// `payload` carries the value attached to the interval. Root-level trees
+/// (`.rit`) store the root feature id; child-level trees may store any other
+/// `Copy` value (e.g. child fid + line offset). The default `P = u32` keeps the
+/// on-disk layout identical to the former `root_fid: u32` field.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Interval<T: Ord + Copy> {
+pub struct Interval<T: Ord + Copy, P: Copy = u32> {
     pub start: T,
     pub end: T,
-    pub root_fid: u32,
+    pub payload: P,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct IntervalTree<T: Ord + Copy> {
-    root: Option<Box<Node<T>>>,
+impl<T: Ord + Copy> Interval<T, u32> {
+    /// Root feature id of a root-level interval (alias of `payload`).
+    #[inline]
+    pub fn root_fid(&self) -> u32 {
+        self.payload
+    }
+}
+
+/// Interval tree laid out flat: the intervals in one array sorted by
+/// `(start, end, payload)`, read as an implicit balanced binary tree (the node at
+/// index `i` sits at level `i.trailing_ones()`), plus the largest `end` of each
+/// node's subtree in a parallel array.
+///
+/// Construction is canonical, so the same set of intervals gives the same tree
+/// whatever the input order. Queries walk the array in order and return hits in
+/// that same order. Only the sorted intervals are serialized (`.rit` format
+/// version 2); the max-end array is rebuilt in one pass on load.
+#[derive(Debug)]
+pub struct IntervalTree<T: Ord + Copy, P: Copy = u32> {
+    intervals: Vec<Interval<T, P>>,
+    /// Largest `end` in the subtree rooted at each index
+    max_end: Vec<T>,
+    /// Level of the root (index `2^max_level - 1`); unused when empty
+    max_level: u32,
+}
+
+/// Iterator over every interval of an `IntervalTree` (see `IntervalTree::iter`).
+pub struct IntervalIter<'a, T: Ord + Copy, P: Copy> {
+    inner: std::slice::Iter<'a, Interval<T, P>>,
+}
+
+impl<'a, T: Ord + Copy, P: Copy> Iterator for IntervalIter<'a, T, P> {
+    type Item = &'a Interval<T, P>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Node<T: Ord + Copy> {
-    center: T,
-    intervals: Vec<Interval<T>>,
-    left: Option<Box<Node<T>>>,
-    right: Option<Box<Node<T>>>,
+/// Canonical order of intervals: `(start, end, payload)`.
+#[inline]
+fn canonical_cmp<T: Ord + Copy, P: Ord + Copy>(a: &Interval<T, P>, b: &Interval<T, P>) -> std::cmp::Ordering {
+    (a.start, a.end, a.payload).cmp(&(b.start, b.end, b.payload))
 }
 
-impl<T> IntervalTree<T>
+/// Subtrees at or below this level are scanned linearly instead of descended.
+const SCAN_LEVEL: u32 = 3;
+
+impl<T, P> IntervalTree<T, P>
 where
     T: Ord + Copy + Serialize + for<'de> Deserialize<'de>,
+    P: Ord + Copy + Serialize + for<'de> Deserialize<'de>,
 {
     /// Build a tree from a list of intervals.
-    pub fn new(intervals: Vec<Interval<T>>) -> Self {
-        let root = Self::build(intervals);
-        Self { root }
+    pub fn new(mut intervals: Vec<Interval<T, P>>) -> Self {
+        intervals.sort_unstable_by(canonical_cmp);
+        Self::from_sorted(intervals)
     }
 
-    fn build(mut intervals: Vec<Interval<T>>) -> Option<Box<Node<T>>> {
-        if intervals.is_empty() {
-            return None;
+    /// Index intervals already in canonical order.
+    fn from_sorted(intervals: Vec<Interval<T, P>>) -> Self {
+        let n = intervals.len();
+        let mut max_end: Vec<T> = intervals.iter().map(|iv| iv.end).collect();
+        if n == 0 {
+            return Self { intervals, max_end, max_level: 0 };
+        }
+        // Level 0 nodes (even indices) are leaves. A node at level k has children at
+        // i -/+ 2^(k-1); a right child past the end stands for the last subtree, whose
+        // max end is carried along in `last`.
+        let mut last_i = (n - 1) & !1;
+        let mut last = max_end[last_i];
+        let mut k = 1;
+        while 1usize << k <= n {
+            let x = 1usize << (k - 1);
+            for i in ((x << 1) - 1..n).step_by(x << 2) {
+                let left = max_end[i - x];
+                let right = if i + x < n { max_end[i + x] } else { last };
+                max_end[i] = max_end[i].max(left).max(right);
+            }
+            last_i = if (last_i >> k) & 1 == 1 { last_i - x } else { last_i + x };
+            if last_i < n {
+                last = last.max(max_end[last_i]);
+            }
+            k += 1;
         }
+        Self { intervals, max_end, max_level: k - 1 }
+    }
 
-        intervals.sort_by_key(|iv| iv.start);
-        let mid = intervals.len() / 2;
-        let center = intervals[mid].start;
+    /// Call `hit` with the index of every interval `iv` where `starts_before(iv.start)`
+    /// and `reaches(iv.end)`, in array order; `reaches` must be monotone (once true for
+    /// an end, true for every larger one) for the max-end pruning to hold.
+    fn visit(&self, starts_before: impl Fn(T) -> bool, reaches: impl Fn(T) -> bool, mut hit: impl FnMut(usize)) {
+        let n = self.intervals.len();
+        if n == 0 {
+            return;
+        }
+        // (index, level, left subtree done)
+        let mut stack: Vec<(usize, u32, bool)> = Vec::with_capacity(64);
+        stack.push(((1 << self.max_level) - 1, self.max_level, false));
+        while let Some((i, k, left_done)) = stack.pop() {
+            if k <= SCAN_LEVEL {
+                let from = i >> k << k;
+                let to = (from + (1 << (k + 1)) - 1).min(n);
+                for j in from..to {
+                    let iv = &self.intervals[j];
+                    if !starts_before(iv.start) {
+                        break;
+                    }
+                    if reaches(iv.end) {
+                        hit(j);
+                    }
+                }
+            } else if !left_done {
+                let left = i - (1 << (k - 1));
+                stack.push((i, k, true));
+                if left >= n || reaches(self.max_end[left]) {
+                    stack.push((left, k - 1, false));
+                }
+            } else if i < n && starts_before(self.intervals[i].start) {
+                if reaches(self.intervals[i].end) {
+                    hit(i);
+                }
+                stack.push((i + (1 << (k - 1)), k - 1, false));
+            }
+        }
+    }
+
+    /// Iterate over all intervals of the tree, sorted by `(start, end, payload)`.
+    pub fn iter(&self) -> IntervalIter<'_, T, P> {
+        IntervalIter { inner: self.intervals.iter() }
+    }
+
+    /// All intervals of the tree, sorted by `(start, end, payload)`.
+    pub fn sorted_intervals(&self) -> Vec<&Interval<T, P>> {
+        self.iter().collect()
+    }
+
+    /// Point query: returns all intervals covering `point` (closed semantics on [start, end]),
+    /// sorted by `(start, end, payload)`.
+    pub fn query_point(&self, point: T) -> Vec<&Interval<T, P>> {
+        record(Counter::TreeQueries, 1);
+        let mut result = Vec::new();
+        self.visit(|s| s <= point, |e| e >= point, |i| result.push(&self.intervals[i]));
+        result
+    }
+
+    /// Interval query (half-open semantics): appends intervals `iv` where
+    /// `iv.start < end && iv.end > start` to `out`, sorted by `(start, end, payload)`.
+    /// Entries already in `out` are left in place.
+    pub fn query_interval<'a>(&'a self, start: T, end: T, out: &mut Vec<&'a Interval<T, P>>) {
+        record(Counter::TreeQueries, 1);
+        self.visit(|s| s < end, |e| e > start, |i| out.push(&self.intervals[i]));
+    }
 
-        let mut left = Vec::new();
-        let mut right = Vec::new();
-        let mut center_ivs = Vec::new();
+    /// Decode one tree serialized in a `.rit` file of format `version` (see
+    /// `RIT_VERSION`): the flat layout from version 2 on, or the boxed centered tree
+    /// of earlier versions (and of legacy files without a header, version 0), which is
+    /// converted.
+    pub fn read_rit_tree<R: Read>(reader: R, version: u32) -> bincode2::Result<Self> {
+        if version >= 2 {
+            return bincode2::deserialize_from(reader);
+        }
+        let legacy: LegacyTree<T, P> = bincode2::deserialize_from(reader)?;
+        let mut intervals = Vec::new();
+        let mut stack: Vec<LegacyNode<T, P>> = legacy.root.map(|n| *n).into_iter().collect();
+        while let Some(node) = stack.pop() {
+            intervals.extend(node.intervals);
+            stack.extend(node.left.map(|n| *n));
+            stack.extend(node.right.map(|n| *n));
+        }
+        Ok(Self::new(intervals))
+    }
+}
 
-        for iv in intervals {
+impl<T, P> Serialize for IntervalTree<T, P>
+where
+    T: Ord + Copy + Serialize,
+    P: Copy + Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.intervals.serialize(serializer)
+    }
+}
+
+impl<'de, T, P> Deserialize<'de> for IntervalTree<T, P>
+where
+    T: Ord + Copy + Serialize + for<'a> Deserialize<'a>,
+    P: Ord + Copy + Serialize + for<'a> Deserialize<'a>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let intervals = Vec::<Interval<T, P>>::deserialize(deserializer)?;
+        // Written sorted; a damaged file must not make queries miss hits
+        if intervals.is_sorted_by(|a, b| canonical_cmp(a, b).is_le()) {
+            Ok(Self::from_sorted(intervals))
+        } else {
+            Ok(Self::new(intervals))
+        }
+    }
+}
+
+/// The boxed centered tree of `.rit` format versions 0 and 1, read only to convert it.
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct LegacyTree<T: Ord + Copy, P: Copy> {
+    root: Option<Box<LegacyNode<T, P>>>,
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct LegacyNode<T: Ord + Copy, P: Copy> {
+    _center: T,
+    intervals: Vec<Interval<T, P>>,
+    left: Option<Box<LegacyNode<T, P>>>,
+    right: Option<Box<LegacyNode<T, P>>>,
+}
+
+/// `intervals` encoded as one tree of a legacy (version 0/1) `.rit`, centered on the
+/// median start at each node as earlier releases built it.
+#[cfg(test)]
+pub(crate) fn legacy_tree_bytes(intervals: Vec<Interval<u32>>) -> Vec<u8> {
+    fn node(mut ivs: Vec<Interval<u32>>) -> Option<Box<LegacyNode<u32, u32>>> {
+        if ivs.is_empty() {
+            return None;
+        }
+        ivs.sort_unstable_by_key(|iv| iv.start);
+        let center = ivs[ivs.len() / 2].start;
+        let (mut left, mut right, mut here) = (Vec::new(), Vec::new(), Vec::new());
+        for iv in ivs {
             if iv.end < center {
                 left.push(iv);
             } else if iv.start > center {
                 right.push(iv);
             } else {
-                center_ivs.push(iv);
+                here.push(iv);
             }
         }
+        Some(Box::new(LegacyNode { _center: center, intervals: here, left: node(left), right: node(right) }))
+    }
+    bincode2::serialize(&LegacyTree { root: node(intervals) }).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{ScratchDir, SynthRng};
+    use crate::utils::tree_io::{RIT_MAGIC, load_trees_mmap, load_trees_streaming};
 
-        Some(Box::new(Node {
-            center,
-            intervals: center_ivs,
-            left: Self::build(left),
-            right: Self::build(right),
-        }))
+    fn random_intervals(n: usize, seed: u64) -> Vec<Interval<u32>> {
+        let mut rng = SynthRng::new(seed, 0);
+        (0..n)
+            .map(|i| {
+                let start = rng.range(0..100_000) as u32;
+                // Repeat some extents so ties are broken by payload
+                let end = if i % 5 == 0 { start + 100 } else { start + 1 + rng.range(0..5_000) as u32 };
+                Interval { start, end, payload: i as u32 }
+            })
+            .collect()
     }
 
-    /// Point query: returns all intervals covering `point` (closed semantics on [start, end]).
-    pub fn query_point(&self, point: T) -> Vec<&Interval<T>> {
-        let mut result = Vec::new();
-        Self::query_point_rec(&self.root, point, &mut result);
-        result
+    fn keys(hits: &[&Interval<u32>]) -> Vec<(u32, u32, u32)> {
+        hits.iter().map(|iv| (iv.start, iv.end, iv.payload)).collect()
     }
 
-    fn query_point_rec<'a>(
-        node: &'a Option<Box<Node<T>>>,
-        point: T,
-        result: &mut Vec<&'a Interval<T>>,
-    ) {
-        if let Some(n) = node {
-            for iv in &n.intervals {
-                if iv.start <= point && point <= iv.end {
-                    result.push(iv);
-                }
-            }
-            if point < n.center {
-                Self::query_point_rec(&n.left, point, result);
-            } else if point > n.center {
-                Self::query_point_rec(&n.right, point, result);
-            } else {
-                // Equal to center: search both sides
-                Self::query_point_rec(&n.left, point, result);
-                Self::query_point_rec(&n.right, point, result);
+    /// Sizes around powers of two, and just past the subtrees `visit` scans linearly
+    /// (`2^(SCAN_LEVEL + 1) - 1` intervals) and their multiples.
+    const SIZES: &[usize] = &[
+        0, 1, 2, 3, 4, 5, 7, 8, 9, 14, 15, 16, 17, 30, 31, 32, 33, 46, 47, 48, 63, 64, 65, 127, 128, 129, 255, 256,
+        257, 1023, 1024, 1025,
+    ];
+
+    /// `n` intervals packed into a span that grows with `n`, so that queries hit many:
+    /// some empty (`start == end`), some long, some repeating an earlier extent.
+    fn dense_intervals(n: usize, seed: u64) -> Vec<Interval<u32>> {
+        let mut rng = SynthRng::new(seed, 1);
+        let span = 20 * n as u64 + 50;
+        let mut out: Vec<Interval<u32>> = Vec::with_capacity(n);
+        for i in 0..n {
+            let payload = i as u32;
+            if i > 0 && rng.range(0..8) == 0 {
+                let prev = &out[rng.range(0..i as u64) as usize];
+                out.push(Interval { start: prev.start, end: prev.end, payload });
+                continue;
             }
+            let start = rng.range(0..span) as u32;
+            let len = match rng.range(0..10) {
+                0 => 0,
+                1 => rng.range(0..span / 2),
+                _ => rng.range(1..60),
+            } as u32;
+            out.push(Interval { start, end: start + len, payload });
         }
+        out
     }
 
-    /// Interval query (half-open semantics): returns intervals `iv` where
-    /// `iv.start < end && iv.end > start`.
-    pub fn query_interval<'a>(&'a self, start: T, end: T, out: &mut Vec<&'a Interval<T>>) {
-        Self::query_interval_rec(&self.root, start, end, out);
+    /// The intervals `keep` selects, in canonical order.
+    fn brute(intervals: &[Interval<u32>], keep: impl Fn(&Interval<u32>) -> bool) -> Vec<(u32, u32, u32)> {
+        let mut hits: Vec<_> =
+            intervals.iter().filter(|iv| keep(iv)).map(|iv| (iv.start, iv.end, iv.payload)).collect();
+        hits.sort_unstable();
+        hits
     }
 
-    fn query_interval_rec<'a>(
-        node: &'a Option<Box<Node<T>>>,
-        start: T,
-        end: T,
-        out: &mut Vec<&'a Interval<T>>,
-    ) {
-        if let Some(n) = node {
-            for iv in &n.intervals {
-                if iv.start < end && iv.end > start {
-                    out.push(iv);
-                }
+    /// Check `tree` against a linear scan of `intervals` on random windows and points.
+    fn assert_matches_brute_force(tree: &IntervalTree<u32>, intervals: &[Interval<u32>], seed: u64) {
+        let n = intervals.len();
+        let span = 20 * n as u32 + 60;
+        let mut rng = SynthRng::new(seed, 2);
+        let mut out = Vec::new();
+        for _ in 0..300 {
+            let s = rng.range(0..span as u64) as u32;
+            let e = s + rng.range(0..(span / 4) as u64 + 2) as u32;
+            out.clear();
+            tree.query_interval(s, e, &mut out);
+            assert_eq!(keys(&out), brute(intervals, |iv| iv.start < e && iv.end > s), "n={} {}..{}", n, s, e);
+            let p = rng.range(0..span as u64) as u32;
+            let want = brute(intervals, |iv| iv.start <= p && iv.end >= p);
+            assert_eq!(keys(&tree.query_point(p)), want, "n={} @{}", n, p);
+        }
+        // Hits are appended after what `out` already holds
+        if let Some(first) = tree.iter().next() {
+            let mut out = vec![first];
+            tree.query_interval(0, span, &mut out);
+            assert_eq!(keys(&out[..1]), keys(&[first]));
+            assert_eq!(keys(&out[1..]), brute(intervals, |iv| iv.start < span && iv.end > 0));
+        }
+    }
+
+    #[test]
+    fn queries_match_a_brute_force_scan() {
+        for &n in SIZES {
+            for seed in 0..4 {
+                let intervals = dense_intervals(n, seed);
+                let tree = IntervalTree::new(intervals.clone());
+                assert_eq!(keys(&tree.sorted_intervals()), brute(&intervals, |_| true));
+                assert_matches_brute_force(&tree, &intervals, seed);
             }
-            if start < n.center {
-                Self::query_interval_rec(&n.left, start, end, out);
+        }
+    }
+
+    #[test]
+    fn legacy_trees_are_converted() {
+        for &n in SIZES {
+            let intervals = dense_intervals(n, 7);
+            let bytes = legacy_tree_bytes(intervals.clone());
+            for version in [0, 1] {
+                let tree: IntervalTree<u32> = IntervalTree::read_rit_tree(&bytes[..], version).unwrap();
+                assert_eq!(keys(&tree.sorted_intervals()), brute(&intervals, |_| true), "n={}", n);
+                assert_matches_brute_force(&tree, &intervals, 8);
             }
-            if end > n.center {
-                Self::query_interval_rec(&n.right, start, end, out);
+        }
+    }
+
+    #[test]
+    fn version_1_rit_files_load_through_both_readers() {
+        let dir = ScratchDir::new("tree-rit-v1").unwrap();
+        let rit = dir.join("g.rit");
+        let trees: Vec<Vec<Interval<u32>>> = SIZES.iter().map(|&n| dense_intervals(n, n as u64)).collect();
+        let encoded: Vec<Vec<u8>> = trees.iter().map(|ivs| legacy_tree_bytes(ivs.clone())).collect();
+        // Header of format version 1 (offset table, boxed trees)
+        let mut buf = RIT_MAGIC.to_vec();
+        buf.extend(1u32.to_le_bytes());
+        buf.extend((trees.len() as u32).to_le_bytes());
+        let mut pos = (buf.len() + 8 * trees.len()) as u64;
+        for bytes in &encoded {
+            buf.extend(pos.to_le_bytes());
+            pos += bytes.len() as u64;
+        }
+        buf.extend(encoded.concat());
+        std::fs::write(&rit, &buf).unwrap();
+
+        // The .rix is not read when the .rit has a header
+        let rix = dir.join("missing.rix");
+        let mapped: Vec<IntervalTree<u32>> = load_trees_mmap(&rit, &rix).unwrap();
+        let streamed: Vec<IntervalTree<u32>> = load_trees_streaming(&rit, &rix).unwrap();
+        assert_eq!(mapped.len(), trees.len());
+        for ((tree, other), intervals) in mapped.iter().zip(&streamed).zip(&trees) {
+            assert_eq!(keys(&tree.sorted_intervals()), brute(intervals, |_| true));
+            assert_eq!(keys(&other.sorted_intervals()), brute(intervals, |_| true));
+            assert_matches_brute_force(tree, intervals, 9);
+        }
+    }
+
+    #[test]
+    fn query_order_does_not_depend_on_input_order() {
+        let intervals = random_intervals(3_000, 1);
+        let tree = IntervalTree::new(intervals.clone());
+        let mut rng = SynthRng::new(2, 0);
+        for round in 0..5 {
+            let mut shuffled = intervals.clone();
+            rng.shuffle(&mut shuffled);
+            let rebuilt = IntervalTree::new(shuffled);
+            // ... and after a round trip through the `.rit` encoding
+            let other: IntervalTree<u32> = bincode2::deserialize(&bincode2::serialize(&rebuilt).unwrap()).unwrap();
+            for q in 0..200u32 {
+                let (s, e) = (q * 500, q * 500 + 700);
+                let (mut a, mut b) = (Vec::new(), Vec::new());
+                tree.query_interval(s, e, &mut a);
+                other.query_interval(s, e, &mut b);
+                assert_eq!(keys(&a), keys(&b), "round {} interval {}..{}", round, s, e);
+                let mut c = Vec::new();
+                rebuilt.query_interval(s, e, &mut c);
+                assert_eq!(keys(&b), keys(&c));
+                assert_eq!(keys(&tree.query_point(s)), keys(&other.query_point(s)));
             }
+            assert_eq!(keys(&tree.sorted_intervals()), keys(&other.sorted_intervals()));
         }
     }
-}
\ No newline at end of file
+}
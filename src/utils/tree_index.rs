@@ -1,83 +1,285 @@
-use crate::{IntervalTree, load_sqs, append_suffix};
-use anyhow::{bail, Context, Result};
-use bincode2::deserialize;
-use memmap2::MmapOptions;
+use crate::{
+    ByteSource, Error, IntervalTree, RIT_VERSION, index_path, load_sqs, parse_rit_header, parse_sqs, rit_format_version,
+};
+use crate::error::Result;
+use rayon::prelude::*;
 use rustc_hash::FxHashMap;
-use std::{fs::File, path::Path};
+use std::{path::Path, sync::OnceLock};
 
 /// Application-facing structure:
-/// - per-sequence interval trees
+/// - per-sequence interval trees, deserialized from the `.rit` bytes on first use
+///   (`tree_for`), so a query on one sequence never decodes the others
 /// - string -> numeric ID mapping
+///
+/// The `.rit` mapping and its tree offsets are kept for the lifetime of the value;
+/// `preload_all` decodes every tree up front (in parallel) for workloads that touch
+/// them all. Threads may share one value: a tree is decoded by whichever asks first.
 #[derive(Debug)]
 pub struct TreeIndexData {
-    pub chr_entries: FxHashMap<u32, IntervalTree<u32>>,
     pub seqid_to_num: FxHashMap<String, u32>,
     pub num_to_seqid: Vec<String>,
+    /// `.rit` bytes; tree `i` spans `offsets[i]..offsets[i + 1]` (the last runs to the end)
+    rit: ByteSource,
+    offsets: Vec<u64>,
+    /// `.rit` format version (`rit_format_version`); below 2 the trees are converted
+    version: u32,
+    /// Decoded trees, by sequence number
+    trees: Vec<OnceLock<IntervalTree<u32>>>,
+    /// Named in errors: the `.rit` path, or `RIT` for in-memory bytes
+    rit_name: String,
 }
 
 impl TreeIndexData {
-    /// Construct TreeIndexData from explicit sequence IDs and `.rit/.rix` files.
-    /// Convenience: derive `{prefix}.rit/.rix` from `index_prefix`.
+    /// Construct TreeIndexData from the `.sqs` and `.rit` files of `gff_path` (and the
+    /// `.rix` of a legacy index, see `parse_rit_header`). Only the offsets are read here.
     pub fn load_tree_index<P: AsRef<Path>>(gff_path: P) -> Result<Self> {
         let path = gff_path.as_ref();
         let (num_to_seqid, seqid_to_num) = load_sqs(path)?;
-        let rit_path = append_suffix(path, ".rit");
-        let rix_path = append_suffix(path, ".rix");
-        
-        let chr_entries = Self::load_region_index(&rit_path, &rix_path)?;
-        
-        Ok(Self {
-            chr_entries,
-            seqid_to_num,
-            num_to_seqid
-        })
-    }
-
-    fn load_region_index(
-        rit_path: &Path,
-        rix_path: &Path,
-    ) -> Result<FxHashMap<u32, IntervalTree<u32>>> {
-        let file = File::open(rit_path).with_context(|| format!("open {}", rit_path.display()))?;
-        let mmap = unsafe { MmapOptions::new().map(&file) }
-            .with_context(|| format!("mmap {}", rit_path.display()))?;
-        let buf: &[u8] = &mmap;
-
-        let offsets: Vec<u64> = {
-            let f = File::open(rix_path).with_context(|| format!("open {}", rix_path.display()))?;
-            serde_json::from_reader::<_, Vec<u64>>(f)
-                .with_context(|| format!("parse json {}", rix_path.display()))?
+        let rit_path = index_path(path, ".rit");
+        let rit = ByteSource::open_artifact(path, ".rit")?;
+        let offsets = match parse_rit_header(&rit).map_err(|e| e.for_index_file(&rit_path))? {
+            Some(offsets) => offsets,
+            None => {
+                // Index from an earlier release: offsets in a separate JSON `.rix`
+                let rix = ByteSource::open_artifact(path, ".rix")?;
+                serde_json::from_slice(&rix).map_err(|e| {
+                    let rix_path = index_path(path, ".rix");
+                    Error::corrupt(rix_path.display().to_string(), format!("invalid JSON offsets: {e}"))
+                })?
+            }
         };
-        if offsets.is_empty() {
-            return Ok(FxHashMap::default());
+        check_offsets(rit.len(), &offsets).map_err(|e| e.for_index_file(&rit_path))?;
+        let version = rit_format_version(&rit);
+        if version < RIT_VERSION {
+            diag!(
+                "[INFO] {} has .rit format version {} (now {}); converting its trees as they load, \
+                 re-run `gffx index` to skip this",
+                rit_path.display(),
+                version,
+                RIT_VERSION
+            );
         }
+        Ok(Self::with_offsets(num_to_seqid, seqid_to_num, rit, offsets, rit_path.display().to_string()))
+    }
 
-        for w in offsets.windows(2) {
-            if w[0] > w[1] {
-                bail!("offsets not sorted ascending: {:?} > {:?}", w[0], w[1]);
-            }
-        }
-        let last = *offsets.last().unwrap() as usize;
-        if last > buf.len() {
-            bail!("last offset {} out of file size {}", last, buf.len());
+    /// Construct TreeIndexData from in-memory `.sqs` and `.rit` bytes (e.g. fetched by
+    /// a browser instead of read from disk). `rix` is read only when `rit` is a legacy
+    /// file without an offset header; pass `&[]` otherwise.
+    pub fn from_bytes(sqs: &[u8], rit: &[u8], rix: &[u8]) -> Result<Self> {
+        let (num_to_seqid, seqid_to_num) = parse_sqs(sqs)?;
+        let offsets = match parse_rit_header(rit)? {
+            Some(offsets) => offsets,
+            None => serde_json::from_slice(rix)
+                .map_err(|e| Error::corrupt("RIX", format!("invalid JSON offsets: {e}")))?,
+        };
+        check_offsets(rit.len(), &offsets)?;
+        Ok(Self::with_offsets(num_to_seqid, seqid_to_num, rit.to_vec().into(), offsets, "RIT".to_string()))
+    }
+
+    fn with_offsets(
+        num_to_seqid: Vec<String>,
+        seqid_to_num: FxHashMap<String, u32>,
+        rit: ByteSource,
+        offsets: Vec<u64>,
+        rit_name: String,
+    ) -> Self {
+        let trees = offsets.iter().map(|_| OnceLock::new()).collect();
+        let version = rit_format_version(&rit);
+        Self { seqid_to_num, num_to_seqid, rit, offsets, version, trees, rit_name }
+    }
+
+    /// The interval tree of sequence number `num`, decoded on first use; `None` when the
+    /// index has no tree for it.
+    pub fn tree_for(&self, num: u32) -> Result<Option<&IntervalTree<u32>>> {
+        let Some(slot) = self.trees.get(num as usize) else { return Ok(None) };
+        if let Some(tree) = slot.get() {
+            return Ok(Some(tree));
         }
+        // Threads racing on one tree may each decode it; the first stored wins
+        let tree = decode_tree(&self.rit, &self.offsets, num as usize, self.version)
+            .map_err(|e| Error::corrupt(self.rit_name.clone(), e))?;
+        Ok(Some(slot.get_or_init(|| tree)))
+    }
+
+    /// Decode every tree not decoded yet, in parallel.
+    pub fn preload_all(&self) -> Result<()> {
+        (0..self.trees.len() as u32).into_par_iter().try_for_each(|num| self.tree_for(num).map(drop))
+    }
 
+    /// Every tree with its sequence number, in sequence-number order (`preload_all`
+    /// first).
+    pub fn all_trees(&self) -> Result<Vec<(u32, &IntervalTree<u32>)>> {
+        self.preload_all()?;
+        Ok(self.trees.iter().enumerate().filter_map(|(num, slot)| Some((num as u32, slot.get()?))).collect())
+    }
+
+    /// Number of trees in the index.
+    pub fn tree_count(&self) -> usize {
+        self.trees.len()
+    }
+
+    /// Number of trees decoded so far.
+    pub fn loaded_tree_count(&self) -> usize {
+        self.trees.iter().filter(|slot| slot.get().is_some()).count()
+    }
+
+    /// Deserialize the per-sequence trees stored back-to-back in `buf` (`.rit` bytes)
+    /// at the given byte `offsets` (its header, or the `.rix` contents of a legacy index),
+    /// all at once. Trees of an older format version are converted.
+    pub fn parse_region_index(
+        buf: &[u8],
+        offsets: &[u64],
+    ) -> Result<FxHashMap<u32, IntervalTree<u32>>> {
+        check_offsets(buf.len(), offsets)?;
+        let version = rit_format_version(buf);
         let mut map = FxHashMap::with_capacity_and_hasher(offsets.len(), Default::default());
-        for (i, start_u64) in offsets.iter().copied().enumerate() {
-            let start = start_u64 as usize;
-            let end = if i + 1 < offsets.len() {
-                offsets[i + 1] as usize
-            } else {
-                buf.len()
-            };
-            if end < start || end > buf.len() {
-                bail!("bad slice range: {}..{} (file len {})", start, end, buf.len());
-            }
-            let slice = &buf[start..end];
-            let tree: IntervalTree<u32> = deserialize(slice).with_context(|| {
-                format!("bincode2 deserialize tree #{} ({}..{})", i, start, end)
-            })?;
+        for i in 0..offsets.len() {
+            let tree = decode_tree(buf, offsets, i, version).map_err(|e| Error::corrupt("RIT", e))?;
             map.insert(i as u32, tree);
         }
         Ok(map)
     }
+
+    /// Sequence number of `seqid`, or `Error::UnknownSeqid`.
+    pub fn seqid_num(&self, seqid: &str) -> Result<u32> {
+        self.seqid_to_num
+            .get(seqid)
+            .copied()
+            .ok_or_else(|| Error::UnknownSeqid { name: seqid.to_string() })
+    }
+
+    /// Roots whose extent overlaps `[start, end)` (0-based, half-open) on `seqid`,
+    /// sorted and deduplicated.
+    pub fn query(&self, seqid: &str, start: u32, end: u32) -> Result<Vec<u32>> {
+        let num = self.seqid_num(seqid)?;
+        let mut roots: Vec<u32> = Vec::new();
+        if let Some(tree) = self.tree_for(num)? {
+            let mut hits = Vec::new();
+            tree.query_interval(start, end, &mut hits);
+            roots.extend(hits.iter().map(|iv| iv.payload));
+        }
+        roots.sort_unstable();
+        roots.dedup();
+        Ok(roots)
+    }
+}
+
+/// `IndexCorrupt` unless `offsets` ascend and stay within `len` bytes of `.rit`.
+fn check_offsets(len: usize, offsets: &[u64]) -> Result<()> {
+    for w in offsets.windows(2) {
+        if w[0] > w[1] {
+            return Err(Error::corrupt(
+                "RIT",
+                format!("offsets not sorted ascending: {:?} > {:?}", w[0], w[1]),
+            ));
+        }
+    }
+    if let Some(&last) = offsets.last()
+        && last > len as u64
+    {
+        return Err(Error::corrupt(
+            "RIT",
+            format!("last offset {} out of file size {}", last, len),
+        ));
+    }
+    Ok(())
+}
+
+/// Tree `i` of `.rit` bytes `buf` of format `version` (offsets already passed
+/// `check_offsets`); the error is the detail of an `IndexCorrupt`.
+fn decode_tree(
+    buf: &[u8],
+    offsets: &[u64],
+    i: usize,
+    version: u32,
+) -> std::result::Result<IntervalTree<u32>, String> {
+    let start = offsets[i] as usize;
+    let end = offsets.get(i + 1).map_or(buf.len(), |&o| o as usize);
+    IntervalTree::read_rit_tree(&buf[start..end], version)
+        .map_err(|e| format!("cannot deserialize tree #{} ({}..{}): {}", i, start, end, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{GffSynth, SAMPLE_GFF, SynthGenome, SynthRng, build_fixture};
+    use crate::utils::tree::legacy_tree_bytes;
+    use std::fs;
+
+    /// Roots hit by random windows over each sequence of `genome`, in sequence order.
+    fn sample_queries(index: &TreeIndexData, genome: &SynthGenome, seed: u64) -> Vec<Vec<u32>> {
+        let mut rng = SynthRng::new(seed, 0);
+        let mut out = Vec::new();
+        for (seqid, len) in &genome.chromosomes {
+            for _ in 0..300 {
+                let start = rng.range(0..*len) as u32;
+                let end = start + 1 + rng.range(0..30_000) as u32;
+                out.push(index.query(seqid, start, end).unwrap());
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn lazy_trees_answer_like_preloaded_ones() {
+        let (genome, fx) = GffSynth::new().chromosomes(4).genes_per_chrom(40).seed(21).fixture().unwrap();
+        let gff = &fx.gff;
+        let preloaded = TreeIndexData::load_tree_index(gff).unwrap();
+        preloaded.preload_all().unwrap();
+        assert_eq!((preloaded.tree_count(), preloaded.loaded_tree_count()), (4, 4));
+        let want = sample_queries(&preloaded, &genome, 3);
+        assert!(want.iter().any(|roots| roots.len() > 1));
+
+        let lazy = TreeIndexData::load_tree_index(gff).unwrap();
+        assert_eq!(lazy.loaded_tree_count(), 0);
+        // A query decodes the tree of its own sequence only
+        assert_eq!(lazy.query("chr2", 0, 50_000).unwrap(), preloaded.query("chr2", 0, 50_000).unwrap());
+        assert_eq!(lazy.loaded_tree_count(), 1);
+        assert_eq!(sample_queries(&lazy, &genome, 3), want);
+
+        // Threads racing on the first use of each tree
+        let lazy = TreeIndexData::load_tree_index(gff).unwrap();
+        let got: Vec<Vec<Vec<u32>>> = (0..8).into_par_iter().map(|_| sample_queries(&lazy, &genome, 3)).collect();
+        assert!(got.iter().all(|g| *g == want));
+        assert_eq!(lazy.loaded_tree_count(), 4);
+    }
+
+    #[test]
+    fn legacy_rit_with_rix_offsets_still_loads() {
+        let (genome, fx) = GffSynth::new().chromosomes(3).genes_per_chrom(40).seed(22).fixture().unwrap();
+        let gff = &fx.gff;
+        let (want, rit, offsets) = {
+            let current = TreeIndexData::load_tree_index(gff).unwrap();
+            let (mut rit, mut offsets) = (Vec::new(), Vec::new());
+            for (_, tree) in current.all_trees().unwrap() {
+                offsets.push(rit.len() as u64);
+                rit.extend(legacy_tree_bytes(tree.iter().cloned().collect()));
+            }
+            (sample_queries(&current, &genome, 4), rit, offsets)
+        };
+        // As written by releases before the offset header: no magic, offsets in `.rix`
+        let rix = serde_json::to_vec(&offsets).unwrap();
+        fs::write(index_path(gff, ".rit"), &rit).unwrap();
+        fs::write(index_path(gff, ".rix"), &rix).unwrap();
+
+        let legacy = TreeIndexData::load_tree_index(gff).unwrap();
+        assert_eq!(legacy.version, 0);
+        assert_eq!(sample_queries(&legacy, &genome, 4), want);
+        let sqs = fs::read(index_path(gff, ".sqs")).unwrap();
+        let in_memory = TreeIndexData::from_bytes(&sqs, &rit, &rix).unwrap();
+        assert_eq!(sample_queries(&in_memory, &genome, 4), want);
+
+        fs::remove_file(index_path(gff, ".rix")).unwrap();
+        assert!(TreeIndexData::load_tree_index(gff).is_err());
+    }
+
+    #[test]
+    fn unknown_seqid_is_its_own_error() {
+        let fx = build_fixture(SAMPLE_GFF).unwrap();
+        let index = TreeIndexData::load_tree_index(&fx.gff).unwrap();
+        assert_eq!(index.query("chr2", 0, 100).unwrap().len(), 1);
+        let err = index.query("chr3", 0, 100).unwrap_err();
+        assert!(matches!(&err, Error::UnknownSeqid { name } if name == "chr3"), "{err:?}");
+        assert!(matches!(index.seqid_num("Chr1"), Err(Error::UnknownSeqid { .. })));
+    }
 }
@@ -1,8 +1,8 @@
-use crate::utils::tree::IntervalTree;
+use crate::{ByteSource, Error, utils::tree::IntervalTree};
 use anyhow::{bail, Context, Result};
-use bincode2::{deserialize, deserialize_from, serialize, serialize_into};
+use bincode2::{deserialize, serialize};
+use byteorder::{ByteOrder, LittleEndian};
 
-use memmap2::MmapOptions;
 use serde::de::DeserializeOwned;
 use std::{
     fs::{self, File},
@@ -10,13 +10,14 @@ use std::{
     path::Path,
 };
 
-impl<T> IntervalTree<T>
+impl<T, P> IntervalTree<T, P>
 where
     T: Ord + Copy + serde::Serialize + for<'de> serde::Deserialize<'de>,
+    P: Ord + Copy + serde::Serialize + for<'de> serde::Deserialize<'de>,
 {
     /// Serialize the whole tree to a file via bincode2.
     pub fn save_to_file(&self, path: &Path) -> std::io::Result<()> {
-        let encoded = serialize(self).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        let encoded = serialize(self).map_err(std::io::Error::other)?;
         fs::write(path, encoded)?;
         Ok(())
     }
@@ -26,78 +27,149 @@ where
         let mut file = File::open(path)?;
         let mut buf = Vec::new();
         file.read_to_end(&mut buf)?;
-        let tree: Self = deserialize(&buf)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        let tree: Self = deserialize(&buf).map_err(std::io::Error::other)?;
         Ok(tree)
     }
 }
 
-/// Save multiple trees back-to-back into a single `.rit` file.
-/// Offsets of each tree (in bytes) should be recorded separately.
-pub fn save_multiple_trees<T>(trees: &[IntervalTree<T>], rit_path: &Path) -> Result<Vec<u64>>
+/// First bytes of a `.rit` file with an offset header.
+pub const RIT_MAGIC: &[u8; 8] = b"GFFXRIT\0";
+
+/// Format version written after `RIT_MAGIC`; readers reject newer versions.
+///
+/// Version 2 stores each tree as its flat sorted interval array (see `IntervalTree`);
+/// version 1 and headerless files hold boxed centered trees, converted when read
+/// (`IntervalTree::read_rit_tree`).
+pub const RIT_VERSION: u32 = 2;
+
+/// Format version of `.rit` bytes `buf`: from its header, or 0 for a legacy file
+/// without one.
+pub fn rit_format_version(buf: &[u8]) -> u32 {
+    match buf.get(RIT_MAGIC.len()..RIT_MAGIC.len() + 4) {
+        Some(v) if buf.starts_with(RIT_MAGIC) => LittleEndian::read_u32(v),
+        _ => 0,
+    }
+}
+
+/// Save multiple trees into a single `.rit` file.
+///
+/// Layout, little-endian: `RIT_MAGIC`, `RIT_VERSION` (u32), tree count (u32), one u64
+/// file offset per tree, then the bincode trees back-to-back at those offsets (the
+/// last runs to the end of the file). Returns the offsets.
+pub fn save_multiple_trees<T, P>(trees: &[IntervalTree<T, P>], rit_path: &Path) -> crate::error::Result<Vec<u64>>
 where
-    T: Ord + Copy + serde::Serialize + for<'de> serde::Deserialize<'de>,
+    T: Ord + Copy + serde::Serialize,
+    P: Copy + serde::Serialize,
 {
-    let mut offsets = Vec::with_capacity(trees.len());
-    let file = File::create(rit_path)?;
-    let mut writer = BufWriter::new(file);
-
-    for tree in trees {
-        // Record current offset
-        let pos = writer.seek(SeekFrom::Current(0))?;
-        offsets.push(pos);
-        // Write tree
-        serialize_into(&mut writer, tree)?;
+    let write = || -> std::io::Result<Vec<u64>> {
+        let encoded = trees
+            .iter()
+            .map(|tree| serialize(tree).map_err(std::io::Error::other))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let mut pos = (RIT_MAGIC.len() + 8 + 8 * trees.len()) as u64;
+        let mut offsets = Vec::with_capacity(trees.len());
+        for bytes in &encoded {
+            offsets.push(pos);
+            pos += bytes.len() as u64;
+        }
+
+        let mut writer = BufWriter::new(File::create(rit_path)?);
+        writer.write_all(RIT_MAGIC)?;
+        writer.write_all(&RIT_VERSION.to_le_bytes())?;
+        writer.write_all(&(trees.len() as u32).to_le_bytes())?;
+        for off in &offsets {
+            writer.write_all(&off.to_le_bytes())?;
+        }
+        for bytes in &encoded {
+            writer.write_all(bytes)?;
+        }
+        writer.flush()?;
+        Ok(offsets)
+    };
+    write().map_err(|e| Error::io(rit_path, e))
+}
+
+/// The tree offsets in the header of `.rit` bytes `buf`, or `None` for a legacy `.rit`
+/// without one, whose offsets are a JSON array in the separate `.rix` (still read by
+/// the loaders, for indexes built by earlier releases).
+pub fn parse_rit_header(buf: &[u8]) -> crate::error::Result<Option<Vec<u64>>> {
+    if !buf.starts_with(RIT_MAGIC) {
+        return Ok(None);
+    }
+    let corrupt = |detail: String| Error::corrupt("RIT", detail);
+    let Some(head) = buf.get(RIT_MAGIC.len()..RIT_MAGIC.len() + 8) else {
+        return Err(corrupt("header truncated".to_string()));
+    };
+    let version = LittleEndian::read_u32(&head[0..4]);
+    if version > RIT_VERSION {
+        return Err(corrupt(format!(
+            "format version {} is newer than this gffx reads ({}); upgrade gffx or re-run `gffx index`",
+            version, RIT_VERSION
+        )));
     }
-    writer.flush()?;
-    Ok(offsets)
+    let n = LittleEndian::read_u32(&head[4..8]) as usize;
+    let table_start = RIT_MAGIC.len() + 8;
+    // Checked: a corrupt count must not wrap around on 32-bit targets
+    let table_end = n.checked_mul(8).and_then(|b| b.checked_add(table_start));
+    let Some(table) = table_end.and_then(|end| buf.get(table_start..end)) else {
+        return Err(corrupt(format!("offset table of {} trees truncated", n)));
+    };
+    Ok(Some(table.chunks_exact(8).map(LittleEndian::read_u64).collect()))
 }
 
-/// Write offsets (Vec<u64>) as JSON into `.rix` file.
-pub fn write_offsets_to_file(offsets: &[u64], rix_path: &Path) -> Result<()> {
-    let file = File::create(rix_path)?;
-    let mut writer = BufWriter::new(file);
-    serde_json::to_writer(&mut writer, offsets)?;
-    writer.flush()?;
-    Ok(())
+/// Offsets of the trees in `.rit` bytes `rit`: from its header, or from the legacy
+/// `.rix` at `rix_path` (JSON) when it has none.
+fn tree_offsets(rit: &[u8], rix_path: &Path) -> Result<Vec<u64>> {
+    if let Some(offsets) = parse_rit_header(rit)? {
+        return Ok(offsets);
+    }
+    let f = File::open(rix_path).with_context(|| format!("open {}", rix_path.display()))?;
+    serde_json::from_reader::<_, Vec<u64>>(BufReader::new(f))
+        .with_context(|| format!("parse json {}", rix_path.display()))
 }
 
-/// Load multiple trees with streaming (using BufReader).
-pub fn load_trees_streaming<T>(rit_path: &Path, rix_path: &Path) -> Result<Vec<IntervalTree<T>>>
+/// Load multiple trees with streaming (using BufReader); `rix_path` is read only for a
+/// legacy `.rit` without an offset header.
+pub fn load_trees_streaming<T, P>(rit_path: &Path, rix_path: &Path) -> Result<Vec<IntervalTree<T, P>>>
 where
-    T: Ord + Copy + DeserializeOwned,
+    T: Ord + Copy + serde::Serialize + DeserializeOwned,
+    P: Ord + Copy + serde::Serialize + DeserializeOwned,
 {
     let mut reader = BufReader::new(File::open(rit_path)?);
-    let offsets: Vec<u64> = {
-        let f = File::open(rix_path)?;
-        serde_json::from_reader(f)?
+    let (version, offsets) = {
+        let mut head = Vec::new();
+        (&mut reader).take(RIT_MAGIC.len() as u64 + 8).read_to_end(&mut head)?;
+        if head.starts_with(RIT_MAGIC) && head.len() == RIT_MAGIC.len() + 8 {
+            // The offset table follows; its length is in the bytes just read
+            let count = LittleEndian::read_u32(&head[RIT_MAGIC.len() + 4..]) as u64;
+            (&mut reader).take(8 * count).read_to_end(&mut head)?;
+        }
+        reader.seek(SeekFrom::Start(0))?;
+        (rit_format_version(&head), tree_offsets(&head, rix_path)?)
     };
 
     let mut trees = Vec::with_capacity(offsets.len());
     for &off in &offsets {
         reader.seek(SeekFrom::Start(off))?;
-        let tree: IntervalTree<T> = deserialize_from(&mut reader)
+        let tree = IntervalTree::read_rit_tree(&mut reader, version)
             .map_err(|e| anyhow::anyhow!("Deserializing failed: {}", e))?;
         trees.push(tree);
     }
     Ok(trees)
 }
 
-/// Load multiple trees via memory-mapping.
-pub fn load_trees_mmap<T>(rit_path: &Path, rix_path: &Path) -> Result<Vec<IntervalTree<T>>>
+/// Load multiple trees via memory-mapping (see `ByteSource` for the fallback);
+/// `rix_path` is read only for a legacy `.rit` without an offset header.
+pub fn load_trees_mmap<T, P>(rit_path: &Path, rix_path: &Path) -> Result<Vec<IntervalTree<T, P>>>
 where
-    T: Ord + Copy + for<'de> serde::Deserialize<'de>,
+    T: Ord + Copy + serde::Serialize + for<'de> serde::Deserialize<'de>,
+    P: Ord + Copy + serde::Serialize + for<'de> serde::Deserialize<'de>,
 {
-    let file = File::open(rit_path).with_context(|| format!("open {}", rit_path.display()))?;
-    let mmap = unsafe { MmapOptions::new().map(&file) }
-        .with_context(|| format!("mmap {}", rit_path.display()))?;
+    let mmap = ByteSource::open(rit_path).with_context(|| format!("open {}", rit_path.display()))?;
     let buf: &[u8] = &mmap;
 
-    let offsets: Vec<u64> = {
-        let f = File::open(rix_path).with_context(|| format!("open {}", rix_path.display()))?;
-        serde_json::from_reader::<_, Vec<u64>>(f)
-            .with_context(|| format!("parse json {}", rix_path.display()))?
-    };
+    let offsets = tree_offsets(buf, rix_path)?;
+    let version = rit_format_version(buf);
 
     if offsets.is_empty() {
         return Ok(Vec::new());
@@ -125,7 +197,7 @@ where
             bail!("bad slice range: {}..{} (file len {})", start, end, buf.len());
         }
         let slice = &buf[start..end];
-        let tree: IntervalTree<T> = deserialize(slice)
+        let tree = IntervalTree::read_rit_tree(slice, version)
             .with_context(|| format!("bincode2 deserialize tree #{} ({}..{})", i, start, end))?;
         out.push(tree);
     }
@@ -0,0 +1,278 @@
+use anyhow::{Context, Result};
+use memchr::memchr;
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::{
+    fs::File,
+    io::{BufWriter, Write, stdout},
+    path::{Path, PathBuf},
+    str,
+};
+
+use crate::{
+    CountingWriter, LengthFilter, RangeSource, TypeFilter, align_blocks_to_lines, fast_id, write_source_header,
+};
+
+const FIVE_PRIME_UTR: &str = "five_prime_UTR";
+const THREE_PRIME_UTR: &str = "three_prime_UTR";
+
+/// Columns of one feature line that UTR derivation needs.
+struct BlockFeature<'a> {
+    seqid: &'a str,
+    source: &'a str,
+    ftype: &'a str,
+    start: u32,
+    end: u32,
+    strand: &'a str,
+    id: Option<&'a str>,
+    parents: Vec<&'a str>,
+}
+
+fn parse_feature(line: &str) -> Option<BlockFeature<'_>> {
+    let mut cols = line.split('\t');
+    let seqid = cols.next()?;
+    let source = cols.next()?;
+    let ftype = cols.next()?;
+    let start = cols.next()?.trim().parse().ok()?;
+    let end = cols.next()?.trim().parse().ok()?;
+    let strand = cols.nth(1)?;
+    let attrs = cols.nth(1)?;
+    let parents = attrs
+        .split(';')
+        .find_map(|kv| kv.trim().strip_prefix("Parent="))
+        .map(|v| v.split(',').map(str::trim).filter(|p| !p.is_empty()).collect())
+        .unwrap_or_default();
+    Some(BlockFeature { seqid, source, ftype, start, end, strand, id: fast_id(attrs), parents })
+}
+
+/// Whether `id` or one of its ancestors (following every `Parent=` value) is in `keep`.
+fn in_selected_subtree(id: &str, parents_of: &FxHashMap<&str, &[&str]>, keep: &FxHashSet<String>) -> bool {
+    let mut stack = vec![id];
+    let mut seen: FxHashSet<&str> = FxHashSet::default();
+    while let Some(cur) = stack.pop() {
+        if keep.contains(cur) {
+            return true;
+        }
+        if seen.insert(cur)
+            && let Some(ps) = parents_of.get(cur)
+        {
+            stack.extend(ps.iter().copied());
+        }
+    }
+    false
+}
+
+/// 1-based inclusive `(start, end)` intervals
+type Spans = Vec<(u32, u32)>;
+
+/// Split the exons of one transcript at its CDS span `[cds_start, cds_end]`.
+/// Returns the exonic pieces left and right of the span, sorted by start.
+fn split_exons(exons: &mut Spans, cds_start: u32, cds_end: u32) -> (Spans, Spans) {
+    exons.sort_unstable();
+    exons.dedup();
+    let mut left = Vec::new();
+    let mut right = Vec::new();
+    for &(s, e) in exons.iter() {
+        if s < cds_start {
+            left.push((s, e.min(cds_start - 1)));
+        }
+        if e > cds_end {
+            right.push((s.max(cds_end + 1), e));
+        }
+    }
+    (left, right)
+}
+
+/// Append synthetic `five_prime_UTR`/`three_prime_UTR` lines for the transcripts of
+/// one block and return the number of lines written.
+///
+/// A transcript is any feature with both exon and CDS children that has no UTR
+/// children of its own. Its UTRs are the exonic intervals outside the CDS span
+/// (first CDS start to last CDS end), assigned 5'/3' by strand; transcripts on an
+/// unknown strand are skipped. Lines carry `ID=<tid>.utr5.<n>` (numbered along
+/// the direction of transcription) and `Parent=<tid>`.
+///
+/// With `keep`, only transcripts that are in `keep` or descend from a member are
+/// derived. `types_filter` and `lengths` apply to the synthetic lines as to any
+/// other line.
+pub fn derive_block_utrs(
+    block: &[u8],
+    keep: Option<&FxHashSet<String>>,
+    types_filter: Option<&TypeFilter>,
+    lengths: LengthFilter,
+    out: &mut Vec<u8>,
+) -> usize {
+    let features: Vec<BlockFeature> = block
+        .split(|&b| b == b'\n')
+        .filter(|l| !l.is_empty() && l[0] != b'#')
+        .filter_map(|l| str::from_utf8(l).ok())
+        .filter_map(|l| parse_feature(l.trim_end_matches('\r')))
+        .collect();
+
+    let mut parents_of: FxHashMap<&str, &[&str]> = FxHashMap::default();
+    let mut exons: FxHashMap<&str, Spans> = FxHashMap::default();
+    let mut cds_span: FxHashMap<&str, (u32, u32)> = FxHashMap::default();
+    let mut has_utr: FxHashSet<&str> = FxHashSet::default();
+    for f in &features {
+        if let Some(id) = f.id {
+            parents_of.insert(id, &f.parents);
+        }
+        for &p in &f.parents {
+            match f.ftype {
+                "exon" => exons.entry(p).or_default().push((f.start, f.end)),
+                "CDS" => {
+                    let span = cds_span.entry(p).or_insert((f.start, f.end));
+                    span.0 = span.0.min(f.start);
+                    span.1 = span.1.max(f.end);
+                }
+                "five_prime_UTR" | "three_prime_UTR" | "UTR" => {
+                    has_utr.insert(p);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut written = 0usize;
+    for f in &features {
+        let Some(tid) = f.id else { continue };
+        let Some(&(cds_start, cds_end)) = cds_span.get(tid) else { continue };
+        if has_utr.contains(tid) || !matches!(f.strand, "+" | "-") {
+            continue;
+        }
+        // Transcripts split over several lines are derived once
+        let Some(mut tx_exons) = exons.remove(tid) else { continue };
+        if keep.is_some_and(|k| !in_selected_subtree(tid, &parents_of, k)) {
+            continue;
+        }
+
+        let (left, right) = split_exons(&mut tx_exons, cds_start, cds_end);
+        let minus = f.strand == "-";
+        let (five, three) = if minus { (right, left) } else { (left, right) };
+        for (ftype, tag, pieces) in [(FIVE_PRIME_UTR, "utr5", five), (THREE_PRIME_UTR, "utr3", three)] {
+            if types_filter.is_some_and(|t| !t.allows(ftype.as_bytes())) {
+                continue;
+            }
+            let n = pieces.len();
+            for (i, (s, e)) in pieces.into_iter().enumerate() {
+                if !lengths.accepts(e - s + 1) {
+                    continue;
+                }
+                let num = if minus { n - i } else { i + 1 };
+                let line = format!(
+                    "{}\t{}\t{}\t{}\t{}\t.\t{}\t.\tID={}.{}.{};Parent={}\n",
+                    f.seqid, f.source, ftype, s, e, f.strand, tid, tag, num, tid
+                );
+                out.extend_from_slice(line.as_bytes());
+                written += 1;
+            }
+        }
+    }
+    written
+}
+
+/// Write the matched blocks followed, per block, by their derived UTR lines.
+///
+/// With `per_root_matches` (per-feature mode) only lines whose ID is matched are
+/// emitted, filtered by `types_filter` and `lengths`, and UTRs are derived for the
+/// matched transcripts and the transcripts below matched features. Without it
+/// (entire-group mode) whole blocks are emitted, less the feature lines failing
+/// `types_filter`, and every transcript is derived.
+pub fn write_gff_with_derived_utrs(
+    gff_path: &Path,
+    blocks: &[(u32, u64, u64)],
+    per_root_matches: Option<&FxHashMap<u32, FxHashSet<String>>>,
+    output_path: &Option<PathBuf>,
+    types_filter: Option<&TypeFilter>,
+    lengths: LengthFilter,
+    verbose: bool,
+) -> Result<()> {
+    let gff = RangeSource::open(gff_path)?;
+    let file_len = gff.len();
+    let blocks = &align_blocks_to_lines(&gff, blocks)?;
+
+    let mut sorted: Vec<(u32, u64, u64)> = blocks
+        .iter()
+        .copied()
+        .filter(|&(_, s, e)| s < e && e <= file_len)
+        .collect();
+    sorted.sort_unstable_by_key(|&(_, s, _)| s);
+    sorted.dedup_by_key(|b| b.1);
+
+    let parts: Vec<Option<(Vec<u8>, usize)>> = sorted
+        .par_iter()
+        .map(|&(root, s, e)| -> Result<Option<(Vec<u8>, usize)>> {
+            let keep = match per_root_matches {
+                Some(m) => match m.get(&root).filter(|k| !k.is_empty()) {
+                    Some(k) => Some(k),
+                    None => return Ok(None),
+                },
+                None => None,
+            };
+            let block = gff.range(s, e)?;
+            let window = &block[..];
+
+            let mut out = Vec::<u8>::with_capacity(window.len() + 256);
+            match keep {
+                None if types_filter.is_none() => out.extend_from_slice(window),
+                None => {
+                    for line in window.split_inclusive(|&b| b == b'\n') {
+                        let body = line.strip_suffix(b"\n").unwrap_or(line);
+                        let body = body.strip_suffix(b"\r").unwrap_or(body);
+                        if line.first() == Some(&b'#') || types_filter.is_none_or(|t| t.allows_line(body)) {
+                            out.extend_from_slice(line);
+                        }
+                    }
+                }
+                Some(keep) => {
+                    let mut pos = 0usize;
+                    while pos < window.len() {
+                        let end = memchr(b'\n', &window[pos..])
+                            .map(|i| pos + i + 1)
+                            .unwrap_or(window.len());
+                        let line = &window[pos..end];
+                        pos = end;
+                        if line.first() == Some(&b'#') {
+                            continue;
+                        }
+                        let body = line.strip_suffix(b"\n").unwrap_or(line);
+                        let body = body.strip_suffix(b"\r").unwrap_or(body);
+                        if types_filter.is_some_and(|t| !t.allows_line(body))
+                            || !lengths.accepts_line(body)
+                        {
+                            continue;
+                        }
+                        let Ok(text) = str::from_utf8(body) else { continue };
+                        if text.split('\t').nth(8).and_then(fast_id).is_some_and(|id| keep.contains(id)) {
+                            out.extend_from_slice(line);
+                        }
+                    }
+                }
+            }
+            if out.last().is_some_and(|&b| b != b'\n') {
+                out.push(b'\n');
+            }
+            let n = derive_block_utrs(window, keep, types_filter, lengths, &mut out);
+            Ok((!out.is_empty()).then_some((out, n)))
+        })
+        .collect::<Result<_>>()?;
+    let parts: Vec<(Vec<u8>, usize)> = parts.into_iter().flatten().collect();
+
+    let raw: Box<dyn Write> = match output_path {
+        Some(p) => Box::new(CountingWriter(File::create(p).with_context(|| format!("Cannot create output: {:?}", p))?)),
+        None => Box::new(CountingWriter(stdout())),
+    };
+    let mut writer = BufWriter::with_capacity(16 * 1024 * 1024, raw);
+    write_source_header(&gff, &mut writer)?;
+    let mut derived = 0usize;
+    for (buf, n) in &parts {
+        writer.write_all(buf)?;
+        derived += n;
+    }
+    writer.flush()?;
+
+    if verbose {
+        diag!("[INFO] Derived {} UTR line(s) in {} block(s)", derived, parts.len());
+    }
+    Ok(())
+}